@@ -0,0 +1,333 @@
+//! Smoothing out everything we don't predict.
+//!
+//! Snapshots arrive at the tick rate at best, and unevenly. Rather than showing each one
+//! as it lands, entities other than our champion are shown a little in the past, between
+//! the two snapshots either side of that moment. How far in the past adapts to how
+//! unevenly snapshots have been arriving. If they stop arriving for a moment, entities
+//! carry on the way they were going for a short while.
+//!
+//! Components are smoothed once registered with [`AppInterpolationExt::interpolate`].
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use common::{
+    gameplay::{Health, Position, TICK_RATE},
+    replication::NetworkId,
+};
+
+use super::prediction::Predicted;
+
+/// The time between snapshots if none are lost.
+const SNAPSHOT_INTERVAL: f64 = 1.0 / TICK_RATE;
+
+/// How many times the measured jitter to allow for on top of one snapshot interval.
+const JITTER_MARGIN: f64 = 2.0;
+
+/// The most we will show entities in the past.
+const MAX_DELAY: f64 = 0.25;
+
+/// How long to extrapolate past the newest snapshot before holding still.
+const MAX_EXTRAPOLATION: f64 = 0.2;
+
+/// How much each new snapshot moves the clock estimates, between 0 and 1.
+const CLOCK_SMOOTHING: f64 = 0.1;
+
+/// How fast the delay follows changes in jitter, as a rate per second.
+const DELAY_RATE: f64 = 2.0;
+
+pub(super) struct InterpolationPlugin;
+
+impl Plugin for InterpolationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SnapshotApplied>()
+            .configure_sets(
+                Update,
+                (InterpolationSet::Record, InterpolationSet::Apply)
+                    .chain()
+                    .run_if(resource_exists::<InterpolationClock>),
+            )
+            .add_systems(Update, track_clock.in_set(InterpolationSet::Record))
+            .interpolate::<Position>()
+            .interpolate::<Health>();
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub(super) enum InterpolationSet {
+    /// Buffers the values the latest snapshot brought.
+    Record,
+    /// Replaces them with what should be shown this frame.
+    Apply,
+}
+
+/// A component whose values can be blended.
+pub trait Interpolate: Component + Clone {
+    /// The value `t` of the way from `self` to `other`. `t` goes past 1 when
+    /// extrapolating.
+    fn interpolate(&self, other: &Self, t: f32) -> Self;
+}
+
+pub trait AppInterpolationExt {
+    /// Shows `C` on entities we don't predict in between snapshots.
+    fn interpolate<C: Interpolate>(&mut self) -> &mut Self;
+}
+
+impl AppInterpolationExt for App {
+    fn interpolate<C: Interpolate>(&mut self) -> &mut Self {
+        self.add_systems(
+            Update,
+            (
+                record::<C>.in_set(InterpolationSet::Record),
+                apply::<C>.in_set(InterpolationSet::Apply),
+            ),
+        )
+    }
+}
+
+/// Sent once the world has been updated from a snapshot.
+#[derive(Event, Debug, Clone, Copy)]
+pub(super) struct SnapshotApplied {
+    pub tick: u64,
+}
+
+fn tick_time(tick: u64) -> f64 {
+    tick as f64 * SNAPSHOT_INTERVAL
+}
+
+/// Where we are on the server's clock, for one connection to a game server.
+#[derive(Resource, Debug, Default)]
+pub(super) struct InterpolationClock {
+    /// The server's time minus ours, as measured by snapshots arriving.
+    offset: Option<f64>,
+    /// How far snapshots arrive from when `offset` says they should, on average.
+    jitter: f64,
+    /// How far in the past entities are shown.
+    delay: f64,
+    /// The server time entities are shown at this frame.
+    render_time: f64,
+}
+
+impl InterpolationClock {
+    /// The tick closest to what entities are shown at, which is what casts are aimed at.
+    pub(super) fn seen_tick(&self) -> u64 {
+        (self.render_time / SNAPSHOT_INTERVAL).round().max(0.0) as u64
+    }
+}
+
+fn track_clock(
+    time: Res<Time<Real>>,
+    mut applied: EventReader<SnapshotApplied>,
+    mut clock: ResMut<InterpolationClock>,
+) {
+    let now = time.elapsed_seconds_f64();
+    for &SnapshotApplied { tick } in applied.read() {
+        let sample = tick_time(tick) - now;
+        match clock.offset {
+            None => {
+                clock.offset = Some(sample);
+                clock.delay = SNAPSHOT_INTERVAL;
+            }
+            Some(offset) => {
+                let deviation = (sample - offset).abs();
+                clock.jitter += (deviation - clock.jitter) * CLOCK_SMOOTHING;
+                clock.offset = Some(offset + (sample - offset) * CLOCK_SMOOTHING);
+            }
+        }
+    }
+
+    let Some(offset) = clock.offset else {
+        return;
+    };
+    let target = (SNAPSHOT_INTERVAL + JITTER_MARGIN * clock.jitter).min(MAX_DELAY);
+    let follow = (DELAY_RATE * time.delta_seconds_f64()).min(1.0);
+    clock.delay += (target - clock.delay) * follow;
+    clock.render_time = now + offset - clock.delay;
+}
+
+/// The values a component had in recent snapshots, by server time, oldest first.
+#[derive(Component, Debug)]
+pub struct InterpolationBuffer<C> {
+    samples: VecDeque<(f64, C)>,
+}
+
+impl<C: Interpolate> InterpolationBuffer<C> {
+    fn push(&mut self, time: f64, value: C) {
+        if self.samples.back().is_some_and(|&(last, _)| last >= time) {
+            return;
+        }
+        self.samples.push_back((time, value));
+    }
+
+    /// The value to show at `time`.
+    fn sample(&mut self, time: f64) -> Option<C> {
+        // Samples before the newest one at or before `time` aren't needed any more, except
+        // that extrapolating takes two.
+        while self.samples.len() > 2 && self.samples[1].0 <= time {
+            self.samples.pop_front();
+        }
+
+        let mut samples = self.samples.iter();
+        let (from_time, from) = samples.next()?;
+        let Some((to_time, to)) = samples.next() else {
+            return Some(from.clone());
+        };
+        if time < *from_time {
+            return Some(from.clone());
+        }
+        let t = (time - from_time) / (to_time - from_time);
+        if t <= 1.0 {
+            return Some(from.interpolate(to, t as f32));
+        }
+        // Both samples are in the past, so carry on from them for a little while.
+        let time = time.min(to_time + MAX_EXTRAPOLATION);
+        let t = (time - from_time) / (to_time - from_time);
+        Some(from.interpolate(to, t as f32))
+    }
+}
+
+/// Buffers the value each entity got from the snapshot just applied.
+#[allow(clippy::type_complexity)]
+fn record<C: Interpolate>(
+    mut applied: EventReader<SnapshotApplied>,
+    mut entities: Query<
+        (Entity, &C, Option<&mut InterpolationBuffer<C>>),
+        (With<NetworkId>, Without<Predicted>),
+    >,
+    mut commands: Commands,
+) {
+    let Some(&SnapshotApplied { tick }) = applied.read().last() else {
+        return;
+    };
+
+    let time = tick_time(tick);
+    for (entity, value, buffer) in &mut entities {
+        match buffer {
+            Some(mut buffer) => buffer.push(time, value.clone()),
+            None => {
+                commands.entity(entity).insert(InterpolationBuffer {
+                    samples: VecDeque::from([(time, value.clone())]),
+                });
+            }
+        }
+    }
+}
+
+fn apply<C: Interpolate>(
+    clock: Res<InterpolationClock>,
+    mut entities: Query<(&mut C, &mut InterpolationBuffer<C>), Without<Predicted>>,
+) {
+    if clock.offset.is_none() {
+        return;
+    }
+    for (mut value, mut buffer) in &mut entities {
+        if let Some(shown) = buffer.sample(clock.render_time) {
+            *value = shown;
+        }
+    }
+}
+
+impl Interpolate for Position {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        Position(self.0.lerp(other.0, t))
+    }
+}
+
+impl Interpolate for Health {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        let current = self.current + (other.current - self.current) * t;
+        Health {
+            current: current.clamp(0.0, other.max),
+            max: other.max,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    /// A buffer of positions along the X axis.
+    fn buffer(samples: &[(f64, f32)]) -> InterpolationBuffer<Position> {
+        InterpolationBuffer {
+            samples: samples
+                .iter()
+                .map(|&(time, x)| (time, Position(Vec2::new(x, 0.0))))
+                .collect(),
+        }
+    }
+
+    fn x_at(buffer: &mut InterpolationBuffer<Position>, time: f64) -> f32 {
+        buffer.sample(time).unwrap().0.x
+    }
+
+    #[test]
+    fn sampling_before_the_first_sample_shows_it() {
+        assert!(buffer(&[]).sample(0.0).is_none());
+        assert_eq!(x_at(&mut buffer(&[(1.0, 5.0)]), 0.0), 5.0);
+        assert_eq!(x_at(&mut buffer(&[(1.0, 5.0), (1.1, 6.0)]), 0.5), 5.0);
+    }
+
+    #[test]
+    fn sampling_between_samples_blends_them() {
+        let mut buffer = buffer(&[(1.0, 0.0), (1.1, 1.0), (1.2, 3.0)]);
+        assert!((x_at(&mut buffer, 1.05) - 0.5).abs() < 1e-4);
+        assert!((x_at(&mut buffer, 1.15) - 2.0).abs() < 1e-4);
+        // The first sample isn't needed any more.
+        assert_eq!(buffer.samples.len(), 2);
+    }
+
+    #[test]
+    fn sampling_past_the_newest_sample_extrapolates_for_a_while() {
+        let mut buffer = buffer(&[(1.0, 0.0), (1.1, 1.0)]);
+        assert!((x_at(&mut buffer, 1.15) - 1.5).abs() < 1e-4);
+        let held = (0.1 + MAX_EXTRAPOLATION as f32) / 0.1;
+        assert!((x_at(&mut buffer, 1.1 + MAX_EXTRAPOLATION) - held).abs() < 1e-3);
+        assert!((x_at(&mut buffer, 10.0) - held).abs() < 1e-3);
+    }
+
+    /// Runs [`track_clock`] for ten seconds at 60 frames a second, with every other
+    /// snapshot arriving `jitter` seconds late.
+    fn clock_with_jitter(jitter: f64) -> InterpolationClock {
+        let start = Instant::now();
+        let mut app = App::new();
+        app.add_event::<SnapshotApplied>()
+            .init_resource::<InterpolationClock>()
+            .insert_resource(Time::<Real>::new(start))
+            .add_systems(Update, track_clock);
+
+        let arrival = |tick: u64| tick_time(tick) + if tick % 2 == 1 { jitter } else { 0.0 };
+        for frame in 1..=600 {
+            let (before, now) = ((frame - 1) as f64 / 60.0, frame as f64 / 60.0);
+            app.world
+                .resource_mut::<Time<Real>>()
+                .update_with_instant(start + Duration::from_secs_f64(now));
+            for tick in 0..300 {
+                if (before..now).contains(&arrival(tick)) {
+                    app.world.send_event(SnapshotApplied { tick });
+                }
+            }
+            app.update();
+        }
+        app.world.remove_resource::<InterpolationClock>().unwrap()
+    }
+
+    #[test]
+    fn the_delay_grows_with_jitter_up_to_a_limit() {
+        let steady = clock_with_jitter(0.0);
+        assert!(
+            (steady.delay - SNAPSHOT_INTERVAL).abs() < 0.01,
+            "{steady:?}"
+        );
+
+        let jittery = clock_with_jitter(0.03);
+        assert!(jittery.delay > SNAPSHOT_INTERVAL + 0.01, "{jittery:?}");
+        assert!(jittery.delay <= MAX_DELAY, "{jittery:?}");
+
+        let awful = clock_with_jitter(0.5);
+        assert!(awful.delay > jittery.delay, "{awful:?}");
+        assert!(awful.delay <= MAX_DELAY, "{awful:?}");
+    }
+}
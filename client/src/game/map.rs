@@ -0,0 +1,166 @@
+//! Drawing the map the game server sent, both in the world and on a minimap in the corner
+//! of the screen.
+//!
+//! Map coordinates are on the XZ plane of the world.
+
+use bevy::{
+    prelude::*,
+    render::camera::{ClearColorConfig, ScalingMode, Viewport},
+};
+use common::{
+    gameplay::{Champion, Inhibitor, Nexus, Position, Team, Tower},
+    map::{MapDefinition, Polygon},
+    Side,
+};
+
+use super::prediction::CorrectionOffset;
+
+/// The minimap's width and height, in physical pixels.
+const MINIMAP_SIZE: u32 = 240;
+
+/// How far above the map the minimap looks down from.
+const MINIMAP_HEIGHT: f32 = 100.0;
+
+pub(super) struct MapPlugin;
+
+impl Plugin for MapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                spawn_minimap.run_if(resource_added::<MapDefinition>),
+                despawn_minimap.run_if(resource_removed::<MapDefinition>()),
+                (draw_map, draw_units).run_if(resource_exists::<MapDefinition>),
+            ),
+        );
+    }
+}
+
+/// The camera showing the whole map from above.
+#[derive(Component)]
+struct Minimap;
+
+fn spawn_minimap(map: Res<MapDefinition>, mut commands: Commands) {
+    let (min, max) = map
+        .terrain
+        .walkable
+        .iter()
+        .map(Polygon::bounds)
+        .fold((Vec2::ZERO, Vec2::ZERO), |(min, max), (lo, hi)| {
+            (min.min(lo), max.max(hi))
+        });
+    let center = ground((min + max) / 2.0);
+    let size = (max - min).max_element();
+
+    commands.spawn((
+        Minimap,
+        Camera3dBundle {
+            camera: Camera {
+                order: 1,
+                viewport: Some(Viewport {
+                    physical_position: UVec2::ZERO,
+                    physical_size: UVec2::splat(MINIMAP_SIZE),
+                    ..default()
+                }),
+                clear_color: ClearColorConfig::Custom(Color::BLACK),
+                ..default()
+            },
+            projection: OrthographicProjection {
+                scaling_mode: ScalingMode::Fixed {
+                    width: size,
+                    height: size,
+                },
+                ..default()
+            }
+            .into(),
+            transform: Transform::from_translation(center + Vec3::Y * MINIMAP_HEIGHT)
+                .looking_at(center, Vec3::NEG_Z),
+            ..default()
+        },
+    ));
+}
+
+fn despawn_minimap(minimaps: Query<Entity, With<Minimap>>, mut commands: Commands) {
+    for minimap in &minimaps {
+        commands.entity(minimap).despawn();
+    }
+}
+
+/// Where a point on the map is in the world.
+fn ground(point: Vec2) -> Vec3 {
+    Vec3::new(point.x, 0.0, point.y)
+}
+
+fn side_color(side: Side) -> Color {
+    match side {
+        Side::RED => Color::rgb(0.9, 0.2, 0.2),
+        Side::BLUE => Color::rgb(0.2, 0.4, 0.9),
+        _ => Color::rgb(0.8, 0.8, 0.2),
+    }
+}
+
+fn draw_polygon(gizmos: &mut Gizmos, polygon: &Polygon, color: Color) {
+    let corners = polygon.0.iter().chain(polygon.0.first());
+    gizmos.linestrip(corners.map(|&corner| ground(corner)), color);
+}
+
+fn draw_map(map: Res<MapDefinition>, mut gizmos: Gizmos) {
+    for area in &map.terrain.walkable {
+        draw_polygon(&mut gizmos, area, Color::GRAY);
+    }
+    for obstacle in &map.terrain.obstacles {
+        draw_polygon(&mut gizmos, obstacle, Color::DARK_GRAY);
+    }
+    for brush in &map.brush {
+        draw_polygon(&mut gizmos, brush, Color::DARK_GREEN);
+    }
+    for base in &map.bases {
+        let color = side_color(base.side);
+        draw_polygon(&mut gizmos, &base.area, color);
+        gizmos.circle(
+            ground(base.fountain.center),
+            Direction3d::Y,
+            base.fountain.radius,
+            color,
+        );
+    }
+    for lane in &map.lanes {
+        let waypoints = lane.waypoints.iter().map(|&waypoint| ground(waypoint));
+        gizmos.linestrip(waypoints, Color::BEIGE);
+    }
+    for camp in &map.jungle_camps {
+        gizmos.circle(
+            ground(camp.position),
+            Direction3d::Y,
+            camp.leash_radius,
+            Color::OLIVE,
+        );
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn draw_units(
+    units: Query<(
+        &Position,
+        Option<&CorrectionOffset>,
+        &Team,
+        Has<Champion>,
+        Has<Tower>,
+        Has<Inhibitor>,
+        Has<Nexus>,
+    )>,
+    mut gizmos: Gizmos,
+) {
+    for (position, offset, team, champion, tower, inhibitor, nexus) in &units {
+        let radius = match (champion, tower, inhibitor, nexus) {
+            (true, ..) => 1.0,
+            (_, true, ..) => 1.5,
+            (.., true, _) => 2.0,
+            (.., true) => 3.0,
+            _ => continue,
+        };
+        // Our champion is drawn where we predicted it until corrections fade out.
+        let position = position.0 + offset.map_or(Vec2::ZERO, |offset| offset.0);
+        gizmos.circle(ground(position), Direction3d::Y, radius, side_color(team.0));
+    }
+}
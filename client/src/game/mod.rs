@@ -0,0 +1,169 @@
+//! Playing a match: the connection to the game server, and the mirror of the world it
+//! replicates.
+
+mod interpolation;
+mod map;
+mod prediction;
+
+use std::{net::SocketAddr, time::Instant};
+
+use bevy::prelude::*;
+use common::{
+    gameplay::navigation::NavGrid,
+    map::MapDefinition,
+    network::game::{
+        snapshot::SnapshotHistory,
+        transport::{Channel, ClientEvent, ClientSocket},
+        GameClientHello, GameClientMessage, GameServerMessage, JoinToken,
+    },
+    replication::ReplicationPlugin,
+};
+
+use self::{
+    interpolation::{InterpolationClock, InterpolationPlugin, InterpolationSet, SnapshotApplied},
+    map::MapPlugin,
+    prediction::{Prediction, PredictionPlugin},
+};
+use crate::nongame::LocalPlayer;
+
+pub struct Game;
+
+impl Plugin for Game {
+    fn build(&self, app: &mut App) {
+        app.add_event::<GameReady>()
+            .add_plugins((
+                ReplicationPlugin,
+                PredictionPlugin,
+                InterpolationPlugin,
+                MapPlugin,
+            ))
+            .add_systems(
+                Update,
+                (connect, receive).chain().before(InterpolationSet::Record),
+            );
+    }
+}
+
+/// The lobby server has a game server waiting for us.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct GameReady {
+    pub addr: SocketAddr,
+    pub token: JoinToken,
+}
+
+/// The connection to the game server hosting our match.
+#[derive(Resource)]
+struct GameServer {
+    socket: ClientSocket,
+    snapshots: SnapshotHistory,
+}
+
+impl GameServer {
+    fn send(&mut self, channel: Channel, msg: &GameClientMessage) {
+        let bytes = postcard::to_allocvec(msg).expect("Messages can always be encoded");
+        if let Err(e) = self.socket.send(channel, &bytes) {
+            eprintln!("Could not send to the game server: {e}");
+        }
+    }
+}
+
+fn connect(
+    mut ready: EventReader<GameReady>,
+    player: Option<Res<LocalPlayer>>,
+    mut commands: Commands,
+) {
+    let Some(&GameReady { addr, token }) = ready.read().last() else {
+        return;
+    };
+    let Some(player) = player else {
+        return;
+    };
+
+    let hello = GameClientHello {
+        player: player.0,
+        token,
+    };
+    match ClientSocket::connect(addr, hello, Instant::now()) {
+        Ok(socket) => {
+            commands.insert_resource(GameServer {
+                socket,
+                snapshots: SnapshotHistory::default(),
+            });
+            commands.insert_resource(Prediction::default());
+            commands.insert_resource(InterpolationClock::default());
+        }
+        Err(e) => eprintln!("Could not connect to the game server: {e}"),
+    }
+}
+
+/// Handles everything the game server sent since last frame. Only the newest snapshot is
+/// applied to the world, but all of them are acknowledged.
+fn receive(world: &mut World) {
+    let Some(mut server) = world.remove_resource::<GameServer>() else {
+        return;
+    };
+
+    let mut connected = true;
+    // The newest command of ours the newest snapshot reflects, if there is a new snapshot.
+    let mut new_snapshot = None;
+    for event in server.socket.receive(Instant::now()) {
+        match event {
+            ClientEvent::Connected => println!("Connected to the game server"),
+            ClientEvent::Refused { reason } => {
+                eprintln!("The game server turned us away: {reason}");
+                connected = false;
+            }
+            ClientEvent::Disconnected => {
+                println!("Disconnected from the game server");
+                connected = false;
+            }
+            ClientEvent::Message { message, .. } => {
+                match postcard::from_bytes::<GameServerMessage>(&message) {
+                    Ok(GameServerMessage::Welcome { setup, map, tick }) => {
+                        println!("Joined the match on {} at tick {tick}", setup.map);
+                        world.insert_resource(NavGrid::new(&map.terrain));
+                        world.insert_resource(*map);
+                    }
+                    Ok(GameServerMessage::Snapshot { delta, input }) => {
+                        match server.snapshots.receive(delta) {
+                            Ok(snapshot) => {
+                                let tick = snapshot.tick;
+                                new_snapshot = Some(input);
+                                server.send(
+                                    Channel::Unreliable,
+                                    &GameClientMessage::AckSnapshot { tick },
+                                );
+                            }
+                            Err(e) => eprintln!("Dropped a snapshot: {e:#}"),
+                        }
+                    }
+                    Ok(GameServerMessage::MatchEnded { winner }) => match winner {
+                        Some(side) => println!("{side} won the match"),
+                        None => println!("The match ended without a winner"),
+                    },
+                    Err(e) => eprintln!("Bad message from the game server: {e}"),
+                }
+            }
+        }
+    }
+
+    if let Some(input) = new_snapshot {
+        let snapshot = server
+            .snapshots
+            .latest()
+            .expect("A snapshot was just received");
+        let tick = snapshot.tick;
+        prediction::apply_snapshot(world, snapshot, input);
+        world.send_event(SnapshotApplied { tick });
+    }
+
+    if connected {
+        server.socket.flush(Instant::now());
+        world.insert_resource(server);
+    } else {
+        world.remove_resource::<Prediction>();
+        world.remove_resource::<InterpolationClock>();
+        world.remove_resource::<MapDefinition>();
+        world.remove_resource::<NavGrid>();
+    }
+}
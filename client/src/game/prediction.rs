@@ -0,0 +1,595 @@
+//! Predicting our own champion.
+//!
+//! Commands are applied to the local champion as soon as they are given, with the same
+//! movement code the server runs, instead of a round trip later. Each snapshot then
+//! rewinds the champion to where the server has it and replays the steps the server has
+//! not accounted for yet. Whatever difference that makes is smoothed out over a few frames
+//! rather than shown as a jump.
+
+use std::collections::VecDeque;
+
+use bevy::{prelude::*, window::PrimaryWindow};
+use common::{
+    gameplay::{
+        self,
+        abilities::Cast,
+        navigation::{self, NavGrid, Path},
+        steering::{self, Body, Collider, Ghosted},
+        AppliedInput, Champion, ChampionCommand, Health, Movement, Position, Team, TICK_RATE,
+    },
+    network::game::{snapshot::Snapshot, transport::Channel, GameClientMessage},
+    replication::{self, NetworkId},
+};
+
+use super::{interpolation::InterpolationClock, GameServer};
+use crate::nongame::{LocalPlayer, MainCamera};
+
+/// How many steps to remember for replaying. About two seconds' worth; steps older than
+/// this are assumed to be confirmed.
+const MAX_STEPS: usize = 64;
+
+/// How fast corrections fade, as a rate per second.
+const CORRECTION_RATE: f32 = 10.0;
+
+/// Corrections bigger than this are shown as they are: something teleported the champion.
+const SNAP_DISTANCE: f32 = 4.0;
+
+pub(super) struct PredictionPlugin;
+
+impl Plugin for PredictionPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Time::<Fixed>::from_hz(TICK_RATE))
+            .add_event::<LocalCommand>()
+            .add_systems(
+                Update,
+                (click_to_move, queue_commands, smooth_corrections)
+                    .chain()
+                    .run_if(resource_exists::<Prediction>),
+            )
+            .add_systems(
+                FixedUpdate,
+                (navigation::update_obstacles, predict)
+                    .chain()
+                    .run_if(resource_exists::<Prediction>.and_then(resource_exists::<GameServer>)),
+            );
+    }
+}
+
+/// An order for our champion.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct LocalCommand(pub ChampionCommand);
+
+/// Marks the champion we predict, which is therefore not interpolated.
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct Predicted;
+
+/// Shown on top of the local champion's predicted position while a correction fades out.
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct CorrectionOffset(pub Vec2);
+
+/// Our champion's predicted steps, for one connection to a game server.
+#[derive(Resource, Debug, Default)]
+pub(super) struct Prediction {
+    next_sequence: u32,
+    next_step: u64,
+    /// Commands waiting for the next step, queued like the simulation's
+    /// [`Inputs`](gameplay::Inputs).
+    pending: Vec<ChampionCommand>,
+    /// Steps that may have to be replayed, oldest first.
+    steps: VecDeque<Step>,
+    /// The newest command the server has applied, and the step we applied it in.
+    acked: Option<(u32, u64)>,
+}
+
+#[derive(Debug)]
+struct Step {
+    number: u64,
+    commands: Vec<(u32, ChampionCommand)>,
+}
+
+impl Prediction {
+    /// Forgets the steps a snapshot for `tick` already accounts for, given the newest
+    /// command it reflects.
+    fn confirm(&mut self, input: Option<AppliedInput>, tick: u64) {
+        let unconfirmed = self
+            .steps
+            .iter()
+            .position(|step| {
+                step.commands
+                    .iter()
+                    .any(|&(sequence, _)| input.is_none_or(|input| sequence > input.sequence))
+            })
+            .unwrap_or(self.steps.len());
+
+        let confirmed = match input {
+            // Nothing we did has reached the simulation yet, so only steps before our
+            // first command are accounted for.
+            None => unconfirmed,
+            Some(input) => {
+                let applied_in = self
+                    .steps
+                    .iter()
+                    .find(|step| {
+                        step.commands
+                            .iter()
+                            .any(|&(sequence, _)| sequence == input.sequence)
+                    })
+                    .map(|step| step.number)
+                    .or(self
+                        .acked
+                        .filter(|&(sequence, _)| sequence == input.sequence)
+                        .map(|(_, step)| step));
+                match applied_in {
+                    Some(applied_in) => {
+                        self.acked = Some((input.sequence, applied_in));
+                        // The server has kept simulating since, one tick per step.
+                        let last = applied_in + tick.saturating_sub(input.tick);
+                        let simulated = self.steps.iter().take_while(|s| s.number <= last);
+                        simulated.count().min(unconfirmed)
+                    }
+                    None => unconfirmed,
+                }
+            }
+        };
+        self.steps.drain(..confirmed);
+    }
+}
+
+/// Another unit our champion can bump into.
+type Obstacle<'a> = (
+    &'a NetworkId,
+    &'a Position,
+    &'a Collider,
+    &'a Movement,
+    Option<&'a Path>,
+    Option<&'a Health>,
+);
+
+fn obstacle_body((id, position, collider, movement, path, health): Obstacle) -> Option<Body> {
+    let alive = health.is_none_or(|health| !health.is_dead());
+    alive.then(|| Body::new(id.0, position, collider, movement, path))
+}
+
+/// Pushes our champion out of the units around it, the way the server does.
+fn collide(
+    id: NetworkId,
+    position: &mut Position,
+    movement: &Movement,
+    path: &Path,
+    collider: Option<Collider>,
+    others: &[Body],
+    nav: Option<&NavGrid>,
+) {
+    let Some(collider) = collider else {
+        return;
+    };
+    let body = Body::new(id.0, position, &collider, movement, Some(path));
+    let push = steering::separation(&body, others.iter().copied());
+    steering::push_unit(position, push, nav);
+}
+
+/// Our champion's collider, unless it doesn't collide right now.
+fn local_collider(entity: EntityRef) -> Option<Collider> {
+    let dead = entity.get::<Health>().is_some_and(Health::is_dead);
+    if dead || entity.contains::<Ghosted>() {
+        return None;
+    }
+    entity.get::<Collider>().copied()
+}
+
+fn local_champion(world: &mut World) -> Option<Entity> {
+    let player = world.get_resource::<LocalPlayer>()?.0;
+    world
+        .query::<(Entity, &Champion)>()
+        .iter(world)
+        .find(|(_, champion)| champion.player == player)
+        .map(|(entity, _)| entity)
+}
+
+/// Applies an authoritative snapshot, then replays our champion's unconfirmed steps on
+/// top of it.
+pub(super) fn apply_snapshot(world: &mut World, snapshot: &Snapshot, input: Option<AppliedInput>) {
+    let predicted = local_champion(world).and_then(|entity| world.get::<Position>(entity).copied());
+    replication::apply_snapshot(world, snapshot);
+
+    let step = world.resource::<Time<Fixed>>().timestep().as_secs_f32();
+    let nav = world.remove_resource::<NavGrid>();
+    world.resource_scope(|world, mut prediction: Mut<Prediction>| {
+        prediction.confirm(input, snapshot.tick);
+
+        let Some(entity) = local_champion(world) else {
+            return;
+        };
+        // Other units are wherever the snapshot has them throughout the replay.
+        let others = world
+            .query_filtered::<(Entity, Obstacle), Without<Ghosted>>()
+            .iter(world)
+            .filter(|&(other, _)| other != entity)
+            .filter_map(|(_, obstacle)| obstacle_body(obstacle))
+            .collect::<Vec<_>>();
+        let collider = local_collider(world.entity(entity));
+        let Some(&id) = world.get::<NetworkId>(entity) else {
+            return;
+        };
+        let dead = world.get::<Health>(entity).is_some_and(Health::is_dead);
+        let mut entity = world.entity_mut(entity);
+        let (Some(mut position), Some(mut movement)) = (
+            entity.get::<Position>().copied(),
+            entity.get::<Movement>().copied(),
+        ) else {
+            return;
+        };
+        let mut path = entity.get::<Path>().cloned().unwrap_or_default();
+
+        for replayed in &prediction.steps {
+            // The server ignores what the dead tell it to do.
+            if dead {
+                break;
+            }
+            for &(_, command) in &replayed.commands {
+                gameplay::apply_command(&mut movement, command);
+            }
+            gameplay::step_movement(&mut position, &mut movement, &mut path, nav.as_ref(), step);
+            collide(
+                id,
+                &mut position,
+                &movement,
+                &path,
+                collider,
+                &others,
+                nav.as_ref(),
+            );
+        }
+
+        let mut offset = entity
+            .get::<CorrectionOffset>()
+            .copied()
+            .unwrap_or_default();
+        if let Some(predicted) = predicted {
+            let error = predicted.0 - position.0;
+            offset.0 = if error.length() < SNAP_DISTANCE {
+                offset.0 + error
+            } else {
+                Vec2::ZERO
+            };
+        }
+        entity.insert((Predicted, position, movement, path, offset));
+    });
+    if let Some(nav) = nav {
+        world.insert_resource(nav);
+    }
+}
+
+/// Where on the map the cursor is pointing.
+fn cursor_on_ground(
+    windows: &Query<&Window, With<PrimaryWindow>>,
+    cameras: &Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+) -> Option<Vec2> {
+    let cursor = windows.get_single().ok()?.cursor_position()?;
+    let (camera, transform) = cameras.get_single().ok()?;
+    let ray = camera.viewport_to_world(transform, cursor)?;
+    // The map is flat, on the XZ plane.
+    let distance = ray.intersect_plane(Vec3::ZERO, Plane3d::new(Vec3::Y))?;
+    Some(ray.get_point(distance).xz())
+}
+
+/// Moves our champion to wherever the ground is right-clicked, or stops it on `S`. `Q`
+/// casts a skillshot towards the cursor, and `W` casts at the enemy nearest the cursor.
+fn click_to_move(
+    mouse: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    player: Res<LocalPlayer>,
+    units: Query<(&Position, &Team, Option<&NetworkId>, Option<&Champion>)>,
+    mut commands: EventWriter<LocalCommand>,
+) {
+    if keys.just_pressed(KeyCode::KeyS) {
+        commands.send(LocalCommand(ChampionCommand::Stop));
+    }
+    let Some(ground) = cursor_on_ground(&windows, &cameras) else {
+        return;
+    };
+    if mouse.just_pressed(MouseButton::Right) {
+        commands.send(LocalCommand(ChampionCommand::MoveTo(ground)));
+    }
+
+    let Some((&Position(origin), &Team(side), ..)) = units
+        .iter()
+        .find(|(.., champion)| champion.is_some_and(|c| c.player == player.0))
+    else {
+        return;
+    };
+    if keys.just_pressed(KeyCode::KeyQ) {
+        let direction = ground - origin;
+        commands.send(LocalCommand(ChampionCommand::Cast(Cast::Skillshot {
+            direction,
+        })));
+    }
+    if keys.just_pressed(KeyCode::KeyW) {
+        let nearest = units
+            .iter()
+            .filter(|(_, team, ..)| team.0 != side)
+            .filter_map(|(position, _, id, _)| Some((position.0.distance(ground), *id?)))
+            .min_by(|(a, _), (b, _)| a.total_cmp(b));
+        if let Some((_, target)) = nearest {
+            commands.send(LocalCommand(ChampionCommand::Cast(Cast::Targeted {
+                target,
+            })));
+        }
+    }
+}
+
+fn queue_commands(mut commands: EventReader<LocalCommand>, mut prediction: ResMut<Prediction>) {
+    prediction
+        .pending
+        .extend(commands.read().map(|&LocalCommand(command)| command));
+}
+
+/// Sends the queued commands and applies them to our champion, along with one tick of
+/// movement.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn predict(
+    time: Res<Time>,
+    player: Res<LocalPlayer>,
+    clock: Option<Res<InterpolationClock>>,
+    nav: Option<Res<NavGrid>>,
+    mut prediction: ResMut<Prediction>,
+    mut server: ResMut<GameServer>,
+    mut champions: Query<
+        (EntityRef, &mut Position, &mut Movement, &mut Path),
+        (With<Champion>, With<Predicted>),
+    >,
+    others: Query<Obstacle, (Without<Predicted>, Without<Ghosted>)>,
+) {
+    let prediction = &mut *prediction;
+    let seen_tick = clock.map_or(0, |clock| clock.seen_tick());
+    let commands = prediction
+        .pending
+        .drain(..)
+        .map(|command| {
+            let sequence = prediction.next_sequence;
+            prediction.next_sequence += 1;
+            server.send(
+                Channel::Reliable,
+                &GameClientMessage::Command {
+                    sequence,
+                    seen_tick,
+                    command,
+                },
+            );
+            (sequence, command)
+        })
+        .collect::<Vec<_>>();
+
+    let champion = champions.iter_mut().find(|(entity, ..)| {
+        entity
+            .get::<Champion>()
+            .is_some_and(|c| c.player == player.0)
+    });
+    if let Some((entity, mut position, mut movement, mut path)) = champion {
+        let dead = entity.get::<Health>().is_some_and(Health::is_dead);
+        if !dead {
+            for &(_, command) in &commands {
+                gameplay::apply_command(&mut movement, command);
+            }
+            let step = time.delta_seconds();
+            gameplay::step_movement(
+                &mut position,
+                &mut movement,
+                &mut path,
+                nav.as_deref(),
+                step,
+            );
+        }
+        let others = others.iter().filter_map(obstacle_body).collect::<Vec<_>>();
+        let collider = local_collider(entity);
+        if let Some(&id) = entity.get::<NetworkId>() {
+            collide(
+                id,
+                &mut position,
+                &movement,
+                &path,
+                collider,
+                &others,
+                nav.as_deref(),
+            );
+        }
+    }
+
+    prediction.steps.push_back(Step {
+        number: prediction.next_step,
+        commands,
+    });
+    prediction.next_step += 1;
+    if prediction.steps.len() > MAX_STEPS {
+        prediction.steps.pop_front();
+    }
+}
+
+fn smooth_corrections(time: Res<Time>, mut offsets: Query<&mut CorrectionOffset>) {
+    let fade = (-CORRECTION_RATE * time.delta_seconds()).exp();
+    for mut offset in &mut offsets {
+        offset.0 *= fade;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        ecs::system::RunSystemOnce,
+        render::camera::{camera_system, ManualTextureViews, Viewport},
+        window::{ExitCondition, WindowResolution},
+    };
+
+    use common::{
+        network::{game::Controller, lobby::PlayerId},
+        replication::{capture_snapshot, Replicated, ReplicationPlugin},
+    };
+    use uuid::Uuid;
+
+    use super::*;
+
+    const PLAYER: PlayerId = PlayerId(Uuid::nil());
+
+    /// Where the server has our champion, walking towards `target`.
+    fn server_snapshot(position: Vec2, target: Vec2, tick: u64) -> Snapshot {
+        let mut server = App::new();
+        server
+            .add_plugins(ReplicationPlugin)
+            .add_systems(Update, replication::assign_network_ids);
+        server.world.spawn((
+            Replicated,
+            Champion {
+                player: PLAYER,
+                controller: Controller::Human,
+            },
+            Position(position),
+            Movement {
+                speed: 6.0,
+                target: Some(target),
+            },
+        ));
+        server.update();
+        capture_snapshot(&mut server.world, tick)
+    }
+
+    /// A client that had predicted our champion at `predicted`, after four steps: a move
+    /// to (10, 0) in the first and another in the last.
+    fn client(predicted: Vec2) -> App {
+        let mut client = App::new();
+        client
+            .add_plugins(ReplicationPlugin)
+            .insert_resource(Time::<Fixed>::from_hz(TICK_RATE))
+            .insert_resource(LocalPlayer(PLAYER))
+            .init_resource::<Prediction>();
+        apply_snapshot(
+            &mut client.world,
+            &server_snapshot(Vec2::ZERO, Vec2::ZERO, 0),
+            None,
+        );
+
+        let target = Vec2::new(10.0, 0.0);
+        let mut prediction = client.world.resource_mut::<Prediction>();
+        prediction.steps = (1..=4)
+            .map(|number| Step {
+                number,
+                commands: match number {
+                    1 => vec![(0, ChampionCommand::MoveTo(target))],
+                    4 => vec![(1, ChampionCommand::MoveTo(target))],
+                    _ => Vec::new(),
+                },
+            })
+            .collect();
+        let champion = local_champion(&mut client.world).unwrap();
+        client.world.get_mut::<Position>(champion).unwrap().0 = predicted;
+        client
+    }
+
+    /// Applies a snapshot for tick 3 in which the server applied our first move in tick 1
+    /// and has the champion at (2, 0). Returns where the champion ends up and its
+    /// correction.
+    fn confirm(client: &mut App) -> (Vec2, Vec2) {
+        let snapshot = server_snapshot(Vec2::new(2.0, 0.0), Vec2::new(10.0, 0.0), 3);
+        let input = AppliedInput {
+            sequence: 0,
+            tick: 1,
+        };
+        apply_snapshot(&mut client.world, &snapshot, Some(input));
+
+        // Only the last step is left to replay.
+        let steps = &client.world.resource::<Prediction>().steps;
+        assert_eq!(steps.iter().map(|s| s.number).collect::<Vec<_>>(), [4]);
+        let champion = local_champion(&mut client.world).unwrap();
+        let entity = client.world.entity(champion);
+        (
+            entity.get::<Position>().unwrap().0,
+            entity.get::<CorrectionOffset>().unwrap().0,
+        )
+    }
+
+    /// Where the champion is after replaying the last step from (2, 0).
+    fn replayed() -> Vec2 {
+        Vec2::new(2.0 + 6.0 / TICK_RATE as f32, 0.0)
+    }
+
+    #[test]
+    fn correct_predictions_are_left_alone() {
+        let mut client = client(replayed());
+        let (position, offset) = confirm(&mut client);
+        assert!(position.distance(replayed()) < 1e-4, "{position}");
+        assert!(offset.length() < 1e-4, "{offset}");
+    }
+
+    #[test]
+    fn small_corrections_are_smoothed_out() {
+        let mut client = client(replayed() + Vec2::new(0.5, 0.0));
+        let (position, offset) = confirm(&mut client);
+        assert!(position.distance(replayed()) < 1e-4, "{position}");
+        // It is still drawn where it was predicted.
+        assert!(offset.distance(Vec2::new(0.5, 0.0)) < 1e-4, "{offset}");
+    }
+
+    #[test]
+    fn big_corrections_snap() {
+        let mut client = client(replayed() + Vec2::new(0.0, SNAP_DISTANCE + 1.0));
+        let (position, offset) = confirm(&mut client);
+        assert!(position.distance(replayed()) < 1e-4, "{position}");
+        assert_eq!(offset, Vec2::ZERO);
+    }
+
+    #[test]
+    fn the_cursor_is_found_through_the_main_camera_when_the_minimap_is_up() {
+        let mut app = App::new();
+        app.add_plugins(WindowPlugin {
+            primary_window: None,
+            exit_condition: ExitCondition::DontExit,
+            close_when_requested: false,
+        })
+        .add_event::<AssetEvent<Image>>()
+        .init_resource::<Assets<Image>>()
+        .init_resource::<ManualTextureViews>()
+        .add_systems(Update, camera_system::<Projection>);
+
+        let mut window = Window {
+            resolution: WindowResolution::new(800.0, 600.0),
+            ..default()
+        };
+        window.set_cursor_position(Some(Vec2::new(400.0, 300.0)));
+        app.world.spawn((window, PrimaryWindow));
+
+        let main = Transform::from_xyz(0.0, 50.0, 0.0).looking_at(Vec3::ZERO, Vec3::NEG_Z);
+        app.world.spawn((
+            MainCamera,
+            Camera3dBundle {
+                global_transform: main.into(),
+                ..default()
+            },
+        ));
+        let minimap = Transform::from_xyz(30.0, 100.0, 30.0)
+            .looking_at(Vec3::new(30.0, 0.0, 30.0), Vec3::NEG_Z);
+        app.world.spawn(Camera3dBundle {
+            camera: Camera {
+                order: 1,
+                viewport: Some(Viewport {
+                    physical_position: UVec2::ZERO,
+                    physical_size: UVec2::splat(240),
+                    ..default()
+                }),
+                ..default()
+            },
+            global_transform: minimap.into(),
+            ..default()
+        });
+        app.update();
+
+        let ground = app.world.run_system_once(
+            |windows: Query<&Window, With<PrimaryWindow>>,
+             cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>| {
+                cursor_on_ground(&windows, &cameras)
+            },
+        );
+        let ground = ground.expect("the cursor should be over the ground");
+        assert!(ground.length() < 0.01, "{ground}");
+    }
+}
@@ -10,10 +10,12 @@
 use bevy::{app::App, DefaultPlugins};
 use bevy_framepace::FramepacePlugin;
 use bevy_mod_picking::DefaultPickingPlugins;
+use game::Game;
 use nongame::NonGame;
 
 const DEBUG: bool = true;
 
+mod game;
 mod nongame;
 pub mod ui;
 
@@ -25,6 +27,7 @@ fn main() {
             DefaultPickingPlugins,
             ui::UiPlugin,
             NonGame {},
+            Game,
         ))
         .run();
 }
@@ -6,7 +6,10 @@ use crate::{ui::{
     button, label, stack, textedit, BuildContext, TextEditComponent, Widget, WidgetExt,
 }, DEBUG};
 
-use super::{destroy_menu, network, quit, ConnectingState, EventChannel, Menu, RequestChannel};
+use super::{
+    destroy_menu, network, quit, ConnectingState, DisconnectNotice, EventChannel,
+    LatestAnnouncement, Menu, RequestChannel,
+};
 
 #[derive(Clone, Event)]
 pub struct ConnectToServer(SocketAddr);
@@ -33,7 +36,12 @@ impl Plugin for InConnectToServerPlugin {
     }
 }
 
-fn make_connect_menu(asset_server: Res<AssetServer>, mut commands: Commands) {
+fn make_connect_menu(
+    asset_server: Res<AssetServer>,
+    notice: Option<Res<DisconnectNotice>>,
+    mut commands: Commands,
+) {
+    let notice = notice.map(|notice| notice.0.clone());
     let mut cx = BuildContext {
         asset_server: &asset_server,
         commands: &mut commands,
@@ -42,8 +50,12 @@ fn make_connect_menu(asset_server: Res<AssetServer>, mut commands: Commands) {
 
     let te = textedit("[::]:65432").build(cx);
 
-    stack(FlexDirection::Column)
-        .with(label("Connect to server:"))
+    let mut menu = stack(FlexDirection::Column);
+    if let Some(notice) = notice {
+        menu.add(label(notice));
+    }
+
+    menu.with(label("Connect to server:"))
         .with(te)
         .with(button(
             label("Connect"),
@@ -72,6 +84,7 @@ fn connect_to_server_system(
     mut next_state: ResMut<NextState<ConnectingState>>,
     mut event_channel: NonSendMut<EventChannel>,
     mut request_channel: ResMut<RequestChannel>,
+    mut commands: Commands,
 ) {
     let events = events.read().collect::<Vec<_>>();
     let &ConnectToServer(addr) = match &events[..] {
@@ -84,6 +97,8 @@ fn connect_to_server_system(
     let (send_request, recv_request) = mpsc::channel();
 
     next_state.set(ConnectingState::Connecting);
+    commands.remove_resource::<DisconnectNotice>();
+    commands.remove_resource::<LatestAnnouncement>();
 
     event_channel.channel = Some(recv_event);
     request_channel.channel = Some(send_request);
@@ -36,10 +36,11 @@ fn connected_to_server(
 ) {
     for event in reader.read() {
         match event {
-            ServerConnectionStatus::Connected => next_state.set(ConnectingState::Connected),
+            ServerConnectionStatus::Connected { .. } => next_state.set(ConnectingState::Connected),
             ServerConnectionStatus::ConnectionFailed => {
                 next_state.set(ConnectingState::NotConnected)
             }
+            ServerConnectionStatus::Disconnected { .. } => {}
         }
     }
 }
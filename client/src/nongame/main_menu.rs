@@ -1,17 +1,23 @@
+mod champion_select;
 mod lobby;
 mod lobby_list;
+mod roles;
 
 use bevy::{app::AppExit, prelude::*};
 use bevy_mod_picking::prelude::*;
+use common::network::lobby::PickMode;
 
 use crate::{
-    ui::{button, img_on_hover_btn, stack, BuildContext, Widget as _, WidgetExt},
+    ui::{button, img_on_hover_btn, label, stack, BuildContext, Widget as _, WidgetExt},
     DEBUG,
 };
 
-use self::{lobby::LobbyPlugin, lobby_list::LobbyListPlugin};
+use self::{
+    champion_select::ChampionSelectPlugin, lobby::LobbyPlugin, lobby_list::LobbyListPlugin,
+    roles::RolesPlugin,
+};
 
-use super::{destroy_menu, network::Request, ConnectingState};
+use super::{destroy_menu, network::Request, ConnectingState, LatestAnnouncement};
 
 pub struct MainMenuPlugin;
 
@@ -21,15 +27,32 @@ impl Plugin for MainMenuPlugin {
             OnEnter(ConnectingState::Connected),
             (make_main_menu, enter_main_menu),
         );
-        app.add_systems(OnExit(ConnectingState::Connected), destroy_menu);
+        app.add_systems(
+            OnExit(ConnectingState::Connected),
+            (destroy_menu, leave_main_menu),
+        );
         app.insert_state(LobbyState::None);
+        app.add_systems(
+            Update,
+            show_announcement.run_if(in_state(ConnectingState::Connected)),
+        );
 
-        app.add_plugins((LobbyListPlugin, LobbyPlugin));
+        app.add_plugins((
+            LobbyListPlugin,
+            LobbyPlugin,
+            RolesPlugin,
+            ChampionSelectPlugin,
+        ));
 
         if DEBUG {
-            app.add_systems(OnEnter(ConnectingState::Connected), |mut e: EventWriter<Request>| {
-                e.send(Request::CreateLobby);
-            });
+            app.add_systems(
+                OnEnter(ConnectingState::Connected),
+                |mut e: EventWriter<Request>| {
+                    e.send(Request::CreateLobby {
+                        pick_mode: PickMode::FreePick,
+                    });
+                },
+            );
         }
     }
 }
@@ -38,6 +61,10 @@ fn enter_main_menu(mut next_state: ResMut<NextState<LobbyState>>) {
     next_state.set(LobbyState::NotInLobby);
 }
 
+fn leave_main_menu(mut next_state: ResMut<NextState<LobbyState>>) {
+    next_state.set(LobbyState::None);
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, States)]
 pub enum LobbyState {
     None,
@@ -48,7 +75,32 @@ pub enum LobbyState {
 #[derive(Component)]
 pub struct MenuHolder;
 
-pub fn make_main_menu(asset_server: Res<AssetServer>, mut commands: Commands) {
+#[derive(Component)]
+struct AnnouncementLabel;
+
+fn announcement_text(announcement: Option<&LatestAnnouncement>) -> String {
+    announcement.map_or_else(String::new, |announcement| {
+        format!("Announcement: {}", announcement.0)
+    })
+}
+
+fn show_announcement(
+    announcement: Option<Res<LatestAnnouncement>>,
+    mut labels: Query<&mut Text, With<AnnouncementLabel>>,
+) {
+    let Some(announcement) = announcement.filter(|a| a.is_changed()) else {
+        return;
+    };
+    for mut text in &mut labels {
+        text.sections[0].value = announcement_text(Some(&announcement));
+    }
+}
+
+pub fn make_main_menu(
+    asset_server: Res<AssetServer>,
+    announcement: Option<Res<LatestAnnouncement>>,
+    mut commands: Commands,
+) {
     let font = asset_server.load("fonts/Roboto-Light.ttf");
 
     let text_style = TextStyle {
@@ -106,8 +158,16 @@ pub fn make_main_menu(asset_server: Res<AssetServer>, mut commands: Commands) {
 
     let button_group = stack(FlexDirection::Row).with(quit_button);
 
+    let announcement = label(announcement_text(announcement.as_deref()))
+        .insert(AnnouncementLabel)
+        .styled(|s| {
+            s.align_self = AlignSelf::Center;
+            s.margin = UiRect::horizontal(Val::Px(16.0));
+        });
+
     let top_bar = stack(FlexDirection::Row)
         .with(tab_bar)
+        .with(announcement)
         .with(button_group)
         .styled(|s| {
             s.padding = UiRect::axes(Val::Px(8.0), Val::Px(8.0));
@@ -0,0 +1,248 @@
+use bevy::{prelude::*, utils::HashMap};
+use common::{
+    champion::ChampionCatalog,
+    network::lobby::{DraftAction, PlayerId},
+    Side,
+};
+
+use crate::{
+    nongame::{
+        network::{ChampionPicksCleared, ChampionSelected, DraftUpdate, Request},
+        Champions, LocalPlayer,
+    },
+    ui::{button, label, stack, Widget, WidgetExt},
+};
+
+use super::LobbyState;
+
+pub struct ChampionSelectPlugin;
+
+impl Plugin for ChampionSelectPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChampionPicks>()
+            .init_resource::<DraftStatus>()
+            .add_systems(
+                OnEnter(LobbyState::InLobby),
+                |mut picks: ResMut<ChampionPicks>, mut draft: ResMut<DraftStatus>| {
+                    picks.clear();
+                    *draft = default();
+                },
+            )
+            .add_systems(
+                Update,
+                (
+                    (champion_selected, champion_picks_cleared, draft_update)
+                        .run_if(in_state(LobbyState::InLobby)),
+                    update_champion_labels,
+                    update_draft_labels,
+                ),
+            );
+    }
+}
+
+/// A player's champion, as the server last told us.
+pub struct Pick {
+    pub champion: String,
+    pub locked_in: bool,
+}
+
+/// Everyone's champion in the current lobby.
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct ChampionPicks(pub HashMap<PlayerId, Pick>);
+
+/// Shows the champion a player has picked.
+#[derive(Component)]
+pub struct SlotChampion(pub PlayerId);
+
+/// Where the lobby's draft is at, if it has one.
+#[derive(Resource, Default)]
+struct DraftStatus {
+    turn: String,
+    bans: Vec<(Side, String)>,
+}
+
+#[derive(Component)]
+enum DraftLabel {
+    Turn,
+    Bans,
+}
+
+/// A button for every champion that can be picked, and one to lock in the one picked.
+pub fn champion_picker(champions: &ChampionCatalog) -> impl Widget {
+    let mut list = stack(FlexDirection::Row);
+    for champion in champions.enabled() {
+        let id = champion.id.clone();
+        list.add(button(
+            champion.name.clone(),
+            move |mut e: EventWriter<Request>| {
+                e.send(Request::SelectChampion {
+                    champion: id.clone(),
+                });
+            },
+        ));
+    }
+
+    stack(FlexDirection::Column)
+        .with(list.styled(|s| {
+            s.column_gap = Val::Px(8.0);
+            s.flex_wrap = FlexWrap::Wrap;
+        }))
+        .with(button(
+            "Lock In",
+            |picks: Res<ChampionPicks>, player: Res<LocalPlayer>, mut e: EventWriter<Request>| {
+                if let Some(pick) = picks.get(&player.0) {
+                    e.send(Request::LockInChampion {
+                        champion: pick.champion.clone(),
+                    });
+                }
+            },
+        ))
+        .styled(|s| {
+            s.align_items = AlignItems::Center;
+            s.row_gap = Val::Px(5.0);
+        })
+}
+
+/// The draft's current turn and bans, and buttons to start it and to ban the champion
+/// picked in [`champion_picker`].
+pub fn draft_panel() -> impl Widget {
+    stack(FlexDirection::Row)
+        .with(label("").insert(DraftLabel::Turn))
+        .with(label("").insert(DraftLabel::Bans))
+        // Only does anything for the owner of a draft lobby.
+        .with(button("Start Draft", |mut e: EventWriter<Request>| {
+            e.send(Request::StartDraft);
+        }))
+        .with(button(
+            "Ban",
+            |picks: Res<ChampionPicks>, player: Res<LocalPlayer>, mut e: EventWriter<Request>| {
+                if let Some(pick) = picks.get(&player.0) {
+                    e.send(Request::BanChampion {
+                        champion: pick.champion.clone(),
+                    });
+                }
+            },
+        ))
+        .styled(|s| {
+            s.column_gap = Val::Px(8.0);
+        })
+}
+
+fn champion_selected(mut e: EventReader<ChampionSelected>, mut picks: ResMut<ChampionPicks>) {
+    for ev in e.read() {
+        picks.insert(
+            ev.player.id,
+            Pick {
+                champion: ev.champion.clone(),
+                locked_in: ev.locked_in,
+            },
+        );
+    }
+}
+
+fn champion_picks_cleared(
+    mut e: EventReader<ChampionPicksCleared>,
+    mut picks: ResMut<ChampionPicks>,
+) {
+    if e.read().count() > 0 {
+        picks.clear();
+    }
+}
+
+fn draft_update(
+    mut e: EventReader<DraftUpdate>,
+    player: Res<LocalPlayer>,
+    mut draft: ResMut<DraftStatus>,
+    mut picks: ResMut<ChampionPicks>,
+) {
+    for ev in e.read() {
+        match ev {
+            DraftUpdate::Turn {
+                side,
+                action,
+                player: picking,
+                time_left,
+            } => {
+                let action = match action {
+                    DraftAction::Ban => "ban",
+                    DraftAction::Pick => "pick",
+                };
+                let secs = time_left.as_secs();
+                draft.turn = if *picking == Some(player.0) {
+                    format!("Your turn to {action} ({secs}s)")
+                } else {
+                    format!("{side} to {action} ({secs}s)")
+                };
+            }
+            DraftUpdate::Banned { side, champion } => {
+                draft
+                    .bans
+                    .extend(champion.clone().map(|champion| (*side, champion)));
+            }
+            DraftUpdate::Finished => draft.turn = "The draft is over".into(),
+            DraftUpdate::Cancelled { reason } => {
+                *draft = DraftStatus {
+                    turn: format!("The draft was cancelled: {reason}"),
+                    bans: Vec::new(),
+                };
+                picks.clear();
+            }
+        }
+    }
+}
+
+/// The name of the champion with `id`, or the id if the catalog doesn't know it.
+fn champion_name<'a>(champions: Option<&'a Champions>, id: &'a str) -> &'a str {
+    champions
+        .and_then(|champions| champions.get(id))
+        .map_or(id, |champion| champion.name.as_str())
+}
+
+fn update_champion_labels(
+    picks: Res<ChampionPicks>,
+    champions: Option<Res<Champions>>,
+    mut labels: Query<(Ref<SlotChampion>, &mut Text)>,
+) {
+    for (label, mut text) in &mut labels {
+        if !picks.is_changed() && !label.is_added() {
+            continue;
+        }
+        text.sections[0].value = match picks.get(&label.0) {
+            Some(pick) => {
+                let name = champion_name(champions.as_deref(), &pick.champion);
+                if pick.locked_in {
+                    format!("{name} (locked in)")
+                } else {
+                    name.to_string()
+                }
+            }
+            None => String::new(),
+        };
+    }
+}
+
+fn update_draft_labels(
+    draft: Res<DraftStatus>,
+    champions: Option<Res<Champions>>,
+    mut labels: Query<(Ref<DraftLabel>, &mut Text)>,
+) {
+    for (label, mut text) in &mut labels {
+        if !draft.is_changed() && !label.is_added() {
+            continue;
+        }
+        text.sections[0].value = match *label {
+            DraftLabel::Turn => draft.turn.clone(),
+            DraftLabel::Bans if draft.bans.is_empty() => String::new(),
+            DraftLabel::Bans => {
+                let names = draft
+                    .bans
+                    .iter()
+                    .map(|(side, id)| {
+                        format!("{} ({side})", champion_name(champions.as_deref(), id))
+                    })
+                    .collect::<Vec<_>>();
+                format!("Bans: {}", names.join(", "))
+            }
+        };
+    }
+}
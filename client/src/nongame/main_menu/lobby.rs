@@ -2,17 +2,29 @@ use std::time::Duration;
 
 use bevy::{prelude::*, utils::hashbrown::HashMap};
 use common::{
-    network::lobby::{LobbyId, LobbyInfo, Player as NetworkPlayer, PlayerId},
+    champion::Role,
+    network::lobby::{BotDifficulty, LobbyId, LobbyInfo, Player as NetworkPlayer, PlayerId},
+    rules::GameRules,
     Side,
 };
 use uuid::Uuid;
 
 use crate::{
-    nongame::network::{LeftLobby, PlayerJoinedLobby, PlayerLeftLobby, Request, UpdateLobbyInfo},
-    ui::{button, stack, Animation, BuildContext, Widget, WidgetExt},
+    nongame::{
+        network::{
+            GameRulesChanged, LeftLobby, PlayerJoinedLobby, PlayerLeftLobby, PlayerSwitchedSide,
+            Request, RolesAssigned, UpdateLobbyInfo,
+        },
+        Champions,
+    },
+    ui::{button, label, stack, Animation, BuildContext, Widget, WidgetExt},
 };
 
-use super::{LobbyState, MenuHolder};
+use super::{
+    champion_select::{champion_picker, draft_panel, SlotChampion},
+    roles::role_picker,
+    LobbyState, MenuHolder,
+};
 
 pub struct LobbyPlugin;
 
@@ -38,7 +50,15 @@ impl Plugin for LobbyPlugin {
         })
         .add_systems(
             Update,
-            (new_lobby_info, player_joined, player_left, you_left)
+            (
+                new_lobby_info,
+                player_joined,
+                player_left,
+                player_switched_side,
+                roles_assigned,
+                game_rules_changed,
+                you_left,
+            )
                 .run_if(in_state(LobbyState::InLobby)),
         );
     }
@@ -50,6 +70,8 @@ fn enter_main_menu(mut commands: Commands) {
             id: LobbyId(Uuid::nil()),
             players: HashMap::new(),
             lobby_owner: PlayerId(Uuid::nil()),
+            roles: HashMap::new(),
+            rules: GameRules::default(),
         },
     });
 }
@@ -72,8 +94,71 @@ pub struct Player {
 #[derive(Component)]
 struct PlayerList(Side);
 
+/// Shows the lobby's [`GameRules`].
+#[derive(Component)]
+struct RulesLabel;
+
+fn rules_text(rules: &GameRules) -> String {
+    let mut text = match rules.preset_name() {
+        Some(name) => format!("Rules: {name}"),
+        None => "Rules: Custom".into(),
+    };
+    text += &format!(
+        " ({} gold, level {}, {}% respawn time, {}% cooldown cap",
+        rules.starting_gold,
+        rules.starting_level,
+        rules.respawn_time_percent,
+        rules.cooldown_reduction_cap
+    );
+    if rules.all_random {
+        text += ", all random";
+    }
+    if !rules.minions {
+        text += ", no minions";
+    }
+    if !rules.disabled_items.is_empty() {
+        text += &format!(", {} items disabled", rules.disabled_items.len());
+    }
+    text + ")"
+}
+
+/// Holds a column for each side, rebuilt whenever the lobby info arrives.
+#[derive(Component)]
+struct TeamColumns;
+
+fn mk_team(team: Side, slots: Vec<Entity>) -> impl Widget {
+    let mut list = stack(FlexDirection::Column);
+    for slot in slots {
+        list.add(slot);
+    }
+
+    stack(FlexDirection::Column)
+        .with(team.to_string())
+        .with(list.insert(PlayerList(team)))
+        .with(button("Add Bot", move |mut e: EventWriter<Request>| {
+            e.send(Request::AddBot {
+                side: team,
+                difficulty: BotDifficulty::Medium,
+            });
+        }))
+        .styled(|s| {
+            s.flex_grow = 1.0;
+        })
+}
+
+/// Which way a player slot on `side` slides in and out: sides in the left half of the
+/// screen use the left edge.
+fn fade_side(side: Side, teams: usize) -> f32 {
+    if (side.0 as usize) * 2 < teams {
+        -1.0
+    } else {
+        1.0
+    }
+}
+
 fn make_lobby_menu(
     asset_server: Res<AssetServer>,
+    champions: Res<Champions>,
     q: Query<Entity, With<MenuHolder>>,
     mut commands: Commands,
 ) {
@@ -84,21 +169,31 @@ fn make_lobby_menu(
 
     let lobby_title = "Lobby".to_string();
 
-    let mut teams = stack(FlexDirection::Row);
-
-    fn mk_team(team: Side) -> impl Widget {
-        stack(FlexDirection::Column)
-            .styled(|s| {
-                s.flex_grow = 1.0;
-            })
-            .insert(PlayerList(team))
-    }
-
-    for side in Side::ALL {
-        teams.add(mk_team(side));
-    }
+    let teams = stack(FlexDirection::Row).insert(TeamColumns);
 
     root.add(lobby_title);
+    root.add(label(rules_text(&GameRules::default())).insert(RulesLabel));
+    // Only does anything for the owner; the server turns everyone else down.
+    root.add(button(
+        "Change Rules",
+        |state: Res<State>, mut e: EventWriter<Request>| {
+            let presets = GameRules::presets();
+            let next = presets
+                .iter()
+                .position(|(_, rules)| *rules == state.info.rules)
+                .map_or(0, |i| (i + 1) % presets.len());
+            e.send(Request::SetGameRules {
+                rules: presets[next].1.clone(),
+            });
+        },
+    ));
+    root.add(role_picker());
+    root.add(champion_picker(&champions));
+    root.add(draft_panel());
+    // Likewise only for the owner, once everyone has locked in.
+    root.add(button("Start Game", |mut e: EventWriter<Request>| {
+        e.send(Request::StartGame);
+    }));
     root.add(teams.styled(|s| {
         s.width = Val::Percent(95.0);
     }));
@@ -151,18 +246,41 @@ fn make_lobby_menu(
 #[derive(Component)]
 struct PlayerSlot(PlayerId);
 
+/// Shows the role assigned to a player.
+#[derive(Component)]
+struct SlotRole(PlayerId);
+
+fn role_text(role: Option<&Role>) -> String {
+    role.map_or(String::new(), Role::to_string)
+}
+
 fn make_player_slot(
     player: &NetworkPlayer,
+    role: Option<&Role>,
     fade_side: f32,
     asset_server: &AssetServer,
     commands: &mut Commands,
 ) -> Entity {
-    stack(FlexDirection::Row)
-        .with(player.username.as_str())
-        .with(button("Kick", |mut e: EventWriter<Request>| {
-            e.send(Request::LeaveLobby);
-        }))
-        .styled(move |s| {
+    let mut slot = stack(FlexDirection::Row);
+    slot.add(label(role_text(role)).insert(SlotRole(player.id)));
+    slot.add(label("").insert(SlotChampion(player.id)));
+    match player.bot {
+        Some(difficulty) => {
+            let id = player.id;
+            slot.add(format!("{} ({difficulty})", player.username));
+            slot.add(button("Remove", move |mut e: EventWriter<Request>| {
+                e.send(Request::RemoveBot { id });
+            }));
+        }
+        None => {
+            slot.add(player.username.as_str());
+            slot.add(button("Kick", |mut e: EventWriter<Request>| {
+                e.send(Request::LeaveLobby);
+            }));
+        }
+    }
+
+    slot.styled(move |s| {
             s.justify_content = JustifyContent::SpaceBetween;
             s.width = Val::Percent(100.0);
             s.padding = UiRect::axes(Val::Px(5.0), Val::Px(5.0));
@@ -215,33 +333,45 @@ fn make_player_slot(
 fn new_lobby_info(
     mut e: EventReader<UpdateLobbyInfo>,
     mut state: ResMut<State>,
-    q: Query<(Entity, &PlayerList)>,
+    q: Query<Entity, With<TeamColumns>>,
+    mut rules_label: Query<&mut Text, With<RulesLabel>>,
     asset_server: Res<AssetServer>,
     mut commands: Commands,
 ) {
     for e in e.read() {
         state.info = e.lobby_info.clone();
+        for mut text in &mut rules_label {
+            text.sections[0].value = rules_text(&state.info.rules);
+        }
 
-        for (e, PlayerList(side)) in &q {
-            commands.entity(e).despawn_descendants();
-
-            let mut slots = vec![];
-
-            for player in state.info.players.get(side).unwrap_or(&vec![]) {
-                let fade_side = match side {
-                    Side::Red => -1.0,
-                    Side::Blue => 1.0,
-                };
-
-                slots.push(make_player_slot(
-                    player,
-                    fade_side,
-                    &asset_server,
-                    &mut commands,
-                ));
-            }
-
-            commands.entity(e).push_children(&slots);
+        let Ok(columns) = q.get_single() else {
+            continue;
+        };
+        commands.entity(columns).despawn_descendants();
+
+        let teams = state.info.players.len();
+        let mut sides = state.info.players.keys().copied().collect::<Vec<_>>();
+        sides.sort();
+
+        for side in sides {
+            let slots = state.info.players[&side]
+                .iter()
+                .map(|player| {
+                    make_player_slot(
+                        player,
+                        state.info.roles.get(&player.id),
+                        fade_side(side, teams),
+                        &asset_server,
+                        &mut commands,
+                    )
+                })
+                .collect();
+
+            let column = mk_team(side, slots).build(&mut BuildContext {
+                asset_server: &asset_server,
+                commands: &mut commands,
+            });
+            commands.entity(columns).add_child(column);
         }
     }
 }
@@ -261,17 +391,16 @@ fn player_joined(
             .or_default()
             .push(ev.player.clone());
 
-        let e = q
+        let Some(e) = q
             .iter()
             .find_map(|(e, PlayerList(s))| (*s == ev.side).then_some(e))
-            .unwrap();
-
-        let fade_side = match ev.side {
-            Side::Red => -1.0,
-            Side::Blue => 1.0,
+        else {
+            continue;
         };
 
-        let slot = make_player_slot(&ev.player, fade_side, &asset_server, &mut commands);
+        let fade_side = fade_side(ev.side, state.info.players.len());
+        let role = state.info.roles.get(&ev.player.id);
+        let slot = make_player_slot(&ev.player, role, fade_side, &asset_server, &mut commands);
         commands.entity(e).add_child(slot);
     }
 }
@@ -283,7 +412,7 @@ fn player_left(
     mut commands: Commands,
 ) {
     for ev in e.read() {
-        let mut side = Side::Red;
+        let mut side = Side::RED;
         for (s, players) in state.info.players.iter_mut() {
             let Some(pos) = players.iter().position(|p| p.id == ev.player.id) else {
                 continue;
@@ -297,10 +426,7 @@ fn player_left(
             .find_map(|(e, &PlayerSlot(id))| (id == ev.player.id).then_some(e))
             .unwrap();
 
-        let fade_side = match side {
-            Side::Red => -1.0,
-            Side::Blue => 1.0,
-        };
+        let fade_side = fade_side(side, state.info.players.len());
 
         commands.entity(e).add(move |mut e: EntityWorldMut<'_>| {
             e.insert(Animation::new(
@@ -318,6 +444,74 @@ fn player_left(
     }
 }
 
+fn player_switched_side(
+    mut e: EventReader<PlayerSwitchedSide>,
+    mut state: ResMut<State>,
+    lists: Query<(Entity, &PlayerList)>,
+    slots: Query<(Entity, &PlayerSlot)>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    for ev in e.read() {
+        for players in state.info.players.values_mut() {
+            players.retain(|p| p.id != ev.player.id);
+        }
+        state
+            .info
+            .players
+            .entry(ev.side)
+            .or_default()
+            .push(ev.player.clone());
+
+        if let Some(slot) = slots
+            .iter()
+            .find_map(|(e, &PlayerSlot(id))| (id == ev.player.id).then_some(e))
+        {
+            commands.entity(slot).despawn_recursive();
+        }
+
+        let Some(list) = lists
+            .iter()
+            .find_map(|(e, PlayerList(s))| (*s == ev.side).then_some(e))
+        else {
+            continue;
+        };
+
+        let fade_side = fade_side(ev.side, state.info.players.len());
+        let role = state.info.roles.get(&ev.player.id);
+        let slot = make_player_slot(&ev.player, role, fade_side, &asset_server, &mut commands);
+        commands.entity(list).add_child(slot);
+    }
+}
+
+fn roles_assigned(
+    mut e: EventReader<RolesAssigned>,
+    mut state: ResMut<State>,
+    mut labels: Query<(&SlotRole, &mut Text)>,
+) {
+    for ev in e.read() {
+        state.info.roles = ev.roles.clone();
+
+        for (SlotRole(id), mut text) in &mut labels {
+            text.sections[0].value = role_text(state.info.roles.get(id));
+        }
+    }
+}
+
+fn game_rules_changed(
+    mut e: EventReader<GameRulesChanged>,
+    mut state: ResMut<State>,
+    mut labels: Query<&mut Text, With<RulesLabel>>,
+) {
+    for ev in e.read() {
+        state.info.rules = ev.rules.clone();
+
+        for mut text in &mut labels {
+            text.sections[0].value = rules_text(&state.info.rules);
+        }
+    }
+}
+
 fn you_left(mut e: EventReader<LeftLobby>, mut next_state: ResMut<NextState<LobbyState>>) {
     for _ in e.read() {
         next_state.set(LobbyState::NotInLobby);
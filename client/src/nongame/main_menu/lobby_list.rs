@@ -4,107 +4,249 @@ use bevy_mod_picking::{
     prelude::On,
 };
 
-use crate::nongame::network::{JoinedLobby, Request, UpdateLobbyList};
+use common::network::lobby::{LobbyFilter, LobbySort, PickMode, ShortLobbyInfo};
 
-use super::{lobby::CurrentLobby, LobbyState, MenuHolder};
+use crate::{
+    nongame::network::{JoinedLobby, Request, UpdateLobbyList},
+    ui::{BuildContext, Widget},
+};
+
+use super::{
+    lobby::CurrentLobby,
+    roles::{role_picker, RoleChoice},
+    LobbyState, MenuHolder,
+};
+
+/// How drafts go in lobbies created with the "Create Draft Lobby" button.
+const DRAFT: PickMode = PickMode::Draft {
+    bans_per_side: 2,
+    turn_secs: 30,
+};
 
 pub struct LobbyListPlugin;
 
 impl Plugin for LobbyListPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            OnEnter(LobbyState::NotInLobby),
-            (make_lobby_list_menu, |mut e: EventWriter<Request>| {
-                e.send(Request::GetLobbyList);
-            }),
-        )
-        .add_systems(
-            Update,
-            (update_lobby_list, lobby_joined).run_if(in_state(LobbyState::NotInLobby)),
-        );
+        app.init_resource::<LobbyListView>()
+            .add_systems(
+                OnEnter(LobbyState::NotInLobby),
+                (make_lobby_list_menu, subscribe),
+            )
+            .add_systems(
+                OnExit(LobbyState::NotInLobby),
+                |mut e: EventWriter<Request>| {
+                    e.send(Request::UnsubscribeLobbyList);
+                },
+            )
+            .add_systems(
+                Update,
+                (
+                    apply_lobby_list_updates,
+                    (render_lobby_list, update_page_label)
+                        .run_if(resource_changed::<LobbyListView>),
+                    lobby_joined,
+                )
+                    .chain()
+                    .run_if(in_state(LobbyState::NotInLobby)),
+            );
     }
 }
 
 #[derive(Component)]
 pub struct LobbyList;
 
-fn make_lobby_list_menu(
-    asset_server: Res<AssetServer>,
-    q: Query<Entity, With<MenuHolder>>,
-    mut commands: Commands,
-) {
-    let menu_holder = q.single();
-    commands.entity(menu_holder).despawn_descendants();
-
-    let font = asset_server.load("fonts/Roboto-Light.ttf");
+#[derive(Component)]
+struct PageLabel;
 
-    let text_style = TextStyle {
-        font,
-        font_size: 16.0,
-        color: Color::GOLD,
-    };
+/// The page of the lobby list the server keeps us up to date on.
+#[derive(Resource, Default)]
+struct LobbyListView {
+    filter: LobbyFilter,
+    sort: LobbySort,
+    page: usize,
+    page_count: usize,
+    lobbies: Vec<ShortLobbyInfo>,
+}
 
-    let button_img = asset_server.load("ui/button.png");
+fn subscribe(view: Res<LobbyListView>, mut e: EventWriter<Request>) {
+    e.send(Request::SubscribeLobbyList {
+        filter: view.filter.clone(),
+        sort: view.sort,
+    });
+}
 
-    let create_lobby_button = commands
+fn spawn_button(
+    commands: &mut Commands,
+    image: Handle<Image>,
+    text: impl Into<String>,
+    text_style: TextStyle,
+    on_click: On<Pointer<Click>>,
+) -> Entity {
+    commands
         .spawn((
             ButtonBundle {
                 style: Style {
                     padding: UiRect::axes(Val::Px(8.0), Val::Px(4.0)),
                     ..default()
                 },
-                image: button_img.clone().into(),
+                image: image.into(),
                 ..default()
             },
             ImageScaleMode::Sliced(TextureSlicer {
                 border: BorderRect::square(16.0),
                 ..default()
             }),
-            On::<Pointer<Click>>::run(|mut e: EventWriter<Request>| {
-                println!("CREATE");
-                e.send(Request::CreateLobby);
-            }),
+            on_click,
         ))
         .with_children(|parent| {
             parent.spawn(TextBundle {
-                text: Text::from_section("Create Lobby", text_style.clone()),
+                text: Text::from_section(text, text_style),
                 ..default()
             });
         })
-        .id();
+        .id()
+}
+
+fn make_lobby_list_menu(
+    asset_server: Res<AssetServer>,
+    q: Query<Entity, With<MenuHolder>>,
+    mut commands: Commands,
+) {
+    let menu_holder = q.single();
+    commands.entity(menu_holder).despawn_descendants();
+
+    let font = asset_server.load("fonts/Roboto-Light.ttf");
+
+    let text_style = TextStyle {
+        font,
+        font_size: 16.0,
+        color: Color::GOLD,
+    };
+
+    let button_img = asset_server.load("ui/button.png");
+
+    let create_lobby_button = spawn_button(
+        &mut commands,
+        button_img.clone(),
+        "Create Lobby",
+        text_style.clone(),
+        On::<Pointer<Click>>::run(|mut e: EventWriter<Request>| {
+            e.send(Request::CreateLobby {
+                pick_mode: PickMode::FreePick,
+            });
+        }),
+    );
+
+    let create_draft_lobby_button = spawn_button(
+        &mut commands,
+        button_img.clone(),
+        "Create Draft Lobby",
+        text_style.clone(),
+        On::<Pointer<Click>>::run(|mut e: EventWriter<Request>| {
+            e.send(Request::CreateLobby { pick_mode: DRAFT });
+        }),
+    );
 
-    let refresh_lobbies_button = commands
+    let find_match_button = spawn_button(
+        &mut commands,
+        button_img.clone(),
+        "Find Match",
+        text_style.clone(),
+        On::<Pointer<Click>>::run(|choice: Res<RoleChoice>, mut e: EventWriter<Request>| {
+            e.send(Request::StartMatchmaking { roles: choice.0 });
+        }),
+    );
+
+    let cancel_match_button = spawn_button(
+        &mut commands,
+        button_img.clone(),
+        "Stop Searching",
+        text_style.clone(),
+        On::<Pointer<Click>>::run(|mut e: EventWriter<Request>| {
+            e.send(Request::StopMatchmaking);
+        }),
+    );
+
+    let role_picker = role_picker().build(&mut BuildContext {
+        asset_server: &asset_server,
+        commands: &mut commands,
+    });
+
+    let hide_full_button = spawn_button(
+        &mut commands,
+        button_img.clone(),
+        "Hide Full",
+        text_style.clone(),
+        On::<Pointer<Click>>::run(
+            |mut view: ResMut<LobbyListView>, mut e: EventWriter<Request>| {
+                view.filter.not_full = !view.filter.not_full;
+                e.send(Request::SubscribeLobbyList {
+                    filter: view.filter.clone(),
+                    sort: view.sort,
+                });
+            },
+        ),
+    );
+
+    let previous_page_button = spawn_button(
+        &mut commands,
+        button_img.clone(),
+        "<",
+        text_style.clone(),
+        On::<Pointer<Click>>::run(|view: Res<LobbyListView>, mut e: EventWriter<Request>| {
+            if view.page > 0 {
+                e.send(Request::ViewLobbyListPage {
+                    page: view.page - 1,
+                });
+            }
+        }),
+    );
+
+    let page_label = commands
         .spawn((
-            ButtonBundle {
+            TextBundle {
+                text: Text::from_section("", text_style.clone()),
                 style: Style {
-                    padding: UiRect::axes(Val::Px(8.0), Val::Px(4.0)),
+                    margin: UiRect::horizontal(Val::Px(8.0)),
+                    align_self: AlignSelf::Center,
                     ..default()
                 },
-                image: button_img.into(),
                 ..default()
             },
-            ImageScaleMode::Sliced(TextureSlicer {
-                border: BorderRect::square(16.0),
-                ..default()
-            }),
-            On::<Pointer<Click>>::run(|mut e: EventWriter<Request>| {
-                e.send(Request::GetLobbyList);
-            }),
+            PageLabel,
         ))
-        .with_children(|parent| {
-            parent.spawn(TextBundle {
-                text: Text::from_section("Refresh Lobbies", text_style.clone()),
-                ..default()
-            });
-        })
         .id();
 
+    let next_page_button = spawn_button(
+        &mut commands,
+        button_img,
+        ">",
+        text_style.clone(),
+        On::<Pointer<Click>>::run(|view: Res<LobbyListView>, mut e: EventWriter<Request>| {
+            if view.page + 1 < view.page_count {
+                e.send(Request::ViewLobbyListPage {
+                    page: view.page + 1,
+                });
+            }
+        }),
+    );
+
     let buttonbar = commands
         .spawn(NodeBundle {
             style: Style { ..default() },
             ..default()
         })
-        .push_children(&[create_lobby_button, refresh_lobbies_button])
+        .push_children(&[
+            create_lobby_button,
+            create_draft_lobby_button,
+            find_match_button,
+            cancel_match_button,
+            role_picker,
+            hide_full_button,
+            previous_page_button,
+            page_label,
+            next_page_button,
+        ])
         .id();
 
     let lobby_name_header = commands
@@ -170,8 +312,47 @@ fn make_lobby_list_menu(
     commands.entity(menu_holder).add_child(root);
 }
 
-fn update_lobby_list(
+fn apply_lobby_list_updates(
     mut events: EventReader<UpdateLobbyList>,
+    mut view: ResMut<LobbyListView>,
+) {
+    for event in events.read() {
+        match event {
+            UpdateLobbyList::Page {
+                page,
+                page_count,
+                lobbies,
+            } => {
+                view.page = *page;
+                view.page_count = *page_count;
+                view.lobbies = lobbies.clone();
+            }
+            UpdateLobbyList::Added(lobby) => view.lobbies.push(lobby.clone()),
+            UpdateLobbyList::Updated(lobby) => {
+                if let Some(shown) = view.lobbies.iter_mut().find(|l| l.id == lobby.id) {
+                    *shown = lobby.clone();
+                }
+            }
+            UpdateLobbyList::Removed(id) => view.lobbies.retain(|l| l.id != *id),
+            UpdateLobbyList::PageCount(page_count) => view.page_count = *page_count,
+        }
+    }
+
+    // The server only tells us what changed, not where it goes, so keep its order.
+    if view.is_changed() {
+        let sort = view.sort;
+        view.lobbies.sort_by(|a, b| sort.compare(a, b));
+    }
+}
+
+fn update_page_label(view: Res<LobbyListView>, mut query: Query<&mut Text, With<PageLabel>>) {
+    for mut text in &mut query {
+        text.sections[0].value = format!("Page {}/{}", view.page + 1, view.page_count.max(1));
+    }
+}
+
+fn render_lobby_list(
+    view: Res<LobbyListView>,
     query: Query<Entity, With<LobbyList>>,
     asset_server: Res<AssetServer>,
     mut commands: Commands,
@@ -186,64 +367,67 @@ fn update_lobby_list(
 
     let button_img = asset_server.load("ui/button.png");
 
-    for event in events.read() {
-        let Ok(e) = query.get_single() else { continue };
+    let Ok(e) = query.get_single() else {
+        return;
+    };
+
+    commands.entity(e).despawn_descendants();
+
+    for lobby in &view.lobbies {
+        let name = commands
+            .spawn(TextBundle {
+                text: Text::from_section(lobby.name.clone(), text_style.clone()),
+                style: Style {
+                    flex_grow: 1.0,
+                    ..default()
+                },
+                ..default()
+            })
+            .id();
 
-        commands.entity(e).despawn_descendants();
+        let players = commands
+            .spawn(TextBundle {
+                text: Text::from_section(
+                    format!("{}/{}", lobby.players, lobby.max_players),
+                    text_style.clone(),
+                ),
+                ..default()
+            })
+            .id();
 
-        for lobby in &event.lobbies {
-            let name = commands
-                .spawn(TextBundle {
-                    text: Text::from_section(format!("Lobby {}", lobby.id), text_style.clone()),
+        let lobby_id = lobby.id;
+        let join = commands
+            .spawn((
+                ButtonBundle {
                     style: Style {
-                        flex_grow: 1.0,
+                        padding: UiRect::axes(Val::Px(8.0), Val::Px(4.0)),
                         ..default()
                     },
+                    image: button_img.clone().into(),
                     ..default()
-                })
-                .id();
-
-            let players = commands
-                .spawn(TextBundle {
-                    text: Text::from_section(format!("{}", lobby.players), text_style.clone()),
+                },
+                ImageScaleMode::Sliced(TextureSlicer {
+                    border: BorderRect::square(16.0),
                     ..default()
-                })
-                .id();
-
-            let lobby_id = lobby.id;
-            let join = commands
-                .spawn((
-                    ButtonBundle {
-                        style: Style {
-                            padding: UiRect::axes(Val::Px(8.0), Val::Px(4.0)),
-                            ..default()
-                        },
-                        image: button_img.clone().into(),
-                        ..default()
-                    },
-                    ImageScaleMode::Sliced(TextureSlicer {
-                        border: BorderRect::square(16.0),
-                        ..default()
-                    }),
-                    On::<Pointer<Click>>::run(move |mut e: EventWriter<Request>| {
-                        e.send(Request::JoinLobby { id: lobby_id });
-                    }),
-                ))
-                .with_children(|parent| {
-                    parent.spawn(TextBundle {
-                        text: Text::from_section("Join", text_style.clone()),
-                        ..default()
-                    });
-                })
-                .id();
+                }),
+                On::<Pointer<Click>>::run(move |mut e: EventWriter<Request>| {
+                    e.send(Request::JoinLobby { id: lobby_id });
+                }),
+            ))
+            .with_children(|parent| {
+                parent.spawn(TextBundle {
+                    text: Text::from_section("Join", text_style.clone()),
+                    ..default()
+                });
+            })
+            .id();
 
-            let entry = commands
-                .spawn(NodeBundle { ..default() })
-                .push_children(&[name, players, join])
-                .id();
+        let entry = commands
+            .spawn(NodeBundle { ..default() })
+            .push_children(&[name, players, join])
+            .id();
 
-            commands.entity(e).push_children(&[entry]);
-        }
+        commands.entity(e).push_children(&[entry]);
     }
 }
 
@@ -0,0 +1,105 @@
+use bevy::prelude::*;
+use common::{champion::Role, network::lobby::RolePreference};
+
+use crate::{
+    nongame::network::Request,
+    ui::{button, label, stack, Widget, WidgetExt},
+};
+
+use super::LobbyState;
+
+pub struct RolesPlugin;
+
+impl Plugin for RolesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RoleChoice>()
+            .add_systems(OnEnter(LobbyState::InLobby), send_role_choice)
+            .add_systems(
+                Update,
+                (
+                    update_role_labels,
+                    send_role_choice
+                        .run_if(resource_changed::<RoleChoice>)
+                        .run_if(in_state(LobbyState::InLobby)),
+                ),
+            );
+    }
+}
+
+/// The roles the player asks for when queueing, and in lobbies.
+#[derive(Resource, Deref, DerefMut)]
+pub struct RoleChoice(pub RolePreference);
+
+impl Default for RoleChoice {
+    fn default() -> Self {
+        Self(RolePreference {
+            primary: Role::Mid,
+            secondary: None,
+        })
+    }
+}
+
+#[derive(Component)]
+enum RoleLabel {
+    Primary,
+    Secondary,
+}
+
+/// Buttons that cycle through the primary and secondary role in [`RoleChoice`].
+pub fn role_picker() -> impl Widget {
+    stack(FlexDirection::Row)
+        .with(button(
+            label("").insert(RoleLabel::Primary),
+            |mut choice: ResMut<RoleChoice>| {
+                let primary = next_role(Some(choice.primary)).unwrap_or(Role::ALL[0]);
+                choice.primary = primary;
+                if choice.secondary == Some(primary) {
+                    choice.secondary = next_role(Some(primary));
+                }
+            },
+        ))
+        .with(button(
+            label("").insert(RoleLabel::Secondary),
+            |mut choice: ResMut<RoleChoice>| {
+                let mut secondary = next_role(choice.secondary);
+                if secondary == Some(choice.primary) {
+                    secondary = next_role(secondary);
+                }
+                choice.secondary = secondary;
+            },
+        ))
+        .styled(|s| {
+            s.column_gap = Val::Px(8.0);
+        })
+}
+
+/// The role after `role` in [`Role::ALL`], with `None` standing for "any" between the
+/// last role and the first.
+fn next_role(role: Option<Role>) -> Option<Role> {
+    match role {
+        None => Some(Role::ALL[0]),
+        Some(role) => {
+            let index = Role::ALL.iter().position(|r| *r == role).unwrap();
+            Role::ALL.get(index + 1).copied()
+        }
+    }
+}
+
+fn update_role_labels(choice: Res<RoleChoice>, mut labels: Query<(Ref<RoleLabel>, &mut Text)>) {
+    for (label, mut text) in &mut labels {
+        if !choice.is_changed() && !label.is_added() {
+            continue;
+        }
+        text.sections[0].value = match *label {
+            RoleLabel::Primary => format!("Primary: {}", choice.primary),
+            RoleLabel::Secondary => match choice.secondary {
+                Some(role) => format!("Secondary: {role}"),
+                None => "Secondary: Any".into(),
+            },
+        };
+    }
+}
+
+fn send_role_choice(choice: Res<RoleChoice>, mut e: EventWriter<Request>) {
+    e.send(Request::SetRolePreference { roles: choice.0 });
+}
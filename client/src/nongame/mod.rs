@@ -6,16 +6,18 @@ mod network;
 use std::sync::mpsc::{Receiver, Sender, TryRecvError};
 
 use bevy::{app::AppExit, prelude::*};
+use common::{champion::ChampionCatalog, network::lobby::PlayerId};
 
-use crate::DEBUG;
+use crate::{game::GameReady, DEBUG};
 
 use self::{
     connect_to_server::{ConnectToServer, InConnectToServerPlugin},
     connecting_to_server::InConnectingToServerPlugin,
     main_menu::MainMenuPlugin,
     network::{
-        JoinedLobby, LeftLobby, PlayerJoinedLobby, PlayerLeftLobby, Request,
-        ServerConnectionStatus, UpdateLobbyInfo, UpdateLobbyList,
+        Announcement, ChampionPicksCleared, ChampionSelected, DraftUpdate, GameRulesChanged,
+        JoinedLobby, LeftLobby, PlayerJoinedLobby, PlayerLeftLobby, PlayerSwitchedSide, Request,
+        RolesAssigned, ServerConnectionStatus, UpdateLobbyInfo, UpdateLobbyList,
     },
 };
 
@@ -39,18 +41,34 @@ impl Plugin for NonGame {
             .add_event::<UpdateLobbyInfo>()
             .add_event::<PlayerJoinedLobby>()
             .add_event::<PlayerLeftLobby>()
+            .add_event::<PlayerSwitchedSide>()
+            .add_event::<RolesAssigned>()
+            .add_event::<GameRulesChanged>()
+            .add_event::<ChampionSelected>()
+            .add_event::<ChampionPicksCleared>()
+            .add_event::<DraftUpdate>()
             .add_event::<JoinedLobby>()
-            .add_event::<LeftLobby>();
+            .add_event::<LeftLobby>()
+            .add_event::<Announcement>();
 
         app.init_non_send_resource::<EventChannel>()
             .init_resource::<RequestChannel>();
 
-        app.add_systems(Update, (event_channel_listener, request_channel_listener));
+        app.add_systems(
+            Update,
+            (
+                event_channel_listener,
+                request_channel_listener,
+                server_disconnected,
+                store_champions,
+                store_announcement,
+            ),
+        );
 
         app.insert_state(ConnectingState::NotConnected);
 
         app.add_systems(Startup, |mut commands: Commands| {
-            commands.spawn(Camera3dBundle { ..default() });
+            commands.spawn((MainCamera, Camera3dBundle { ..default() }));
         });
 
         app.add_plugins((
@@ -69,6 +87,54 @@ pub enum ConnectingState {
     Connected,
 }
 
+/// The camera the world is seen through, as opposed to the minimap's.
+#[derive(Component)]
+pub struct MainCamera;
+
+/// Why the last connection to the server ended, shown on the connect screen.
+#[derive(Resource)]
+pub struct DisconnectNotice(pub String);
+
+fn server_disconnected(
+    mut reader: EventReader<ServerConnectionStatus>,
+    mut next_state: ResMut<NextState<ConnectingState>>,
+    mut commands: Commands,
+) {
+    for event in reader.read() {
+        if let ServerConnectionStatus::Disconnected { reason } = event {
+            commands.insert_resource(DisconnectNotice(reason.clone()));
+            next_state.set(ConnectingState::NotConnected);
+        }
+    }
+}
+
+/// The last announcement from the server, shown in the main menu.
+#[derive(Resource)]
+pub struct LatestAnnouncement(pub String);
+
+fn store_announcement(mut reader: EventReader<Announcement>, mut commands: Commands) {
+    for Announcement { msg } in reader.read() {
+        commands.insert_resource(LatestAnnouncement(msg.clone()));
+    }
+}
+
+/// The champions the server offers, sent when connecting.
+#[derive(Resource, Deref)]
+pub struct Champions(pub ChampionCatalog);
+
+/// Who the server knows us as.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct LocalPlayer(pub PlayerId);
+
+fn store_champions(mut reader: EventReader<ServerConnectionStatus>, mut commands: Commands) {
+    for event in reader.read() {
+        if let ServerConnectionStatus::Connected { id, champions } = event {
+            commands.insert_resource(LocalPlayer(*id));
+            commands.insert_resource(Champions(champions.clone()));
+        }
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 fn event_channel_listener(
     event_channel: NonSend<EventChannel>,
@@ -77,8 +143,16 @@ fn event_channel_listener(
     mut update_lobby_info: EventWriter<UpdateLobbyInfo>,
     mut player_joined_lobby: EventWriter<PlayerJoinedLobby>,
     mut player_left_lobby: EventWriter<PlayerLeftLobby>,
+    mut player_switched_side: EventWriter<PlayerSwitchedSide>,
+    mut roles_assigned: EventWriter<RolesAssigned>,
+    mut game_rules_changed: EventWriter<GameRulesChanged>,
+    mut champion_selected: EventWriter<ChampionSelected>,
+    mut champion_picks_cleared: EventWriter<ChampionPicksCleared>,
+    mut draft_update: EventWriter<DraftUpdate>,
     mut joined_lobby: EventWriter<JoinedLobby>,
     mut left_lobby: EventWriter<LeftLobby>,
+    mut announcement: EventWriter<Announcement>,
+    mut game_ready: EventWriter<GameReady>,
 ) {
     let Some(channel) = event_channel.channel.as_ref() else {
         return;
@@ -104,12 +178,36 @@ fn event_channel_listener(
         network::Event::PlayerLeftLobby(event) => {
             player_left_lobby.send(event);
         }
+        network::Event::PlayerSwitchedSide(event) => {
+            player_switched_side.send(event);
+        }
+        network::Event::RolesAssigned(event) => {
+            roles_assigned.send(event);
+        }
+        network::Event::GameRulesChanged(event) => {
+            game_rules_changed.send(event);
+        }
+        network::Event::ChampionSelected(event) => {
+            champion_selected.send(event);
+        }
+        network::Event::ChampionPicksCleared(event) => {
+            champion_picks_cleared.send(event);
+        }
+        network::Event::DraftUpdate(event) => {
+            draft_update.send(event);
+        }
         network::Event::JoinedLobby(event) => {
             joined_lobby.send(event);
         }
         network::Event::LeftLobby(event) => {
             left_lobby.send(event);
         }
+        network::Event::Announcement(event) => {
+            announcement.send(event);
+        }
+        network::Event::GameReady(event) => {
+            game_ready.send(event);
+        }
     }
 }
 
@@ -117,13 +215,13 @@ fn request_channel_listener(
     mut events: EventReader<Request>,
     request_channel: Res<RequestChannel>,
 ) {
+    let Some(channel) = request_channel.channel.as_ref() else {
+        return;
+    };
     for event in events.read() {
-        request_channel
-            .channel
-            .as_ref()
-            .unwrap()
-            .send(event.clone())
-            .unwrap();
+        // The connection thread has exited if this fails; the disconnect is reported
+        // through the event channel.
+        let _ = channel.send(event.clone());
     }
 }
 
@@ -1,25 +1,75 @@
 use std::{
     net::{SocketAddr, TcpStream},
     sync::mpsc::{Receiver, Sender},
+    time::Duration,
 };
 
-use common::{network::{
-    lobby::{
-        LobbyClientMessage, LobbyClientNewConnectionMessage, LobbyId, LobbyInfo, LobbyServerMessage, Player, PlayerWithSide, ShortLobbyInfo
+use common::{
+    champion::{ChampionCatalog, Role},
+    network::{
+        lobby::{
+            BotDifficulty, DraftAction, LobbyClientMessage, LobbyClientNewConnectionMessage,
+            LobbyFilter, LobbyId, LobbyInfo, LobbyServerMessage, LobbySettings, LobbySort,
+            PickMode, Player, PlayerId, PlayerWithSide, RolePreference, ShortLobbyInfo,
+        },
+        TcpStreamExt,
     },
-    TcpStreamExt,
-}, Side};
+    rules::GameRules,
+    Side,
+};
 use uuid::Uuid;
 
-use bevy::prelude::Event as BevyEvent;
+use crate::game::GameReady;
+
+use bevy::{prelude::Event as BevyEvent, utils::HashMap};
+
+/// How many lobbies the lobby browser shows at once.
+const LOBBY_LIST_PAGE_SIZE: usize = 12;
 
 #[derive(Debug, Clone, BevyEvent)]
 pub enum Request {
-    GetLobbyList,
-    CreateLobby,
+    StartMatchmaking {
+        roles: RolePreference,
+    },
+    StopMatchmaking,
+    SubscribeLobbyList {
+        filter: LobbyFilter,
+        sort: LobbySort,
+    },
+    ViewLobbyListPage {
+        page: usize,
+    },
+    UnsubscribeLobbyList,
+    CreateLobby {
+        pick_mode: PickMode,
+    },
     GetLobbyInfo { id: LobbyId },
     JoinLobby { id: LobbyId },
     LeaveLobby,
+    SetRolePreference {
+        roles: RolePreference,
+    },
+    SetGameRules {
+        rules: GameRules,
+    },
+    AddBot {
+        side: Side,
+        difficulty: BotDifficulty,
+    },
+    RemoveBot {
+        id: PlayerId,
+    },
+    SelectChampion {
+        champion: String,
+    },
+    LockInChampion {
+        champion: String,
+    },
+    StartDraft,
+    BanChampion {
+        champion: String,
+    },
+    StartGame,
 }
 
 pub enum Event {
@@ -28,19 +78,43 @@ pub enum Event {
     UpdateLobbyInfo(UpdateLobbyInfo),
     PlayerJoinedLobby(PlayerJoinedLobby),
     PlayerLeftLobby(PlayerLeftLobby),
+    PlayerSwitchedSide(PlayerSwitchedSide),
+    RolesAssigned(RolesAssigned),
+    GameRulesChanged(GameRulesChanged),
+    ChampionSelected(ChampionSelected),
+    ChampionPicksCleared(ChampionPicksCleared),
+    DraftUpdate(DraftUpdate),
     JoinedLobby(JoinedLobby),
     LeftLobby(LeftLobby),
+    Announcement(Announcement),
+    GameReady(GameReady),
 }
 
 #[derive(BevyEvent)]
 pub enum ServerConnectionStatus {
-    Connected,
+    Connected {
+        id: PlayerId,
+        champions: ChampionCatalog,
+    },
     ConnectionFailed,
+    /// The connection was closed after being established; `reason` is shown to the user.
+    Disconnected {
+        reason: String,
+    },
 }
 
+/// A change to the page of the lobby list the client is subscribed to.
 #[derive(BevyEvent)]
-pub struct UpdateLobbyList {
-    pub lobbies: Vec<ShortLobbyInfo>,
+pub enum UpdateLobbyList {
+    Page {
+        page: usize,
+        page_count: usize,
+        lobbies: Vec<ShortLobbyInfo>,
+    },
+    Added(ShortLobbyInfo),
+    Updated(ShortLobbyInfo),
+    Removed(LobbyId),
+    PageCount(usize),
 }
 
 #[derive(BevyEvent)]
@@ -59,6 +133,56 @@ pub struct PlayerLeftLobby {
     pub player: Player,
 }
 
+#[derive(BevyEvent)]
+pub struct PlayerSwitchedSide {
+    pub player: Player,
+    pub side: Side,
+}
+
+#[derive(BevyEvent)]
+pub struct RolesAssigned {
+    pub roles: HashMap<PlayerId, Role>,
+}
+
+#[derive(BevyEvent)]
+pub struct GameRulesChanged {
+    pub rules: GameRules,
+}
+
+/// A player in the lobby selected a champion, or locked it in.
+#[derive(BevyEvent)]
+pub struct ChampionSelected {
+    pub player: Player,
+    pub champion: String,
+    pub locked_in: bool,
+}
+
+/// Every champion picked in the lobby was dropped.
+#[derive(BevyEvent)]
+pub struct ChampionPicksCleared;
+
+/// Progress of the lobby's draft.
+#[derive(BevyEvent)]
+pub enum DraftUpdate {
+    Turn {
+        side: Side,
+        action: DraftAction,
+        /// Who picks, on pick turns.
+        player: Option<PlayerId>,
+        time_left: Duration,
+    },
+    /// `champion` is `None` if the side let its ban turn run out.
+    Banned {
+        side: Side,
+        champion: Option<String>,
+    },
+    Finished,
+    /// Picks and bans were cleared.
+    Cancelled {
+        reason: String,
+    },
+}
+
 #[derive(BevyEvent)]
 pub struct JoinedLobby {
     pub lobby_id: LobbyId,
@@ -67,21 +191,21 @@ pub struct JoinedLobby {
 #[derive(BevyEvent)]
 pub struct LeftLobby;
 
+/// A message from the server's operators to everyone connected.
+#[derive(BevyEvent)]
+pub struct Announcement {
+    pub msg: String,
+}
+
 pub fn connect_to_server(
     addr: SocketAddr,
     send_event: Sender<Event>,
     recv_request: Receiver<Request>,
 ) {
     println!("Connecting to server...");
+    // `Connected` is only reported once the server welcomes us, in `event_listener`.
     let mut stream = match TcpStream::connect(addr) {
-        Ok(stream) => {
-            send_event
-                .send(Event::ServerConnectionStatus(
-                    ServerConnectionStatus::Connected,
-                ))
-                .unwrap();
-            stream
-        }
+        Ok(stream) => stream,
         Err(e) => {
             send_event
                 .send(Event::ServerConnectionStatus(
@@ -109,53 +233,229 @@ pub fn connect_to_server(
 pub fn event_sender(recv_request: Receiver<Request>, mut stream: TcpStream) {
     loop {
         println!("Waiting for request to send...");
-        let request = recv_request.recv().unwrap();
+        let Ok(request) = recv_request.recv() else {
+            return;
+        };
         println!("Request {request:?} received");
 
         let msg = match request {
-            Request::GetLobbyList => LobbyClientMessage::ListLobbies,
+            Request::StartMatchmaking { roles } => LobbyClientMessage::StartMatchmaking { roles },
+            Request::StopMatchmaking => LobbyClientMessage::StopMatchmaking,
+            Request::SubscribeLobbyList { filter, sort } => {
+                LobbyClientMessage::SubscribeLobbyList {
+                    filter,
+                    sort,
+                    page_size: LOBBY_LIST_PAGE_SIZE,
+                }
+            }
+            Request::ViewLobbyListPage { page } => LobbyClientMessage::ViewLobbyListPage { page },
+            Request::UnsubscribeLobbyList => LobbyClientMessage::UnsubscribeLobbyList,
             Request::GetLobbyInfo { id } => LobbyClientMessage::GetLobbyInfo { id },
-            Request::JoinLobby { id } => LobbyClientMessage::JoinLobby { id },
+            Request::JoinLobby { id } => LobbyClientMessage::JoinLobby { id, password: None },
             Request::LeaveLobby => LobbyClientMessage::LeaveLobby,
-            Request::CreateLobby => LobbyClientMessage::CreateLobby,
+            Request::SetRolePreference { roles } => LobbyClientMessage::SetRolePreference { roles },
+            Request::SetGameRules { rules } => LobbyClientMessage::SetGameRules { rules },
+            Request::AddBot { side, difficulty } => LobbyClientMessage::AddBot { side, difficulty },
+            Request::RemoveBot { id } => LobbyClientMessage::RemoveBot { id },
+            Request::SelectChampion { champion } => LobbyClientMessage::SelectChampion { champion },
+            Request::LockInChampion { champion } => LobbyClientMessage::LockInChampion { champion },
+            Request::StartDraft => LobbyClientMessage::StartDraft,
+            Request::BanChampion { champion } => LobbyClientMessage::BanChampion { champion },
+            Request::StartGame => LobbyClientMessage::StartGame,
+            Request::CreateLobby { pick_mode } => LobbyClientMessage::CreateLobby {
+                settings: LobbySettings {
+                    pick_mode,
+                    ..LobbySettings::default()
+                },
+            },
         };
 
-        stream.write_message(&msg).unwrap();
+        if let Err(e) = stream.write_message(&msg) {
+            eprintln!("Could not send request: {e}");
+            return;
+        }
     }
 }
 
 pub fn event_listener(send_event: Sender<Event>, mut stream: TcpStream) {
+    let disconnected = |reason: String| {
+        let _ = send_event.send(Event::ServerConnectionStatus(
+            ServerConnectionStatus::Disconnected { reason },
+        ));
+    };
+
     loop {
         println!("Listening for events...");
-        let msg = stream.read_message::<LobbyServerMessage>(None).unwrap();
+        let msg = match stream.read_message::<LobbyServerMessage>(None) {
+            Ok(msg) => msg,
+            Err(e) => {
+                eprintln!("Connection lost: {e}");
+                disconnected("Lost connection to the server.".into());
+                return;
+            }
+        };
         println!("{msg:?}");
         let event = match msg {
+            LobbyServerMessage::Welcome {
+                id,
+                username,
+                champions,
+            } => {
+                println!("Connected as {username}");
+                Some(Event::ServerConnectionStatus(
+                    ServerConnectionStatus::Connected { id, champions },
+                ))
+            }
+            LobbyServerMessage::UsernameRejected { error } => {
+                disconnected(format!("{error}."));
+                return;
+            }
             LobbyServerMessage::OK => None,
             LobbyServerMessage::Negative { msg } => {
                 eprintln!("Negative received: {msg}");
                 None
             }
+            LobbyServerMessage::RateLimited { retry_after } => {
+                eprintln!("Rate limited, retry after {retry_after:?}");
+                None
+            }
             LobbyServerMessage::StopMatchmaking => todo!(),
-            LobbyServerMessage::LobbyList { lobbies } => {
-                Some(Event::UpdateLobbyList(UpdateLobbyList { lobbies }))
+            LobbyServerMessage::LobbyList { .. } => None,
+            LobbyServerMessage::LobbyListPage {
+                page,
+                page_count,
+                lobbies,
+            } => Some(Event::UpdateLobbyList(UpdateLobbyList::Page {
+                page,
+                page_count,
+                lobbies,
+            })),
+            LobbyServerMessage::LobbyListAdded { lobby } => {
+                Some(Event::UpdateLobbyList(UpdateLobbyList::Added(lobby)))
+            }
+            LobbyServerMessage::LobbyListUpdated { lobby } => {
+                Some(Event::UpdateLobbyList(UpdateLobbyList::Updated(lobby)))
+            }
+            LobbyServerMessage::LobbyListRemoved { id } => {
+                Some(Event::UpdateLobbyList(UpdateLobbyList::Removed(id)))
             }
+            LobbyServerMessage::LobbyListPageCount { page_count } => Some(Event::UpdateLobbyList(
+                UpdateLobbyList::PageCount(page_count),
+            )),
             LobbyServerMessage::LobbyInfo { info } => {
                 Some(Event::UpdateLobbyInfo(UpdateLobbyInfo { lobby_info: info }))
             }
-            LobbyServerMessage::MatchmakingDone { lobby_id } => todo!(),
+            LobbyServerMessage::MatchmakingDone { lobby_id } => {
+                println!("Found a match in lobby {lobby_id}");
+                None
+            }
             LobbyServerMessage::PlayerJoinedLobby { player, side } => Some(Event::PlayerJoinedLobby(PlayerJoinedLobby { player, side })),
             LobbyServerMessage::PlayerLeftLobby { player } => Some(Event::PlayerLeftLobby(PlayerLeftLobby { player })),
-            LobbyServerMessage::PlayerSwitchedSide { player, side } => todo!(),
-            LobbyServerMessage::PlayerSelectedChampion { player, champion } => todo!(),
-            LobbyServerMessage::PlayerLockedInChampion { player, champion } => todo!(),
+            LobbyServerMessage::LobbyOwnerChanged { owner } => {
+                println!("{owner} now owns the lobby");
+                None
+            }
+            LobbyServerMessage::PlayerSwitchedSide { player, side } => {
+                Some(Event::PlayerSwitchedSide(PlayerSwitchedSide {
+                    player,
+                    side,
+                }))
+            }
+            LobbyServerMessage::RolesAssigned { roles } => {
+                Some(Event::RolesAssigned(RolesAssigned { roles }))
+            }
+            LobbyServerMessage::GameRulesChanged { rules } => {
+                Some(Event::GameRulesChanged(GameRulesChanged { rules }))
+            }
+            LobbyServerMessage::PlayerSelectedChampion { player, champion } => {
+                Some(Event::ChampionSelected(ChampionSelected {
+                    player,
+                    champion,
+                    locked_in: false,
+                }))
+            }
+            LobbyServerMessage::PlayerLockedInChampion { player, champion } => {
+                Some(Event::ChampionSelected(ChampionSelected {
+                    player,
+                    champion,
+                    locked_in: true,
+                }))
+            }
+            LobbyServerMessage::ChampionPicksCleared => {
+                Some(Event::ChampionPicksCleared(ChampionPicksCleared))
+            }
+            LobbyServerMessage::DraftTurn {
+                side,
+                action,
+                player,
+                time_left,
+            } => Some(Event::DraftUpdate(DraftUpdate::Turn {
+                side,
+                action,
+                player,
+                time_left,
+            })),
+            LobbyServerMessage::ChampionBanned { side, champion } => {
+                Some(Event::DraftUpdate(DraftUpdate::Banned { side, champion }))
+            }
+            LobbyServerMessage::DraftFinished => Some(Event::DraftUpdate(DraftUpdate::Finished)),
+            LobbyServerMessage::DraftCancelled { reason } => {
+                Some(Event::DraftUpdate(DraftUpdate::Cancelled { reason }))
+            }
+            LobbyServerMessage::GameStarting => {
+                println!("Looking for a game server");
+                None
+            }
+            LobbyServerMessage::GameReady { addr, token } => {
+                println!("The game is ready at {addr}");
+                Some(Event::GameReady(GameReady { addr, token }))
+            }
+            LobbyServerMessage::GameFailed { reason } => {
+                println!("The game could not be started: {reason}");
+                None
+            }
+            LobbyServerMessage::GameEnded { winner } => {
+                match winner {
+                    Some(side) => println!("{side} won the game"),
+                    None => println!("The game ended without a winner"),
+                }
+                None
+            }
+            LobbyServerMessage::ChatMessage { from, text } => {
+                println!("[{}] {text}", from.username);
+                None
+            }
             LobbyServerMessage::YouJoinedLobby { lobby_id } => {
                 Some(Event::JoinedLobby(JoinedLobby { lobby_id }))
             }
+            LobbyServerMessage::YouAreSpectating { lobby_id } => {
+                Some(Event::JoinedLobby(JoinedLobby { lobby_id }))
+            }
             LobbyServerMessage::YouLeftLobby => Some(Event::LeftLobby(LeftLobby)),
+            LobbyServerMessage::Announcement { msg } => {
+                Some(Event::Announcement(Announcement { msg }))
+            }
+            LobbyServerMessage::Kicked { reason } => {
+                disconnected(format!("You were kicked from the server: {reason}"));
+                return;
+            }
+            LobbyServerMessage::ServerShuttingDown {
+                reason,
+                reconnect_after,
+            } => {
+                let hint = match reconnect_after {
+                    Some(after) => format!(" Try reconnecting in {} seconds.", after.as_secs()),
+                    None => String::new(),
+                };
+                disconnected(format!("The server is shutting down: {reason}.{hint}"));
+                return;
+            }
         };
 
         if let Some(event) = event {
-            send_event.send(event).unwrap();
+            if send_event.send(event).is_err() {
+                return;
+            }
         }
     }
 }
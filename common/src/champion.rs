@@ -0,0 +1,92 @@
+//! The champions players can pick, as defined by the lobby server's data files.
+
+use std::fmt::Display;
+
+use anyhow::ensure;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Role {
+    Top,
+    Jungle,
+    Mid,
+    Bot,
+    Support,
+}
+
+impl Role {
+    pub const ALL: [Role; 5] = [Role::Top, Role::Jungle, Role::Mid, Role::Bot, Role::Support];
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Role::Top => write!(f, "Top"),
+            Role::Jungle => write!(f, "Jungle"),
+            Role::Mid => write!(f, "Mid"),
+            Role::Bot => write!(f, "Bot"),
+            Role::Support => write!(f, "Support"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Champion {
+    /// What champions are called in messages, like `SelectChampion`.
+    pub id: String,
+    pub name: String,
+    /// Roles the champion is suited to, best first.
+    pub roles: Vec<Role>,
+    /// Asset path of the champion's portrait.
+    pub icon: String,
+    /// Disabled champions are listed, but can't be picked or banned.
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChampionCatalog {
+    champions: Vec<Champion>,
+}
+
+impl ChampionCatalog {
+    /// Fails if two champions share an id.
+    pub fn new(champions: Vec<Champion>) -> anyhow::Result<Self> {
+        for (i, champion) in champions.iter().enumerate() {
+            ensure!(
+                !champion.id.is_empty(),
+                "Champion {:?} has no id",
+                champion.name
+            );
+            ensure!(
+                champions[..i].iter().all(|c| c.id != champion.id),
+                "Champion id {:?} is used more than once",
+                champion.id
+            );
+        }
+
+        Ok(Self { champions })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Champion> {
+        self.champions.iter().find(|c| c.id == id)
+    }
+
+    /// Whether `id` names a champion that can be picked.
+    pub fn is_enabled(&self, id: &str) -> bool {
+        self.get(id).is_some_and(|c| c.enabled)
+    }
+
+    /// Every champion, in the order of the catalog.
+    pub fn iter(&self) -> impl Iterator<Item = &Champion> {
+        self.champions.iter()
+    }
+
+    pub fn enabled(&self) -> impl Iterator<Item = &Champion> {
+        self.iter().filter(|c| c.enabled)
+    }
+}
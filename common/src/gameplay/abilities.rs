@@ -0,0 +1,231 @@
+//! Champion abilities, and hitting things with them fairly despite latency.
+//!
+//! Players aim at where they see their targets, and they see the world a little in the
+//! past. So casts are checked against where hitboxes were on the tick the caster was
+//! looking at, from a short history, rather than where they are now. How far back that
+//! can go is capped by [`LagCompensation`], so nobody hits targets that are long gone.
+
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{Champion, Health, Position, Team, Tick, TICK_RATE};
+use crate::{network::lobby::PlayerId, replication::NetworkId, Side};
+
+/// How many ticks of hitbox positions are kept. Rewinding further than this is never
+/// possible, whatever [`LagCompensation`] allows.
+pub const HITBOX_HISTORY: usize = 30;
+
+pub const SKILLSHOT_RANGE: f32 = 12.0;
+/// How wide a skillshot is, either side of its line.
+pub const SKILLSHOT_WIDTH: f32 = 0.5;
+pub const SKILLSHOT_DAMAGE: f32 = 80.0;
+
+/// How far from the caster a targeted cast's target can be, to the edge of its hitbox.
+pub const TARGETED_RANGE: f32 = 6.0;
+pub const TARGETED_DAMAGE: f32 = 60.0;
+
+/// Ticks between casts.
+pub const CAST_COOLDOWN: u64 = TICK_RATE as u64;
+
+/// An ability cast, as commanded.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Cast {
+    /// Hits the first enemy along a line from the caster.
+    Skillshot { direction: Vec2 },
+    /// Hits a chosen enemy in range.
+    Targeted { target: NetworkId },
+}
+
+/// How far back casts are checked, at most.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct LagCompensation {
+    pub max_rewind_ticks: u64,
+}
+
+impl LagCompensation {
+    pub const DEFAULT_MAX_REWIND: Duration = Duration::from_millis(200);
+
+    pub fn new(max_rewind: Duration) -> Self {
+        Self {
+            max_rewind_ticks: (max_rewind.as_secs_f64() * TICK_RATE).round() as u64,
+        }
+    }
+}
+
+impl Default for LagCompensation {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_MAX_REWIND)
+    }
+}
+
+/// Something abilities can hit: a circle around its [`Position`].
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Hitbox {
+    pub radius: f32,
+}
+
+/// The first tick the champion can cast again on.
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Cooldown(pub u64);
+
+/// A cast waiting to be resolved on this tick.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QueuedCast {
+    pub player: PlayerId,
+    pub cast: Cast,
+    /// The tick of the world the caster was looking at.
+    pub seen_tick: u64,
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct Casts(pub Vec<QueuedCast>);
+
+#[derive(Debug, Clone, Copy)]
+struct PastHitbox {
+    entity: Entity,
+    position: Vec2,
+    radius: f32,
+    side: Side,
+}
+
+/// Where every hitbox was at the end of each recent tick, oldest first.
+#[derive(Resource, Debug, Default)]
+pub struct HitboxHistory {
+    ticks: VecDeque<(u64, Vec<PastHitbox>)>,
+}
+
+impl HitboxHistory {
+    fn hitboxes_at(&self, tick: u64) -> Option<&[PastHitbox]> {
+        self.ticks
+            .iter()
+            .rev()
+            .find(|&&(t, _)| t == tick)
+            .map(|(_, hitboxes)| hitboxes.as_slice())
+    }
+}
+
+/// Remembers where every living thing's hitbox is as of this tick.
+pub(super) fn record_hitboxes(
+    tick: Res<Tick>,
+    mut history: ResMut<HitboxHistory>,
+    hitboxes: Query<(Entity, &Position, &Hitbox, &Team, &Health)>,
+) {
+    if history.ticks.len() == HITBOX_HISTORY {
+        history.ticks.pop_front();
+    }
+    let hitboxes = hitboxes
+        .iter()
+        .filter(|(.., health)| !health.is_dead())
+        .map(|(entity, position, hitbox, team, _)| PastHitbox {
+            entity,
+            position: position.0,
+            radius: hitbox.radius,
+            side: team.0,
+        })
+        .collect();
+    history.ticks.push_back((tick.0, hitboxes));
+}
+
+/// The tick to check a cast against: the one the caster saw, unless that is further back
+/// than allowed or remembered.
+fn rewind_tick(now: u64, seen: u64, max_rewind: u64, history: &HitboxHistory) -> u64 {
+    let oldest = history.ticks.front().map_or(now, |&(tick, _)| tick);
+    seen.min(now)
+        .max(now.saturating_sub(max_rewind))
+        .max(oldest)
+}
+
+/// How far along a ray from `origin` in `direction` it first comes within `radius` of
+/// `center`, if it does.
+fn ray_hits_circle(origin: Vec2, direction: Vec2, center: Vec2, radius: f32) -> Option<f32> {
+    if direction == Vec2::ZERO {
+        return None;
+    }
+    let to_center = center - origin;
+    let along = to_center.dot(direction);
+    let closest = (to_center - direction * along).length_squared();
+    if closest > radius * radius {
+        return None;
+    }
+    let distance = along - (radius * radius - closest).sqrt();
+    (distance >= 0.0 || to_center.length() <= radius).then_some(distance.max(0.0))
+}
+
+type Unit<'a> = (
+    Entity,
+    &'a Position,
+    &'a Team,
+    &'a mut Health,
+    Option<&'a Champion>,
+    Option<&'a mut Cooldown>,
+    Option<&'a NetworkId>,
+);
+
+/// Carries out this tick's casts. Casters cast from where they are now, at their
+/// targets as they were on the tick they saw.
+pub(super) fn resolve_casts(
+    tick: Res<Tick>,
+    compensation: Res<LagCompensation>,
+    history: Res<HitboxHistory>,
+    mut casts: ResMut<Casts>,
+    mut units: Query<Unit>,
+) {
+    for cast in casts.0.drain(..) {
+        let Some((_, position, team, health, _, Some(mut cooldown), _)) = units
+            .iter_mut()
+            .find(|(_, _, _, _, champion, ..)| champion.is_some_and(|c| c.player == cast.player))
+        else {
+            continue;
+        };
+        if health.is_dead() || tick.0 < cooldown.0 {
+            continue;
+        }
+        cooldown.0 = tick.0 + CAST_COOLDOWN;
+        let (origin, side) = (position.0, team.0);
+
+        let rewound = rewind_tick(
+            tick.0,
+            cast.seen_tick,
+            compensation.max_rewind_ticks,
+            &history,
+        );
+        let Some(hitboxes) = history.hitboxes_at(rewound) else {
+            continue;
+        };
+        let mut enemies = hitboxes.iter().filter(|hitbox| hitbox.side != side);
+
+        let (hit, damage) = match cast.cast {
+            Cast::Skillshot { direction } => {
+                let direction = direction.normalize_or_zero();
+                let first = enemies
+                    .filter_map(|hitbox| {
+                        let radius = hitbox.radius + SKILLSHOT_WIDTH;
+                        let distance = ray_hits_circle(origin, direction, hitbox.position, radius)?;
+                        (distance <= SKILLSHOT_RANGE).then_some((distance, hitbox.entity))
+                    })
+                    .min_by(|(a, _), (b, _)| a.total_cmp(b));
+                (first.map(|(_, entity)| entity), SKILLSHOT_DAMAGE)
+            }
+            Cast::Targeted { target } => {
+                let target = units
+                    .iter()
+                    .find(|(.., id)| *id == Some(&target))
+                    .map(|(entity, ..)| entity);
+                let in_range = enemies.find(|hitbox| {
+                    Some(hitbox.entity) == target
+                        && hitbox.position.distance(origin) <= TARGETED_RANGE + hitbox.radius
+                });
+                (in_range.map(|hitbox| hitbox.entity), TARGETED_DAMAGE)
+            }
+        };
+
+        let Some(Ok((_, _, _, mut health, ..))) = hit.map(|hit| units.get_mut(hit)) else {
+            continue;
+        };
+        if !health.is_dead() {
+            health.current = (health.current - damage).max(0.0);
+        }
+    }
+}
@@ -0,0 +1,448 @@
+//! The match simulation. The game server runs it authoritatively; clients run the same
+//! systems to predict what the server will do.
+
+use std::f32::consts::TAU;
+
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use self::{
+    abilities::{Cast, Casts, Cooldown, Hitbox, HitboxHistory, LagCompensation, QueuedCast},
+    navigation::{NavGrid, NavObstacle, Path},
+    steering::Collider,
+};
+use crate::{
+    map::{MapDefinition, StructureKind},
+    network::{
+        game::{Controller, MatchSetup},
+        lobby::PlayerId,
+    },
+    replication::Replicated,
+    Side,
+};
+
+pub mod abilities;
+pub mod navigation;
+pub mod steering;
+
+/// Simulation steps per second.
+pub const TICK_RATE: f64 = 30.0;
+
+const CHAMPION_HEALTH: f32 = 600.0;
+const CHAMPION_SPEED: f32 = 6.0;
+const CHAMPION_RADIUS: f32 = 0.6;
+/// How long a champion stays dead at the normal respawn time.
+const RESPAWN_SECS: f64 = 10.0;
+const NEXUS_HEALTH: f32 = 5000.0;
+const NEXUS_RADIUS: f32 = 3.0;
+const TOWER_HEALTH: f32 = 2500.0;
+const TOWER_RADIUS: f32 = 1.5;
+const INHIBITOR_HEALTH: f32 = 3000.0;
+const INHIBITOR_RADIUS: f32 = 2.0;
+
+/// Runs the simulation in [`FixedUpdate`] at [`TICK_RATE`].
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Time::<Fixed>::from_hz(TICK_RATE))
+            .init_resource::<Tick>()
+            .init_resource::<Inputs>()
+            .init_resource::<AppliedInputs>()
+            .init_resource::<Casts>()
+            .init_resource::<HitboxHistory>()
+            .init_resource::<LagCompensation>()
+            .add_event::<MatchOver>()
+            .add_systems(
+                FixedUpdate,
+                (
+                    advance_tick,
+                    apply_inputs,
+                    navigation::update_obstacles,
+                    move_units,
+                    steering::collide_units,
+                    abilities::record_hitboxes,
+                    abilities::resolve_casts,
+                    respawn_champions,
+                    check_victory,
+                )
+                    .chain()
+                    .in_set(SimulationSet),
+            );
+    }
+}
+
+/// Every system that steps the simulation, in order.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SimulationSet;
+
+/// How many steps the simulation has taken.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Tick(pub u64);
+
+/// Where something is on the map, which is flat.
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq)]
+pub struct Position(pub Vec2);
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Team(pub Side);
+
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn full(max: f32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Movement {
+    /// Distance covered per second.
+    pub speed: f32,
+    pub target: Option<Vec2>,
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Champion {
+    pub player: PlayerId,
+    pub controller: Controller,
+}
+
+/// Brings a champion back to life where it started, some time after it dies.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Respawn {
+    pub position: Vec2,
+    /// How many ticks a death lasts.
+    pub delay: u64,
+    /// The tick the champion comes back on, while it is dead.
+    pub at: Option<u64>,
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gold(pub u32);
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Level(pub u32);
+
+/// A side's main building. A side loses when theirs is destroyed.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Nexus;
+
+/// Guards a lane.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tower;
+
+/// Guards the way from a lane into a base.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Inhibitor;
+
+/// An order given to a champion by whoever controls it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ChampionCommand {
+    MoveTo(Vec2),
+    Stop,
+    Cast(Cast),
+}
+
+impl ChampionCommand {
+    /// Whether the command's coordinates are all finite numbers. Anything else came from a
+    /// broken or malicious client and would poison the unit's position.
+    pub fn is_valid(&self) -> bool {
+        match self {
+            ChampionCommand::MoveTo(target) => target.is_finite(),
+            ChampionCommand::Cast(Cast::Skillshot { direction }) => direction.is_finite(),
+            ChampionCommand::Stop | ChampionCommand::Cast(Cast::Targeted { .. }) => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChampionInput {
+    pub player: PlayerId,
+    /// Numbers each player's commands in the order they gave them.
+    pub sequence: u32,
+    pub command: ChampionCommand,
+    /// The tick of the world the player was looking at when they gave the command.
+    pub seen_tick: u64,
+}
+
+/// Commands waiting for the next tick. They are applied in the order they were pushed.
+///
+/// This is a queue rather than events, so that commands given on frames where no tick
+/// runs are not lost.
+#[derive(Resource, Debug, Default)]
+pub struct Inputs(pub Vec<ChampionInput>);
+
+/// The newest command the simulation has applied, and on which tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppliedInput {
+    pub sequence: u32,
+    pub tick: u64,
+}
+
+/// The newest command applied for each player, so clients can tell which of their
+/// predictions a snapshot already accounts for.
+#[derive(Resource, Debug, Default)]
+pub struct AppliedInputs(pub HashMap<PlayerId, AppliedInput>);
+
+/// Sent once, on the tick the match is decided. `winner` is `None` if no side is left
+/// standing.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchOver {
+    pub winner: Option<Side>,
+}
+
+/// Spawns the map's structures for every side in the match, and every participant's
+/// champion in their side's fountain.
+pub fn spawn_match(commands: &mut Commands, setup: &MatchSetup, map: &MapDefinition) {
+    // Nothing in the simulation buys items, reduces cooldowns or spawns minions yet, so
+    // those rules only get as far as the log for now.
+    let rules = &setup.rules;
+    println!(
+        "Rules: {} items disabled, cooldown reduction capped at {}%, minions {}, super \
+        minions {}",
+        rules.disabled_items.len(),
+        rules.cooldown_reduction_cap,
+        if rules.minions { "on" } else { "off" },
+        if rules.super_minions { "on" } else { "off" },
+    );
+
+    let sides = Side::all(setup.teams).collect::<Vec<_>>();
+    for structure in &map.structures {
+        if !sides.contains(&structure.side) {
+            continue;
+        }
+        let mut entity = commands.spawn((
+            Replicated,
+            Team(structure.side),
+            Position(structure.position),
+        ));
+        let radius = match structure.kind {
+            StructureKind::Nexus => {
+                entity.insert((Nexus, Health::full(NEXUS_HEALTH)));
+                NEXUS_RADIUS
+            }
+            StructureKind::Tower => {
+                entity.insert((Tower, Health::full(TOWER_HEALTH)));
+                TOWER_RADIUS
+            }
+            StructureKind::Inhibitor => {
+                entity.insert((Inhibitor, Health::full(INHIBITOR_HEALTH)));
+                INHIBITOR_RADIUS
+            }
+        };
+        entity.insert((Hitbox { radius }, NavObstacle { radius }));
+    }
+
+    let respawn_delay =
+        (RESPAWN_SECS * TICK_RATE) as u64 * u64::from(setup.rules.respawn_time_percent) / 100;
+    for side in sides {
+        let Some(base) = map.base(side) else {
+            continue;
+        };
+        let team = setup
+            .participants
+            .iter()
+            .filter(|participant| participant.side == side)
+            .collect::<Vec<_>>();
+        // Spread out around the middle of the fountain, so nobody starts on top of anyone.
+        for (i, participant) in team.iter().enumerate() {
+            let angle = TAU * i as f32 / team.len() as f32;
+            let position =
+                base.fountain.center + Vec2::from_angle(angle) * base.fountain.radius / 2.0;
+            commands.spawn((
+                Replicated,
+                Champion {
+                    player: participant.id,
+                    controller: participant.controller,
+                },
+                Team(side),
+                Position(position),
+                Health::full(CHAMPION_HEALTH),
+                Respawn {
+                    position,
+                    delay: respawn_delay,
+                    at: None,
+                },
+                Movement {
+                    speed: CHAMPION_SPEED,
+                    target: None,
+                },
+                Path::default(),
+                Collider {
+                    radius: CHAMPION_RADIUS,
+                },
+                Gold(setup.rules.starting_gold),
+                Level(setup.rules.starting_level),
+                Hitbox {
+                    radius: CHAMPION_RADIUS,
+                },
+                Cooldown::default(),
+            ));
+        }
+    }
+}
+
+fn advance_tick(mut tick: ResMut<Tick>) {
+    tick.0 += 1;
+}
+
+/// Carries out a command's effect on movement. Clients predicting their own champion use
+/// this too.
+pub fn apply_command(movement: &mut Movement, command: ChampionCommand) {
+    match command {
+        ChampionCommand::MoveTo(target) => movement.target = Some(target),
+        ChampionCommand::Stop => movement.target = None,
+        ChampionCommand::Cast(_) => {}
+    }
+}
+
+/// Moves a unit along its path towards its target for `step` seconds, planning the path
+/// first if the target is new. Without a [`NavGrid`], units walk straight there. Clients
+/// predicting their own champion use this too.
+pub fn step_movement(
+    position: &mut Position,
+    movement: &mut Movement,
+    path: &mut Path,
+    nav: Option<&NavGrid>,
+    step: f32,
+) {
+    let Some(target) = movement.target else {
+        return;
+    };
+    if path.destination != Some(target) {
+        *path = Path::plan(nav, position.0, target);
+    }
+
+    let mut distance = movement.speed * step;
+    while let Some(&waypoint) = path.waypoints.front() {
+        let to_waypoint = waypoint - position.0;
+        if to_waypoint.length() > distance {
+            position.0 += to_waypoint.normalize() * distance;
+            return;
+        }
+        position.0 = waypoint;
+        distance -= to_waypoint.length();
+        path.waypoints.pop_front();
+    }
+    movement.target = None;
+    path.destination = None;
+}
+
+fn apply_inputs(
+    tick: Res<Tick>,
+    mut inputs: ResMut<Inputs>,
+    mut applied: ResMut<AppliedInputs>,
+    mut casts: ResMut<Casts>,
+    mut champions: Query<(&Champion, &mut Movement, Option<&Health>)>,
+) {
+    for input in inputs.0.drain(..) {
+        if !input.command.is_valid() {
+            continue;
+        }
+        let Some((_, mut movement, health)) = champions
+            .iter_mut()
+            .find(|(c, ..)| c.player == input.player)
+        else {
+            continue;
+        };
+        // The dead can't do anything, but their commands still count as applied.
+        if health.is_none_or(|health| !health.is_dead()) {
+            apply_command(&mut movement, input.command);
+            if let ChampionCommand::Cast(cast) = input.command {
+                casts.0.push(QueuedCast {
+                    player: input.player,
+                    cast,
+                    seen_tick: input.seen_tick,
+                });
+            }
+        }
+        applied.0.insert(
+            input.player,
+            AppliedInput {
+                sequence: input.sequence,
+                tick: tick.0,
+            },
+        );
+    }
+}
+
+fn move_units(
+    time: Res<Time>,
+    nav: Option<Res<NavGrid>>,
+    mut units: Query<(&mut Position, &mut Movement, &mut Path, Option<&Health>)>,
+) {
+    let step = time.delta_seconds();
+    for (mut position, mut movement, mut path, health) in &mut units {
+        let dead = health.is_some_and(Health::is_dead);
+        if movement.target.is_some() && !dead {
+            step_movement(
+                &mut position,
+                &mut movement,
+                &mut path,
+                nav.as_deref(),
+                step,
+            );
+        }
+    }
+}
+
+/// Stops champions where they die, and brings them back once their respawn time is up.
+fn respawn_champions(
+    tick: Res<Tick>,
+    mut champions: Query<(
+        &mut Respawn,
+        &mut Health,
+        &mut Position,
+        &mut Movement,
+        &mut Path,
+    )>,
+) {
+    for (mut respawn, mut health, mut position, mut movement, mut path) in &mut champions {
+        if !health.is_dead() {
+            continue;
+        }
+        match respawn.at {
+            None => {
+                respawn.at = Some(tick.0 + respawn.delay);
+                movement.target = None;
+                *path = Path::default();
+            }
+            Some(at) if tick.0 >= at => {
+                respawn.at = None;
+                health.current = health.max;
+                position.0 = respawn.position;
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+/// Ends the match once at most one side still has a standing nexus.
+fn check_victory(
+    nexuses: Query<(&Team, &Health), With<Nexus>>,
+    mut over: EventWriter<MatchOver>,
+    mut decided: Local<bool>,
+) {
+    if *decided || nexuses.is_empty() {
+        return;
+    }
+
+    let mut standing = nexuses
+        .iter()
+        .filter(|(_, health)| !health.is_dead())
+        .map(|(team, _)| team.0);
+    let winner = standing.next();
+    if standing.next().is_none() {
+        *decided = true;
+        over.send(MatchOver { winner });
+    }
+}
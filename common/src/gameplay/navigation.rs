@@ -0,0 +1,376 @@
+//! Finding ways around the map.
+//!
+//! The map's walkable terrain is sampled on a fine grid, which units path across with A*.
+//! Paths are then smoothed into as few straight legs as the terrain allows. Things like
+//! structures block the grid too, as [`NavObstacle`]s, and can come and go during a match;
+//! paths they cut are planned again.
+//!
+//! Everything here is deterministic, so clients predicting their champion plan the same
+//! paths the server does.
+
+use std::{cmp::Reverse, collections::BinaryHeap, collections::VecDeque};
+
+use bevy::{prelude::*, utils::HashMap};
+
+use super::Position;
+use crate::map::{Terrain, TerrainGrid};
+
+/// The width of a grid cell.
+pub const NAV_CELL_SIZE: f32 = 0.5;
+
+/// The cost of moving to a neighbouring cell, straight and diagonally.
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+/// Cells that are in no component, because they aren't walkable.
+const NO_COMPONENT: u32 = u32::MAX;
+
+/// The map's terrain as units see it when pathing.
+#[derive(Resource, Debug, Clone)]
+pub struct NavGrid {
+    terrain: TerrainGrid,
+    /// Which cells some [`NavObstacle`] covers.
+    blocked: Vec<bool>,
+    /// Which cells can reach each other through the terrain, ignoring obstacles.
+    components: Vec<u32>,
+}
+
+/// Blocks a circle around its [`Position`] for pathing.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct NavObstacle {
+    pub radius: f32,
+}
+
+/// Where a unit is going and the way it is taking there. Planned again whenever the
+/// destination differs from the unit's [`Movement`](super::Movement) target.
+#[derive(Component, Debug, Default, Clone, PartialEq)]
+pub struct Path {
+    pub destination: Option<Vec2>,
+    /// The points to walk to in a straight line, one after the other. The last one is
+    /// the destination, or as close to it as the unit can get.
+    pub waypoints: VecDeque<Vec2>,
+}
+
+impl Path {
+    /// Plans a path from `from` to `to`. Without a grid, that is a straight line.
+    pub fn plan(nav: Option<&NavGrid>, from: Vec2, to: Vec2) -> Self {
+        let waypoints = match nav {
+            Some(nav) => nav.find_path(from, to).unwrap_or_default(),
+            None => vec![to],
+        };
+        Path {
+            destination: Some(to),
+            waypoints: waypoints.into(),
+        }
+    }
+}
+
+impl NavGrid {
+    pub fn new(terrain: &Terrain) -> Self {
+        let terrain = TerrainGrid::new(terrain, NAV_CELL_SIZE);
+        let mut components = vec![NO_COMPONENT; terrain.walkable.len()];
+        let mut next = 0;
+        for start in 0..components.len() {
+            if !terrain.walkable[start] || components[start] != NO_COMPONENT {
+                continue;
+            }
+            components[start] = next;
+            let mut queue = VecDeque::from([start]);
+            while let Some(cell) = queue.pop_front() {
+                for neighbour in terrain.neighbours(cell) {
+                    if terrain.walkable[neighbour] && components[neighbour] == NO_COMPONENT {
+                        components[neighbour] = next;
+                        queue.push_back(neighbour);
+                    }
+                }
+            }
+            next += 1;
+        }
+
+        Self {
+            blocked: vec![false; terrain.walkable.len()],
+            terrain,
+            components,
+        }
+    }
+
+    fn cell_walkable(&self, cell: usize) -> bool {
+        self.terrain.walkable[cell] && !self.blocked[cell]
+    }
+
+    /// Whether a unit can stand at `point`, given the terrain and obstacles.
+    pub fn is_walkable(&self, point: Vec2) -> bool {
+        self.terrain
+            .cell_at(point)
+            .is_some_and(|cell| self.cell_walkable(cell))
+    }
+
+    /// Replaces the obstacles blocking the grid.
+    pub fn set_obstacles(&mut self, obstacles: impl IntoIterator<Item = (Vec2, f32)>) {
+        self.blocked.fill(false);
+        for (center, radius) in obstacles {
+            let (min, max) = (center - radius, center + radius);
+            let cells = ((max - min) / NAV_CELL_SIZE).ceil().as_uvec2() + 1;
+            for y in 0..cells.y {
+                for x in 0..cells.x {
+                    let point = min + UVec2::new(x, y).as_vec2() * NAV_CELL_SIZE;
+                    let Some(cell) = self.terrain.cell_at(point) else {
+                        continue;
+                    };
+                    if self.terrain.center(cell).distance(center) <= radius {
+                        self.blocked[cell] = true;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether a unit can walk in a straight line from `from` to `to`.
+    pub fn line_walkable(&self, from: Vec2, to: Vec2) -> bool {
+        let (Some(start), Some(end)) = (self.terrain.cell_at(from), self.terrain.cell_at(to))
+        else {
+            return false;
+        };
+        let width = self.terrain.width;
+        let (mut x, mut y) = ((start % width) as i64, (start / width) as i64);
+        let (end_x, end_y) = ((end % width) as i64, (end / width) as i64);
+
+        // Steps through every cell the line crosses, one boundary at a time.
+        let direction = to - from;
+        let step_x = if direction.x < 0.0 { -1 } else { 1 };
+        let step_y = if direction.y < 0.0 { -1 } else { 1 };
+        let cell_min = self.terrain.center(start) - NAV_CELL_SIZE / 2.0;
+        let first_boundary = |direction: f32, min: f32, from: f32| {
+            if direction == 0.0 {
+                return f32::INFINITY;
+            }
+            let edge = if direction > 0.0 {
+                min + NAV_CELL_SIZE
+            } else {
+                min
+            };
+            (edge - from) / direction
+        };
+        let mut next_x = first_boundary(direction.x, cell_min.x, from.x);
+        let mut next_y = first_boundary(direction.y, cell_min.y, from.y);
+        let delta_x = (NAV_CELL_SIZE / direction.x).abs();
+        let delta_y = (NAV_CELL_SIZE / direction.y).abs();
+
+        for _ in 0..=(end_x - x).abs() + (end_y - y).abs() {
+            if !self.cell_walkable((y * width as i64 + x) as usize) {
+                return false;
+            }
+            if (x, y) == (end_x, end_y) {
+                break;
+            }
+            if next_x < next_y {
+                x += step_x;
+                next_x += delta_x;
+            } else {
+                y += step_y;
+                next_y += delta_y;
+            }
+        }
+        true
+    }
+
+    /// Whether a unit at `from` can still walk `waypoints` without running into
+    /// anything.
+    pub fn path_clear(&self, from: Vec2, waypoints: &VecDeque<Vec2>) -> bool {
+        let mut from = from;
+        waypoints.iter().all(|&to| {
+            let clear = self.line_walkable(from, to);
+            from = to;
+            clear
+        })
+    }
+
+    /// The walkable cell nearest to `point` that `accept` is happy with.
+    fn nearest_cell(&self, point: Vec2, accept: impl Fn(usize) -> bool) -> Option<usize> {
+        let (width, height) = (self.terrain.width as i64, self.terrain.height as i64);
+        let offset = ((point - self.terrain.origin) / NAV_CELL_SIZE).floor();
+        let center_x = (offset.x as i64).clamp(0, width - 1);
+        let center_y = (offset.y as i64).clamp(0, height - 1);
+
+        let mut best: Option<(f32, usize)> = None;
+        for ring in 0..width.max(height) {
+            // Nothing further out can be closer than what was already found.
+            if best.is_some_and(|(distance, _)| (ring - 1) as f32 * NAV_CELL_SIZE > distance) {
+                break;
+            }
+            let (left, right) = (center_x - ring, center_x + ring);
+            let (bottom, top) = (center_y - ring, center_y + ring);
+            let rows = (left..=right).flat_map(|x| [(x, bottom), (x, top)]);
+            let columns = (bottom + 1..top).flat_map(|y| [(left, y), (right, y)]);
+            for (x, y) in rows.chain(columns) {
+                if x < 0 || y < 0 || x >= width || y >= height {
+                    continue;
+                }
+                let cell = (y * width + x) as usize;
+                if !self.cell_walkable(cell) || !accept(cell) {
+                    continue;
+                }
+                let distance = self.terrain.center(cell).distance(point);
+                if best.is_none_or(|(best, _)| distance < best) {
+                    best = Some((distance, cell));
+                }
+            }
+        }
+        best.map(|(_, cell)| cell)
+    }
+
+    /// Where a unit at `from` should head for when told to go to `to`: `to` itself if it
+    /// can get there, or otherwise the closest point to it that it can get to.
+    pub fn closest_reachable(&self, from: Vec2, to: Vec2) -> Option<Vec2> {
+        let start = self.nearest_cell(from, |_| true)?;
+        let component = self.components[start];
+        match self.terrain.cell_at(to) {
+            Some(cell) if self.cell_walkable(cell) && self.components[cell] == component => {
+                Some(to)
+            }
+            _ => {
+                let cell = self.nearest_cell(to, |cell| self.components[cell] == component)?;
+                Some(self.terrain.center(cell))
+            }
+        }
+    }
+
+    fn heuristic(&self, from: usize, to: usize) -> u32 {
+        let width = self.terrain.width;
+        let dx = (from % width).abs_diff(to % width) as u32;
+        let dy = (from / width).abs_diff(to / width) as u32;
+        STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+    }
+
+    /// The cells reachable from `cell` in one move, and what that costs. Diagonal moves
+    /// can't cut corners.
+    fn moves(&self, cell: usize) -> impl Iterator<Item = (usize, u32)> + '_ {
+        let (width, height) = (self.terrain.width as i64, self.terrain.height as i64);
+        let (x, y) = ((cell as i64) % width, (cell as i64) / width);
+        let open = move |x: i64, y: i64| {
+            (x >= 0 && y >= 0 && x < width && y < height)
+                && self.cell_walkable((y * width + x) as usize)
+        };
+        [
+            (1, 0),
+            (-1, 0),
+            (0, 1),
+            (0, -1),
+            (1, 1),
+            (1, -1),
+            (-1, 1),
+            (-1, -1),
+        ]
+        .into_iter()
+        .filter(move |&(dx, dy)| {
+            open(x + dx, y + dy) && (dx == 0 || dy == 0 || (open(x + dx, y) && open(x, y + dy)))
+        })
+        .map(move |(dx, dy)| {
+            let cost = if dx == 0 || dy == 0 {
+                STRAIGHT_COST
+            } else {
+                DIAGONAL_COST
+            };
+            (((y + dy) * width + x + dx) as usize, cost)
+        })
+    }
+
+    /// A* from `start` to `goal`, as cells. If obstacles cut `goal` off, this leads to
+    /// the cell closest to it instead.
+    fn search(&self, start: usize, goal: usize) -> Vec<usize> {
+        let mut open = BinaryHeap::from([Reverse((self.heuristic(start, goal), 0, start))]);
+        let mut visited = HashMap::from([(start, (0, start))]);
+        let mut closest = (self.heuristic(start, goal), start);
+
+        while let Some(Reverse((_, cost, cell))) = open.pop() {
+            if cell == goal {
+                closest = (0, goal);
+                break;
+            }
+            if visited[&cell].0 < cost {
+                continue;
+            }
+            closest = closest.min((self.heuristic(cell, goal), cell));
+            for (next, step) in self.moves(cell) {
+                let cost = cost + step;
+                if visited.get(&next).is_some_and(|&(known, _)| known <= cost) {
+                    continue;
+                }
+                visited.insert(next, (cost, cell));
+                open.push(Reverse((cost + self.heuristic(next, goal), cost, next)));
+            }
+        }
+
+        let mut cells = vec![closest.1];
+        while let Some(&(_, parent)) = visited.get(cells.last().unwrap()) {
+            if parent == *cells.last().unwrap() {
+                break;
+            }
+            cells.push(parent);
+        }
+        cells.reverse();
+        cells
+    }
+
+    /// The waypoints from `from` to as close to `to` as possible, not counting `from`
+    /// itself. `None` if there is no walkable terrain at all.
+    pub fn find_path(&self, from: Vec2, to: Vec2) -> Option<Vec<Vec2>> {
+        let destination = self.closest_reachable(from, to)?;
+        let start = self.nearest_cell(from, |_| true)?;
+        let goal = self.nearest_cell(destination, |_| true)?;
+        let cells = self.search(start, goal);
+
+        let mut points = cells
+            .iter()
+            .map(|&cell| self.terrain.center(cell))
+            .collect::<Vec<_>>();
+        if cells.last() == Some(&goal) {
+            *points.last_mut().unwrap() = destination;
+        }
+
+        // Cuts every corner the terrain allows, from wherever the unit really is.
+        let mut waypoints = Vec::new();
+        let mut anchor = from;
+        for (i, &point) in points.iter().enumerate() {
+            if i > 0 && !self.line_walkable(anchor, point) {
+                anchor = points[i - 1];
+                waypoints.push(anchor);
+            }
+        }
+        let last = *points.last().unwrap();
+        if waypoints.last() != Some(&last) && last != from {
+            waypoints.push(last);
+        }
+        Some(waypoints)
+    }
+}
+
+/// Blocks the grid where obstacles are, and has paths they now cut planned again.
+#[allow(clippy::type_complexity)]
+pub fn update_obstacles(
+    nav: Option<ResMut<NavGrid>>,
+    changed: Query<
+        (),
+        (
+            With<NavObstacle>,
+            Or<(Changed<Position>, Changed<NavObstacle>)>,
+        ),
+    >,
+    mut removed: RemovedComponents<NavObstacle>,
+    obstacles: Query<(&Position, &NavObstacle)>,
+    mut paths: Query<(&Position, &mut Path)>,
+) {
+    let Some(mut nav) = nav else {
+        return;
+    };
+    if changed.is_empty() && removed.read().count() == 0 {
+        return;
+    }
+
+    nav.set_obstacles(obstacles.iter().map(|(position, o)| (position.0, o.radius)));
+    for (position, mut path) in &mut paths {
+        if !path.waypoints.is_empty() && !nav.path_clear(position.0, &path.waypoints) {
+            path.destination = None;
+        }
+    }
+}
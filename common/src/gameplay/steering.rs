@@ -0,0 +1,168 @@
+//! Keeping units from standing on top of each other.
+//!
+//! After units move each tick, any two that overlap are pushed apart. A unit standing
+//! still isn't pushed by units walking into it, so it blocks their way and they have to go
+//! around. Units walking into each other sidestep to their right, so they pass rather
+//! than push head on. [`Ghosted`] units ignore all of this.
+//!
+//! Every unit's push is worked out from where everyone was before any of them moved, so
+//! the result doesn't depend on the order units are handled in. Clients predicting their
+//! champion run the same code against the units around it.
+
+use bevy::prelude::*;
+
+use super::{
+    navigation::{NavGrid, Path},
+    Health, Movement, Position,
+};
+use crate::replication::NetworkId;
+
+/// Units take up a circle of this radius around their [`Position`].
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Collider {
+    pub radius: f32,
+}
+
+/// Walks through other units, and they through it.
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Ghosted;
+
+/// A unit as collisions see it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Body {
+    pub position: Vec2,
+    pub radius: f32,
+    /// Which way the unit is walking, if it is.
+    pub heading: Option<Vec2>,
+    /// Tells apart units standing exactly on top of each other, so they can be pushed
+    /// opposite ways. The unit's [`NetworkId`], so that clients predicting a collision
+    /// push the same way the server does.
+    pub id: u32,
+}
+
+impl Body {
+    pub fn new(
+        id: u32,
+        position: &Position,
+        collider: &Collider,
+        movement: &Movement,
+        path: Option<&Path>,
+    ) -> Self {
+        let next = path
+            .and_then(|path| path.waypoints.front().copied())
+            .or(movement.target);
+        Body {
+            position: position.0,
+            radius: collider.radius,
+            heading: next
+                .map(|next| (next - position.0).normalize_or_zero())
+                .filter(|&heading| heading != Vec2::ZERO),
+            id,
+        }
+    }
+}
+
+/// How far `body` has to move to stop overlapping `others`.
+pub fn separation(body: &Body, others: impl IntoIterator<Item = Body>) -> Vec2 {
+    let mut push = Vec2::ZERO;
+    for other in others {
+        let away = body.position - other.position;
+        let overlap = body.radius + other.radius - away.length();
+        if overlap <= 0.0 {
+            continue;
+        }
+        // Units standing still hold their ground against units walking into them.
+        let share = match (body.heading, other.heading) {
+            (None, Some(_)) => continue,
+            (Some(_), None) => 1.0,
+            _ => 0.5,
+        };
+        // Units exactly on top of each other have no way apart, so they agree on one and
+        // go opposite ways along it. The one with the lower id goes the way it would
+        // sidestep to.
+        let normal = away.try_normalize().unwrap_or_else(|| {
+            let lower = body.id <= other.id;
+            let (first, second) = if lower {
+                (body.heading, other.heading)
+            } else {
+                (other.heading, body.heading)
+            };
+            let axis = first.or(second).map_or(Vec2::Y, |heading| heading.perp());
+            if lower {
+                -axis
+            } else {
+                axis
+            }
+        });
+        push += normal * overlap * share;
+
+        if let Some(heading) = body.heading {
+            if heading.dot(-away) > 0.0 {
+                push -= heading.perp() * overlap * share;
+            }
+        }
+    }
+    push
+}
+
+/// Moves a unit by `push`, but not into unwalkable terrain: it slides along walls
+/// instead.
+pub fn push_unit(position: &mut Position, push: Vec2, nav: Option<&NavGrid>) {
+    let Some(nav) = nav else {
+        position.0 += push;
+        return;
+    };
+    let candidates = [push, Vec2::new(push.x, 0.0), Vec2::new(0.0, push.y)];
+    if let Some(push) = candidates
+        .into_iter()
+        .find(|&push| nav.line_walkable(position.0, position.0 + push))
+    {
+        position.0 += push;
+    }
+}
+
+/// Pushes overlapping units apart. Ghosts and the dead don't collide.
+#[allow(clippy::type_complexity)]
+pub(super) fn collide_units(
+    nav: Option<Res<NavGrid>>,
+    mut units: Query<
+        (
+            Entity,
+            Option<&NetworkId>,
+            &mut Position,
+            &Collider,
+            &Movement,
+            Option<&Path>,
+            Option<&Health>,
+        ),
+        Without<Ghosted>,
+    >,
+) {
+    let bodies = units
+        .iter()
+        .filter(|(.., health)| health.is_none_or(|health| !health.is_dead()))
+        .map(|(entity, id, position, collider, movement, path, _)| {
+            // Only units in tests go without replicating.
+            let id = id.map_or(entity.index(), |id| id.0);
+            Body::new(id, position, collider, movement, path)
+        })
+        .collect::<Vec<_>>();
+
+    let pushes = bodies
+        .iter()
+        .enumerate()
+        .map(|(i, body)| {
+            let others = bodies[..i].iter().chain(&bodies[i + 1..]).copied();
+            separation(body, others)
+        })
+        .collect::<Vec<_>>();
+
+    let living = units
+        .iter_mut()
+        .filter(|(.., health)| health.is_none_or(|health| !health.is_dead()));
+    for ((_, _, mut position, ..), push) in living.zip(pushes) {
+        if push != Vec2::ZERO {
+            push_unit(&mut position, push, nav.as_deref());
+        }
+    }
+}
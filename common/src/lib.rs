@@ -1,13 +1,40 @@
+use std::fmt::Display;
+
 use serde::{Deserialize, Serialize};
 
+pub mod champion;
+pub mod gameplay;
+pub mod map;
 pub mod network;
+pub mod replication;
+pub mod rules;
 
+/// A team in a game. How many there are depends on the lobby; see
+/// [`LobbySettings::teams`](network::lobby::LobbySettings::teams).
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub enum Side {
-    Red,
-    Blue,
-}
+pub struct Side(pub u8);
 
 impl Side {
-    pub const ALL: [Side; 2] = [Side::Red, Side::Blue];
+    pub const RED: Side = Side(0);
+    pub const BLUE: Side = Side(1);
+
+    /// The first `count` sides, in order.
+    pub fn all(count: usize) -> impl Iterator<Item = Side> {
+        (0..count).map(|i| Side(i as u8))
+    }
+
+    /// The side after this one out of `count`, wrapping around to the first.
+    pub fn next(self, count: usize) -> Side {
+        Side(((self.0 as usize + 1) % count) as u8)
+    }
+}
+
+impl Display for Side {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Side::RED => write!(f, "Red"),
+            Side::BLUE => write!(f, "Blue"),
+            Side(n) => write!(f, "Team {}", n + 1),
+        }
+    }
 }
@@ -0,0 +1,380 @@
+//! Maps, as loaded from JSON files.
+//!
+//! A map is flat and lies on the XY plane: the same coordinates as
+//! [`Position`](crate::gameplay::Position). Areas are polygons, given as their corners in
+//! order.
+
+use std::{collections::VecDeque, path::Path};
+
+use anyhow::{ensure, Context};
+use bevy::{ecs::system::Resource, math::Vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::Side;
+
+/// How finely terrain is sampled when checking what can reach what.
+pub const VALIDATION_CELL_SIZE: f32 = 1.0;
+
+/// The map a match is played on. The game server spawns the match from it and sends it
+/// to clients to draw.
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MapDefinition {
+    pub name: String,
+    pub terrain: Terrain,
+    /// One per side that can play on this map.
+    pub bases: Vec<Base>,
+    pub lanes: Vec<Lane>,
+    pub structures: Vec<Structure>,
+    #[serde(default)]
+    pub jungle_camps: Vec<JungleCamp>,
+    /// Areas units inside can't be seen from outside.
+    #[serde(default)]
+    pub brush: Vec<Polygon>,
+}
+
+/// Where units can go: anywhere inside one of the walkable areas, unless it is also
+/// inside an obstacle.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Terrain {
+    pub walkable: Vec<Polygon>,
+    #[serde(default)]
+    pub obstacles: Vec<Polygon>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Base {
+    pub side: Side,
+    pub area: Polygon,
+    /// Where the side's champions spawn and respawn.
+    pub fountain: Circle,
+}
+
+/// A path minion waves follow between two bases: `sides[0]`'s minions walk the
+/// waypoints in order, and `sides[1]`'s in reverse.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lane {
+    pub name: String,
+    pub sides: [Side; 2],
+    pub waypoints: Vec<Vec2>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Structure {
+    pub kind: StructureKind,
+    pub side: Side,
+    /// The lane a tower or inhibitor guards.
+    #[serde(default)]
+    pub lane: Option<String>,
+    pub position: Vec2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StructureKind {
+    Tower,
+    Inhibitor,
+    Nexus,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JungleCamp {
+    pub name: String,
+    pub position: Vec2,
+    /// How far the camp's monsters chase before going back.
+    pub leash_radius: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Circle {
+    pub center: Vec2,
+    pub radius: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Polygon(pub Vec<Vec2>);
+
+impl Polygon {
+    pub fn contains(&self, point: Vec2) -> bool {
+        let corners = &self.0;
+        let mut inside = false;
+        for (i, &a) in corners.iter().enumerate() {
+            let b = corners[(i + 1) % corners.len()];
+            if (a.y > point.y) != (b.y > point.y)
+                && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x)
+            {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+
+    /// The smallest and largest corner on each axis.
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        self.0.iter().fold(
+            (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+            |(min, max), &corner| (min.min(corner), max.max(corner)),
+        )
+    }
+}
+
+impl Terrain {
+    pub fn is_walkable(&self, point: Vec2) -> bool {
+        self.walkable.iter().any(|area| area.contains(point))
+            && !self.obstacles.iter().any(|area| area.contains(point))
+    }
+}
+
+impl MapDefinition {
+    /// Reads a map file without checking the map makes sense.
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Could not open {}", path.display()))?;
+        serde_json::from_reader(std::io::BufReader::new(file))
+            .with_context(|| format!("Invalid map in {}", path.display()))
+    }
+
+    /// Reads a map file, and fails if the map has any [problems](Self::problems).
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let map = Self::read(path)?;
+        let problems = map.problems();
+        ensure!(
+            problems.is_empty(),
+            "{} has problems: {}",
+            path.display(),
+            problems.join("; ")
+        );
+        Ok(map)
+    }
+
+    pub fn base(&self, side: Side) -> Option<&Base> {
+        self.bases.iter().find(|base| base.side == side)
+    }
+
+    /// Everything wrong with the map: structures it is missing, references to things
+    /// that don't exist, and places units could never get to.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = self.structure_problems();
+        if problems.is_empty() {
+            problems.extend(self.reachability_problems());
+        }
+        problems
+    }
+
+    fn structure_problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.bases.len() < 2 {
+            problems.push("There must be at least two bases".into());
+        }
+        if self.terrain.walkable.iter().any(|area| area.0.len() < 3) {
+            problems.push("Walkable areas need at least three corners".into());
+        }
+
+        for (i, base) in self.bases.iter().enumerate() {
+            let side = base.side;
+            if self.bases[..i].iter().any(|other| other.side == side) {
+                problems.push(format!("{side} has more than one base"));
+            }
+            let nexuses = self
+                .structures
+                .iter()
+                .filter(|s| s.side == side && s.kind == StructureKind::Nexus)
+                .count();
+            if nexuses != 1 {
+                problems.push(format!("{side} has {nexuses} nexuses instead of one"));
+            }
+        }
+
+        for lane in &self.lanes {
+            if lane.waypoints.len() < 2 {
+                problems.push(format!("Lane {} needs at least two waypoints", lane.name));
+            }
+            for side in lane.sides {
+                if self.base(side).is_none() {
+                    problems.push(format!(
+                        "Lane {} leads to {side}, which has no base",
+                        lane.name
+                    ));
+                }
+                for kind in [StructureKind::Tower, StructureKind::Inhibitor] {
+                    let guarded = self.structures.iter().any(|s| {
+                        s.side == side && s.kind == kind && s.lane.as_ref() == Some(&lane.name)
+                    });
+                    if !guarded {
+                        problems.push(format!("{side} has no {kind:?} in lane {}", lane.name));
+                    }
+                }
+            }
+        }
+
+        for structure in &self.structures {
+            if self.base(structure.side).is_none() {
+                problems.push(format!(
+                    "There is a {:?} for {}, which has no base",
+                    structure.kind, structure.side
+                ));
+            }
+            if let Some(lane) = &structure.lane {
+                if !self.lanes.iter().any(|l| &l.name == lane) {
+                    problems.push(format!("There is no lane {lane}"));
+                }
+            }
+        }
+        problems
+    }
+
+    fn reachability_problems(&self) -> Vec<String> {
+        let grid = TerrainGrid::new(&self.terrain, VALIDATION_CELL_SIZE);
+        let start = self
+            .bases
+            .first()
+            .and_then(|base| grid.cell_at(base.fountain.center))
+            .filter(|&cell| grid.is_walkable(cell));
+        let Some(start) = start else {
+            return vec!["The first base's fountain is not on walkable terrain".into()];
+        };
+
+        let reached = grid.flood_fill(start);
+        let reachable = |point: Vec2| grid.nearest_walkable(point).is_some_and(|c| reached[c]);
+        let mut problems = Vec::new();
+
+        for base in &self.bases {
+            if !reachable(base.fountain.center) {
+                problems.push(format!("{}'s fountain can't be reached", base.side));
+            }
+        }
+        for structure in &self.structures {
+            if !reachable(structure.position) {
+                problems.push(format!(
+                    "{}'s {:?} at {} can't be reached",
+                    structure.side, structure.kind, structure.position
+                ));
+            }
+        }
+        for camp in &self.jungle_camps {
+            if !reachable(camp.position) {
+                problems.push(format!("Jungle camp {} can't be reached", camp.name));
+            }
+        }
+        for lane in &self.lanes {
+            let blocked = lane.waypoints.windows(2).find_map(|leg| {
+                let steps = (leg[0].distance(leg[1]) / (VALIDATION_CELL_SIZE / 2.0)).ceil();
+                (0..=steps as usize)
+                    .map(|i| leg[0].lerp(leg[1], i as f32 / steps.max(1.0)))
+                    .find(|&point| !self.terrain.is_walkable(point))
+            });
+            if let Some(point) = blocked {
+                problems.push(format!("Lane {} is blocked at {point}", lane.name));
+            }
+        }
+
+        // Walkable terrain nobody can get to is almost certainly a mistake.
+        let mut seen = reached;
+        for cell in 0..grid.walkable.len() {
+            if grid.walkable[cell] && !seen[cell] {
+                let area = grid.flood_fill(cell);
+                let size = area.iter().filter(|&&reached| reached).count();
+                problems.push(format!(
+                    "{size} cells of walkable terrain around {} can't be reached",
+                    grid.center(cell)
+                ));
+                for (seen, reached) in seen.iter_mut().zip(area) {
+                    *seen |= reached;
+                }
+            }
+        }
+        problems
+    }
+}
+
+/// Terrain sampled on a grid of square cells, each walkable or not depending on its
+/// center.
+#[derive(Debug, Clone)]
+pub struct TerrainGrid {
+    pub origin: Vec2,
+    pub cell_size: f32,
+    pub width: usize,
+    pub height: usize,
+    /// Row by row, starting from `origin`.
+    pub walkable: Vec<bool>,
+}
+
+impl TerrainGrid {
+    /// Covers every walkable area.
+    pub fn new(terrain: &Terrain, cell_size: f32) -> Self {
+        let (min, max) = terrain
+            .walkable
+            .iter()
+            .map(Polygon::bounds)
+            .fold((Vec2::ZERO, Vec2::ZERO), |(min, max), (lo, hi)| {
+                (min.min(lo), max.max(hi))
+            });
+        let cells = ((max - min) / cell_size).ceil();
+        let (width, height) = (cells.x as usize, cells.y as usize);
+
+        let mut grid = Self {
+            origin: min,
+            cell_size,
+            width,
+            height,
+            walkable: Vec::new(),
+        };
+        grid.walkable = (0..width * height)
+            .map(|cell| terrain.is_walkable(grid.center(cell)))
+            .collect();
+        grid
+    }
+
+    pub fn cell_at(&self, point: Vec2) -> Option<usize> {
+        let offset = ((point - self.origin) / self.cell_size).floor();
+        let (x, y) = (offset.x as usize, offset.y as usize);
+        (offset.min_element() >= 0.0 && x < self.width && y < self.height)
+            .then_some(y * self.width + x)
+    }
+
+    pub fn center(&self, cell: usize) -> Vec2 {
+        let (x, y) = (cell % self.width, cell / self.width);
+        self.origin + (Vec2::new(x as f32, y as f32) + 0.5) * self.cell_size
+    }
+
+    pub fn is_walkable(&self, cell: usize) -> bool {
+        self.walkable[cell]
+    }
+
+    /// The cells sharing an edge with `cell`.
+    pub fn neighbours(&self, cell: usize) -> impl Iterator<Item = usize> + '_ {
+        let (x, y) = (cell % self.width, cell / self.width);
+        [
+            (x > 0).then(|| cell - 1),
+            (x + 1 < self.width).then_some(cell + 1),
+            (y > 0).then(|| cell - self.width),
+            (y + 1 < self.height).then_some(cell + self.width),
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    /// The walkable cell containing `point`, or the closest one next to it. Things
+    /// like structures sit on cells that are otherwise blocked.
+    pub fn nearest_walkable(&self, point: Vec2) -> Option<usize> {
+        let cell = self.cell_at(point)?;
+        if self.walkable[cell] {
+            return Some(cell);
+        }
+        self.neighbours(cell).find(|&n| self.walkable[n])
+    }
+
+    /// Every walkable cell that can be reached from `start`.
+    fn flood_fill(&self, start: usize) -> Vec<bool> {
+        let mut reached = vec![false; self.walkable.len()];
+        reached[start] = true;
+        let mut queue = VecDeque::from([start]);
+        while let Some(cell) = queue.pop_front() {
+            for next in self.neighbours(cell) {
+                if self.walkable[next] && !reached[next] {
+                    reached[next] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+        reached
+    }
+}
@@ -0,0 +1,58 @@
+use std::net::{IpAddr, SocketAddr};
+
+use serde::{Deserialize, Serialize};
+
+use super::lobby::{LobbyId, PlayerId};
+
+/// Where the lobby server listens for admin connections unless configured otherwise.
+pub const DEFAULT_ADMIN_SOCKET: &str = "/tmp/lobby-server-admin.sock";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum AdminRequest {
+    ListPlayers,
+    ListLobbies,
+    Kick {
+        player: PlayerId,
+        reason: String,
+    },
+    /// Kicks the player and refuses further connections from their address.
+    Ban {
+        player: PlayerId,
+        reason: String,
+    },
+    Unban {
+        addr: IpAddr,
+    },
+    CloseLobby {
+        lobby: LobbyId,
+    },
+    Announce {
+        msg: String,
+    },
+    Shutdown {
+        reason: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum AdminResponse {
+    OK,
+    Error { msg: String },
+    Players { players: Vec<AdminPlayerInfo> },
+    Lobbies { lobbies: Vec<AdminLobbyInfo> },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminPlayerInfo {
+    pub id: PlayerId,
+    pub username: String,
+    pub addr: SocketAddr,
+    pub in_lobby: Option<LobbyId>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminLobbyInfo {
+    pub id: LobbyId,
+    pub owner: PlayerId,
+    pub players: usize,
+}
@@ -0,0 +1,53 @@
+//! How the lobby server hands matches to game servers. A game server connects to the
+//! lobby server, says it is ready, hosts at most one match, and reports back until the
+//! match is over.
+
+use std::{net::SocketAddr, time::Duration};
+
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::Side;
+
+use super::{
+    game::{JoinToken, MatchSetup},
+    lobby::{LobbyId, PlayerId},
+};
+
+/// Where the lobby server listens for game servers unless configured otherwise.
+pub const DEFAULT_ALLOCATION_ADDR: &str = "127.0.0.1:65434";
+
+/// How often a game server hosting a match sends [`GameServerReport::Health`].
+pub const HEALTH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// From the lobby server to a game server.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum AllocationRequest {
+    /// Only players with one of `tokens` may join.
+    HostMatch {
+        lobby: LobbyId,
+        setup: MatchSetup,
+        tokens: HashMap<PlayerId, JoinToken>,
+    },
+}
+
+/// From a game server to the lobby server.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum GameServerReport {
+    /// The first message on the connection. Clients can reach the game server at `addr`.
+    Ready {
+        addr: SocketAddr,
+    },
+    /// The game server took the match and is waiting for players.
+    Hosting {
+        lobby: LobbyId,
+    },
+    Health {
+        connected_players: usize,
+        tick: u64,
+    },
+    /// The game server closes the connection after sending this.
+    Finished {
+        winner: Option<Side>,
+    },
+}
@@ -1 +1,88 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
+pub mod snapshot;
+pub mod transport;
+
+use self::snapshot::SnapshotDelta;
+use crate::{
+    champion::Role,
+    gameplay::{AppliedInput, ChampionCommand},
+    map::MapDefinition,
+    network::lobby::{BotDifficulty, PlayerId},
+    rules::GameRules,
+    Side,
+};
+
+/// Everything a game server needs to know to host a lobby's match.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchSetup {
+    pub map: String,
+    /// How many sides there are, including any without participants.
+    pub teams: usize,
+    pub rules: GameRules,
+    pub participants: Vec<MatchParticipant>,
+}
+
+/// Someone taking part in a match, as the lobby server hands them to a game server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchParticipant {
+    pub id: PlayerId,
+    pub username: String,
+    pub side: Side,
+    pub champion: Option<String>,
+    pub role: Option<Role>,
+    pub controller: Controller,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Controller {
+    /// A connected client plays this participant.
+    Human,
+    /// The game server plays this participant.
+    Bot { difficulty: BotDifficulty },
+}
+
+/// Proves a client is the player it claims to be. The lobby server makes one for each
+/// player when a match starts, and gives it to both the player and the game server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct JoinToken(pub Uuid);
+
+/// Sent to open a connection to a game server; see [`transport`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameClientHello {
+    pub player: PlayerId,
+    pub token: JoinToken,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum GameClientMessage {
+    /// Sent reliably. `sequence` counts up from 0, so the server can say which commands
+    /// it has applied. `seen_tick` is the tick of the world the client was showing, which
+    /// casts are checked against.
+    Command {
+        sequence: u32,
+        seen_tick: u64,
+        command: ChampionCommand,
+    },
+    /// The client has the snapshot for `tick`, so later ones can be sent relative to it.
+    AckSnapshot { tick: u64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum GameServerMessage {
+    /// Sent when the server accepts a connection. `tick` is the simulation's current tick.
+    Welcome {
+        setup: MatchSetup,
+        map: Box<MapDefinition>,
+        tick: u64,
+    },
+    /// The replicated world as of a tick. Sent unreliably every tick; see [`snapshot`].
+    /// `input` is the newest of the recipient's commands the world reflects.
+    Snapshot {
+        delta: SnapshotDelta,
+        input: Option<AppliedInput>,
+    },
+    /// The server closes the connection once this has been acknowledged.
+    MatchEnded { winner: Option<Side> },
+}
@@ -0,0 +1,179 @@
+//! Per-tick snapshots of the replicated game world, and the deltas between them that are
+//! actually sent.
+//!
+//! The game server sends each client the latest snapshot as a delta against the last
+//! snapshot that client acknowledged, or in full if it has acknowledged none it still
+//! remembers. Only components that differ from the baseline are included.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use anyhow::{ensure, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::replication::NetworkId;
+
+/// How many recent snapshots the server keeps as baselines, and clients keep to resolve
+/// deltas against. About two seconds' worth.
+pub const SNAPSHOT_HISTORY: usize = 64;
+
+/// The replicated state of every networked entity on one tick.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub tick: u64,
+    pub entities: BTreeMap<NetworkId, EntityState>,
+}
+
+/// An entity's replicated components, encoded, in the order of the
+/// [`ReplicationRegistry`](crate::replication::ReplicationRegistry). `None` for components
+/// the entity doesn't have, which may also be left off the end.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EntityState {
+    pub components: Vec<Option<Vec<u8>>>,
+}
+
+impl EntityState {
+    pub fn component(&self, index: usize) -> Option<&[u8]> {
+        self.components.get(index)?.as_deref()
+    }
+}
+
+impl PartialEq for EntityState {
+    fn eq(&self, other: &Self) -> bool {
+        let count = self.components.len().max(other.components.len());
+        (0..count).all(|i| self.component(i) == other.component(i))
+    }
+}
+
+/// A snapshot, as the changes from an earlier one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotDelta {
+    pub tick: u64,
+    /// The tick of the snapshot this is relative to. `None` if it is relative to nothing,
+    /// so it holds the whole snapshot.
+    pub baseline: Option<u64>,
+    /// Entities that are new or have changed.
+    pub changed: Vec<EntityDelta>,
+    pub removed: Vec<NetworkId>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityDelta {
+    pub id: NetworkId,
+    /// Components that changed, by registry index, with their new value or `None` if they
+    /// were removed.
+    pub components: Vec<(u8, Option<Vec<u8>>)>,
+}
+
+impl Snapshot {
+    /// What has to be sent for someone who has `baseline` to end up with this snapshot.
+    pub fn delta_from(&self, baseline: Option<&Snapshot>) -> SnapshotDelta {
+        let empty = BTreeMap::new();
+        let base = baseline.map_or(&empty, |b| &b.entities);
+
+        let changed = self
+            .entities
+            .iter()
+            .filter_map(|(&id, state)| {
+                let before = base.get(&id);
+                let count = state
+                    .components
+                    .len()
+                    .max(before.map_or(0, |b| b.components.len()));
+                let components = (0..count)
+                    .filter(|&i| state.component(i) != before.and_then(|b| b.component(i)))
+                    .map(|i| (i as u8, state.component(i).map(<[u8]>::to_vec)))
+                    .collect::<Vec<_>>();
+                (!components.is_empty() || before.is_none())
+                    .then_some(EntityDelta { id, components })
+            })
+            .collect();
+        let removed = base
+            .keys()
+            .filter(|id| !self.entities.contains_key(id))
+            .copied()
+            .collect();
+
+        SnapshotDelta {
+            tick: self.tick,
+            baseline: baseline.map(|b| b.tick),
+            changed,
+            removed,
+        }
+    }
+}
+
+impl SnapshotDelta {
+    /// Rebuilds the snapshot from the one it is relative to.
+    pub fn apply(self, baseline: Option<&Snapshot>) -> anyhow::Result<Snapshot> {
+        ensure!(
+            baseline.map(|b| b.tick) == self.baseline,
+            "Delta for tick {} needs baseline {:?}",
+            self.tick,
+            self.baseline
+        );
+
+        let mut entities = baseline.map(|b| b.entities.clone()).unwrap_or_default();
+        for id in self.removed {
+            entities.remove(&id);
+        }
+        for delta in self.changed {
+            let state = entities.entry(delta.id).or_default();
+            for (index, value) in delta.components {
+                let index = usize::from(index);
+                if state.components.len() <= index {
+                    state.components.resize(index + 1, None);
+                }
+                state.components[index] = value;
+            }
+        }
+
+        Ok(Snapshot {
+            tick: self.tick,
+            entities,
+        })
+    }
+}
+
+/// The most recent snapshots, oldest first.
+#[derive(Debug, Default)]
+pub struct SnapshotHistory {
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl SnapshotHistory {
+    pub fn push(&mut self, snapshot: Snapshot) {
+        if self.snapshots.len() == SNAPSHOT_HISTORY {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    pub fn get(&self, tick: u64) -> Option<&Snapshot> {
+        self.snapshots.iter().rev().find(|s| s.tick == tick)
+    }
+
+    pub fn latest(&self) -> Option<&Snapshot> {
+        self.snapshots.back()
+    }
+
+    /// Rebuilds the snapshot `delta` describes and remembers it. Fails if the baseline
+    /// has already been forgotten, or if `delta` is older than the latest snapshot.
+    pub fn receive(&mut self, delta: SnapshotDelta) -> anyhow::Result<&Snapshot> {
+        ensure!(
+            self.latest().is_none_or(|latest| delta.tick > latest.tick),
+            "Snapshot for tick {} is out of date",
+            delta.tick
+        );
+        let baseline = match delta.baseline {
+            Some(tick) => Some(
+                self.get(tick)
+                    .with_context(|| format!("No snapshot for tick {tick}"))?,
+            ),
+            None => None,
+        };
+
+        let snapshot = delta.apply(baseline)?;
+        self.push(snapshot);
+        Ok(self.latest().unwrap())
+    }
+}
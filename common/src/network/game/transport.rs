@@ -0,0 +1,862 @@
+//! A connection-oriented transport over UDP, for talking to game servers.
+//!
+//! Every packet carries a sequence number and acknowledges the packets most recently
+//! received from the other side. Two channels are built on that:
+//!
+//! - [`Channel::Unreliable`] messages are sent once, and any that arrive after a newer
+//!   one are dropped. Meant for snapshots and inputs, which are stale by the time they
+//!   could be resent.
+//! - [`Channel::Reliable`] messages are resent until acknowledged, and delivered exactly
+//!   once and in order. Meant for events.
+//!
+//! Messages too big for one packet are split into fragments. A client opens a connection
+//! with its [`GameClientHello`], so the server can check the join token before accepting
+//! it, and either side gives up on the other after hearing nothing for [`TIMEOUT`].
+//!
+//! [`Connection`] does the bookkeeping for one side of a connection without touching the
+//! network; [`ServerSocket`] and [`ClientSocket`] run connections over a UDP socket.
+
+use std::{
+    collections::VecDeque,
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, ensure};
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+
+use super::GameClientHello;
+
+/// Bumped whenever the packet format changes, so mismatched clients are turned away.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Packets are kept to this size, which fits the minimum IPv6 MTU with room for the IP
+/// and UDP headers.
+pub const MAX_PACKET_SIZE: usize = 1200;
+
+/// How much of a message goes in each fragment, leaving room for headers.
+const FRAGMENT_SIZE: usize = 1024;
+
+/// A message can be split into at most this many fragments.
+const MAX_FRAGMENTS: usize = 256;
+
+pub const MAX_MESSAGE_SIZE: usize = FRAGMENT_SIZE * MAX_FRAGMENTS;
+
+/// A connection is dropped after hearing nothing from the other side for this long.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A connection with nothing to send still sends an empty packet this often, to carry
+/// acks and show that it is alive.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often a client repeats its connection request until it is answered.
+const CONNECT_RETRY_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Reliable fragments are never resent sooner than this, however low the round trip time.
+const MIN_RESEND_DELAY: Duration = Duration::from_millis(50);
+
+/// At most this many reliable fragments, counting from the oldest unacknowledged one, are
+/// sent at a time. This keeps fragment ids unambiguous despite wrapping around.
+const RELIABLE_WINDOW: u16 = 1024;
+
+/// How many packets before the newest received one are acknowledged along with it.
+const ACK_BITS: u16 = 32;
+
+/// Worst-case encoded size of a packet's header, and of a frame's besides its data.
+const PACKET_OVERHEAD: usize = 16;
+const FRAME_OVERHEAD: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Channel {
+    /// Sent once. Messages older than the newest one received are dropped.
+    Unreliable,
+    /// Resent until acknowledged, and delivered exactly once, in order.
+    Reliable,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Packet {
+    /// From a client, repeated until answered.
+    Connect {
+        protocol: u32,
+        hello: GameClientHello,
+    },
+    Accepted,
+    Refused {
+        reason: String,
+    },
+    Data {
+        sequence: u16,
+        /// The newest packet received from the other side, if any...
+        ack: Option<u16>,
+        /// ...and which of the [`ACK_BITS`] before it were received too, as bit `n` for
+        /// `ack - n - 1`.
+        ack_bits: u32,
+        frames: Vec<Frame>,
+    },
+    /// Best effort; the other side times out if this is lost.
+    Disconnect,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum Frame {
+    Unreliable {
+        message: u16,
+        fragment: Fragment,
+        data: Vec<u8>,
+    },
+    Reliable {
+        id: u16,
+        fragment: Fragment,
+        data: Vec<u8>,
+    },
+}
+
+impl Frame {
+    fn encoded_size(&self) -> usize {
+        match self {
+            Frame::Unreliable { data, .. } | Frame::Reliable { data, .. } => {
+                data.len() + FRAME_OVERHEAD
+            }
+        }
+    }
+}
+
+/// Which part of a message a frame holds. `last` is the index of the final fragment, so
+/// that [`MAX_FRAGMENTS`] fit in a byte.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Fragment {
+    index: u8,
+    last: u8,
+}
+
+/// Whether sequence number `a` comes after `b`, allowing for wrap-around.
+fn is_newer(a: u16, b: u16) -> bool {
+    a != b && a.wrapping_sub(b) < 0x8000
+}
+
+struct SentPacket {
+    sequence: u16,
+    sent_at: Instant,
+    /// Ids of the reliable fragments it carried.
+    reliable: Vec<u16>,
+}
+
+struct ReliableFragment {
+    id: u16,
+    fragment: Fragment,
+    data: Vec<u8>,
+    sent_at: Option<Instant>,
+    acked: bool,
+}
+
+/// An unreliable message of which only some fragments have arrived.
+struct PartialMessage {
+    message: u16,
+    fragments: Vec<Option<Vec<u8>>>,
+    missing: usize,
+}
+
+/// One side of a connection: sequencing, acks, both channels and fragmentation, but no
+/// IO. Feed it packets from the other side with [`Connection::receive`], and send what
+/// [`Connection::transmit`] returns.
+pub struct Connection {
+    next_sequence: u16,
+    /// The newest packet received, and which of the [`ACK_BITS`] before it were received
+    /// too. Sent back as acks.
+    remote_sequence: Option<u16>,
+    received_bits: u32,
+    /// Packets sent but not yet acknowledged, oldest first.
+    in_flight: VecDeque<SentPacket>,
+    rtt: Duration,
+    last_sent: Option<Instant>,
+    last_received: Instant,
+    closed: bool,
+
+    unreliable_out: Vec<Frame>,
+    next_unreliable: u16,
+    /// The newest unreliable message delivered; older ones are dropped.
+    newest_unreliable: Option<u16>,
+    unreliable_partial: Option<PartialMessage>,
+
+    /// Reliable fragments from the oldest unacknowledged one onwards, in id order.
+    reliable_out: VecDeque<ReliableFragment>,
+    next_reliable: u16,
+    /// The reliable fragment to deliver next. Later ones wait in `reliable_in`.
+    next_expected: u16,
+    reliable_in: HashMap<u16, (Fragment, Vec<u8>)>,
+    reliable_partial: Vec<u8>,
+    /// The fragment last added to `reliable_partial`, while a message is half assembled.
+    reliable_fragment: Option<Fragment>,
+
+    received: VecDeque<(Channel, Vec<u8>)>,
+}
+
+impl Connection {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            next_sequence: 0,
+            remote_sequence: None,
+            received_bits: 0,
+            in_flight: VecDeque::new(),
+            rtt: Duration::from_millis(100),
+            last_sent: None,
+            last_received: now,
+            closed: false,
+            unreliable_out: Vec::new(),
+            next_unreliable: 0,
+            newest_unreliable: None,
+            unreliable_partial: None,
+            reliable_out: VecDeque::new(),
+            next_reliable: 0,
+            next_expected: 0,
+            reliable_in: HashMap::new(),
+            reliable_partial: Vec::new(),
+            reliable_fragment: None,
+            received: VecDeque::new(),
+        }
+    }
+
+    /// Queues a message for the next [`Connection::transmit`].
+    pub fn send(&mut self, channel: Channel, message: &[u8]) -> anyhow::Result<()> {
+        ensure!(
+            message.len() <= MAX_MESSAGE_SIZE,
+            "Message is too large: {} bytes",
+            message.len()
+        );
+
+        // An empty message still takes a fragment.
+        let chunks = message.chunks(FRAGMENT_SIZE).collect::<Vec<_>>();
+        let chunks = if chunks.is_empty() {
+            vec![&[][..]]
+        } else {
+            chunks
+        };
+        let last = (chunks.len() - 1) as u8;
+
+        for (index, data) in chunks.into_iter().enumerate() {
+            let fragment = Fragment {
+                index: index as u8,
+                last,
+            };
+            match channel {
+                Channel::Unreliable => self.unreliable_out.push(Frame::Unreliable {
+                    message: self.next_unreliable,
+                    fragment,
+                    data: data.to_vec(),
+                }),
+                Channel::Reliable => {
+                    self.reliable_out.push_back(ReliableFragment {
+                        id: self.next_reliable,
+                        fragment,
+                        data: data.to_vec(),
+                        sent_at: None,
+                        acked: false,
+                    });
+                    self.next_reliable = self.next_reliable.wrapping_add(1);
+                }
+            }
+        }
+        if channel == Channel::Unreliable {
+            self.next_unreliable = self.next_unreliable.wrapping_add(1);
+        }
+        Ok(())
+    }
+
+    /// The next message received, if any.
+    pub fn poll_message(&mut self) -> Option<(Channel, Vec<u8>)> {
+        self.received.pop_front()
+    }
+
+    /// Handles a packet from the other side.
+    pub fn receive(&mut self, packet: &[u8], now: Instant) -> anyhow::Result<()> {
+        self.handle(postcard::from_bytes(packet)?, now)
+    }
+
+    fn handle(&mut self, packet: Packet, now: Instant) -> anyhow::Result<()> {
+        match packet {
+            Packet::Data {
+                sequence,
+                ack,
+                ack_bits,
+                frames,
+            } => {
+                self.record_received(sequence);
+                if let Some(ack) = ack {
+                    self.process_acks(ack, ack_bits, now);
+                }
+                for frame in frames {
+                    match frame {
+                        Frame::Unreliable {
+                            message,
+                            fragment,
+                            data,
+                        } => self.receive_unreliable(message, fragment, data),
+                        Frame::Reliable { id, fragment, data } => {
+                            self.receive_reliable(id, fragment, data)
+                        }
+                    }
+                }
+            }
+            Packet::Disconnect => self.closed = true,
+            Packet::Connect { .. } | Packet::Accepted | Packet::Refused { .. } => {
+                bail!("Unexpected handshake packet on an open connection")
+            }
+        }
+        self.last_received = now;
+        Ok(())
+    }
+
+    /// Packets to send now: queued messages, reliable fragments due to be resent, or a
+    /// keepalive if nothing has been sent in a while.
+    pub fn transmit(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        let resend_delay = (self.rtt * 3 / 2).max(MIN_RESEND_DELAY);
+        let window_start = self.reliable_out.front().map_or(0, |f| f.id);
+
+        let mut frames = Vec::new();
+        for fragment in &mut self.reliable_out {
+            if fragment.id.wrapping_sub(window_start) >= RELIABLE_WINDOW {
+                break;
+            }
+            if fragment.acked || fragment.sent_at.is_some_and(|at| now - at < resend_delay) {
+                continue;
+            }
+            fragment.sent_at = Some(now);
+            let frame = Frame::Reliable {
+                id: fragment.id,
+                fragment: fragment.fragment,
+                data: fragment.data.clone(),
+            };
+            frames.push((frame, Some(fragment.id)));
+        }
+        frames.extend(self.unreliable_out.drain(..).map(|frame| (frame, None)));
+
+        let mut packets = Vec::new();
+        let mut current = Vec::new();
+        let mut reliable = Vec::new();
+        let mut size = PACKET_OVERHEAD;
+        for (frame, id) in frames {
+            if size + frame.encoded_size() > MAX_PACKET_SIZE {
+                let full = std::mem::take(&mut current);
+                packets.push(self.finish_packet(full, std::mem::take(&mut reliable), now));
+                size = PACKET_OVERHEAD;
+            }
+            size += frame.encoded_size();
+            current.push(frame);
+            reliable.extend(id);
+        }
+
+        let keepalive_due = self
+            .last_sent
+            .is_none_or(|at| now - at >= KEEPALIVE_INTERVAL);
+        if !current.is_empty() || (packets.is_empty() && keepalive_due) {
+            packets.push(self.finish_packet(current, reliable, now));
+        }
+        packets
+    }
+
+    fn finish_packet(&mut self, frames: Vec<Frame>, reliable: Vec<u16>, now: Instant) -> Vec<u8> {
+        let sequence = self.next_sequence;
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+
+        // Packets this far behind can no longer be acknowledged.
+        while self
+            .in_flight
+            .front()
+            .is_some_and(|p| sequence.wrapping_sub(p.sequence) > ACK_BITS)
+        {
+            self.in_flight.pop_front();
+        }
+        self.in_flight.push_back(SentPacket {
+            sequence,
+            sent_at: now,
+            reliable,
+        });
+        self.last_sent = Some(now);
+
+        let packet = Packet::Data {
+            sequence,
+            ack: self.remote_sequence,
+            ack_bits: self.received_bits,
+            frames,
+        };
+        postcard::to_allocvec(&packet).expect("Packets can always be encoded")
+    }
+
+    fn record_received(&mut self, sequence: u16) {
+        match self.remote_sequence {
+            None => self.remote_sequence = Some(sequence),
+            Some(newest) if is_newer(sequence, newest) => {
+                let shift = u32::from(sequence.wrapping_sub(newest));
+                self.received_bits = self.received_bits.checked_shl(shift).unwrap_or(0)
+                    | 1u32.checked_shl(shift - 1).unwrap_or(0);
+                self.remote_sequence = Some(sequence);
+            }
+            Some(newest) => {
+                let behind = newest.wrapping_sub(sequence);
+                if (1..=ACK_BITS).contains(&behind) {
+                    self.received_bits |= 1 << (behind - 1);
+                }
+            }
+        }
+    }
+
+    fn process_acks(&mut self, ack: u16, ack_bits: u32, now: Instant) {
+        let is_acked = |sequence: u16| {
+            let behind = ack.wrapping_sub(sequence);
+            behind == 0 || (1..=ACK_BITS).contains(&behind) && ack_bits & (1 << (behind - 1)) != 0
+        };
+
+        let (acked, unacked) = std::mem::take(&mut self.in_flight)
+            .into_iter()
+            .partition::<VecDeque<_>, _>(|p| is_acked(p.sequence));
+        self.in_flight = unacked;
+
+        for packet in acked {
+            self.rtt = (self.rtt * 7 + (now - packet.sent_at)) / 8;
+            for id in packet.reliable {
+                self.ack_reliable(id);
+            }
+        }
+        while self.reliable_out.front().is_some_and(|f| f.acked) {
+            self.reliable_out.pop_front();
+        }
+    }
+
+    fn ack_reliable(&mut self, id: u16) {
+        let Some(front) = self.reliable_out.front() else {
+            return;
+        };
+        let index = id.wrapping_sub(front.id) as usize;
+        if let Some(fragment) = self.reliable_out.get_mut(index).filter(|f| f.id == id) {
+            fragment.acked = true;
+        }
+    }
+
+    fn receive_unreliable(&mut self, message: u16, fragment: Fragment, data: Vec<u8>) {
+        if self
+            .newest_unreliable
+            .is_some_and(|newest| !is_newer(message, newest))
+        {
+            return;
+        }
+        if fragment.last == 0 {
+            self.deliver_unreliable(message, data);
+            return;
+        }
+
+        let fragments = usize::from(fragment.last) + 1;
+        let partial = match &mut self.unreliable_partial {
+            Some(partial) if partial.message == message => partial,
+            Some(partial) if is_newer(partial.message, message) => return,
+            slot => slot.insert(PartialMessage {
+                message,
+                fragments: vec![None; fragments],
+                missing: fragments,
+            }),
+        };
+        if partial.fragments.len() != fragments {
+            return;
+        }
+
+        let Some(slot) = partial.fragments.get_mut(usize::from(fragment.index)) else {
+            return;
+        };
+        if slot.is_none() {
+            *slot = Some(data);
+            partial.missing -= 1;
+        }
+        if partial.missing == 0 {
+            let partial = self.unreliable_partial.take().unwrap();
+            let data = partial.fragments.into_iter().flatten().flatten().collect();
+            self.deliver_unreliable(message, data);
+        }
+    }
+
+    fn deliver_unreliable(&mut self, message: u16, data: Vec<u8>) {
+        self.newest_unreliable = Some(message);
+        if self
+            .unreliable_partial
+            .as_ref()
+            .is_some_and(|partial| !is_newer(partial.message, message))
+        {
+            self.unreliable_partial = None;
+        }
+        self.received.push_back((Channel::Unreliable, data));
+    }
+
+    fn receive_reliable(&mut self, id: u16, fragment: Fragment, data: Vec<u8>) {
+        // Anything outside the window was delivered already, and this is a resend.
+        if id.wrapping_sub(self.next_expected) >= RELIABLE_WINDOW {
+            return;
+        }
+        self.reliable_in.insert(id, (fragment, data));
+
+        while let Some((fragment, data)) = self.reliable_in.remove(&self.next_expected) {
+            self.next_expected = self.next_expected.wrapping_add(1);
+            // Fragments arrive in order, so one that doesn't continue the message being
+            // assembled means the other side is broken; what we have so far is dropped.
+            let follows = self.reliable_fragment.is_some_and(|previous| {
+                fragment.index == previous.index + 1 && fragment.last == previous.last
+            });
+            if fragment.index == 0 {
+                self.reliable_partial.clear();
+            } else if !follows {
+                self.reliable_partial.clear();
+                self.reliable_fragment = None;
+                continue;
+            }
+            // More than a well-behaved sender could have split up.
+            if self.reliable_partial.len() + data.len() > MAX_MESSAGE_SIZE {
+                self.closed = true;
+                self.reliable_partial.clear();
+                self.reliable_fragment = None;
+                return;
+            }
+            self.reliable_partial.extend(data);
+            if fragment.index == fragment.last {
+                let message = std::mem::take(&mut self.reliable_partial);
+                self.received.push_back((Channel::Reliable, message));
+                self.reliable_fragment = None;
+            } else {
+                self.reliable_fragment = Some(fragment);
+            }
+        }
+    }
+
+    /// Whether every reliable message sent so far has been acknowledged.
+    pub fn is_idle(&self) -> bool {
+        self.reliable_out.is_empty()
+    }
+
+    /// Whether the other side said it was disconnecting, or sent a reliable message too
+    /// large to assemble.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn is_timed_out(&self, now: Instant) -> bool {
+        now - self.last_received >= TIMEOUT
+    }
+
+    /// A smoothed estimate of the round trip time.
+    pub fn rtt(&self) -> Duration {
+        self.rtt
+    }
+}
+
+fn encode(packet: &Packet) -> Vec<u8> {
+    postcard::to_allocvec(packet).expect("Packets can always be encoded")
+}
+
+/// Reads every packet waiting on a non-blocking socket.
+fn receive_packets(socket: &UdpSocket) -> Vec<(SocketAddr, Packet)> {
+    let mut packets = Vec::new();
+    let mut buffer = [0; MAX_PACKET_SIZE];
+    // Stops at `WouldBlock`, or at an ICMP error about an earlier packet, which timeouts
+    // take care of anyway.
+    while let Ok((len, addr)) = socket.recv_from(&mut buffer) {
+        if let Ok(packet) = postcard::from_bytes(&buffer[..len]) {
+            packets.push((addr, packet));
+        }
+    }
+    packets
+}
+
+/// What happened on a [`ServerSocket`] since it was last checked.
+#[derive(Debug)]
+pub enum ServerEvent {
+    /// Someone asked to connect. They hear nothing back until the request is answered
+    /// with [`ServerSocket::accept`] or [`ServerSocket::refuse`].
+    ConnectRequest {
+        addr: SocketAddr,
+        hello: GameClientHello,
+    },
+    Message {
+        addr: SocketAddr,
+        channel: Channel,
+        message: Vec<u8>,
+    },
+    /// An accepted client disconnected or timed out.
+    Disconnected { addr: SocketAddr },
+}
+
+struct Peer {
+    connection: Connection,
+    accepted: bool,
+}
+
+/// Connections from any number of clients, on one non-blocking socket.
+pub struct ServerSocket {
+    socket: UdpSocket,
+    peers: HashMap<SocketAddr, Peer>,
+}
+
+impl ServerSocket {
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        Self::new(UdpSocket::bind(addr)?)
+    }
+
+    pub fn new(socket: UdpSocket) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            peers: HashMap::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Handles every packet that has arrived, and drops connections that timed out.
+    pub fn receive(&mut self, now: Instant) -> Vec<ServerEvent> {
+        let mut events = Vec::new();
+        for (addr, packet) in receive_packets(&self.socket) {
+            match packet {
+                Packet::Connect { protocol, hello } => match self.peers.get(&addr) {
+                    // Our answer was lost.
+                    Some(peer) if peer.accepted => self.send_packet(addr, &Packet::Accepted),
+                    Some(_) => {}
+                    None if protocol != PROTOCOL_VERSION => {
+                        let reason = format!(
+                            "The server speaks protocol version {PROTOCOL_VERSION}, not {protocol}"
+                        );
+                        self.send_packet(addr, &Packet::Refused { reason });
+                    }
+                    None => {
+                        let peer = Peer {
+                            connection: Connection::new(now),
+                            accepted: false,
+                        };
+                        self.peers.insert(addr, peer);
+                        events.push(ServerEvent::ConnectRequest { addr, hello });
+                    }
+                },
+                Packet::Accepted | Packet::Refused { .. } => {}
+                packet => {
+                    if let Some(peer) = self.peers.get_mut(&addr).filter(|peer| peer.accepted) {
+                        let _ = peer.connection.handle(packet, now);
+                    }
+                }
+            }
+        }
+
+        self.peers.retain(|&addr, peer| {
+            while let Some((channel, message)) = peer.connection.poll_message() {
+                events.push(ServerEvent::Message {
+                    addr,
+                    channel,
+                    message,
+                });
+            }
+
+            let gone = peer.connection.is_closed() || peer.connection.is_timed_out(now);
+            if gone && peer.accepted {
+                events.push(ServerEvent::Disconnected { addr });
+            }
+            !gone
+        });
+        events
+    }
+
+    pub fn accept(&mut self, addr: SocketAddr) {
+        if let Some(peer) = self.peers.get_mut(&addr) {
+            peer.accepted = true;
+            self.send_packet(addr, &Packet::Accepted);
+        }
+    }
+
+    pub fn refuse(&mut self, addr: SocketAddr, reason: String) {
+        if self.peers.remove(&addr).is_some() {
+            self.send_packet(addr, &Packet::Refused { reason });
+        }
+    }
+
+    /// Queues a message for the next [`ServerSocket::flush`].
+    pub fn send(
+        &mut self,
+        addr: SocketAddr,
+        channel: Channel,
+        message: &[u8],
+    ) -> anyhow::Result<()> {
+        match self.peers.get_mut(&addr) {
+            Some(peer) if peer.accepted => peer.connection.send(channel, message),
+            _ => bail!("{addr} is not connected"),
+        }
+    }
+
+    /// Closes a connection without waiting for anything still unacknowledged.
+    pub fn disconnect(&mut self, addr: SocketAddr) {
+        if self.peers.remove(&addr).is_some() {
+            self.send_packet(addr, &Packet::Disconnect);
+        }
+    }
+
+    /// Sends whatever every connection has to send.
+    pub fn flush(&mut self, now: Instant) {
+        for (&addr, peer) in &mut self.peers {
+            if !peer.accepted {
+                continue;
+            }
+            for packet in peer.connection.transmit(now) {
+                let _ = self.socket.send_to(&packet, addr);
+            }
+        }
+    }
+
+    /// Whether every client has acknowledged every reliable message sent to it.
+    pub fn is_idle(&self) -> bool {
+        self.peers.values().all(|peer| peer.connection.is_idle())
+    }
+
+    fn send_packet(&self, addr: SocketAddr, packet: &Packet) {
+        let _ = self.socket.send_to(&encode(packet), addr);
+    }
+}
+
+/// What happened on a [`ClientSocket`] since it was last checked.
+#[derive(Debug)]
+pub enum ClientEvent {
+    Connected,
+    /// The server turned the connection down. Nothing else happens afterwards.
+    Refused {
+        reason: String,
+    },
+    Message {
+        channel: Channel,
+        message: Vec<u8>,
+    },
+    /// The server disconnected, or could not be reached. Nothing else happens afterwards.
+    Disconnected,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClientState {
+    Connecting,
+    Connected,
+    Closed,
+}
+
+/// A connection to a game server.
+pub struct ClientSocket {
+    socket: UdpSocket,
+    hello: GameClientHello,
+    state: ClientState,
+    connection: Connection,
+    started: Instant,
+    last_attempt: Option<Instant>,
+}
+
+impl ClientSocket {
+    /// Starts connecting to `server`. Messages can be sent right away; they go out once
+    /// the server accepts.
+    pub fn connect(server: SocketAddr, hello: GameClientHello, now: Instant) -> io::Result<Self> {
+        let local = match server {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(server)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            socket,
+            hello,
+            state: ClientState::Connecting,
+            connection: Connection::new(now),
+            started: now,
+            last_attempt: None,
+        })
+    }
+
+    /// Handles every packet that has arrived, and notices if the server is gone.
+    pub fn receive(&mut self, now: Instant) -> Vec<ClientEvent> {
+        let mut events = Vec::new();
+        for (_, packet) in receive_packets(&self.socket) {
+            match (self.state, packet) {
+                (ClientState::Connecting, Packet::Accepted) => {
+                    self.state = ClientState::Connected;
+                    self.connection.last_received = now;
+                    events.push(ClientEvent::Connected);
+                }
+                (ClientState::Connecting, Packet::Refused { reason }) => {
+                    self.state = ClientState::Closed;
+                    events.push(ClientEvent::Refused { reason });
+                }
+                (ClientState::Connected, packet) => {
+                    let _ = self.connection.handle(packet, now);
+                }
+                _ => {}
+            }
+        }
+
+        match self.state {
+            ClientState::Connecting if now - self.started >= TIMEOUT => {
+                self.state = ClientState::Closed;
+                events.push(ClientEvent::Disconnected);
+            }
+            ClientState::Connected => {
+                while let Some((channel, message)) = self.connection.poll_message() {
+                    events.push(ClientEvent::Message { channel, message });
+                }
+                if self.connection.is_closed() || self.connection.is_timed_out(now) {
+                    self.state = ClientState::Closed;
+                    events.push(ClientEvent::Disconnected);
+                }
+            }
+            _ => {}
+        }
+        events
+    }
+
+    /// Queues a message for the next [`ClientSocket::flush`].
+    pub fn send(&mut self, channel: Channel, message: &[u8]) -> anyhow::Result<()> {
+        ensure!(self.state != ClientState::Closed, "Not connected");
+        self.connection.send(channel, message)
+    }
+
+    /// Sends whatever the connection has to send, or asks to connect again while
+    /// waiting for an answer.
+    pub fn flush(&mut self, now: Instant) {
+        match self.state {
+            ClientState::Connecting => {
+                if self
+                    .last_attempt
+                    .is_some_and(|at| now - at < CONNECT_RETRY_INTERVAL)
+                {
+                    return;
+                }
+                self.last_attempt = Some(now);
+                let connect = Packet::Connect {
+                    protocol: PROTOCOL_VERSION,
+                    hello: self.hello.clone(),
+                };
+                let _ = self.socket.send(&encode(&connect));
+            }
+            ClientState::Connected => {
+                for packet in self.connection.transmit(now) {
+                    let _ = self.socket.send(&packet);
+                }
+            }
+            ClientState::Closed => {}
+        }
+    }
+
+    pub fn disconnect(&mut self) {
+        if self.state == ClientState::Connected {
+            let _ = self.socket.send(&encode(&Packet::Disconnect));
+        }
+        self.state = ClientState::Closed;
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state == ClientState::Connected
+    }
+
+    pub fn rtt(&self) -> Duration {
+        self.connection.rtt()
+    }
+}
@@ -0,0 +1,32 @@
+//! Draft pick mode: the sides take turns banning champions, then take turns picking
+//! them, with a time limit on every turn.
+
+use serde::{Deserialize, Serialize};
+
+use crate::Side;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DraftAction {
+    Ban,
+    Pick,
+}
+
+/// Which side picks on each turn of a draft between `teams` teams of `team_size`. The
+/// order snakes back and forth: first to last, then last to first, and so on.
+///
+/// For two teams of five that is 1-2-2-2-2-1.
+pub fn pick_order(teams: usize, team_size: usize) -> impl Iterator<Item = Side> {
+    (0..teams * team_size).map(move |turn| {
+        let (round, index) = (turn / teams, turn % teams);
+        if round % 2 == 0 {
+            Side(index as u8)
+        } else {
+            Side((teams - 1 - index) as u8)
+        }
+    })
+}
+
+/// Which side bans on each turn of a draft; sides simply take turns in order.
+pub fn ban_order(teams: usize, bans_per_side: usize) -> impl Iterator<Item = Side> {
+    (0..teams * bans_per_side).map(move |turn| Side((turn % teams) as u8))
+}
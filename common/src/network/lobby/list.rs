@@ -0,0 +1,112 @@
+//! The lobby browser: what a lobby is created with, and how clients filter and sort
+//! the lobbies they are shown.
+
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+use crate::rules::GameRules;
+
+use super::ShortLobbyInfo;
+
+/// Most players a single lobby can hold.
+pub const MAX_LOBBY_PLAYERS: usize = 10;
+
+/// Most sides a lobby can have: enough for a free-for-all in a full lobby.
+pub const MAX_TEAMS: usize = MAX_LOBBY_PLAYERS;
+
+/// Longest lobby name accepted, in characters.
+pub const MAX_LOBBY_NAME_LENGTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GameMode {
+    #[default]
+    Classic,
+    Practice,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LobbySettings {
+    pub name: String,
+    pub mode: GameMode,
+    pub map: String,
+    pub max_players: usize,
+    /// How many sides players are split into. A free-for-all has as many as
+    /// `max_players`.
+    pub teams: usize,
+    /// Players have to give this to join, if set.
+    pub password: Option<String>,
+    pub pick_mode: PickMode,
+    /// The owner can change these after creating the lobby.
+    pub rules: GameRules,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PickMode {
+    /// Everyone picks whenever they like, and the same champion can be picked twice.
+    #[default]
+    FreePick,
+    /// The owner starts a draft: bans and then picks, one turn at a time. Every champion
+    /// can only be picked once.
+    Draft {
+        bans_per_side: usize,
+        turn_secs: u64,
+    },
+}
+
+impl Default for LobbySettings {
+    fn default() -> Self {
+        Self {
+            name: "Lobby".into(),
+            mode: GameMode::default(),
+            map: "default".into(),
+            max_players: MAX_LOBBY_PLAYERS,
+            teams: 2,
+            password: None,
+            pick_mode: PickMode::default(),
+            rules: GameRules::default(),
+        }
+    }
+}
+
+/// Which lobbies a lobby list subscription shows. Unset fields match everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LobbyFilter {
+    pub mode: Option<GameMode>,
+    pub map: Option<String>,
+    /// Hide lobbies that have no room left.
+    pub not_full: bool,
+    pub has_password: Option<bool>,
+}
+
+impl LobbyFilter {
+    pub fn matches(&self, lobby: &ShortLobbyInfo) -> bool {
+        self.mode.is_none_or(|mode| lobby.mode == mode)
+            && self.map.as_ref().is_none_or(|map| &lobby.map == map)
+            && (!self.not_full || lobby.players < lobby.max_players)
+            && self
+                .has_password
+                .is_none_or(|has_password| lobby.has_password == has_password)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LobbySort {
+    #[default]
+    Name,
+    MostPlayers,
+    FewestPlayers,
+}
+
+impl LobbySort {
+    /// Orders two lobbies for display. Ties are broken by id so that the server and
+    /// the client agree on where each lobby goes.
+    pub fn compare(self, a: &ShortLobbyInfo, b: &ShortLobbyInfo) -> Ordering {
+        let ordering = match self {
+            LobbySort::Name => a.name.cmp(&b.name),
+            LobbySort::MostPlayers => b.players.cmp(&a.players),
+            LobbySort::FewestPlayers => a.players.cmp(&b.players),
+        };
+        ordering.then_with(|| a.id.cmp(&b.id))
+    }
+}
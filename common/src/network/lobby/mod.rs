@@ -1,12 +1,23 @@
-use std::fmt::Display;
+use std::{fmt::Display, net::SocketAddr, time::Duration};
 
 use bevy::utils::HashMap;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::Side;
+use crate::{
+    champion::{ChampionCatalog, Role},
+    network::game::JoinToken,
+    rules::GameRules,
+    Side,
+};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub mod draft;
+pub mod list;
+
+pub use draft::DraftAction;
+pub use list::{GameMode, LobbyFilter, LobbySettings, LobbySort, PickMode};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct LobbyId(pub Uuid);
 
 impl Display for LobbyId {
@@ -29,41 +40,277 @@ pub struct LobbyClientNewConnectionMessage {
     pub username: String,
 }
 
+/// Why the server would not let a client use the username it asked for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UsernameError {
+    TooShort {
+        min: usize,
+    },
+    TooLong {
+        max: usize,
+    },
+    InvalidCharacter {
+        character: char,
+    },
+    /// Mixes letters from different scripts, like Latin and Cyrillic.
+    MixedScripts,
+    Reserved,
+    Blocked,
+    /// Someone else on the server has this name, or one that looks the same.
+    Taken,
+}
+
+impl Display for UsernameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UsernameError::TooShort { min } => {
+                write!(f, "Usernames need at least {min} characters")
+            }
+            UsernameError::TooLong { max } => {
+                write!(f, "Usernames can have at most {max} characters")
+            }
+            UsernameError::InvalidCharacter { character } => {
+                write!(f, "Usernames cannot contain {character:?}")
+            }
+            UsernameError::MixedScripts => write!(f, "Usernames cannot mix alphabets"),
+            UsernameError::Reserved => write!(f, "That username is reserved"),
+            UsernameError::Blocked => write!(f, "That username is not allowed"),
+            UsernameError::Taken => write!(f, "That username is already taken"),
+        }
+    }
+}
+
+impl std::error::Error for UsernameError {}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum LobbyClientMessage {
-    StartMatchmaking,
+    /// Joins the matchmaking queue. The server answers `MatchmakingDone` once it has put
+    /// the player in a lobby.
+    StartMatchmaking {
+        roles: RolePreference,
+    },
     StopMatchmaking,
-    CreateLobby,
+    CreateLobby {
+        settings: LobbySettings,
+    },
     ListLobbies,
-    JoinLobby { id: LobbyId },
+    /// Starts pushing the first page of matching lobbies, and any later changes to it.
+    /// Replaces an existing subscription.
+    SubscribeLobbyList {
+        filter: LobbyFilter,
+        sort: LobbySort,
+        page_size: usize,
+    },
+    /// Moves an existing subscription to another page.
+    ViewLobbyListPage {
+        page: usize,
+    },
+    UnsubscribeLobbyList,
+    JoinLobby {
+        id: LobbyId,
+        password: Option<String>,
+    },
+    /// Joins a lobby without taking a slot; spectators see everything players do.
+    SpectateLobby {
+        id: LobbyId,
+        password: Option<String>,
+    },
     LeaveLobby,
-    GetLobbyInfo { id: LobbyId },
+    GetLobbyInfo {
+        id: LobbyId,
+    },
     SwitchSide,
-    SelectChampion { champion: String },
-    LockInChampion { champion: String },
+    /// Sets the roles the player wants in their current lobby.
+    SetRolePreference {
+        roles: RolePreference,
+    },
+    /// Changes the rules of the lobby's game. Only the lobby owner can do this.
+    SetGameRules {
+        rules: GameRules,
+    },
+    /// Adds an AI player to a side. Only the lobby owner can do this.
+    AddBot {
+        side: Side,
+        difficulty: BotDifficulty,
+    },
+    RemoveBot {
+        id: PlayerId,
+    },
+    SelectChampion {
+        champion: String,
+    },
+    /// In a draft this is only accepted on the player's own pick turn.
+    LockInChampion {
+        champion: String,
+    },
+    /// Starts the draft in a lobby using [`PickMode::Draft`]. Only the lobby owner can
+    /// do this.
+    StartDraft,
+    /// Bans a champion on a ban turn for the player's side.
+    BanChampion {
+        champion: String,
+    },
+    /// Starts the lobby's game once everyone has a champion. Only the lobby owner can do
+    /// this.
+    StartGame,
+    Chat {
+        text: String,
+    },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum LobbyServerMessage {
+    /// The first message on a new connection, once the server has accepted the client.
+    /// `username` may differ from the one asked for, e.g. with a discriminator added.
+    Welcome {
+        id: PlayerId,
+        username: String,
+        champions: ChampionCatalog,
+    },
+    /// Sent instead of `Welcome`. The server closes the connection after sending this.
+    UsernameRejected {
+        error: UsernameError,
+    },
     OK,
-    Negative { msg: String },
+    Negative {
+        msg: String,
+    },
+    /// The request was dropped because too many were sent; `retry_after` is when the
+    /// next one would be accepted.
+    RateLimited {
+        retry_after: Duration,
+    },
     StopMatchmaking,
-    LobbyList { lobbies: Vec<ShortLobbyInfo> },
-    LobbyInfo { info: LobbyInfo },
-    MatchmakingDone { lobby_id: LobbyId },
-    PlayerJoinedLobby { player: Player, side: Side },
-    PlayerLeftLobby { player: Player },
-    PlayerSwitchedSide { player: Player, side: Side },
-    PlayerSelectedChampion { player: Player, champion: String },
-    PlayerLockedInChampion { player: Player, champion: String },
-    YouJoinedLobby { lobby_id: LobbyId },
+    LobbyList {
+        lobbies: Vec<ShortLobbyInfo>,
+    },
+    /// The full contents of the subscribed page, sent when subscribing or changing
+    /// page. After this only the changes below are sent.
+    LobbyListPage {
+        page: usize,
+        page_count: usize,
+        lobbies: Vec<ShortLobbyInfo>,
+    },
+    LobbyListAdded {
+        lobby: ShortLobbyInfo,
+    },
+    LobbyListUpdated {
+        lobby: ShortLobbyInfo,
+    },
+    LobbyListRemoved {
+        id: LobbyId,
+    },
+    LobbyListPageCount {
+        page_count: usize,
+    },
+    LobbyInfo {
+        info: LobbyInfo,
+    },
+    MatchmakingDone {
+        lobby_id: LobbyId,
+    },
+    PlayerJoinedLobby {
+        player: Player,
+        side: Side,
+    },
+    PlayerLeftLobby {
+        player: Player,
+    },
+    LobbyOwnerChanged {
+        owner: PlayerId,
+    },
+    PlayerSwitchedSide {
+        player: Player,
+        side: Side,
+    },
+    /// The roles of everyone in the lobby who has any, sent whenever they change.
+    RolesAssigned {
+        roles: HashMap<PlayerId, Role>,
+    },
+    GameRulesChanged {
+        rules: GameRules,
+    },
+    PlayerSelectedChampion {
+        player: Player,
+        champion: String,
+    },
+    PlayerLockedInChampion {
+        player: Player,
+        champion: String,
+    },
+    /// Every champion picked in the lobby was dropped, because the rules now hand them
+    /// out at random.
+    ChampionPicksCleared,
+    /// A new draft turn began. `player` is who picks, on pick turns; anyone on `side`
+    /// can ban on ban turns.
+    DraftTurn {
+        side: Side,
+        action: DraftAction,
+        player: Option<PlayerId>,
+        time_left: Duration,
+    },
+    /// `champion` is `None` if the side let its ban turn run out.
+    ChampionBanned {
+        side: Side,
+        champion: Option<String>,
+    },
+    DraftFinished,
+    /// The draft was stopped, and picks and bans were cleared.
+    DraftCancelled {
+        reason: String,
+    },
+    /// The lobby is waiting for a game server to host its game.
+    GameStarting,
+    /// Sent to each player once the game server is up; they connect to `addr` over the
+    /// game transport with `token`.
+    GameReady {
+        addr: SocketAddr,
+        token: JoinToken,
+    },
+    /// The game could not be started, or its game server stopped responding. The lobby
+    /// is open again.
+    GameFailed {
+        reason: String,
+    },
+    /// The game is over. The lobby closes after this.
+    GameEnded {
+        winner: Option<Side>,
+    },
+    ChatMessage {
+        from: Player,
+        text: String,
+    },
+    YouJoinedLobby {
+        lobby_id: LobbyId,
+    },
+    YouAreSpectating {
+        lobby_id: LobbyId,
+    },
     YouLeftLobby,
+    Announcement {
+        msg: String,
+    },
+    /// The server closes the connection after sending this.
+    Kicked {
+        reason: String,
+    },
+    /// The server closes the connection after sending this.
+    ServerShuttingDown {
+        reason: String,
+        /// When it is worth trying to reconnect, if the server expects to come back.
+        reconnect_after: Option<Duration>,
+    },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ShortLobbyInfo {
     pub id: LobbyId,
+    pub name: String,
+    pub mode: GameMode,
+    pub map: String,
     pub players: usize,
+    pub max_players: usize,
+    pub has_password: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -71,12 +318,42 @@ pub struct LobbyInfo {
     pub id: LobbyId,
     pub players: HashMap<Side, Vec<Player>>,
     pub lobby_owner: PlayerId,
+    /// Only players who asked for roles are assigned one.
+    pub roles: HashMap<PlayerId, Role>,
+    pub rules: GameRules,
+}
+
+/// The roles a player would like to play, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RolePreference {
+    pub primary: Role,
+    /// `None` if any role will do after the primary one.
+    pub secondary: Option<Role>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Player {
     pub id: PlayerId,
     pub username: String,
+    /// Set if this player is controlled by the server.
+    pub bot: Option<BotDifficulty>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BotDifficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Display for BotDifficulty {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BotDifficulty::Easy => write!(f, "Easy"),
+            BotDifficulty::Medium => write!(f, "Medium"),
+            BotDifficulty::Hard => write!(f, "Hard"),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -4,11 +4,21 @@ use std::{
     time::Duration,
 };
 
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+use anyhow::ensure;
 use serde::{Deserialize, Serialize};
 
+pub mod admin;
+pub mod allocation;
 pub mod game;
 pub mod lobby;
 
+/// The largest message either side will frame. The length prefix is checked against this
+/// before anything is allocated for the message.
+pub const MAX_FRAME_SIZE: u32 = 64 * 1024;
+
 pub trait TcpStreamExt {
     fn read_message<T: for<'de> Deserialize<'de>>(
         &mut self,
@@ -17,32 +27,46 @@ pub trait TcpStreamExt {
     fn write_message<T: Serialize>(&mut self, value: &T) -> anyhow::Result<()>;
 }
 
-impl TcpStreamExt for TcpStream {
-    fn read_message<T: for<'de> Deserialize<'de>>(
-        &mut self,
-        timeout: Option<Duration>,
-    ) -> anyhow::Result<T> {
-        let old_timeout = self.read_timeout().unwrap_or(None);
-        self.set_read_timeout(timeout)?;
-        let mut len = [0; 4];
-        self.read_exact(&mut len)?;
-        let len: u32 = u32::from_be_bytes(len);
-        println!("Reading {} bytes", len);
-
-        let mut buffer = vec![0; len as _];
-        self.read_exact(&mut buffer)?;
-        self.set_read_timeout(old_timeout)?;
-        Ok(postcard::from_bytes(&buffer)?)
-        // Ok(serde_json::from_slice(&buffer)?)
-    }
-
-    fn write_message<T: Serialize>(&mut self, value: &T) -> anyhow::Result<()> {
-        let bytes = postcard::to_allocvec(value)?;
-        // let bytes = serde_json::to_vec(value)?;
-        let len = (bytes.len() as u32).to_be_bytes();
-        println!("Writing {} bytes", bytes.len());
-        self.write_all(&len)?;
-        self.write_all(&bytes)?;
-        Ok(())
-    }
+macro_rules! impl_stream_ext {
+    ($stream:ty) => {
+        impl TcpStreamExt for $stream {
+            fn read_message<T: for<'de> Deserialize<'de>>(
+                &mut self,
+                timeout: Option<Duration>,
+            ) -> anyhow::Result<T> {
+                let old_timeout = self.read_timeout().unwrap_or(None);
+                self.set_read_timeout(timeout)?;
+                let mut len = [0; 4];
+                self.read_exact(&mut len)?;
+                let len: u32 = u32::from_be_bytes(len);
+                println!("Reading {} bytes", len);
+                ensure!(len <= MAX_FRAME_SIZE, "Frame is too large: {len} bytes");
+
+                let mut buffer = vec![0; len as _];
+                self.read_exact(&mut buffer)?;
+                self.set_read_timeout(old_timeout)?;
+                Ok(postcard::from_bytes(&buffer)?)
+                // Ok(serde_json::from_slice(&buffer)?)
+            }
+
+            fn write_message<T: Serialize>(&mut self, value: &T) -> anyhow::Result<()> {
+                let bytes = postcard::to_allocvec(value)?;
+                // let bytes = serde_json::to_vec(value)?;
+                ensure!(
+                    bytes.len() <= MAX_FRAME_SIZE as usize,
+                    "Message is too large: {} bytes",
+                    bytes.len()
+                );
+                let len = (bytes.len() as u32).to_be_bytes();
+                println!("Writing {} bytes", bytes.len());
+                self.write_all(&len)?;
+                self.write_all(&bytes)?;
+                Ok(())
+            }
+        }
+    };
 }
+
+impl_stream_ext!(TcpStream);
+#[cfg(unix)]
+impl_stream_ext!(UnixStream);
@@ -0,0 +1,398 @@
+//! Mirroring the game server's entities on clients.
+//!
+//! Entities the server marks [`Replicated`] get a [`NetworkId`], which names them the same
+//! way everywhere. Each tick the server captures their registered components into a
+//! [`Snapshot`]; clients apply snapshots to entities of their own, found through
+//! [`NetworkEntities`].
+//!
+//! Components are replicated once registered with [`AppReplicationExt::replicate`]. The
+//! registry has to be the same on both ends, since components are identified by the
+//! order they were registered in.
+
+use bevy::{
+    ecs::world::{EntityRef, EntityWorldMut},
+    prelude::*,
+    utils::HashMap,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    gameplay::{
+        navigation::{NavObstacle, Path},
+        steering::{Collider, Ghosted},
+        Champion, Gold, Health, Inhibitor, Level, Movement, Nexus, Position, Team, Tower,
+    },
+    network::{
+        game::{snapshot::EntityState, snapshot::Snapshot, Controller},
+        lobby::PlayerId,
+    },
+    Side,
+};
+
+/// Positions and distances are sent in fixed point, with this many steps per unit.
+pub const POSITION_SCALE: f32 = 64.0;
+
+/// Registers the components every match replicates.
+pub struct ReplicationPlugin;
+
+impl Plugin for ReplicationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NetworkEntities>()
+            .replicate::<Position>()
+            .replicate::<Team>()
+            .replicate::<Health>()
+            .replicate::<Movement>()
+            .replicate::<Path>()
+            .replicate::<NavObstacle>()
+            .replicate::<Collider>()
+            .replicate::<Ghosted>()
+            .replicate::<Champion>()
+            .replicate::<Gold>()
+            .replicate::<Level>()
+            .replicate::<Nexus>()
+            .replicate::<Tower>()
+            .replicate::<Inhibitor>();
+    }
+}
+
+/// Names a replicated entity the same way on the server and every client.
+#[derive(
+    Component, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct NetworkId(pub u32);
+
+/// Marks an entity on the server for replication. It is given a [`NetworkId`] by
+/// [`assign_network_ids`].
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct Replicated;
+
+/// A component that can be replicated.
+pub trait Replicate: Component + Sized {
+    /// What is sent: usually a quantized copy of the component.
+    type State: Serialize + DeserializeOwned;
+
+    fn to_state(&self) -> Self::State;
+    fn from_state(state: Self::State) -> Self;
+}
+
+struct ReplicatedComponent {
+    capture: fn(&EntityRef) -> Option<Vec<u8>>,
+    apply: fn(&mut EntityWorldMut, &[u8]) -> anyhow::Result<()>,
+    remove: fn(&mut EntityWorldMut),
+}
+
+/// Every replicated component, in the order snapshots list them.
+#[derive(Resource, Default)]
+pub struct ReplicationRegistry {
+    components: Vec<ReplicatedComponent>,
+}
+
+impl ReplicationRegistry {
+    pub fn register<C: Replicate>(&mut self) {
+        assert!(
+            self.components.len() <= u8::MAX as usize,
+            "Too many replicated components"
+        );
+        self.components.push(ReplicatedComponent {
+            capture: |entity| {
+                let component = entity.get::<C>()?;
+                Some(postcard::to_allocvec(&component.to_state()).expect("States can be encoded"))
+            },
+            apply: |entity, bytes| {
+                entity.insert(C::from_state(postcard::from_bytes(bytes)?));
+                Ok(())
+            },
+            remove: |entity| {
+                entity.remove::<C>();
+            },
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+}
+
+pub trait AppReplicationExt {
+    /// Adds `C` to the [`ReplicationRegistry`].
+    fn replicate<C: Replicate>(&mut self) -> &mut Self;
+}
+
+impl AppReplicationExt for App {
+    fn replicate<C: Replicate>(&mut self) -> &mut Self {
+        self.init_resource::<ReplicationRegistry>();
+        self.world
+            .resource_mut::<ReplicationRegistry>()
+            .register::<C>();
+        self
+    }
+}
+
+/// On clients, the local entity mirroring each replicated one.
+#[derive(Resource, Debug, Default)]
+pub struct NetworkEntities(pub HashMap<NetworkId, Entity>);
+
+/// Gives every newly [`Replicated`] entity a [`NetworkId`]. Ids are never reused.
+pub fn assign_network_ids(
+    new: Query<Entity, (With<Replicated>, Without<NetworkId>)>,
+    mut next_id: Local<u32>,
+    mut commands: Commands,
+) {
+    for entity in &new {
+        commands.entity(entity).insert(NetworkId(*next_id));
+        *next_id += 1;
+    }
+}
+
+/// The replicated state of every entity with a [`NetworkId`].
+pub fn capture_snapshot(world: &mut World, tick: u64) -> Snapshot {
+    let mut query = world.query::<(&NetworkId, EntityRef)>();
+    let registry = world.resource::<ReplicationRegistry>();
+
+    let entities = query
+        .iter(world)
+        .map(|(&id, entity)| {
+            let components = registry
+                .components
+                .iter()
+                .map(|component| (component.capture)(&entity))
+                .collect();
+            (id, EntityState { components })
+        })
+        .collect();
+    Snapshot { tick, entities }
+}
+
+/// Makes the local mirror entities match `snapshot`: spawning, updating and despawning
+/// them as needed.
+pub fn apply_snapshot(world: &mut World, snapshot: &Snapshot) {
+    world.resource_scope(|world, mut mirrors: Mut<NetworkEntities>| {
+        world.resource_scope(|world, registry: Mut<ReplicationRegistry>| {
+            mirrors.0.retain(|id, &mut entity| {
+                let keep = snapshot.entities.contains_key(id);
+                if !keep {
+                    world.despawn(entity);
+                }
+                keep
+            });
+
+            for (&id, state) in &snapshot.entities {
+                let entity = match mirrors.0.get(&id) {
+                    Some(&entity) if world.get_entity(entity).is_some() => entity,
+                    _ => {
+                        let entity = world.spawn(id).id();
+                        mirrors.0.insert(id, entity);
+                        entity
+                    }
+                };
+
+                let mut entity = world.entity_mut(entity);
+                for (index, component) in registry.components.iter().enumerate() {
+                    match state.component(index) {
+                        Some(bytes) => {
+                            if let Err(e) = (component.apply)(&mut entity, bytes) {
+                                warn!("Could not apply component {index} of {id:?}: {e}");
+                            }
+                        }
+                        None => (component.remove)(&mut entity),
+                    }
+                }
+            }
+        });
+    });
+}
+
+fn quantize(value: f32) -> i32 {
+    (value * POSITION_SCALE).round() as i32
+}
+
+fn dequantize(value: i32) -> f32 {
+    value as f32 / POSITION_SCALE
+}
+
+fn quantize_vec2(v: Vec2) -> [i32; 2] {
+    [quantize(v.x), quantize(v.y)]
+}
+
+fn dequantize_vec2([x, y]: [i32; 2]) -> Vec2 {
+    Vec2::new(dequantize(x), dequantize(y))
+}
+
+impl Replicate for Position {
+    type State = [i32; 2];
+
+    fn to_state(&self) -> Self::State {
+        quantize_vec2(self.0)
+    }
+
+    fn from_state(state: Self::State) -> Self {
+        Position(dequantize_vec2(state))
+    }
+}
+
+impl Replicate for Team {
+    type State = Side;
+
+    fn to_state(&self) -> Self::State {
+        self.0
+    }
+
+    fn from_state(state: Self::State) -> Self {
+        Team(state)
+    }
+}
+
+impl Replicate for Health {
+    /// Whole hit points are enough to show.
+    type State = [u32; 2];
+
+    fn to_state(&self) -> Self::State {
+        [self.current.max(0.0).ceil() as u32, self.max.ceil() as u32]
+    }
+
+    fn from_state([current, max]: Self::State) -> Self {
+        Health {
+            current: current as f32,
+            max: max as f32,
+        }
+    }
+}
+
+impl Replicate for Movement {
+    type State = (i32, Option<[i32; 2]>);
+
+    fn to_state(&self) -> Self::State {
+        (quantize(self.speed), self.target.map(quantize_vec2))
+    }
+
+    fn from_state((speed, target): Self::State) -> Self {
+        Movement {
+            speed: dequantize(speed),
+            target: target.map(dequantize_vec2),
+        }
+    }
+}
+
+impl Replicate for Path {
+    type State = (Option<[i32; 2]>, Vec<[i32; 2]>);
+
+    fn to_state(&self) -> Self::State {
+        let waypoints = self.waypoints.iter().copied().map(quantize_vec2).collect();
+        (self.destination.map(quantize_vec2), waypoints)
+    }
+
+    fn from_state((destination, waypoints): Self::State) -> Self {
+        Path {
+            destination: destination.map(dequantize_vec2),
+            waypoints: waypoints.into_iter().map(dequantize_vec2).collect(),
+        }
+    }
+}
+
+impl Replicate for NavObstacle {
+    type State = i32;
+
+    fn to_state(&self) -> Self::State {
+        quantize(self.radius)
+    }
+
+    fn from_state(radius: Self::State) -> Self {
+        NavObstacle {
+            radius: dequantize(radius),
+        }
+    }
+}
+
+impl Replicate for Collider {
+    type State = i32;
+
+    fn to_state(&self) -> Self::State {
+        quantize(self.radius)
+    }
+
+    fn from_state(radius: Self::State) -> Self {
+        Collider {
+            radius: dequantize(radius),
+        }
+    }
+}
+
+impl Replicate for Ghosted {
+    type State = ();
+
+    fn to_state(&self) -> Self::State {}
+
+    fn from_state((): Self::State) -> Self {
+        Ghosted
+    }
+}
+
+impl Replicate for Champion {
+    type State = (PlayerId, Controller);
+
+    fn to_state(&self) -> Self::State {
+        (self.player, self.controller)
+    }
+
+    fn from_state((player, controller): Self::State) -> Self {
+        Champion { player, controller }
+    }
+}
+
+impl Replicate for Gold {
+    type State = u32;
+
+    fn to_state(&self) -> Self::State {
+        self.0
+    }
+
+    fn from_state(state: Self::State) -> Self {
+        Gold(state)
+    }
+}
+
+impl Replicate for Level {
+    type State = u32;
+
+    fn to_state(&self) -> Self::State {
+        self.0
+    }
+
+    fn from_state(state: Self::State) -> Self {
+        Level(state)
+    }
+}
+
+impl Replicate for Nexus {
+    type State = ();
+
+    fn to_state(&self) -> Self::State {}
+
+    fn from_state((): Self::State) -> Self {
+        Nexus
+    }
+}
+
+impl Replicate for Tower {
+    type State = ();
+
+    fn to_state(&self) -> Self::State {}
+
+    fn from_state((): Self::State) -> Self {
+        Tower
+    }
+}
+
+impl Replicate for Inhibitor {
+    type State = ();
+
+    fn to_state(&self) -> Self::State {}
+
+    fn from_state((): Self::State) -> Self {
+        Inhibitor
+    }
+}
@@ -0,0 +1,107 @@
+//! Rules a lobby's owner can change for a game, for custom games and balance
+//! experiments.
+
+use anyhow::ensure;
+use serde::{Deserialize, Serialize};
+
+/// Highest level a champion can reach.
+pub const MAX_LEVEL: u32 = 18;
+
+/// Most items a lobby can disable.
+pub const MAX_DISABLED_ITEMS: usize = 64;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct GameRules {
+    pub starting_gold: u32,
+    pub starting_level: u32,
+    /// Respawn timers are scaled by this, in percent: 50 halves them.
+    pub respawn_time_percent: u32,
+    /// Everyone is given a random champion instead of picking one.
+    pub all_random: bool,
+    /// Ids of items that can't be bought.
+    pub disabled_items: Vec<String>,
+    /// Most cooldown reduction a champion can have, in percent.
+    pub cooldown_reduction_cap: u32,
+    pub minions: bool,
+    /// Whether destroying an inhibitor spawns super minions. Has no effect without
+    /// `minions`.
+    pub super_minions: bool,
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        Self {
+            starting_gold: 500,
+            starting_level: 1,
+            respawn_time_percent: 100,
+            all_random: false,
+            disabled_items: Vec::new(),
+            cooldown_reduction_cap: 40,
+            minions: true,
+            super_minions: true,
+        }
+    }
+}
+
+impl GameRules {
+    /// Named rule sets to choose from, starting with the default one.
+    pub fn presets() -> Vec<(&'static str, GameRules)> {
+        vec![
+            ("Standard", GameRules::default()),
+            (
+                "Quick",
+                GameRules {
+                    starting_gold: 1500,
+                    starting_level: 3,
+                    respawn_time_percent: 50,
+                    ..Default::default()
+                },
+            ),
+            (
+                "All Random",
+                GameRules {
+                    all_random: true,
+                    ..Default::default()
+                },
+            ),
+            (
+                "Skirmish",
+                GameRules {
+                    starting_level: 6,
+                    cooldown_reduction_cap: 60,
+                    minions: false,
+                    super_minions: false,
+                    ..Default::default()
+                },
+            ),
+        ]
+    }
+
+    /// The name of the preset these rules match, if any.
+    pub fn preset_name(&self) -> Option<&'static str> {
+        Self::presets()
+            .into_iter()
+            .find_map(|(name, rules)| (rules == *self).then_some(name))
+    }
+
+    /// Fails if a value is out of range, so a game could not be played with these rules.
+    pub fn check(&self) -> anyhow::Result<()> {
+        ensure!(
+            (1..=MAX_LEVEL).contains(&self.starting_level),
+            "Starting level must be 1 to {MAX_LEVEL}"
+        );
+        ensure!(
+            (10..=1000).contains(&self.respawn_time_percent),
+            "Respawn time must be 10% to 1000% of normal"
+        );
+        ensure!(
+            self.cooldown_reduction_cap <= 80,
+            "Cooldown reduction can be capped at 80% at most"
+        );
+        ensure!(
+            self.disabled_items.len() <= MAX_DISABLED_ITEMS,
+            "At most {MAX_DISABLED_ITEMS} items can be disabled"
+        );
+        Ok(())
+    }
+}
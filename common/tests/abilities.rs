@@ -0,0 +1,123 @@
+use bevy::prelude::*;
+use common::{
+    gameplay::{
+        abilities::{Cast, Cooldown, Hitbox, LagCompensation, SKILLSHOT_DAMAGE, TARGETED_DAMAGE},
+        Champion, ChampionCommand, ChampionInput, Health, Inputs, Movement, Position,
+        SimulationPlugin, Team,
+    },
+    network::{game::Controller, lobby::PlayerId},
+    replication::NetworkId,
+    Side,
+};
+use uuid::Uuid;
+
+const HEALTH: f32 = 600.0;
+
+struct Duel {
+    app: App,
+    caster: PlayerId,
+    target: Entity,
+}
+
+impl Duel {
+    /// A caster at the origin, and an enemy that is 5 units along the X axis until
+    /// tick 5, then moves off it.
+    fn new(max_rewind_ticks: u64) -> Self {
+        let mut app = App::new();
+        app.add_plugins(SimulationPlugin)
+            .init_resource::<Time>()
+            .insert_resource(LagCompensation { max_rewind_ticks });
+
+        let caster = PlayerId(Uuid::new_v4());
+        let champion = |player, side, position| {
+            (
+                Champion {
+                    player,
+                    controller: Controller::Human,
+                },
+                Team(side),
+                Position(position),
+                Health::full(HEALTH),
+                Movement {
+                    speed: 0.0,
+                    target: None,
+                },
+                Hitbox { radius: 0.5 },
+                Cooldown::default(),
+            )
+        };
+        app.world.spawn(champion(caster, Side::RED, Vec2::ZERO));
+        let target = app
+            .world
+            .spawn(champion(
+                PlayerId(Uuid::new_v4()),
+                Side::BLUE,
+                Vec2::new(5.0, 0.0),
+            ))
+            .insert(NetworkId(7))
+            .id();
+
+        let mut duel = Self {
+            app,
+            caster,
+            target,
+        };
+        duel.run_ticks(4);
+        duel.app.world.get_mut::<Position>(target).unwrap().0 = Vec2::new(5.0, 6.0);
+        duel.run_ticks(4);
+        duel
+    }
+
+    fn run_ticks(&mut self, count: usize) {
+        for _ in 0..count {
+            self.app.world.run_schedule(FixedUpdate);
+        }
+    }
+
+    /// Casts on the next tick, as seen from `seen_tick`, and returns the damage done.
+    fn cast(&mut self, cast: Cast, seen_tick: u64) -> f32 {
+        let caster = self.caster;
+        self.app
+            .world
+            .resource_mut::<Inputs>()
+            .0
+            .push(ChampionInput {
+                player: caster,
+                sequence: 0,
+                command: ChampionCommand::Cast(cast),
+                seen_tick,
+            });
+        let before = self.app.world.get::<Health>(self.target).unwrap().current;
+        self.run_ticks(1);
+
+        let mut cooldowns = self.app.world.query::<&mut Cooldown>();
+        for mut cooldown in cooldowns.iter_mut(&mut self.app.world) {
+            cooldown.0 = 0;
+        }
+        before - self.app.world.get::<Health>(self.target).unwrap().current
+    }
+}
+
+const ALONG_X: Cast = Cast::Skillshot { direction: Vec2::X };
+const AT_TARGET: Cast = Cast::Targeted {
+    target: NetworkId(7),
+};
+
+#[test]
+fn casts_hit_where_the_caster_saw_their_target() {
+    let mut duel = Duel::new(10);
+
+    assert_eq!(duel.cast(ALONG_X, 3), SKILLSHOT_DAMAGE);
+    assert_eq!(duel.cast(ALONG_X, 9), 0.0);
+    assert_eq!(duel.cast(AT_TARGET, 3), TARGETED_DAMAGE);
+    // Claiming to have seen the future doesn't help either.
+    assert_eq!(duel.cast(ALONG_X, 100), 0.0);
+}
+
+#[test]
+fn rewinding_is_capped() {
+    let mut duel = Duel::new(2);
+
+    assert_eq!(duel.cast(ALONG_X, 3), 0.0);
+    assert_eq!(duel.cast(AT_TARGET, 3), 0.0);
+}
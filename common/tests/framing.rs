@@ -0,0 +1,31 @@
+#![cfg(unix)]
+
+use std::{io::Write, os::unix::net::UnixStream};
+
+use common::network::{TcpStreamExt, MAX_FRAME_SIZE};
+
+#[test]
+fn messages_survive_framing() {
+    let (mut a, mut b) = UnixStream::pair().unwrap();
+    a.write_message(&("hello".to_string(), 42u32)).unwrap();
+    let message: (String, u32) = b.read_message(None).unwrap();
+    assert_eq!(message, ("hello".to_string(), 42));
+}
+
+#[test]
+fn oversized_frames_are_rejected_before_reading_them() {
+    let (mut a, mut b) = UnixStream::pair().unwrap();
+    // Only the length goes out: reading the rest would block forever.
+    a.write_all(&(MAX_FRAME_SIZE + 1).to_be_bytes()).unwrap();
+
+    let error = b.read_message::<Vec<u8>>(None).unwrap_err();
+    // Not an IO error, so servers count it as the other side's fault.
+    assert!(error.downcast_ref::<std::io::Error>().is_none(), "{error}");
+}
+
+#[test]
+fn oversized_messages_are_not_sent() {
+    let (mut a, _b) = UnixStream::pair().unwrap();
+    let message = vec![0u8; MAX_FRAME_SIZE as usize + 1];
+    assert!(a.write_message(&message).is_err());
+}
@@ -0,0 +1,49 @@
+use std::path::Path;
+
+use bevy::math::Vec2;
+use common::{
+    map::{MapDefinition, Polygon, StructureKind},
+    Side,
+};
+
+fn default_map() -> MapDefinition {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../game-server/maps/default.json");
+    MapDefinition::load(&path).unwrap()
+}
+
+#[test]
+fn default_map_is_valid() {
+    let map = default_map();
+
+    assert!(map.base(Side::RED).is_some());
+    assert!(map.base(Side::BLUE).is_some());
+    for base in &map.bases {
+        assert!(map.terrain.is_walkable(base.fountain.center));
+    }
+}
+
+#[test]
+fn problems_are_found() {
+    let mut map = default_map();
+    map.structures.retain(|s| {
+        !(s.side == Side::BLUE
+            && s.kind == StructureKind::Tower
+            && s.lane.as_deref() == Some("top"))
+    });
+    assert_eq!(map.problems(), ["Blue has no Tower in lane top"]);
+
+    // Walls blue's fountain off from everything else.
+    let mut map = default_map();
+    map.terrain.obstacles.push(Polygon(vec![
+        Vec2::new(80.0, 80.0),
+        Vec2::new(100.0, 80.0),
+        Vec2::new(100.0, 84.0),
+        Vec2::new(84.0, 84.0),
+        Vec2::new(84.0, 100.0),
+        Vec2::new(80.0, 100.0),
+    ]));
+    let problems = map.problems();
+    assert_eq!(problems.len(), 2, "{problems:?}");
+    assert_eq!(problems[0], "Blue's fountain can't be reached");
+    assert!(problems[1].contains("walkable terrain"));
+}
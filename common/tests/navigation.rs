@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use common::{
+    gameplay::{
+        navigation::{NavGrid, NavObstacle, Path},
+        Movement, Position, SimulationPlugin, TICK_RATE,
+    },
+    map::{Polygon, Terrain},
+};
+
+fn square(min: Vec2, max: Vec2) -> Polygon {
+    Polygon(vec![
+        min,
+        Vec2::new(max.x, min.y),
+        max,
+        Vec2::new(min.x, max.y),
+    ])
+}
+
+/// A 40 by 40 field with a wall across the middle, open at its top end.
+fn walled_field() -> NavGrid {
+    NavGrid::new(&Terrain {
+        walkable: vec![square(Vec2::splat(-20.0), Vec2::splat(20.0))],
+        obstacles: vec![square(Vec2::new(-2.0, -20.0), Vec2::new(2.0, 10.0))],
+    })
+}
+
+/// Whether walking `waypoints` from `from` stays on walkable terrain throughout.
+fn walkable(nav: &NavGrid, from: Vec2, waypoints: &[Vec2]) -> bool {
+    nav.path_clear(from, &waypoints.iter().copied().collect())
+}
+
+#[test]
+fn paths_go_around_walls() {
+    let nav = walled_field();
+    let (from, to) = (Vec2::new(-10.0, 0.0), Vec2::new(10.0, 0.0));
+
+    let waypoints = nav.find_path(from, to).unwrap();
+    assert!(!nav.line_walkable(from, to));
+    assert!(walkable(&nav, from, &waypoints));
+    assert_eq!(waypoints.last(), Some(&to));
+    // Smoothed down to going around the end of the wall.
+    assert!(waypoints.len() <= 4, "{waypoints:?}");
+    assert!(waypoints.iter().any(|waypoint| waypoint.y > 10.0));
+
+    assert_eq!(nav.find_path(from, to), Some(waypoints));
+}
+
+#[test]
+fn unwalkable_destinations_lead_as_close_as_possible() {
+    let mut nav = walled_field();
+    let from = Vec2::new(-10.0, 0.0);
+
+    let inside_wall = nav.closest_reachable(from, Vec2::new(1.0, 0.0)).unwrap();
+    assert!(nav.is_walkable(inside_wall));
+    assert!((inside_wall - Vec2::new(2.0, 0.0)).length() < 1.0);
+
+    let off_the_map = nav.closest_reachable(from, Vec2::new(-50.0, 0.0)).unwrap();
+    assert!((off_the_map - Vec2::new(-20.0, 0.0)).length() < 1.0);
+
+    // Closing the gap leaves the near side of the wall as close as it gets.
+    nav.set_obstacles([(Vec2::new(0.0, 15.0), 6.0)]);
+    let waypoints = nav.find_path(from, Vec2::new(10.0, 0.0)).unwrap();
+    assert!(walkable(&nav, from, &waypoints));
+    assert!(waypoints.last().unwrap().x < -1.0, "{waypoints:?}");
+}
+
+#[test]
+fn units_replan_around_new_obstacles() {
+    let mut app = App::new();
+    let mut time = Time::<()>::default();
+    time.advance_by(Duration::from_secs_f64(1.0 / TICK_RATE));
+    app.add_plugins(SimulationPlugin)
+        .insert_resource(time)
+        .insert_resource(walled_field());
+
+    let target = Vec2::new(10.0, 15.0);
+    let unit = app
+        .world
+        .spawn((
+            Position(Vec2::new(-10.0, 15.0)),
+            Movement {
+                speed: 6.0,
+                target: Some(target),
+            },
+            Path::default(),
+        ))
+        .id();
+    app.world.run_schedule(FixedUpdate);
+    app.world
+        .spawn((Position(Vec2::new(0.0, 15.0)), NavObstacle { radius: 3.0 }));
+
+    let mut positions = Vec::new();
+    for _ in 0..600 {
+        app.world.run_schedule(FixedUpdate);
+        positions.push(app.world.get::<Position>(unit).unwrap().0);
+    }
+
+    let nav = app.world.resource::<NavGrid>();
+    assert!(positions.iter().all(|&position| nav.is_walkable(position)));
+    assert_eq!(positions.last(), Some(&target));
+}
@@ -0,0 +1,117 @@
+use std::{path::Path, time::Duration};
+
+use bevy::{ecs::system::RunSystemOnce, prelude::*};
+use common::{
+    gameplay::{
+        self, AppliedInputs, Champion, ChampionCommand, ChampionInput, Health, Inputs, Movement,
+        Position, SimulationPlugin, TICK_RATE,
+    },
+    map::MapDefinition,
+    network::{
+        game::{Controller, MatchParticipant, MatchSetup},
+        lobby::PlayerId,
+    },
+    rules::GameRules,
+    Side,
+};
+use uuid::Uuid;
+
+const PLAYER: PlayerId = PlayerId(Uuid::from_u128(1));
+
+/// A match with a single champion, respawning at `respawn_time_percent`.
+fn one_champion(respawn_time_percent: u32) -> (App, Entity) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../game-server/maps/default.json");
+    let map = MapDefinition::load(&path).unwrap();
+    let setup = MatchSetup {
+        map: "default".into(),
+        teams: 2,
+        rules: GameRules {
+            respawn_time_percent,
+            ..Default::default()
+        },
+        participants: vec![MatchParticipant {
+            id: PLAYER,
+            username: "alice".into(),
+            side: Side::RED,
+            champion: None,
+            role: None,
+            controller: Controller::Human,
+        }],
+    };
+
+    let mut app = App::new();
+    let mut time = Time::<()>::default();
+    time.advance_by(Duration::from_secs_f64(1.0 / TICK_RATE));
+    app.add_plugins(SimulationPlugin).insert_resource(time);
+    app.world.run_system_once(move |mut commands: Commands| {
+        gameplay::spawn_match(&mut commands, &setup, &map);
+    });
+
+    let champion = app
+        .world
+        .query_filtered::<Entity, With<Champion>>()
+        .single(&app.world);
+    (app, champion)
+}
+
+/// Kills `champion` away from where it started, and returns how many ticks it stays dead.
+fn time_dead(app: &mut App, champion: Entity) -> u32 {
+    let start = app.world.get::<Position>(champion).unwrap().0;
+    app.world.get_mut::<Position>(champion).unwrap().0 += Vec2::new(20.0, 0.0);
+    app.world.get_mut::<Health>(champion).unwrap().current = 0.0;
+
+    let mut ticks = 0;
+    while app.world.get::<Health>(champion).unwrap().is_dead() {
+        assert!(ticks < 10_000, "The champion never respawned");
+        app.world.run_schedule(FixedUpdate);
+        ticks += 1;
+    }
+
+    let health = app.world.get::<Health>(champion).unwrap();
+    assert_eq!(health.current, health.max);
+    assert_eq!(app.world.get::<Position>(champion).unwrap().0, start);
+    ticks
+}
+
+#[test]
+fn dead_champions_respawn_where_they_started() {
+    let (mut app, champion) = one_champion(100);
+
+    assert_eq!(time_dead(&mut app, champion), 301);
+    // And again, now that the first death is over.
+    assert_eq!(time_dead(&mut app, champion), 301);
+}
+
+#[test]
+fn the_rules_scale_the_respawn_time() {
+    let (mut app, champion) = one_champion(50);
+
+    assert_eq!(time_dead(&mut app, champion), 151);
+}
+
+#[test]
+fn the_dead_stay_put() {
+    let (mut app, champion) = one_champion(100);
+    app.world.get_mut::<Health>(champion).unwrap().current = 0.0;
+    app.world.run_schedule(FixedUpdate);
+    let position = app.world.get::<Position>(champion).unwrap().0;
+
+    app.world.resource_mut::<Inputs>().0.push(ChampionInput {
+        player: PLAYER,
+        sequence: 0,
+        command: ChampionCommand::MoveTo(position + Vec2::new(10.0, 0.0)),
+        seen_tick: 0,
+    });
+    for _ in 0..30 {
+        app.world.run_schedule(FixedUpdate);
+    }
+
+    assert_eq!(app.world.get::<Position>(champion).unwrap().0, position);
+    assert_eq!(app.world.get::<Movement>(champion).unwrap().target, None);
+    // The command still counts as handled, so the client stops predicting it.
+    assert!(app
+        .world
+        .resource::<AppliedInputs>()
+        .0
+        .contains_key(&PLAYER));
+}
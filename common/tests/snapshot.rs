@@ -0,0 +1,156 @@
+use bevy::prelude::*;
+use common::{
+    gameplay::{Health, Movement, Nexus, Position, Team},
+    network::game::snapshot::{EntityState, Snapshot, SnapshotHistory},
+    replication::{
+        apply_snapshot, assign_network_ids, capture_snapshot, NetworkEntities, NetworkId,
+        Replicated, ReplicationPlugin,
+    },
+    Side,
+};
+
+fn state(components: &[Option<&[u8]>]) -> EntityState {
+    EntityState {
+        components: components.iter().map(|c| c.map(<[u8]>::to_vec)).collect(),
+    }
+}
+
+fn snapshot(tick: u64, entities: impl IntoIterator<Item = (u32, EntityState)>) -> Snapshot {
+    Snapshot {
+        tick,
+        entities: entities
+            .into_iter()
+            .map(|(id, state)| (NetworkId(id), state))
+            .collect(),
+    }
+}
+
+#[test]
+fn deltas_only_carry_what_changed() {
+    let before = snapshot(
+        1,
+        [
+            (0, state(&[Some(&[1]), Some(&[2])])),
+            (1, state(&[Some(&[3])])),
+            (2, state(&[Some(&[4])])),
+        ],
+    );
+    let after = snapshot(
+        2,
+        [
+            (0, state(&[Some(&[1]), None])),
+            (1, state(&[Some(&[3])])),
+            (3, state(&[Some(&[5])])),
+        ],
+    );
+
+    let delta = after.delta_from(Some(&before));
+    assert_eq!(delta.baseline, Some(1));
+    let changed = delta
+        .changed
+        .iter()
+        .map(|e| (e.id.0, e.components.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        changed,
+        [(0, vec![(1, None)]), (3, vec![(0, Some(vec![5]))])]
+    );
+    assert_eq!(delta.removed, [NetworkId(2)]);
+
+    assert_eq!(delta.apply(Some(&before)).unwrap(), after);
+    assert_eq!(after.delta_from(None).apply(None).unwrap(), after);
+}
+
+#[test]
+fn history_needs_the_baseline_and_rejects_old_snapshots() {
+    let first = snapshot(1, [(0, state(&[Some(&[1])]))]);
+    let second = snapshot(2, [(0, state(&[Some(&[2])]))]);
+    let third = snapshot(3, [(0, state(&[Some(&[3])]))]);
+
+    let mut history = SnapshotHistory::default();
+    assert!(history.receive(second.delta_from(Some(&first))).is_err());
+    assert_eq!(history.receive(first.delta_from(None)).unwrap(), &first);
+    assert_eq!(
+        history.receive(third.delta_from(Some(&first))).unwrap(),
+        &third
+    );
+    assert!(history.receive(second.delta_from(Some(&first))).is_err());
+}
+
+#[test]
+fn clients_mirror_the_server_world() {
+    let mut server = App::new();
+    server
+        .add_plugins(ReplicationPlugin)
+        .add_systems(Update, assign_network_ids);
+    let nexus = server
+        .world
+        .spawn((
+            Replicated,
+            Nexus,
+            Team(Side::BLUE),
+            Position(Vec2::new(1.0, -2.5)),
+        ))
+        .id();
+    let unit = server
+        .world
+        .spawn((
+            Replicated,
+            Position(Vec2::new(10.01, 0.0)),
+            Health::full(100.0),
+            Movement {
+                speed: 6.0,
+                target: Some(Vec2::ONE),
+            },
+        ))
+        .id();
+    server.world.spawn(Position(Vec2::ZERO));
+    server.update();
+
+    let mut client = App::new();
+    client.add_plugins(ReplicationPlugin);
+    let mut history = SnapshotHistory::default();
+    let mut sync = |server: &mut App, client: &mut App, tick| {
+        let snapshot = capture_snapshot(&mut server.world, tick);
+        let delta = snapshot.delta_from(history.latest());
+        let snapshot = history.receive(delta).unwrap();
+        apply_snapshot(&mut client.world, snapshot);
+    };
+
+    sync(&mut server, &mut client, 1);
+    let mirrors = client.world.resource::<NetworkEntities>().0.clone();
+    assert_eq!(mirrors.len(), 2);
+    let mirror_of = |entity| mirrors[server.world.get::<NetworkId>(entity).unwrap()];
+    let (nexus_mirror, unit_mirror) = (mirror_of(nexus), mirror_of(unit));
+
+    let mirrored_nexus = client.world.entity(nexus_mirror);
+    assert!(mirrored_nexus.contains::<Nexus>());
+    assert_eq!(mirrored_nexus.get::<Team>(), Some(&Team(Side::BLUE)));
+    assert_eq!(
+        mirrored_nexus.get::<Position>(),
+        Some(&Position(Vec2::new(1.0, -2.5)))
+    );
+    assert!(mirrored_nexus.get::<Health>().is_none());
+
+    // Positions are quantized, so are only close.
+    let position = client.world.get::<Position>(unit_mirror).unwrap().0;
+    assert!(position.distance(Vec2::new(10.01, 0.0)) < 0.01);
+    assert_eq!(
+        client.world.get::<Movement>(unit_mirror),
+        Some(&Movement {
+            speed: 6.0,
+            target: Some(Vec2::ONE),
+        })
+    );
+
+    server.world.entity_mut(unit).remove::<Movement>();
+    server.world.get_mut::<Health>(unit).unwrap().current = 40.0;
+    server.world.despawn(nexus);
+    sync(&mut server, &mut client, 2);
+
+    assert!(client.world.get_entity(nexus_mirror).is_none());
+    assert_eq!(client.world.resource::<NetworkEntities>().0.len(), 1);
+    let mirrored_unit = client.world.entity(unit_mirror);
+    assert!(mirrored_unit.get::<Movement>().is_none());
+    assert_eq!(mirrored_unit.get::<Health>().unwrap().current, 40.0);
+}
@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use common::{
+    gameplay::{
+        navigation::Path,
+        steering::{self, Body, Collider, Ghosted},
+        Movement, Position, SimulationPlugin, TICK_RATE,
+    },
+    replication::NetworkId,
+};
+
+const RADIUS: f32 = 0.6;
+
+fn field() -> App {
+    let mut app = App::new();
+    let mut time = Time::<()>::default();
+    time.advance_by(Duration::from_secs_f64(1.0 / TICK_RATE));
+    app.add_plugins(SimulationPlugin).insert_resource(time);
+    app
+}
+
+fn spawn_unit(app: &mut App, position: Vec2, target: Option<Vec2>) -> Entity {
+    app.world
+        .spawn((
+            Position(position),
+            Movement { speed: 6.0, target },
+            Path::default(),
+            Collider { radius: RADIUS },
+        ))
+        .id()
+}
+
+fn position(app: &App, unit: Entity) -> Vec2 {
+    app.world.get::<Position>(unit).unwrap().0
+}
+
+/// Runs ticks until nothing is moving, and returns the closest `a` and `b` came to each
+/// other along the way.
+fn run(app: &mut App, a: Entity, b: Entity) -> f32 {
+    let mut closest = f32::INFINITY;
+    for _ in 0..300 {
+        app.world.run_schedule(FixedUpdate);
+        closest = closest.min(position(app, a).distance(position(app, b)));
+    }
+    closest
+}
+
+#[test]
+fn units_walking_into_each_other_pass() {
+    let mut app = field();
+    let (left, right) = (Vec2::new(-5.0, 0.0), Vec2::new(5.0, 0.0));
+    let a = spawn_unit(&mut app, left, Some(right));
+    let b = spawn_unit(&mut app, right, Some(left));
+
+    let closest = run(&mut app, a, b);
+    assert_eq!(position(&app, a), right);
+    assert_eq!(position(&app, b), left);
+    // Overlapping a little for a tick is fine, walking through each other isn't.
+    assert!(closest > RADIUS, "{closest}");
+}
+
+#[test]
+fn units_standing_still_block() {
+    let mut app = field();
+    let target = Vec2::new(5.0, 0.0);
+    let walker = spawn_unit(&mut app, Vec2::new(-5.0, 0.0), Some(target));
+    let blocker = spawn_unit(&mut app, Vec2::ZERO, None);
+
+    let closest = run(&mut app, walker, blocker);
+    assert_eq!(position(&app, blocker), Vec2::ZERO);
+    assert_eq!(position(&app, walker), target);
+    assert!(closest > RADIUS, "{closest}");
+}
+
+#[test]
+fn units_on_top_of_each_other_are_pushed_apart() {
+    let mut app = field();
+    let a = spawn_unit(&mut app, Vec2::ZERO, None);
+    let b = spawn_unit(&mut app, Vec2::ZERO, None);
+
+    app.world.run_schedule(FixedUpdate);
+    let distance = position(&app, a).distance(position(&app, b));
+    assert!(distance > 2.0 * RADIUS - 0.01, "{distance}");
+}
+
+#[test]
+fn clients_push_units_on_top_of_each_other_the_way_the_server_does() {
+    let mut app = field();
+    // Network ids in the opposite order to the entities, as a client's may well be.
+    let a = spawn_unit(&mut app, Vec2::ZERO, None);
+    let b = spawn_unit(&mut app, Vec2::ZERO, None);
+    app.world.entity_mut(a).insert(NetworkId(7));
+    app.world.entity_mut(b).insert(NetworkId(3));
+    app.world.run_schedule(FixedUpdate);
+
+    // A client predicting `a` only knows the network ids.
+    let unit = |id| {
+        let movement = Movement {
+            speed: 6.0,
+            target: None,
+        };
+        Body::new(
+            id,
+            &Position(Vec2::ZERO),
+            &Collider { radius: RADIUS },
+            &movement,
+            None,
+        )
+    };
+    let mut predicted = Position(Vec2::ZERO);
+    let push = steering::separation(&unit(7), [unit(3)]);
+    steering::push_unit(&mut predicted, push, None);
+
+    assert_eq!(predicted.0, position(&app, a));
+    assert_ne!(position(&app, a), position(&app, b));
+}
+
+#[test]
+fn ghosts_walk_through_units() {
+    let mut app = field();
+    let target = Vec2::new(5.0, 0.0);
+    let ghost = spawn_unit(&mut app, Vec2::new(-5.0, 0.0), Some(target));
+    app.world.entity_mut(ghost).insert(Ghosted);
+    let blocker = spawn_unit(&mut app, Vec2::ZERO, None);
+
+    let closest = run(&mut app, ghost, blocker);
+    assert!(closest < 0.1, "{closest}");
+    assert_eq!(position(&app, ghost), target);
+}
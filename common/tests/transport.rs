@@ -0,0 +1,294 @@
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use common::network::{
+    game::{
+        transport::{
+            Channel, ClientEvent, ClientSocket, Connection, ServerEvent, ServerSocket,
+            MAX_MESSAGE_SIZE, MAX_PACKET_SIZE, TIMEOUT,
+        },
+        GameClientHello, JoinToken,
+    },
+    lobby::PlayerId,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+const STEP: Duration = Duration::from_millis(10);
+
+/// Two connections with a lossy, reordering link between them.
+struct Link {
+    a: Connection,
+    b: Connection,
+    now: Instant,
+    /// Every packet whose number is a multiple of this is dropped.
+    drop_every: usize,
+    sent: usize,
+}
+
+impl Link {
+    fn new(drop_every: usize) -> Self {
+        let now = Instant::now();
+        Self {
+            a: Connection::new(now),
+            b: Connection::new(now),
+            now,
+            drop_every,
+            sent: 0,
+        }
+    }
+
+    /// Advances time by one step and exchanges packets, delivering each direction's
+    /// surviving packets in reverse order.
+    fn step(&mut self) {
+        self.now += STEP;
+        for (from_a, packets) in [
+            (true, self.a.transmit(self.now)),
+            (false, self.b.transmit(self.now)),
+        ] {
+            let mut delivered = Vec::new();
+            for packet in packets {
+                assert!(packet.len() <= MAX_PACKET_SIZE);
+                self.sent += 1;
+                if !self.sent.is_multiple_of(self.drop_every) {
+                    delivered.push(packet);
+                }
+            }
+            let to = if from_a { &mut self.b } else { &mut self.a };
+            for packet in delivered.into_iter().rev() {
+                to.receive(&packet, self.now).unwrap();
+            }
+        }
+    }
+
+    fn received_by_b(&mut self) -> Vec<(Channel, Vec<u8>)> {
+        std::iter::from_fn(|| self.b.poll_message()).collect()
+    }
+}
+
+#[test]
+fn reliable_messages_arrive_once_and_in_order_despite_loss() {
+    let mut link = Link::new(3);
+    let mut received = Vec::new();
+    for i in 0..200u32 {
+        link.a.send(Channel::Reliable, &i.to_be_bytes()).unwrap();
+        link.step();
+        received.extend(link.received_by_b());
+    }
+    for _ in 0..100 {
+        link.step();
+        received.extend(link.received_by_b());
+    }
+
+    let expected = (0..200u32)
+        .map(|i| (Channel::Reliable, i.to_be_bytes().to_vec()))
+        .collect::<Vec<_>>();
+    assert_eq!(received, expected);
+    assert!(link.a.is_idle());
+}
+
+#[test]
+fn unreliable_messages_older_than_the_newest_are_dropped() {
+    let mut link = Link::new(usize::MAX);
+    for i in 0..3u8 {
+        link.a.send(Channel::Unreliable, &[i; 2000]).unwrap();
+    }
+    link.a.send(Channel::Unreliable, &[3]).unwrap();
+    // Everything goes out in one step and is delivered back to front, so only the last
+    // message is left standing.
+    link.step();
+
+    assert_eq!(link.received_by_b(), vec![(Channel::Unreliable, vec![3])]);
+}
+
+#[test]
+fn large_messages_are_split_and_reassembled() {
+    let mut link = Link::new(4);
+    let big = (0..20_000).map(|i| i as u8).collect::<Vec<_>>();
+    link.a.send(Channel::Reliable, &big).unwrap();
+    let mut received = Vec::new();
+    for _ in 0..50 {
+        link.step();
+        received.extend(link.received_by_b());
+    }
+    assert_eq!(received, vec![(Channel::Reliable, big.clone())]);
+
+    let mut link = Link::new(usize::MAX);
+    link.a.send(Channel::Unreliable, &big).unwrap();
+    link.step();
+    assert_eq!(link.received_by_b(), vec![(Channel::Unreliable, big)]);
+}
+
+#[test]
+fn connections_time_out_when_the_other_side_goes_quiet() {
+    let mut link = Link::new(usize::MAX);
+    link.step();
+    assert!(!link.b.is_timed_out(link.now));
+    assert!(link.b.is_timed_out(link.now + TIMEOUT));
+
+    link.now += TIMEOUT / 2;
+    link.step();
+    assert!(!link.b.is_timed_out(link.now + TIMEOUT / 2));
+}
+
+/// The transport's packets as they go over the wire, for sending ones that a well-behaved
+/// [`Connection`] never would. Variants are numbered by position, so the ones that aren't
+/// used here only hold their place.
+#[derive(Serialize)]
+#[allow(dead_code)]
+enum WirePacket {
+    Connect,
+    Accepted,
+    Refused,
+    Data {
+        sequence: u16,
+        ack: Option<u16>,
+        ack_bits: u32,
+        frames: Vec<WireFrame>,
+    },
+}
+
+#[derive(Serialize)]
+#[allow(dead_code)]
+enum WireFrame {
+    Unreliable,
+    Reliable {
+        id: u16,
+        fragment: (u8, u8),
+        data: Vec<u8>,
+    },
+}
+
+/// A packet carrying reliable fragments `(index, last, data)` with consecutive ids from
+/// `first_id`.
+fn reliable_packet(sequence: u16, first_id: u16, fragments: Vec<(u8, u8, Vec<u8>)>) -> Vec<u8> {
+    let frames = fragments
+        .into_iter()
+        .zip(first_id..)
+        .map(|((index, last, data), id)| WireFrame::Reliable {
+            id,
+            fragment: (index, last),
+            data,
+        })
+        .collect();
+    postcard::to_allocvec(&WirePacket::Data {
+        sequence,
+        ack: None,
+        ack_bits: 0,
+        frames,
+    })
+    .unwrap()
+}
+
+#[test]
+fn reliable_fragments_out_of_sequence_are_dropped() {
+    let now = Instant::now();
+    let mut connection = Connection::new(now);
+    let packet = reliable_packet(
+        0,
+        0,
+        vec![
+            // Skips a fragment...
+            (0, 2, b"ab".to_vec()),
+            (2, 2, b"ef".to_vec()),
+            // ...changes how many fragments there are...
+            (0, 2, b"ab".to_vec()),
+            (1, 3, b"cd".to_vec()),
+            (2, 3, b"ef".to_vec()),
+            // ...and finally gets it right.
+            (0, 1, b"ab".to_vec()),
+            (1, 1, b"cd".to_vec()),
+        ],
+    );
+    connection.receive(&packet, now).unwrap();
+
+    let received = std::iter::from_fn(|| connection.poll_message()).collect::<Vec<_>>();
+    assert_eq!(received, vec![(Channel::Reliable, b"abcd".to_vec())]);
+    assert!(!connection.is_closed());
+}
+
+#[test]
+fn connections_sending_oversized_reliable_messages_are_closed() {
+    let now = Instant::now();
+    let mut connection = Connection::new(now);
+    // Each fragment holds half the largest message, so three are more than any sender
+    // could have split one into.
+    let fragments = (0..3)
+        .map(|index| (index, 255, vec![0; MAX_MESSAGE_SIZE / 2]))
+        .collect::<Vec<_>>();
+    for (sequence, fragment) in fragments.into_iter().enumerate() {
+        let packet = reliable_packet(sequence as u16, sequence as u16, vec![fragment]);
+        connection.receive(&packet, now).unwrap();
+    }
+
+    assert!(connection.is_closed());
+    assert_eq!(connection.poll_message(), None);
+}
+
+fn hello(token: JoinToken) -> GameClientHello {
+    GameClientHello {
+        player: PlayerId(Uuid::new_v4()),
+        token,
+    }
+}
+
+/// Pumps both sockets until the client reports something.
+fn pump(
+    server: &mut ServerSocket,
+    client: &mut ClientSocket,
+    mut on_server: impl FnMut(&mut ServerSocket, ServerEvent),
+) -> Vec<ClientEvent> {
+    for _ in 0..200 {
+        let now = Instant::now();
+        client.flush(now);
+        std::thread::sleep(Duration::from_millis(5));
+        for event in server.receive(now) {
+            on_server(server, event);
+        }
+        server.flush(now);
+        std::thread::sleep(Duration::from_millis(5));
+        let events = client.receive(Instant::now());
+        if !events.is_empty() {
+            return events;
+        }
+    }
+    panic!("The client heard nothing");
+}
+
+#[test]
+fn the_server_decides_who_connects() {
+    let mut server = ServerSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr: SocketAddr = server.local_addr().unwrap();
+    let valid = JoinToken(Uuid::new_v4());
+    let answer = move |server: &mut ServerSocket, event| {
+        if let ServerEvent::ConnectRequest { addr, hello } = event {
+            if hello.token == valid {
+                server.accept(addr);
+            } else {
+                server.refuse(addr, "Invalid join token".into());
+            }
+        }
+    };
+
+    let mut client =
+        ClientSocket::connect(addr, hello(JoinToken(Uuid::new_v4())), Instant::now()).unwrap();
+    let events = pump(&mut server, &mut client, answer);
+    assert!(
+        matches!(&events[..], [ClientEvent::Refused { reason }] if reason == "Invalid join token")
+    );
+
+    let mut client = ClientSocket::connect(addr, hello(valid), Instant::now()).unwrap();
+    let events = pump(&mut server, &mut client, answer);
+    assert!(matches!(events[..], [ClientEvent::Connected]));
+
+    client.send(Channel::Reliable, b"hello").unwrap();
+    let events = pump(&mut server, &mut client, |server, event| {
+        if let ServerEvent::Message { addr, message, .. } = event {
+            assert_eq!(message, b"hello");
+            server.send(addr, Channel::Reliable, b"welcome").unwrap();
+        }
+    });
+    assert!(matches!(&events[..], [ClientEvent::Message { message, .. }] if message == b"welcome"));
+}
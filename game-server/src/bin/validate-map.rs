@@ -0,0 +1,44 @@
+//! Checks map files for missing structures and areas units can't reach, before a game
+//! server tries to host a match on them.
+
+use std::{path::Path, process::ExitCode};
+
+use common::map::MapDefinition;
+
+const USAGE: &str = "Usage: validate-map <map file>...";
+
+fn main() -> ExitCode {
+    let paths = std::env::args().skip(1).collect::<Vec<_>>();
+    if paths.is_empty() {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    }
+
+    let mut valid = true;
+    for path in &paths {
+        match MapDefinition::read(Path::new(path)) {
+            Ok(map) => {
+                let problems = map.problems();
+                if problems.is_empty() {
+                    println!("{path}: OK");
+                } else {
+                    valid = false;
+                    println!("{path}: {} problems", problems.len());
+                    for problem in problems {
+                        println!("    {problem}");
+                    }
+                }
+            }
+            Err(e) => {
+                valid = false;
+                println!("{path}: {e:#}");
+            }
+        }
+    }
+
+    if valid {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
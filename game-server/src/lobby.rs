@@ -0,0 +1,77 @@
+//! The connection to the lobby server that handed out the match, which hears how the
+//! match is going and how it ended.
+
+use std::net::TcpStream;
+
+use bevy::prelude::*;
+use common::{
+    gameplay::{MatchOver, Tick},
+    network::{
+        allocation::{GameServerReport, HEALTH_INTERVAL},
+        TcpStreamExt,
+    },
+};
+
+use crate::network::Connections;
+
+pub struct LobbyPlugin {
+    pub stream: TcpStream,
+}
+
+impl Plugin for LobbyPlugin {
+    fn build(&self, app: &mut App) {
+        let stream = self
+            .stream
+            .try_clone()
+            .expect("Could not clone the lobby server connection");
+
+        app.insert_resource(LobbyLink {
+            stream,
+            health_timer: Timer::new(HEALTH_INTERVAL, TimerMode::Repeating),
+            finished: false,
+        })
+        .add_systems(Update, (report_health, report_result));
+    }
+}
+
+#[derive(Resource)]
+struct LobbyLink {
+    stream: TcpStream,
+    health_timer: Timer,
+    /// Set once the result is reported, after which there is nothing more to say.
+    finished: bool,
+}
+
+impl LobbyLink {
+    fn report(&mut self, report: GameServerReport) {
+        if let Err(e) = self.stream.write_message(&report) {
+            eprintln!("Could not report to the lobby server: {e}");
+        }
+    }
+}
+
+fn report_health(
+    time: Res<Time<Real>>,
+    tick: Res<Tick>,
+    connections: Res<Connections>,
+    mut link: ResMut<LobbyLink>,
+) {
+    if !link.health_timer.tick(time.delta()).just_finished() || link.finished {
+        return;
+    }
+
+    link.report(GameServerReport::Health {
+        connected_players: connections.connected_players(),
+        tick: tick.0,
+    });
+}
+
+/// Tells the lobby server who won, so it can close the lobby.
+fn report_result(mut over: EventReader<MatchOver>, mut link: ResMut<LobbyLink>) {
+    let Some(&MatchOver { winner }) = over.read().next() else {
+        return;
+    };
+
+    link.report(GameServerReport::Finished { winner });
+    link.finished = true;
+}
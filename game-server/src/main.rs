@@ -1,3 +1,216 @@
+//! Hosts a single match: takes it from the lobby server, runs the simulation at a fixed
+//! tick rate for the players assigned to it, and exits once the match is over.
+
+use std::{
+    net::{Ipv4Addr, SocketAddr, TcpStream, UdpSocket},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{ensure, Context};
+use bevy::{app::ScheduleRunnerPlugin, prelude::*, utils::HashMap};
+use common::{
+    gameplay::{
+        self, abilities::LagCompensation, navigation::NavGrid, SimulationPlugin, TICK_RATE,
+    },
+    map::MapDefinition,
+    network::{
+        allocation::{AllocationRequest, GameServerReport},
+        game::{JoinToken, MatchSetup},
+        lobby::{LobbyId, PlayerId},
+        TcpStreamExt,
+    },
+    replication::ReplicationPlugin,
+    Side,
+};
+use lobby::LobbyPlugin;
+use network::NetworkPlugin;
+
+mod lobby;
+mod network;
+
+const DEFAULT_LISTEN_ADDR: &str = "[::]:65433";
+
+const DEFAULT_MAPS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/maps");
+
+const USAGE: &str = "Usage: game-server <lobby server allocation address> [listen address] \
+    [public address] [--max-rewind-ms <milliseconds>] [--maps-dir <directory>]";
+
+/// The match this server is hosting.
+#[derive(Resource)]
+pub struct Match(pub MatchSetup);
+
+/// What each player has to present to join the match.
+#[derive(Resource)]
+pub struct JoinTokens(pub HashMap<PlayerId, JoinToken>);
+
 fn main() {
-    println!("Hello, world!");
+    let mut compensation = LagCompensation::default();
+    let mut maps_dir = PathBuf::from(DEFAULT_MAPS_DIR);
+    let mut positional = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--max-rewind-ms" => match args.next().and_then(|ms| ms.parse().ok()) {
+                Some(ms) => compensation = LagCompensation::new(Duration::from_millis(ms)),
+                None => {
+                    eprintln!("{USAGE}");
+                    return;
+                }
+            },
+            "--maps-dir" => match args.next() {
+                Some(dir) => maps_dir = dir.into(),
+                None => {
+                    eprintln!("{USAGE}");
+                    return;
+                }
+            },
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut args = positional.into_iter();
+    let Some(lobby_addr) = args.next() else {
+        eprintln!("{USAGE}");
+        return;
+    };
+
+    let listen_addr = args.next().unwrap_or_else(|| DEFAULT_LISTEN_ADDR.into());
+    let socket = match listen_addr
+        .parse::<SocketAddr>()
+        .context("Invalid listen address")
+        .and_then(|addr| UdpSocket::bind(addr).context("Could not listen"))
+    {
+        Ok(socket) => socket,
+        Err(e) => {
+            eprintln!("{e:#}: {listen_addr}");
+            return;
+        }
+    };
+    let public_addr = match args.next().map(|addr| addr.parse::<SocketAddr>()) {
+        Some(Ok(addr)) => addr,
+        Some(Err(e)) => {
+            eprintln!("Invalid public address: {e}");
+            return;
+        }
+        None => default_public_addr(socket.local_addr().unwrap()),
+    };
+
+    let Assignment {
+        stream,
+        lobby,
+        setup,
+        map,
+        tokens,
+    } = match wait_for_match(&lobby_addr, public_addr, &maps_dir) {
+        Ok(assignment) => assignment,
+        Err(e) => {
+            eprintln!("Could not get a match from the lobby server: {e:#}");
+            return;
+        }
+    };
+    println!(
+        "Hosting the match for lobby {lobby} on {} with {} players on {listen_addr}",
+        map.name,
+        setup.participants.len()
+    );
+
+    App::new()
+        .add_plugins(
+            MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+                1.0 / TICK_RATE,
+            ))),
+        )
+        .add_plugins((
+            SimulationPlugin,
+            ReplicationPlugin,
+            NetworkPlugin { socket },
+            LobbyPlugin { stream },
+        ))
+        .insert_resource(compensation)
+        .insert_resource(Match(setup))
+        .insert_resource(map)
+        .insert_resource(JoinTokens(tokens))
+        .add_systems(Startup, start_match)
+        .run();
+
+    println!("Shut down");
+}
+
+/// Where clients should connect when told nothing else: the listen address, unless
+/// that is a wildcard.
+fn default_public_addr(listen_addr: SocketAddr) -> SocketAddr {
+    if listen_addr.ip().is_unspecified() {
+        SocketAddr::new(Ipv4Addr::LOCALHOST.into(), listen_addr.port())
+    } else {
+        listen_addr
+    }
+}
+
+/// A match handed to this server by the lobby server.
+struct Assignment {
+    /// The connection to the lobby server, which stays open while the match is played.
+    stream: TcpStream,
+    lobby: LobbyId,
+    setup: MatchSetup,
+    map: MapDefinition,
+    tokens: HashMap<PlayerId, JoinToken>,
+}
+
+/// Tells the lobby server this game server is ready, and waits until it is given a
+/// match.
+fn wait_for_match(
+    lobby_addr: &str,
+    public_addr: SocketAddr,
+    maps_dir: &Path,
+) -> anyhow::Result<Assignment> {
+    let mut stream = TcpStream::connect(lobby_addr)
+        .with_context(|| format!("Could not connect to {lobby_addr}"))?;
+    stream.write_message(&GameServerReport::Ready { addr: public_addr })?;
+    println!("Waiting for a match from {lobby_addr}, reachable at {public_addr}");
+
+    let AllocationRequest::HostMatch {
+        lobby,
+        setup,
+        tokens,
+    } = stream.read_message::<AllocationRequest>(None)?;
+    setup
+        .rules
+        .check()
+        .with_context(|| format!("Invalid rules for lobby {lobby}"))?;
+    ensure!(
+        !setup.rules.all_random || setup.participants.iter().all(|p| p.champion.is_some()),
+        "Lobby {lobby} is all random, but not everyone was given a champion"
+    );
+    let map = load_map(maps_dir, &setup)?;
+    stream.write_message(&GameServerReport::Hosting { lobby })?;
+    Ok(Assignment {
+        stream,
+        lobby,
+        setup,
+        map,
+        tokens,
+    })
+}
+
+/// Loads the match's map from `maps_dir`, making sure every side has a base on it.
+fn load_map(maps_dir: &Path, setup: &MatchSetup) -> anyhow::Result<MapDefinition> {
+    let name = &setup.map;
+    ensure!(
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+        "Invalid map name {name:?}"
+    );
+    let map = MapDefinition::load(&maps_dir.join(format!("{name}.json")))?;
+    for side in Side::all(setup.teams) {
+        ensure!(map.base(side).is_some(), "{name} has no base for {side}");
+    }
+    Ok(map)
+}
+
+fn start_match(setup: Res<Match>, map: Res<MapDefinition>, mut commands: Commands) {
+    commands.insert_resource(NavGrid::new(&map.terrain));
+    gameplay::spawn_match(&mut commands, &setup.0, &map);
 }
@@ -0,0 +1,446 @@
+//! Connections from the match's players, over the UDP transport. The socket is
+//! non-blocking and is polled once per frame.
+//!
+//! After every tick, each player is sent a snapshot of the replicated world relative to
+//! the last one they acknowledged.
+
+use std::{
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use anyhow::{ensure, Context};
+use bevy::{app::AppExit, prelude::*, utils::HashMap};
+use common::{
+    gameplay::{AppliedInputs, ChampionInput, Inputs, MatchOver, Tick},
+    map::MapDefinition,
+    network::{
+        game::{
+            snapshot::SnapshotHistory,
+            transport::{Channel, ServerEvent, ServerSocket},
+            Controller, GameClientHello, GameClientMessage, GameServerMessage, MatchSetup,
+        },
+        lobby::PlayerId,
+    },
+    replication::{self, assign_network_ids},
+};
+
+use crate::{JoinTokens, Match};
+
+/// How long to wait for the first player before giving up on the match.
+const JOIN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long to keep resending how the match ended before exiting anyway.
+const LINGER_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct NetworkPlugin {
+    pub socket: UdpSocket,
+}
+
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut App) {
+        let socket = self
+            .socket
+            .try_clone()
+            .and_then(ServerSocket::new)
+            .expect("Could not set up the game socket");
+
+        app.insert_resource(Connections {
+            socket,
+            players: HashMap::new(),
+            addrs: HashMap::new(),
+            anyone_joined: false,
+            started: Instant::now(),
+            ending: None,
+        })
+        .add_systems(
+            Update,
+            (
+                receive,
+                abandon_match,
+                end_match,
+                assign_network_ids,
+                send_snapshots.run_if(resource_changed::<Tick>),
+                flush,
+            )
+                .chain(),
+        );
+    }
+}
+
+#[derive(Resource)]
+pub struct Connections {
+    socket: ServerSocket,
+    players: HashMap<PlayerId, Peer>,
+    addrs: HashMap<SocketAddr, PlayerId>,
+    /// Whether any player has connected so far.
+    anyone_joined: bool,
+    started: Instant,
+    /// Set once the match is over. The server exits when everyone has heard, or at this
+    /// deadline.
+    ending: Option<Instant>,
+}
+
+struct Peer {
+    addr: SocketAddr,
+    /// The newest snapshot the player has acknowledged.
+    acked: Option<u64>,
+}
+
+impl Connections {
+    pub fn connected_players(&self) -> usize {
+        self.players.len()
+    }
+
+    fn send(&mut self, player: PlayerId, channel: Channel, msg: &GameServerMessage) {
+        let Some(addr) = self.players.get(&player).map(|peer| peer.addr) else {
+            return;
+        };
+        let bytes = postcard::to_allocvec(msg).expect("Messages can always be encoded");
+        if let Err(e) = self.socket.send(addr, channel, &bytes) {
+            eprintln!("Could not send to {player}: {e}");
+        }
+    }
+}
+
+fn receive(
+    setup: Res<Match>,
+    map: Res<MapDefinition>,
+    tokens: Res<JoinTokens>,
+    tick: Res<Tick>,
+    mut connections: ResMut<Connections>,
+    mut inputs: ResMut<Inputs>,
+) {
+    if connections.ending.is_some() {
+        return;
+    }
+
+    for event in connections.socket.receive(Instant::now()) {
+        match event {
+            ServerEvent::ConnectRequest { addr, hello } => {
+                let player = hello.player;
+                if let Err(e) = check_player(&setup.0, &tokens, &connections, &hello) {
+                    println!("Turned away {player}: {e}");
+                    connections.socket.refuse(addr, e.to_string());
+                    continue;
+                }
+
+                println!("{player} joined from {addr}");
+                connections.socket.accept(addr);
+                connections
+                    .players
+                    .insert(player, Peer { addr, acked: None });
+                connections.addrs.insert(addr, player);
+                connections.anyone_joined = true;
+                let welcome = GameServerMessage::Welcome {
+                    setup: setup.0.clone(),
+                    map: Box::new(map.clone()),
+                    tick: tick.0,
+                };
+                connections.send(player, Channel::Reliable, &welcome);
+            }
+            ServerEvent::Message { addr, message, .. } => {
+                let Some(&player) = connections.addrs.get(&addr) else {
+                    continue;
+                };
+                match postcard::from_bytes::<GameClientMessage>(&message) {
+                    Ok(GameClientMessage::Command { command, .. }) if !command.is_valid() => {
+                        eprintln!("Bad command from {player}: {command:?}");
+                    }
+                    Ok(GameClientMessage::Command {
+                        sequence,
+                        seen_tick,
+                        command,
+                    }) => {
+                        inputs.0.push(ChampionInput {
+                            player,
+                            sequence,
+                            command,
+                            seen_tick,
+                        });
+                    }
+                    Ok(GameClientMessage::AckSnapshot { tick }) => {
+                        if let Some(peer) = connections.players.get_mut(&player) {
+                            peer.acked = peer.acked.max(Some(tick));
+                        }
+                    }
+                    Err(e) => eprintln!("Bad message from {player}: {e}"),
+                }
+            }
+            ServerEvent::Disconnected { addr } => {
+                if let Some(player) = connections.addrs.remove(&addr) {
+                    connections.players.remove(&player);
+                    println!("{player} left");
+                }
+            }
+        }
+    }
+}
+
+/// Fails unless the hello comes from a human in the match who isn't connected yet, with
+/// the token the lobby server gave them.
+fn check_player(
+    setup: &MatchSetup,
+    tokens: &JoinTokens,
+    connections: &Connections,
+    hello: &GameClientHello,
+) -> anyhow::Result<()> {
+    let player = hello.player;
+    let participant = setup
+        .participants
+        .iter()
+        .find(|p| p.id == player)
+        .context("You are not in this match")?;
+    ensure!(
+        participant.controller == Controller::Human,
+        "That player is controlled by the server"
+    );
+    ensure!(
+        tokens.0.get(&player) == Some(&hello.token),
+        "Invalid join token"
+    );
+    ensure!(
+        !connections.players.contains_key(&player),
+        "That player is already connected"
+    );
+    Ok(())
+}
+
+/// Ends the match without a winner once every player has left, or if nobody joins in
+/// time.
+fn abandon_match(
+    connections: Res<Connections>,
+    mut over: EventWriter<MatchOver>,
+    mut abandoned: Local<bool>,
+) {
+    if *abandoned || !connections.players.is_empty() {
+        return;
+    }
+
+    if connections.anyone_joined || connections.started.elapsed() >= JOIN_TIMEOUT {
+        println!("Abandoning the match: no players are connected");
+        *abandoned = true;
+        over.send(MatchOver { winner: None });
+    }
+}
+
+/// Tells everyone how the match ended. [`flush`] exits once they have all heard.
+fn end_match(mut over: EventReader<MatchOver>, mut connections: ResMut<Connections>) {
+    let Some(&MatchOver { winner }) = over.read().next() else {
+        return;
+    };
+
+    match winner {
+        Some(side) => println!("{side} won the match"),
+        None => println!("The match ended without a winner"),
+    }
+
+    let players = connections.players.keys().copied().collect::<Vec<_>>();
+    for player in players {
+        connections.send(
+            player,
+            Channel::Reliable,
+            &GameServerMessage::MatchEnded { winner },
+        );
+    }
+    connections.ending = Some(Instant::now() + LINGER_TIMEOUT);
+}
+
+/// Sends every player the world as of the latest tick, relative to the last snapshot they
+/// acknowledged if it is still remembered, and which of their commands it reflects.
+fn send_snapshots(world: &mut World, mut history: Local<SnapshotHistory>) {
+    let tick = world.resource::<Tick>().0;
+    let snapshot = replication::capture_snapshot(world, tick);
+
+    world.resource_scope(|world, mut connections: Mut<Connections>| {
+        if connections.ending.is_some() {
+            return;
+        }
+        let applied = world.resource::<AppliedInputs>();
+        let players = connections
+            .players
+            .iter()
+            .map(|(&player, peer)| (player, peer.acked))
+            .collect::<Vec<_>>();
+        for (player, acked) in players {
+            let baseline = acked.and_then(|tick| history.get(tick));
+            let msg = GameServerMessage::Snapshot {
+                delta: snapshot.delta_from(baseline),
+                input: applied.0.get(&player).copied(),
+            };
+            connections.send(player, Channel::Unreliable, &msg);
+        }
+    });
+    history.push(snapshot);
+}
+
+/// Sends everything queued this frame, and exits once everyone has acknowledged how the
+/// match ended.
+fn flush(mut connections: ResMut<Connections>, mut exit: EventWriter<AppExit>) {
+    let now = Instant::now();
+    if let Some(deadline) = connections.ending {
+        // Acks still have to be read while waiting.
+        connections.socket.receive(now);
+        if connections.socket.is_idle() || now >= deadline {
+            let addrs = connections.addrs.keys().copied().collect::<Vec<_>>();
+            for addr in addrs {
+                connections.socket.disconnect(addr);
+            }
+            exit.send(AppExit);
+            return;
+        }
+    }
+    connections.socket.flush(now);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv4Addr, path::Path, thread};
+
+    use common::{
+        gameplay::ChampionCommand,
+        network::game::{
+            transport::{ClientEvent, ClientSocket},
+            JoinToken, MatchParticipant,
+        },
+        replication::ReplicationPlugin,
+        rules::GameRules,
+        Side,
+    };
+    use uuid::Uuid;
+
+    use super::*;
+
+    const PLAYER: PlayerId = PlayerId(Uuid::from_u128(1));
+    const TOKEN: JoinToken = JoinToken(Uuid::from_u128(2));
+
+    /// A server for a match with one human player, and its address.
+    fn server() -> (App, SocketAddr) {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        let map =
+            MapDefinition::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("maps/default.json"))
+                .unwrap();
+        let setup = MatchSetup {
+            map: "default".into(),
+            teams: 2,
+            rules: GameRules::default(),
+            participants: vec![MatchParticipant {
+                id: PLAYER,
+                username: "player".into(),
+                side: Side::RED,
+                champion: None,
+                role: None,
+                controller: Controller::Human,
+            }],
+        };
+
+        let mut app = App::new();
+        app.add_event::<MatchOver>()
+            .add_event::<AppExit>()
+            .init_resource::<Tick>()
+            .init_resource::<Inputs>()
+            .init_resource::<AppliedInputs>()
+            .insert_resource(Match(setup))
+            .insert_resource(map)
+            .insert_resource(JoinTokens(HashMap::from([(PLAYER, TOKEN)])))
+            .add_plugins((ReplicationPlugin, NetworkPlugin { socket }));
+        (app, addr)
+    }
+
+    fn connect(addr: SocketAddr, token: JoinToken) -> ClientSocket {
+        let hello = GameClientHello {
+            player: PLAYER,
+            token,
+        };
+        ClientSocket::connect(addr, hello, Instant::now()).unwrap()
+    }
+
+    /// Lets the client and the server talk for a while, and returns what the client heard.
+    fn exchange(app: &mut App, client: &mut ClientSocket) -> Vec<ClientEvent> {
+        let mut events = Vec::new();
+        for _ in 0..20 {
+            client.flush(Instant::now());
+            thread::sleep(Duration::from_millis(5));
+            app.update();
+            thread::sleep(Duration::from_millis(5));
+            events.extend(client.receive(Instant::now()));
+        }
+        events
+    }
+
+    fn send_command(client: &mut ClientSocket, sequence: u32, command: ChampionCommand) {
+        let msg = GameClientMessage::Command {
+            sequence,
+            seen_tick: 0,
+            command,
+        };
+        client
+            .send(Channel::Reliable, &postcard::to_allocvec(&msg).unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn players_with_the_right_token_are_welcomed() {
+        let (mut app, addr) = server();
+        let mut client = connect(addr, TOKEN);
+
+        let events = exchange(&mut app, &mut client);
+        assert!(matches!(events[0], ClientEvent::Connected));
+        let welcomed = events.iter().any(|event| match event {
+            ClientEvent::Message { message, .. } => matches!(
+                postcard::from_bytes(message),
+                Ok(GameServerMessage::Welcome { .. })
+            ),
+            _ => false,
+        });
+        assert!(welcomed);
+        assert_eq!(app.world.resource::<Connections>().connected_players(), 1);
+    }
+
+    #[test]
+    fn players_with_the_wrong_token_are_refused() {
+        let (mut app, addr) = server();
+        let mut client = connect(addr, JoinToken(Uuid::from_u128(3)));
+
+        let events = exchange(&mut app, &mut client);
+        assert!(matches!(
+            &events[..],
+            [ClientEvent::Refused { reason }] if reason == "Invalid join token"
+        ));
+        assert_eq!(app.world.resource::<Connections>().connected_players(), 0);
+    }
+
+    #[test]
+    fn commands_become_inputs() {
+        let (mut app, addr) = server();
+        let mut client = connect(addr, TOKEN);
+        exchange(&mut app, &mut client);
+
+        let target = Vec2::new(3.0, 4.0);
+        send_command(&mut client, 0, ChampionCommand::MoveTo(target));
+        exchange(&mut app, &mut client);
+
+        let inputs = &app.world.resource::<Inputs>().0;
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0].player, PLAYER);
+        assert_eq!(inputs[0].command, ChampionCommand::MoveTo(target));
+    }
+
+    #[test]
+    fn commands_with_bad_coordinates_are_dropped() {
+        let (mut app, addr) = server();
+        let mut client = connect(addr, TOKEN);
+        exchange(&mut app, &mut client);
+
+        send_command(&mut client, 0, ChampionCommand::MoveTo(Vec2::NAN));
+        send_command(
+            &mut client,
+            1,
+            ChampionCommand::MoveTo(Vec2::new(f32::INFINITY, 0.0)),
+        );
+        exchange(&mut app, &mut client);
+
+        assert!(app.world.resource::<Inputs>().0.is_empty());
+    }
+}
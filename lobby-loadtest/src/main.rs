@@ -0,0 +1,282 @@
+//! Simulates many lobby clients against a local `lobby-server` and reports how it copes.
+
+mod report;
+mod script;
+
+use std::{
+    net::{SocketAddr, TcpStream},
+    path::PathBuf,
+    sync::{mpsc, Arc},
+    time::{Duration, Instant},
+};
+
+use common::{
+    champion::Role,
+    network::{
+        lobby::{
+            LobbyClientMessage, LobbyClientNewConnectionMessage, LobbyId, LobbyServerMessage,
+            LobbySettings, RolePreference,
+        },
+        TcpStreamExt,
+    },
+};
+use report::{Report, Sample};
+use script::{Script, Step};
+
+/// How long a client waits for the answer to a request before counting it as lost.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const USAGE: &str = "\
+Usage: lobby-loadtest [options]
+
+Options:
+    --addr <addr>       Lobby server to connect to (default [::1]:65432)
+    --clients <n>       Number of simulated clients (default 100)
+    --ramp-up <ms>      Delay between starting clients (default 10)
+    --script <path>     JSON script for the clients to follow (default: built-in)";
+
+struct Options {
+    addr: SocketAddr,
+    clients: usize,
+    ramp_up: Duration,
+    script: Option<PathBuf>,
+}
+
+fn main() {
+    let options = match parse_options() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+
+    let script = match &options.script {
+        Some(path) => match Script::load(path) {
+            Ok(script) => script,
+            Err(e) => {
+                eprintln!("Could not load {}: {e}", path.display());
+                std::process::exit(1);
+            }
+        },
+        None => Script::default(),
+    };
+    let script = Arc::new(script);
+
+    println!(
+        "Running {} clients against {}...",
+        options.clients, options.addr
+    );
+
+    let (send, recv) = mpsc::channel();
+    let start = Instant::now();
+
+    for index in 0..options.clients {
+        let send = send.clone();
+        let script = script.clone();
+        let addr = options.addr;
+        std::thread::spawn(move || run_client(index, addr, &script, send));
+        std::thread::sleep(options.ramp_up);
+    }
+    drop(send);
+
+    let mut report = Report::default();
+    for event in recv {
+        match event {
+            ClientEvent::Sample(sample) => report.add(sample),
+            ClientEvent::ConnectFailed => report.connect_failed(),
+        }
+    }
+
+    report.print(start.elapsed());
+}
+
+fn parse_options() -> anyhow::Result<Options> {
+    let mut options = Options {
+        addr: "[::1]:65432".parse()?,
+        clients: 100,
+        ramp_up: Duration::from_millis(10),
+        script: None,
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow::anyhow!(USAGE));
+        match arg.as_str() {
+            "--addr" => options.addr = value()?.parse()?,
+            "--clients" => options.clients = value()?.parse()?,
+            "--ramp-up" => options.ramp_up = Duration::from_millis(value()?.parse()?),
+            "--script" => options.script = Some(value()?.into()),
+            _ => anyhow::bail!(USAGE),
+        }
+    }
+
+    Ok(options)
+}
+
+enum ClientEvent {
+    Sample(Sample),
+    ConnectFailed,
+}
+
+struct Client {
+    username: String,
+    stream: TcpStream,
+    known_lobbies: Vec<LobbyId>,
+    in_lobby: bool,
+    rng: u64,
+}
+
+fn run_client(index: usize, addr: SocketAddr, script: &Script, events: mpsc::Sender<ClientEvent>) {
+    let username = format!("bot-{index}");
+
+    let Ok((stream, username)) = connect(addr, username) else {
+        let _ = events.send(ClientEvent::ConnectFailed);
+        return;
+    };
+
+    let mut client = Client {
+        username,
+        stream,
+        known_lobbies: vec![],
+        in_lobby: false,
+        rng: 0x9E37_79B9_7F4A_7C15 ^ (index as u64 + 1),
+    };
+
+    for _ in 0..script.repeat {
+        for step in &script.steps {
+            if let Some(wait) = step.wait() {
+                std::thread::sleep(wait);
+                continue;
+            }
+
+            let Some(sample) = client.run_step(step) else {
+                continue;
+            };
+            let disconnected = sample.error.as_deref() == Some("disconnected");
+            let _ = events.send(ClientEvent::Sample(sample));
+            if disconnected {
+                return;
+            }
+        }
+    }
+}
+
+/// Connects and waits to be welcomed, returning the username the server assigned.
+fn connect(addr: SocketAddr, username: String) -> anyhow::Result<(TcpStream, String)> {
+    let mut stream = TcpStream::connect(addr)?;
+    stream.write_message(&LobbyClientNewConnectionMessage { username })?;
+
+    match stream.read_message::<LobbyServerMessage>(Some(REPLY_TIMEOUT))? {
+        LobbyServerMessage::Welcome { username, .. } => Ok((stream, username)),
+        other => anyhow::bail!("not welcomed: {other:?}"),
+    }
+}
+
+impl Client {
+    /// Sends the request for `step` and waits for the server's answer to it. Returns
+    /// `None` if the step doesn't apply right now, like leaving while not in a lobby.
+    fn run_step(&mut self, step: &Step) -> Option<Sample> {
+        let msg = match step {
+            Step::ListLobbies => LobbyClientMessage::ListLobbies,
+            Step::CreateLobby if !self.in_lobby => LobbyClientMessage::CreateLobby {
+                settings: LobbySettings {
+                    name: self.username.clone(),
+                    ..Default::default()
+                },
+            },
+            Step::JoinLobby if !self.in_lobby && !self.known_lobbies.is_empty() => {
+                let index = self.next_random() as usize % self.known_lobbies.len();
+                LobbyClientMessage::JoinLobby {
+                    id: self.known_lobbies[index],
+                    password: None,
+                }
+            }
+            Step::LeaveLobby if self.in_lobby => LobbyClientMessage::LeaveLobby,
+            Step::Queue if !self.in_lobby => LobbyClientMessage::StartMatchmaking {
+                roles: RolePreference {
+                    primary: Role::ALL[self.next_random() as usize % Role::ALL.len()],
+                    secondary: None,
+                },
+            },
+            Step::StopQueue => LobbyClientMessage::StopMatchmaking,
+            Step::Chat { text } if self.in_lobby => LobbyClientMessage::Chat { text: text.clone() },
+            _ => return None,
+        };
+
+        let start = Instant::now();
+        let error = match self.stream.write_message(&msg) {
+            Ok(()) => self.await_reply(step, start).err(),
+            Err(_) => Some("disconnected".to_string()),
+        };
+
+        Some(Sample {
+            step: step.name(),
+            latency: start.elapsed(),
+            error,
+        })
+    }
+
+    /// Reads messages until one answers `step`, skipping broadcasts meant for others.
+    fn await_reply(&mut self, step: &Step, start: Instant) -> Result<(), String> {
+        loop {
+            let remaining = REPLY_TIMEOUT.saturating_sub(start.elapsed());
+            if remaining.is_zero() {
+                return Err("timeout".into());
+            }
+
+            let msg = self
+                .stream
+                .read_message::<LobbyServerMessage>(Some(remaining))
+                .map_err(
+                    |e| match e.downcast_ref::<std::io::Error>().map(|e| e.kind()) {
+                        Some(std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {
+                            "timeout".to_string()
+                        }
+                        _ => "disconnected".to_string(),
+                    },
+                )?;
+
+            match (step, msg) {
+                (_, LobbyServerMessage::Negative { msg }) => return Err(msg),
+                (_, LobbyServerMessage::RateLimited { .. }) => return Err("rate limited".into()),
+                (_, LobbyServerMessage::Kicked { .. })
+                | (_, LobbyServerMessage::ServerShuttingDown { .. }) => {
+                    return Err("disconnected".into())
+                }
+                (Step::ListLobbies, LobbyServerMessage::LobbyList { lobbies }) => {
+                    self.known_lobbies = lobbies.into_iter().map(|lobby| lobby.id).collect();
+                    return Ok(());
+                }
+                (
+                    Step::CreateLobby | Step::JoinLobby,
+                    LobbyServerMessage::YouJoinedLobby { .. },
+                ) => {
+                    self.in_lobby = true;
+                    return Ok(());
+                }
+                (Step::LeaveLobby, LobbyServerMessage::YouLeftLobby) => {
+                    self.in_lobby = false;
+                    return Ok(());
+                }
+                (Step::Chat { .. }, LobbyServerMessage::ChatMessage { from, .. })
+                    if from.username == self.username =>
+                {
+                    return Ok(());
+                }
+                (Step::Queue | Step::StopQueue, LobbyServerMessage::OK) => return Ok(()),
+                // Can arrive while waiting on any step, once enough clients are queued.
+                (_, LobbyServerMessage::MatchmakingDone { .. }) => self.in_lobby = true,
+                _ => {}
+            }
+        }
+    }
+
+    /// xorshift64, good enough for picking lobbies without pulling in a dependency.
+    fn next_random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+}
@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use std::collections::BTreeMap;
+
+/// The outcome of a single scripted request.
+pub struct Sample {
+    pub step: &'static str,
+    pub latency: Duration,
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+struct StepStats {
+    latencies: Vec<Duration>,
+    errors: BTreeMap<String, usize>,
+}
+
+#[derive(Default)]
+pub struct Report {
+    steps: BTreeMap<&'static str, StepStats>,
+    connect_failures: usize,
+}
+
+impl Report {
+    pub fn add(&mut self, sample: Sample) {
+        let stats = self.steps.entry(sample.step).or_default();
+        stats.latencies.push(sample.latency);
+        if let Some(error) = sample.error {
+            *stats.errors.entry(error).or_default() += 1;
+        }
+    }
+
+    pub fn connect_failed(&mut self) {
+        self.connect_failures += 1;
+    }
+
+    pub fn print(&mut self, elapsed: Duration) {
+        println!(
+            "{:<12} {:>8} {:>8} {:>10} {:>10} {:>10} {:>10}",
+            "step", "count", "errors", "p50", "p90", "p99", "max"
+        );
+
+        let mut total = 0;
+        for (step, stats) in &mut self.steps {
+            stats.latencies.sort();
+            let errors = stats.errors.values().sum::<usize>();
+            total += stats.latencies.len();
+
+            println!(
+                "{:<12} {:>8} {:>8} {:>10.2?} {:>10.2?} {:>10.2?} {:>10.2?}",
+                step,
+                stats.latencies.len(),
+                errors,
+                percentile(&stats.latencies, 0.50),
+                percentile(&stats.latencies, 0.90),
+                percentile(&stats.latencies, 0.99),
+                stats.latencies.last().copied().unwrap_or_default(),
+            );
+        }
+
+        println!();
+        println!(
+            "{total} requests in {elapsed:.2?} ({:.1}/s), {} clients failed to connect",
+            total as f64 / elapsed.as_secs_f64(),
+            self.connect_failures
+        );
+
+        for (step, stats) in &self.steps {
+            for (error, count) in &stats.errors {
+                println!("  {step}: {count}x {error}");
+            }
+        }
+    }
+}
+
+/// `sorted` must be in ascending order.
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let index = ((sorted.len() - 1) as f64 * p).round() as usize;
+    sorted[index]
+}
@@ -0,0 +1,89 @@
+use std::{path::Path, time::Duration};
+
+use serde::Deserialize;
+
+/// What each simulated client does, loaded from a JSON file.
+#[derive(Debug, Deserialize)]
+pub struct Script {
+    pub steps: Vec<Step>,
+    /// How many times each client runs through `steps`.
+    #[serde(default = "default_repeat")]
+    pub repeat: usize,
+}
+
+fn default_repeat() -> usize {
+    1
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "action")]
+pub enum Step {
+    ListLobbies,
+    CreateLobby,
+    /// Joins a random lobby from the last lobby list, if there was one.
+    JoinLobby,
+    LeaveLobby,
+    Queue,
+    StopQueue,
+    Chat {
+        text: String,
+    },
+    Wait {
+        ms: u64,
+    },
+}
+
+impl Step {
+    /// Name used for the step in the report.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Step::ListLobbies => "ListLobbies",
+            Step::CreateLobby => "CreateLobby",
+            Step::JoinLobby => "JoinLobby",
+            Step::LeaveLobby => "LeaveLobby",
+            Step::Queue => "Queue",
+            Step::StopQueue => "StopQueue",
+            Step::Chat { .. } => "Chat",
+            Step::Wait { .. } => "Wait",
+        }
+    }
+
+    pub fn wait(&self) -> Option<Duration> {
+        match self {
+            Step::Wait { ms } => Some(Duration::from_millis(*ms)),
+            _ => None,
+        }
+    }
+}
+
+impl Script {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+}
+
+impl Default for Script {
+    /// Browses, hosts or joins a lobby, chats a little and leaves again.
+    fn default() -> Self {
+        Self {
+            steps: vec![
+                Step::ListLobbies,
+                Step::JoinLobby,
+                Step::Chat {
+                    text: "hello".into(),
+                },
+                Step::Wait { ms: 250 },
+                Step::LeaveLobby,
+                Step::CreateLobby,
+                Step::Wait { ms: 500 },
+                Step::Chat {
+                    text: "anyone?".into(),
+                },
+                Step::LeaveLobby,
+                Step::Wait { ms: 250 },
+            ],
+            repeat: 5,
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use std::{
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    sync::mpsc,
+};
+
+use common::network::{
+    admin::{AdminRequest, AdminResponse},
+    TcpStreamExt,
+};
+
+use crate::{Command, CommandSender};
+
+/// Accepts admin connections on a local Unix socket and forwards their requests
+/// into the command queue.
+pub fn listen_admin(path: PathBuf, sender: CommandSender) {
+    // A stale socket from a previous run would make the bind fail.
+    let _ = std::fs::remove_file(&path);
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Could not bind admin socket {}: {e}", path.display());
+            return;
+        }
+    };
+
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+
+        let sender = sender.clone();
+        std::thread::spawn(move || admin_connection(stream, sender));
+    }
+}
+
+fn admin_connection(mut stream: UnixStream, sender: CommandSender) {
+    while let Ok(request) = stream.read_message::<AdminRequest>(None) {
+        println!("Admin request: {request:?}");
+
+        // The server may exit as soon as it has drained, before the reply could be
+        // written, so shutdowns are acknowledged up front.
+        if let AdminRequest::Shutdown { reason } = request {
+            let _ = stream.write_message(&AdminResponse::OK);
+            let _ = sender.send(Command::Shutdown { reason });
+            break;
+        }
+
+        let (reply, response) = mpsc::channel();
+        if sender.send(Command::Admin { request, reply }).is_err() {
+            break;
+        }
+
+        let response = response.recv().unwrap_or(AdminResponse::Error {
+            msg: "Server is shutting down".into(),
+        });
+
+        if stream.write_message(&response).is_err() {
+            break;
+        }
+    }
+}
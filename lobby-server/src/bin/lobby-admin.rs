@@ -0,0 +1,134 @@
+//! Command line client for the lobby server's admin socket.
+
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+use common::network::{
+    admin::{AdminRequest, AdminResponse, DEFAULT_ADMIN_SOCKET},
+    lobby::{LobbyId, PlayerId},
+    TcpStreamExt,
+};
+use uuid::Uuid;
+
+const USAGE: &str = "\
+Usage: lobby-admin [--socket <path>] <command>
+
+Commands:
+    players                       List connected players
+    lobbies                       List open lobbies
+    kick <player-id> [reason]     Disconnect a player
+    ban <player-id> [reason]      Disconnect a player and ban their address
+    unban <ip>                    Lift a ban
+    close-lobby <lobby-id>        Remove everyone from a lobby
+    announce <message>            Send a message to every connected client
+    shutdown [reason]             Shut the server down";
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(unix))]
+fn run() -> anyhow::Result<()> {
+    anyhow::bail!("The admin socket is only available on Unix platforms")
+}
+
+#[cfg(unix)]
+fn run() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    let mut socket = PathBuf::from(DEFAULT_ADMIN_SOCKET);
+    if args.first().map(String::as_str) == Some("--socket") {
+        if args.len() < 2 {
+            anyhow::bail!(USAGE);
+        }
+        socket = args.remove(1).into();
+        args.remove(0);
+    }
+
+    let request = parse_request(&args)?;
+
+    let mut stream = UnixStream::connect(&socket)
+        .map_err(|e| anyhow::anyhow!("Could not connect to {}: {e}", socket.display()))?;
+    stream.write_message(&request)?;
+
+    match stream.read_message::<AdminResponse>(None)? {
+        AdminResponse::OK => println!("OK"),
+        AdminResponse::Error { msg } => anyhow::bail!("Error: {msg}"),
+        AdminResponse::Players { players } => {
+            for player in players {
+                let lobby = player
+                    .in_lobby
+                    .map_or_else(|| "-".to_string(), |id| id.to_string());
+                println!(
+                    "{}\t{}\t{}\tlobby: {}",
+                    player.id, player.username, player.addr, lobby
+                );
+            }
+        }
+        AdminResponse::Lobbies { lobbies } => {
+            for lobby in lobbies {
+                println!(
+                    "{}\towner: {}\tplayers: {}",
+                    lobby.id, lobby.owner, lobby.players
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_request(args: &[String]) -> anyhow::Result<AdminRequest> {
+    let Some((command, rest)) = args.split_first() else {
+        anyhow::bail!(USAGE);
+    };
+    let text = |default: &str| {
+        if rest.len() > 1 {
+            rest[1..].join(" ")
+        } else {
+            default.to_string()
+        }
+    };
+
+    Ok(match command.as_str() {
+        "players" => AdminRequest::ListPlayers,
+        "lobbies" => AdminRequest::ListLobbies,
+        "kick" => AdminRequest::Kick {
+            player: PlayerId(parse_uuid(rest.first())?),
+            reason: text("Kicked by an administrator"),
+        },
+        "ban" => AdminRequest::Ban {
+            player: PlayerId(parse_uuid(rest.first())?),
+            reason: text("Banned by an administrator"),
+        },
+        "unban" => AdminRequest::Unban {
+            addr: rest
+                .first()
+                .ok_or_else(|| anyhow::anyhow!(USAGE))?
+                .parse()?,
+        },
+        "close-lobby" => AdminRequest::CloseLobby {
+            lobby: LobbyId(parse_uuid(rest.first())?),
+        },
+        "announce" if !rest.is_empty() => AdminRequest::Announce {
+            msg: rest.join(" "),
+        },
+        "shutdown" => AdminRequest::Shutdown {
+            reason: if rest.is_empty() {
+                "The server is shutting down".to_string()
+            } else {
+                rest.join(" ")
+            },
+        },
+        _ => anyhow::bail!(USAGE),
+    })
+}
+
+fn parse_uuid(arg: Option<&String>) -> anyhow::Result<Uuid> {
+    let arg = arg.ok_or_else(|| anyhow::anyhow!(USAGE))?;
+    Ok(arg.parse()?)
+}
@@ -0,0 +1,27 @@
+use std::path::Path;
+
+use anyhow::Context;
+use common::champion::{Champion, ChampionCatalog};
+
+/// Loads every `*.json` file in `dir` as a [`Champion`]. The catalog is ordered by file
+/// name, which is also the order bots pick champions in.
+pub fn load_catalog(dir: &Path) -> anyhow::Result<ChampionCatalog> {
+    let mut paths = std::fs::read_dir(dir)
+        .with_context(|| format!("Could not read {}", dir.display()))?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "json"));
+    paths.sort();
+
+    let champions = paths
+        .iter()
+        .map(|path| {
+            let file = std::fs::File::open(path)?;
+            let champion: Champion = serde_json::from_reader(file)
+                .with_context(|| format!("Invalid champion in {}", path.display()))?;
+            Ok(champion)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    ChampionCatalog::new(champions)
+}
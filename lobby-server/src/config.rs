@@ -0,0 +1,75 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use common::network::admin::DEFAULT_ADMIN_SOCKET;
+use lobby_server::{rate_limit::RateLimitConfig, username::UsernameRules};
+use serde::Deserialize;
+
+use crate::game_servers::GameServerConfig;
+
+/// Environment variable pointing at the config file to load.
+const CONFIG_PATH_VAR: &str = "LOBBY_SERVER_CONFIG";
+const DEFAULT_CONFIG_PATH: &str = "lobby-server.json";
+
+/// The champions and maps in the source tree, wherever the server is run from.
+const DEFAULT_CHAMPIONS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/champions");
+const DEFAULT_MAPS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../game-server/maps");
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub listen_addr: SocketAddr,
+    pub admin_socket: PathBuf,
+    /// Where bans and other state that outlives the process are kept.
+    pub state_file: PathBuf,
+    /// How long a shutdown waits for clients to disconnect before exiting anyway.
+    pub drain_timeout_secs: u64,
+    /// Sent to clients on shutdown as a hint for when to try reconnecting.
+    pub reconnect_hint_secs: Option<u64>,
+    /// Where to serve Prometheus metrics, if anywhere.
+    pub metrics_addr: Option<SocketAddr>,
+    pub rate_limits: RateLimitConfig,
+    pub usernames: UsernameRules,
+    /// Directory of champion data files, one JSON file per champion.
+    pub champions_dir: PathBuf,
+    /// Directory of the maps game servers can load, one JSON file per map.
+    pub maps_dir: PathBuf,
+    pub game_servers: GameServerConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen_addr: "[::]:65432".parse().unwrap(),
+            admin_socket: DEFAULT_ADMIN_SOCKET.into(),
+            state_file: "lobby-server-state.json".into(),
+            drain_timeout_secs: 10,
+            reconnect_hint_secs: Some(30),
+            metrics_addr: Some("127.0.0.1:9100".parse().unwrap()),
+            rate_limits: RateLimitConfig::default(),
+            usernames: UsernameRules::default(),
+            champions_dir: DEFAULT_CHAMPIONS_DIR.into(),
+            maps_dir: DEFAULT_MAPS_DIR.into(),
+            game_servers: GameServerConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config from `$LOBBY_SERVER_CONFIG`, or `lobby-server.json` in the working
+    /// directory. Missing fields, and a missing default file, fall back to the defaults.
+    pub fn load() -> anyhow::Result<Self> {
+        let path = match std::env::var_os(CONFIG_PATH_VAR) {
+            Some(path) => PathBuf::from(path),
+            None => {
+                let path = PathBuf::from(DEFAULT_CONFIG_PATH);
+                if !path.exists() {
+                    return Ok(Self::default());
+                }
+                path
+            }
+        };
+
+        let file = std::fs::File::open(&path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+}
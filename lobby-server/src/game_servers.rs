@@ -0,0 +1,393 @@
+//! Finding game servers for started games. Game servers connect to the lobby server on
+//! their own, or are spawned by it when configured to, and each one hosts a single
+//! match.
+
+use std::{
+    collections::VecDeque,
+    net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream},
+    path::PathBuf,
+    process::Child,
+    sync::mpsc::{self, Receiver, Sender},
+    time::{Duration, Instant},
+};
+
+use bevy::utils::HashMap;
+use common::{
+    network::{
+        allocation::{AllocationRequest, GameServerReport, DEFAULT_ALLOCATION_ADDR},
+        lobby::LobbyId,
+        TcpStreamExt,
+    },
+    Side,
+};
+use lobby_server::MatchRequest;
+use serde::Deserialize;
+
+use crate::{Command, CommandSender};
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct GameServerConfig {
+    /// Where game servers connect to be given matches.
+    pub allocation_addr: SocketAddr,
+    /// How to start more game servers when none are free. Without this, games wait for
+    /// a game server to connect on its own.
+    pub spawn: Option<SpawnConfig>,
+    /// A game fails if no game server takes it within this many seconds...
+    pub allocation_timeout_secs: u64,
+    /// ...or if its game server goes this long without reporting.
+    pub health_timeout_secs: u64,
+}
+
+impl Default for GameServerConfig {
+    fn default() -> Self {
+        Self {
+            allocation_addr: DEFAULT_ALLOCATION_ADDR.parse().unwrap(),
+            spawn: None,
+            allocation_timeout_secs: 30,
+            health_timeout_secs: 10,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SpawnConfig {
+    /// The game server executable.
+    pub binary: PathBuf,
+    /// The address spawned game servers listen on.
+    pub listen_ip: IpAddr,
+    /// The address clients are told to connect to.
+    pub public_ip: IpAddr,
+    /// Spawned game servers get consecutive ports starting at this one...
+    pub first_port: u16,
+    /// ...and there are never more than this many at once.
+    pub max_servers: u16,
+    /// Passed on to every spawned game server after the addresses, such as
+    /// `["--max-rewind-ms", "150"]`.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// What became of a game, to be passed on to the lobby [`State`](lobby_server::State).
+#[derive(Debug)]
+pub enum GameEvent {
+    Allocated {
+        lobby: LobbyId,
+        addr: SocketAddr,
+    },
+    Failed {
+        lobby: LobbyId,
+        reason: String,
+    },
+    Finished {
+        lobby: LobbyId,
+        winner: Option<Side>,
+    },
+}
+
+struct GameServer {
+    /// Where clients can reach it.
+    addr: SocketAddr,
+    sender: Sender<AllocationRequest>,
+    status: Status,
+}
+
+enum Status {
+    Idle,
+    /// Sent a match, which it hasn't confirmed yet.
+    Assigned {
+        lobby: LobbyId,
+    },
+    Hosting {
+        lobby: LobbyId,
+    },
+}
+
+/// Every connected game server, and the games waiting for one.
+pub struct GameServers {
+    servers: HashMap<u64, GameServer>,
+    /// When each game server with a match last reported. Idle game servers have nothing
+    /// to report.
+    last_heard: HashMap<u64, Instant>,
+    waiting: VecDeque<(MatchRequest, Instant)>,
+    /// Spawned game servers that are still running, by port.
+    children: HashMap<u16, Child>,
+    /// Spawned game servers that haven't connected yet.
+    starting: usize,
+}
+
+impl GameServers {
+    pub fn new() -> Self {
+        Self {
+            servers: HashMap::new(),
+            last_heard: HashMap::new(),
+            waiting: VecDeque::new(),
+            children: HashMap::new(),
+            starting: 0,
+        }
+    }
+
+    /// Queues a game until a game server is free for it.
+    pub fn request(&mut self, config: &GameServerConfig, request: MatchRequest, now: Instant) {
+        self.waiting.push_back((request, now));
+        self.assign(config, now);
+    }
+
+    /// Stops waiting for a game server for the lobby's game.
+    pub fn cancel(&mut self, lobby: LobbyId) {
+        self.waiting.retain(|(request, _)| request.lobby != lobby);
+    }
+
+    pub fn server_ready(
+        &mut self,
+        config: &GameServerConfig,
+        id: u64,
+        addr: SocketAddr,
+        sender: Sender<AllocationRequest>,
+        now: Instant,
+    ) {
+        println!("Game server {id} is ready at {addr}");
+        self.starting = self.starting.saturating_sub(1);
+        self.servers.insert(
+            id,
+            GameServer {
+                addr,
+                sender,
+                status: Status::Idle,
+            },
+        );
+        self.assign(config, now);
+    }
+
+    pub fn report(&mut self, id: u64, report: GameServerReport, now: Instant) -> Option<GameEvent> {
+        let server = self.servers.get_mut(&id)?;
+        if !matches!(server.status, Status::Idle) {
+            self.last_heard.insert(id, now);
+        }
+
+        match (report, &server.status) {
+            (GameServerReport::Hosting { lobby }, &Status::Assigned { lobby: assigned })
+                if lobby == assigned =>
+            {
+                server.status = Status::Hosting { lobby };
+                Some(GameEvent::Allocated {
+                    lobby,
+                    addr: server.addr,
+                })
+            }
+            (GameServerReport::Health { .. }, _) => None,
+            (GameServerReport::Finished { winner }, &Status::Hosting { lobby }) => {
+                println!("Game server {id} finished the game for lobby {lobby}");
+                self.forget(id);
+                Some(GameEvent::Finished { lobby, winner })
+            }
+            (report, _) => {
+                println!("Unexpected report from game server {id}: {report:?}");
+                None
+            }
+        }
+    }
+
+    /// The connection to a game server closed.
+    pub fn server_lost(&mut self, id: u64) -> Option<GameEvent> {
+        let server = self.forget(id)?;
+        println!("Lost game server {id}");
+        let lobby = server.status.lobby()?;
+        Some(GameEvent::Failed {
+            lobby,
+            reason: "The game server stopped unexpectedly".into(),
+        })
+    }
+
+    /// Fails games that have waited too long for a game server, or whose game server
+    /// went quiet.
+    pub fn tick(&mut self, config: &GameServerConfig, now: Instant) -> Vec<GameEvent> {
+        let mut events = Vec::new();
+
+        let allocation_timeout = Duration::from_secs(config.allocation_timeout_secs);
+        while self
+            .waiting
+            .front()
+            .is_some_and(|(_, since)| now >= *since + allocation_timeout)
+        {
+            let (request, _) = self.waiting.pop_front().unwrap();
+            events.push(GameEvent::Failed {
+                lobby: request.lobby,
+                reason: "No game server is available".into(),
+            });
+        }
+
+        let health_timeout = Duration::from_secs(config.health_timeout_secs);
+        let silent = self
+            .last_heard
+            .iter()
+            .filter(|(_, heard)| now >= **heard + health_timeout)
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+        for id in silent {
+            let Some(server) = self.forget(id) else {
+                continue;
+            };
+            println!("Game server {id} stopped reporting");
+            if let Some(lobby) = server.status.lobby() {
+                events.push(GameEvent::Failed {
+                    lobby,
+                    reason: "The game server stopped responding".into(),
+                });
+            }
+        }
+
+        self.children
+            .retain(|_, child| child.try_wait().is_ok_and(|status| status.is_none()));
+        // Those that exited before connecting never will.
+        self.starting = self.starting.min(self.children.len());
+        events
+    }
+
+    pub fn next_deadline(&self, config: &GameServerConfig) -> Option<Instant> {
+        let allocation_timeout = Duration::from_secs(config.allocation_timeout_secs);
+        let health_timeout = Duration::from_secs(config.health_timeout_secs);
+        let waiting = self
+            .waiting
+            .front()
+            .map(|(_, since)| *since + allocation_timeout);
+        let silent = self
+            .last_heard
+            .values()
+            .map(|heard| *heard + health_timeout)
+            .min();
+        waiting.into_iter().chain(silent).min()
+    }
+
+    /// Hands waiting games to idle game servers, and spawns more game servers for the
+    /// rest if allowed to.
+    fn assign(&mut self, config: &GameServerConfig, now: Instant) {
+        for (&id, server) in &mut self.servers {
+            if !matches!(server.status, Status::Idle) {
+                continue;
+            }
+            let Some((request, _)) = self.waiting.pop_front() else {
+                break;
+            };
+
+            println!(
+                "Giving the game for lobby {} to game server {id}",
+                request.lobby
+            );
+            server.status = Status::Assigned {
+                lobby: request.lobby,
+            };
+            self.last_heard.insert(id, now);
+            // A failed send means the connection is closing, and `server_lost` will fail
+            // the game.
+            let _ = server.sender.send(AllocationRequest::HostMatch {
+                lobby: request.lobby,
+                setup: request.setup,
+                tokens: request.tokens,
+            });
+        }
+
+        if let Some(spawn) = &config.spawn {
+            while self.waiting.len() > self.starting {
+                if let Err(e) = self.spawn(config.allocation_addr, spawn) {
+                    eprintln!("Could not start a game server: {e:#}");
+                    break;
+                }
+                self.starting += 1;
+            }
+        }
+    }
+
+    fn spawn(&mut self, allocation_addr: SocketAddr, config: &SpawnConfig) -> anyhow::Result<()> {
+        let port = (config.first_port..)
+            .take(config.max_servers.into())
+            .find(|port| !self.children.contains_key(port))
+            .ok_or_else(|| anyhow::anyhow!("All {} game servers are busy", config.max_servers))?;
+
+        let child = std::process::Command::new(&config.binary)
+            .arg(allocation_addr.to_string())
+            .arg(SocketAddr::new(config.listen_ip, port).to_string())
+            .arg(SocketAddr::new(config.public_ip, port).to_string())
+            .args(&config.args)
+            .spawn()?;
+        println!("Started a game server on port {port}");
+        self.children.insert(port, child);
+        Ok(())
+    }
+
+    fn forget(&mut self, id: u64) -> Option<GameServer> {
+        self.last_heard.remove(&id);
+        self.servers.remove(&id)
+    }
+}
+
+impl Status {
+    fn lobby(&self) -> Option<LobbyId> {
+        match *self {
+            Status::Idle => None,
+            Status::Assigned { lobby } | Status::Hosting { lobby } => Some(lobby),
+        }
+    }
+}
+
+/// Accepts connections from game servers and forwards what they say into the command
+/// queue. Each game server is told apart by a connection id.
+pub fn listen_game_servers(addr: SocketAddr, sender: CommandSender) {
+    let listener = match TcpListener::bind(addr) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Could not bind game server endpoint {addr}: {e}");
+            return;
+        }
+    };
+
+    for (id, stream) in (0..).zip(listener.incoming()) {
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+        let Ok(GameServerReport::Ready { addr }) =
+            stream.read_message::<GameServerReport>(Some(Duration::from_secs(3)))
+        else {
+            continue;
+        };
+        let Ok(writer) = stream.try_clone() else {
+            continue;
+        };
+
+        let (send, recv) = mpsc::channel();
+        std::thread::spawn(move || send_game_server(writer, recv));
+        let connected = Command::GameServerConnected {
+            id,
+            addr,
+            sender: send,
+        };
+        if sender.send(connected).is_err() {
+            break;
+        }
+
+        let sender = sender.clone();
+        std::thread::spawn(move || game_server_connection(id, stream, sender));
+    }
+}
+
+fn game_server_connection(id: u64, mut stream: TcpStream, sender: CommandSender) {
+    while let Ok(report) = stream.read_message::<GameServerReport>(None) {
+        if sender
+            .send(Command::GameServerReport { id, report })
+            .is_err()
+        {
+            return;
+        }
+    }
+    let _ = sender.send(Command::GameServerLost(id));
+}
+
+fn send_game_server(mut stream: TcpStream, receiver: Receiver<AllocationRequest>) {
+    while let Ok(request) = receiver.recv() {
+        if stream.write_message(&request).is_err() {
+            break;
+        }
+    }
+
+    let _ = stream.shutdown(Shutdown::Both);
+}
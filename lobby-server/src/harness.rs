@@ -0,0 +1,164 @@
+//! Drives a [`State`] with scripted fake players, for testing lobby logic without sockets.
+
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::utils::HashMap;
+use common::{
+    champion::{Champion, ChampionCatalog, Role},
+    network::lobby::{LobbyClientMessage, LobbyServerMessage, PlayerId, UsernameError},
+};
+use uuid::Uuid;
+
+use crate::{Outgoing, State};
+
+/// Asserts that the next message `player` received matches a pattern, and evaluates to it.
+///
+/// ```ignore
+/// let lobby_id = expect_msg!(harness, alice, LobbyServerMessage::YouJoinedLobby { lobby_id } => lobby_id);
+/// ```
+#[macro_export]
+macro_rules! expect_msg {
+    ($harness:expr, $player:expr, $pattern:pat $(if $guard:expr)? => $result:expr) => {
+        match $harness.recv($player) {
+            Some($pattern) $(if $guard)? => $result,
+            other => panic!(
+                "expected {} to receive {}, got {:?}",
+                $harness.username($player),
+                stringify!($pattern),
+                other
+            ),
+        }
+    };
+    ($harness:expr, $player:expr, $pattern:pat $(if $guard:expr)?) => {
+        $crate::expect_msg!($harness, $player, $pattern $(if $guard)? => ())
+    };
+}
+
+/// A small catalog for tests, so they don't depend on the server's data files. Bots
+/// pick in this order: `warrior`, `ranger`, `mage`, `guardian`, `cleric`, `knight`.
+pub fn champions() -> ChampionCatalog {
+    let champion = |id: &str, role| Champion {
+        id: id.into(),
+        name: id.into(),
+        roles: vec![role],
+        icon: format!("champions/{id}.png"),
+        enabled: true,
+    };
+
+    ChampionCatalog::new(vec![
+        champion("warrior", Role::Top),
+        champion("ranger", Role::Bot),
+        champion("mage", Role::Mid),
+        champion("guardian", Role::Support),
+        champion("cleric", Role::Support),
+        champion("knight", Role::Jungle),
+    ])
+    .unwrap()
+}
+
+/// Maps for tests: `default`, which has bases for two sides like the real one, and
+/// `triangle`, which has three.
+pub fn maps() -> HashMap<String, usize> {
+    HashMap::from([("default".into(), 2), ("triangle".into(), 3)])
+}
+
+#[derive(Default)]
+pub struct Harness {
+    pub state: State,
+    inboxes: HashMap<PlayerId, VecDeque<LobbyServerMessage>>,
+    usernames: HashMap<PlayerId, String>,
+}
+
+impl Harness {
+    /// A harness whose state has [`champions`] to pick from.
+    pub fn new() -> Self {
+        Self::with_state(State::new().with_champions(champions()).with_maps(maps()))
+    }
+
+    pub fn with_state(state: State) -> Self {
+        Self {
+            state,
+            ..Self::default()
+        }
+    }
+
+    /// Connects a player, panicking if their username is rejected.
+    #[track_caller]
+    pub fn connect(&mut self, username: &str) -> PlayerId {
+        match self.try_connect(username) {
+            Ok(id) => id,
+            Err(e) => panic!("could not connect {username}: {e}"),
+        }
+    }
+
+    /// Connects a player and takes their `Welcome`, so their inbox starts out empty.
+    pub fn try_connect(&mut self, username: &str) -> Result<PlayerId, UsernameError> {
+        let id = PlayerId(Uuid::new_v4());
+        let outgoing = self.state.client_connected(id, username)?;
+        self.inboxes.insert(id, VecDeque::new());
+        self.usernames.insert(id, username.to_string());
+        self.deliver(outgoing);
+
+        if let Some(LobbyServerMessage::Welcome { username, .. }) = self.recv(id) {
+            self.usernames.insert(id, username);
+        }
+        Ok(id)
+    }
+
+    pub fn disconnect(&mut self, player: PlayerId) {
+        let outgoing = self.state.client_disconnected(player);
+        self.inboxes.remove(&player);
+        self.deliver(outgoing);
+    }
+
+    pub fn send(&mut self, player: PlayerId, msg: LobbyClientMessage) {
+        let outgoing = self.state.handle_message(player, msg);
+        self.deliver(outgoing);
+    }
+
+    /// Moves the state's clock forward without waiting, firing any timers that run out.
+    pub fn advance(&mut self, by: Duration) {
+        let now = self.state.now() + by;
+        let outgoing = self.state.tick(now);
+        self.deliver(outgoing);
+    }
+
+    pub fn deliver(&mut self, outgoing: Vec<Outgoing>) {
+        for Outgoing { to, msg } in outgoing {
+            if let Some(inbox) = self.inboxes.get_mut(&to) {
+                inbox.push_back(msg);
+            }
+        }
+    }
+
+    /// Takes the oldest message `player` has received but not yet looked at.
+    pub fn recv(&mut self, player: PlayerId) -> Option<LobbyServerMessage> {
+        self.inboxes.get_mut(&player)?.pop_front()
+    }
+
+    /// Takes every message `player` has received but not yet looked at.
+    pub fn drain(&mut self, player: PlayerId) -> Vec<LobbyServerMessage> {
+        self.inboxes
+            .get_mut(&player)
+            .map(|inbox| inbox.drain(..).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn username(&self, player: PlayerId) -> &str {
+        self.usernames
+            .get(&player)
+            .map_or("<unknown>", String::as_str)
+    }
+
+    #[track_caller]
+    pub fn assert_no_messages(&self, player: PlayerId) {
+        if let Some(inbox) = self.inboxes.get(&player) {
+            assert!(
+                inbox.is_empty(),
+                "expected {} to have no messages, got {:?}",
+                self.username(player),
+                inbox
+            );
+        }
+    }
+}
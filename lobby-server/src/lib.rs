@@ -0,0 +1,12 @@
+//! Lobby logic, independent of how clients are connected.
+//!
+//! [`State`] is driven by telling it about connecting and disconnecting clients and the
+//! messages they send. Every call returns the messages that should be delivered in
+//! response; getting them to the right client is up to the caller.
+
+pub mod harness;
+pub mod rate_limit;
+mod state;
+pub mod username;
+
+pub use state::*;
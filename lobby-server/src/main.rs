@@ -1,282 +1,576 @@
-#![feature(try_blocks)]
 use std::{
-    net::{TcpListener, TcpStream},
-    sync::mpsc::{self, Receiver, Sender},
-    time::Duration,
+    net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::Ordering,
+        mpsc::{self, Receiver, RecvTimeoutError, SendError, Sender},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
 };
 
-use bevy::utils::HashMap;
+use bevy::utils::{HashMap, HashSet};
 use common::{
+    champion::ChampionCatalog,
     network::{
+        admin::{AdminPlayerInfo, AdminRequest, AdminResponse},
+        allocation::{AllocationRequest, GameServerReport},
         lobby::{
-            LobbyClientMessage, LobbyClientNewConnectionMessage, LobbyId, LobbyInfo,
-            LobbyServerMessage, Player as NetworkPlayer, PlayerId, ShortLobbyInfo,
+            LobbyClientMessage, LobbyClientNewConnectionMessage, LobbyServerMessage, PlayerId,
         },
         TcpStreamExt,
     },
-    Side,
 };
+use config::Config;
+use game_servers::{GameEvent, GameServers};
+use lobby_server::{
+    rate_limit::{RateLimiter, Verdict},
+    Outgoing, State,
+};
+use metrics::Metrics;
+use persistence::PersistentState;
 use uuid::Uuid;
 
+#[cfg(unix)]
+mod admin;
+mod champions;
+mod config;
+mod game_servers;
+mod maps;
+mod metrics;
+mod persistence;
+
+/// How long exiting waits for messages still queued for clients to be written out.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
 fn main() {
-    State::new().run();
-}
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Could not load config: {e}");
+            return;
+        }
+    };
 
-struct Client {
-    player_id: PlayerId,
-    username: String,
-    sender: Sender<LobbyServerMessage>,
-    in_lobby: Option<LobbyId>,
+    let champions = match champions::load_catalog(&config.champions_dir) {
+        Ok(champions) => champions,
+        Err(e) => {
+            eprintln!("Could not load champions: {e:#}");
+            return;
+        }
+    };
+    println!("Loaded {} champions", champions.iter().count());
+
+    let maps = match maps::load_sides(&config.maps_dir) {
+        Ok(maps) => maps,
+        Err(e) => {
+            eprintln!("Could not load maps: {e:#}");
+            return;
+        }
+    };
+    println!("Loaded {} maps", maps.len());
+
+    Server::new(config, champions, maps).run();
 }
 
-struct Lobby {
-    id: LobbyId,
-    players: HashMap<Side, Vec<PlayerId>>,
-    owner: PlayerId,
+/// The transport side of a connected client.
+struct Connection {
+    addr: SocketAddr,
+    sender: Sender<LobbyServerMessage>,
+    /// Writes what is sent to `sender` to the client. It stops once `sender` is dropped
+    /// and everything queued has been written.
+    writer: JoinHandle<()>,
+    rate_limiter: RateLimiter,
 }
 
 enum Command {
-    NewClient(Client),
+    NewClient {
+        id: PlayerId,
+        username: String,
+        connection: Box<Connection>,
+    },
     ClientDisconnected(PlayerId),
     MsgFromClient {
         id: PlayerId,
-        msg: LobbyClientMessage,
+        msg: Box<LobbyClientMessage>,
     },
+    Admin {
+        request: AdminRequest,
+        reply: Sender<AdminResponse>,
+    },
+    Shutdown {
+        reason: String,
+    },
+    GameServerConnected {
+        id: u64,
+        addr: SocketAddr,
+        sender: Sender<AllocationRequest>,
+    },
+    GameServerReport {
+        id: u64,
+        report: GameServerReport,
+    },
+    GameServerLost(u64),
+}
+
+/// Sending half of the command queue, which keeps the queue length metric up to date.
+#[derive(Clone)]
+struct CommandSender {
+    sender: Sender<Command>,
+    metrics: Arc<Metrics>,
+}
+
+impl CommandSender {
+    fn send(&self, command: Command) -> Result<(), SendError<Command>> {
+        self.metrics.command_queue.fetch_add(1, Ordering::Relaxed);
+        self.sender.send(command).inspect_err(|_| {
+            self.metrics.command_queue.fetch_sub(1, Ordering::Relaxed);
+        })
+    }
 }
 
-pub struct State {
-    players: HashMap<PlayerId, Client>,
-    lobbies: HashMap<LobbyId, Lobby>,
+/// Owns the lobby [`State`] and connects it to clients over TCP.
+struct Server {
+    config: Config,
+    state: State,
+    connections: HashMap<PlayerId, Connection>,
+    /// Writers of connections that are gone, which may still be sending their last
+    /// messages.
+    closing: Vec<JoinHandle<()>>,
+    banned: HashSet<IpAddr>,
+    game_servers: GameServers,
+    metrics: Arc<Metrics>,
+    /// Set once a shutdown has been requested; the server exits when everything has
+    /// drained or this deadline passes, whichever comes first.
+    drain_deadline: Option<Instant>,
 }
 
-impl State {
-    pub fn new() -> Self {
+impl Server {
+    fn new(config: Config, champions: ChampionCatalog, maps: HashMap<String, usize>) -> Self {
+        let persistent = match PersistentState::load(&config.state_file) {
+            Ok(persistent) => persistent,
+            Err(e) => {
+                eprintln!("Could not load {}: {e}", config.state_file.display());
+                PersistentState::default()
+            }
+        };
+
         Self {
-            players: HashMap::new(),
-            lobbies: HashMap::new(),
+            state: State::with_username_rules(config.usernames.clone())
+                .with_champions(champions)
+                .with_maps(maps),
+            config,
+            connections: HashMap::new(),
+            closing: Vec::new(),
+            banned: persistent.banned.into_iter().collect(),
+            game_servers: GameServers::new(),
+            metrics: Arc::default(),
+            drain_deadline: None,
         }
     }
 
-    pub fn run(&mut self) {
+    fn run(&mut self) {
         let (send, recv) = mpsc::channel();
+        let send = CommandSender {
+            sender: send,
+            metrics: self.metrics.clone(),
+        };
 
-        std::thread::spawn(|| listen(send));
+        if let Some(addr) = self.config.metrics_addr {
+            let metrics = self.metrics.clone();
+            std::thread::spawn(move || metrics::serve_metrics(addr, metrics));
+        }
+
+        {
+            let send = send.clone();
+            let handler = move || {
+                let _ = send.send(Command::Shutdown {
+                    reason: "The server is shutting down".into(),
+                });
+            };
+            if let Err(e) = ctrlc::set_handler(handler) {
+                eprintln!("Could not install signal handler: {e}");
+            }
+        }
+
+        #[cfg(unix)]
+        {
+            let path = self.config.admin_socket.clone();
+            let send = send.clone();
+            std::thread::spawn(move || admin::listen_admin(path, send));
+        }
+
+        {
+            let addr = self.config.game_servers.allocation_addr;
+            let send = send.clone();
+            std::thread::spawn(move || game_servers::listen_game_servers(addr, send));
+        }
+
+        let addr = self.config.listen_addr;
+        let metrics = self.metrics.clone();
+        std::thread::spawn(move || listen(addr, send, metrics));
 
         loop {
-            match recv.recv().unwrap() {
-                Command::NewClient(client) => {
-                    self.players.insert(client.player_id, client);
+            if self.drain_deadline.is_some() && self.drained() {
+                break;
+            }
+
+            let wake_at = [
+                self.drain_deadline,
+                self.state.next_deadline(),
+                self.game_servers.next_deadline(&self.config.game_servers),
+            ]
+            .into_iter()
+            .flatten()
+            .min();
+            let command = match wake_at {
+                None => Some(recv.recv().unwrap()),
+                Some(wake_at) => {
+                    match recv.recv_timeout(wake_at.saturating_duration_since(Instant::now())) {
+                        Ok(command) => Some(command),
+                        Err(RecvTimeoutError::Timeout) => None,
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
                 }
-                Command::ClientDisconnected(id) => {
-                    self.leave_lobby(id);
-                    self.players.remove(&id);
+            };
+
+            let now = Instant::now();
+            let outgoing = self.state.tick(now);
+            self.deliver(outgoing);
+            for event in self.game_servers.tick(&self.config.game_servers, now) {
+                self.game_event(event);
+            }
+
+            match command {
+                Some(command) => {
+                    self.metrics.command_queue.fetch_sub(1, Ordering::Relaxed);
+                    self.handle_command(command);
+                    let cancelled = self.state.take_cancelled_games();
+                    for lobby in &cancelled {
+                        self.game_servers.cancel(*lobby);
+                    }
+                    for request in self.state.take_match_requests() {
+                        if cancelled.contains(&request.lobby) {
+                            continue;
+                        }
+                        self.game_servers.request(
+                            &self.config.game_servers,
+                            request,
+                            Instant::now(),
+                        );
+                    }
                 }
-                Command::MsgFromClient { id, msg } => {
-                    self.handle_message(id, msg);
+                None if self
+                    .drain_deadline
+                    .is_some_and(|deadline| deadline <= Instant::now()) =>
+                {
+                    println!(
+                        "Drain timed out with {} clients still connected",
+                        self.connections.len()
+                    );
+                    break;
                 }
+                None => {}
             }
+            self.update_gauges();
+            self.closing.retain(|writer| !writer.is_finished());
         }
+
+        self.flush_writers();
+        self.persist();
+        #[cfg(unix)]
+        let _ = std::fs::remove_file(&self.config.admin_socket);
+        println!("Shut down");
     }
 
-    fn handle_message(&mut self, player_id: PlayerId, msg: LobbyClientMessage) {
-        let client = self.players.get_mut(&player_id).unwrap();
-        match msg {
-            LobbyClientMessage::StartMatchmaking => todo!(),
-            LobbyClientMessage::StopMatchmaking => todo!(),
-            LobbyClientMessage::CreateLobby => {
-                if client.in_lobby.is_some() {
-                    let send = client.sender.send(LobbyServerMessage::Negative {
-                        msg: "Cannot create lobby while in one".into(),
+    fn handle_command(&mut self, command: Command) {
+        match command {
+            Command::NewClient {
+                id,
+                username,
+                connection,
+            } => {
+                if self.drain_deadline.is_some() {
+                    let _ = connection
+                        .sender
+                        .send(self.shutdown_message("The server is shutting down".into()));
+                    self.closing.push(connection.writer);
+                    return;
+                }
+                if self.banned.contains(&connection.addr.ip()) {
+                    let _ = connection.sender.send(LobbyServerMessage::Kicked {
+                        reason: "You are banned from this server".into(),
                     });
+                    self.closing.push(connection.writer);
                     return;
                 }
-
-                let lobby_id = LobbyId(Uuid::new_v4());
-                let lobby = Lobby {
-                    id: lobby_id,
-                    players: {
-                        let mut map = HashMap::new();
-                        map.insert(Side::Red, vec![player_id]);
-                        map.insert(Side::Blue, vec![]);
-                        map
-                    },
-                    owner: player_id,
-                };
-
-                self.lobbies.insert(lobby_id, lobby);
-                client.in_lobby = Some(lobby_id);
-
-                let _ = client
-                    .sender
-                    .send(LobbyServerMessage::YouJoinedLobby { lobby_id });
+                match self.state.client_connected(id, &username) {
+                    Ok(outgoing) => {
+                        self.connections.insert(id, *connection);
+                        self.deliver(outgoing);
+                    }
+                    Err(error) => {
+                        println!("Rejected username {username:?}: {error}");
+                        self.metrics
+                            .username_rejections
+                            .fetch_add(1, Ordering::Relaxed);
+                        let _ = connection
+                            .sender
+                            .send(LobbyServerMessage::UsernameRejected { error });
+                        self.closing.push(connection.writer);
+                    }
+                }
             }
-            LobbyClientMessage::ListLobbies => {
-                let _ = client.sender.send(LobbyServerMessage::LobbyList {
-                    lobbies: self
-                        .lobbies
-                        .values()
-                        .map(|lobby| ShortLobbyInfo {
-                            id: lobby.id,
-                            players: lobby.players.values().map(|v| v.len()).sum(),
-                        })
-                        .collect(),
-                });
+            Command::ClientDisconnected(id) => {
+                // Clients turned away in `NewClient` were never added to the state.
+                if let Some(connection) = self.connections.remove(&id) {
+                    self.closing.push(connection.writer);
+                    let outgoing = self.state.client_disconnected(id);
+                    self.deliver(outgoing);
+                }
             }
-            LobbyClientMessage::JoinLobby { id } => {
-                if client.in_lobby.is_some() {
-                    client
-                        .sender
-                        .send(LobbyServerMessage::Negative {
-                            msg: "Cannot join lobby while in one already".into(),
-                        })
-                        .unwrap();
+            Command::MsgFromClient { id, msg } => {
+                let kind = metrics::message_kind(&msg);
+                if !self.check_rate_limit(id, kind) {
                     return;
                 }
 
-                let Some(lobby) = self.lobbies.get_mut(&id) else {
-                    let _ = client.sender.send(LobbyServerMessage::Negative {
-                        msg: "Cannot join lobby; lobby does not exist".into(),
-                    });
-                    return;
-                };
-
-                let side = lobby
-                    .players
-                    .iter()
-                    .fold(
-                        (Side::Red, usize::MAX),
-                        |(min_side, min_side_count), (side, players)| {
-                            #[allow(clippy::if_same_then_else)]
-                            if players.len() < min_side_count {
-                                (*side, players.len())
-                            } else if players.len() == min_side_count && *side < min_side {
-                                (*side, players.len())
-                            } else {
-                                (min_side, min_side_count)
-                            }
-                        },
-                    )
-                    .0;
-                let players = lobby.players.get_mut(&side).unwrap();
-
-                players.push(player_id);
-                client.in_lobby = Some(lobby.id);
-
-                let joined_player = NetworkPlayer {
-                    id: player_id,
-                    username: client.username.clone(),
-                };
-
-                let _ = client
-                    .sender
-                    .send(LobbyServerMessage::YouJoinedLobby { lobby_id: id });
-
-                for player in lobby.players.values().flatten() {
-                    if *player == player_id {
-                        continue;
-                    }
-
-                    let client = self.players.get(player).unwrap();
-                    let _ = client.sender.send(LobbyServerMessage::PlayerJoinedLobby {
-                        player: joined_player.clone(),
-                        side,
-                    });
+                let start = Instant::now();
+                let outgoing = self.state.handle_message(id, *msg);
+                self.deliver(outgoing);
+                self.metrics.record_message(kind, start.elapsed());
+            }
+            Command::Admin { request, reply } => {
+                let _ = reply.send(self.handle_admin(request));
+            }
+            Command::Shutdown { reason } => self.begin_shutdown(reason),
+            Command::GameServerConnected { id, addr, sender } => self.game_servers.server_ready(
+                &self.config.game_servers,
+                id,
+                addr,
+                sender,
+                Instant::now(),
+            ),
+            Command::GameServerReport { id, report } => {
+                if let Some(event) = self.game_servers.report(id, report, Instant::now()) {
+                    self.game_event(event);
                 }
             }
-            LobbyClientMessage::LeaveLobby => {
-                self.leave_lobby(player_id);
+            Command::GameServerLost(id) => {
+                if let Some(event) = self.game_servers.server_lost(id) {
+                    self.game_event(event);
+                }
             }
-            LobbyClientMessage::GetLobbyInfo { id } => {
-                let Some(lobby) = self.lobbies.get(&id) else {
-                    let _ = client.sender.send(LobbyServerMessage::Negative {
-                        msg: "Cannot get lobby info of non-existant lobby".into(),
-                    });
-                    return;
-                };
+        }
+    }
 
-                let lobby_info = LobbyInfo {
-                    id,
-                    players: lobby
-                        .players
-                        .iter()
-                        .map(|(side, players)| {
-                            (
-                                *side,
-                                players
-                                    .iter()
-                                    .map(|p| {
-                                        let client = self.players.get(p).unwrap();
-                                        NetworkPlayer {
-                                            id: *p,
-                                            username: client.username.clone(),
-                                        }
-                                    })
-                                    .collect(),
-                            )
-                        })
-                        .collect(),
-                    lobby_owner: lobby.owner,
-                };
+    /// Tells the lobby state how a game it started is going.
+    fn game_event(&mut self, event: GameEvent) {
+        let outgoing = match event {
+            GameEvent::Allocated { lobby, addr } => self.state.game_allocated(lobby, addr),
+            GameEvent::Failed { lobby, reason } => {
+                println!("The game for lobby {lobby} failed: {reason}");
+                self.state.game_failed(lobby, reason)
+            }
+            GameEvent::Finished { lobby, winner } => self.state.game_finished(lobby, winner),
+        };
+        self.deliver(outgoing);
+    }
 
-                let _ = self
-                    .players
-                    .get(&player_id)
-                    .unwrap()
+    /// Returns whether a message of type `kind` from `id` should be handled. Clients over
+    /// their limit are told so, and clients that keep going over it are disconnected.
+    fn check_rate_limit(&mut self, id: PlayerId, kind: &'static str) -> bool {
+        let Some(connection) = self.connections.get_mut(&id) else {
+            return false;
+        };
+
+        match connection
+            .rate_limiter
+            .check(&self.config.rate_limits, kind, Instant::now())
+        {
+            Verdict::Allowed => true,
+            Verdict::Limited { retry_after } => {
+                self.metrics.rate_limited.fetch_add(1, Ordering::Relaxed);
+                let _ = connection
                     .sender
-                    .send(LobbyServerMessage::LobbyInfo { info: lobby_info });
+                    .send(LobbyServerMessage::RateLimited { retry_after });
+                false
+            }
+            Verdict::Abusive => {
+                println!("Dropping {id} for repeatedly exceeding rate limits");
+                self.metrics
+                    .rate_limit_drops
+                    .fetch_add(1, Ordering::Relaxed);
+                let _ = connection.sender.send(LobbyServerMessage::Kicked {
+                    reason: "Too many requests".into(),
+                });
+                false
             }
-            LobbyClientMessage::SwitchSide => todo!(),
-            LobbyClientMessage::SelectChampion { champion } => todo!(),
-            LobbyClientMessage::LockInChampion { champion } => todo!(),
         }
     }
 
-    fn leave_lobby(&mut self, player: PlayerId) {
-        let Some(client) = self.players.get_mut(&player) else {
-            return;
-        };
-        let Some(lobby_id) = client.in_lobby else {
-            return;
-        };
-        let Some(lobby) = self.lobbies.get_mut(&lobby_id) else {
+    fn update_gauges(&self) {
+        self.metrics
+            .connected_players
+            .store(self.state.player_count() as u64, Ordering::Relaxed);
+        self.metrics
+            .lobbies
+            .store(self.state.lobby_count() as u64, Ordering::Relaxed);
+    }
+
+    /// Stops taking new clients and tells every connected client why it is being
+    /// disconnected. `run` keeps going until those connections have closed.
+    fn begin_shutdown(&mut self, reason: String) {
+        if self.drain_deadline.is_some() {
             return;
-        };
+        }
 
-        for players in lobby.players.values_mut() {
-            let Some(pos) = players.iter().position(|&id| id == player) else {
-                continue;
-            };
+        println!("Shutting down: {reason}");
+        self.drain_deadline =
+            Some(Instant::now() + Duration::from_secs(self.config.drain_timeout_secs));
+        self.broadcast(self.shutdown_message(reason));
+    }
 
-            players.remove(pos);
-            break;
+    fn shutdown_message(&self, reason: String) -> LobbyServerMessage {
+        LobbyServerMessage::ServerShuttingDown {
+            reason,
+            reconnect_after: self.config.reconnect_hint_secs.map(Duration::from_secs),
         }
+    }
 
-        client.in_lobby = None;
-
-        let _ = client.sender.send(LobbyServerMessage::YouLeftLobby);
+    /// Whether there is nothing left to wait for before exiting.
+    ///
+    /// Games in progress carry on without the lobby server, so only open client
+    /// connections count.
+    fn drained(&self) -> bool {
+        self.connections.is_empty()
+    }
 
-        if lobby.players.values().all(|v| v.is_empty()) {
-            self.lobbies.remove(&lobby_id);
-            return;
+    /// Waits a little for every connection to finish writing what is queued for it, such
+    /// as the shutdown announcement, so exiting doesn't cut it off.
+    fn flush_writers(&mut self) {
+        // Dropping the senders is what lets the writers finish.
+        let mut writers = self
+            .connections
+            .drain()
+            .map(|(_, connection)| connection.writer)
+            .chain(self.closing.drain(..))
+            .collect::<Vec<_>>();
+        let deadline = Instant::now() + FLUSH_TIMEOUT;
+        loop {
+            writers.retain(|writer| !writer.is_finished());
+            if writers.is_empty() {
+                return;
+            }
+            if Instant::now() >= deadline {
+                println!("{} connections were cut off while sending", writers.len());
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
         }
+    }
 
-        let left_player = NetworkPlayer {
-            id: player,
-            username: client.username.clone(),
+    fn persist(&self) {
+        let persistent = PersistentState {
+            banned: self.banned.iter().copied().collect(),
         };
+        if let Err(e) = persistent.save(&self.config.state_file) {
+            eprintln!("Could not save {}: {e}", self.config.state_file.display());
+        }
+    }
+
+    fn handle_admin(&mut self, request: AdminRequest) -> AdminResponse {
+        match request {
+            AdminRequest::ListPlayers => AdminResponse::Players {
+                players: self
+                    .state
+                    .players()
+                    .filter_map(|player| {
+                        Some(AdminPlayerInfo {
+                            id: player.id,
+                            username: player.username.to_string(),
+                            addr: self.connections.get(&player.id)?.addr,
+                            in_lobby: player.in_lobby,
+                        })
+                    })
+                    .collect(),
+            },
+            AdminRequest::ListLobbies => AdminResponse::Lobbies {
+                lobbies: self.state.lobbies().collect(),
+            },
+            AdminRequest::Kick { player, reason } => {
+                let Some(connection) = self.connections.get(&player) else {
+                    return AdminResponse::Error {
+                        msg: format!("No player with id {player}"),
+                    };
+                };
+                // The connection is closed once the message is written, which in turn
+                // removes the player through `ClientDisconnected`.
+                let _ = connection
+                    .sender
+                    .send(LobbyServerMessage::Kicked { reason });
+                AdminResponse::OK
+            }
+            AdminRequest::Ban { player, reason } => {
+                let Some(connection) = self.connections.get(&player) else {
+                    return AdminResponse::Error {
+                        msg: format!("No player with id {player}"),
+                    };
+                };
+                self.banned.insert(connection.addr.ip());
+                let _ = connection
+                    .sender
+                    .send(LobbyServerMessage::Kicked { reason });
+                AdminResponse::OK
+            }
+            AdminRequest::Unban { addr } => {
+                if self.banned.remove(&addr) {
+                    AdminResponse::OK
+                } else {
+                    AdminResponse::Error {
+                        msg: format!("{addr} is not banned"),
+                    }
+                }
+            }
+            AdminRequest::CloseLobby { lobby } => match self.state.close_lobby(lobby) {
+                Ok(outgoing) => {
+                    self.deliver(outgoing);
+                    AdminResponse::OK
+                }
+                Err(e) => AdminResponse::Error { msg: e.to_string() },
+            },
+            AdminRequest::Announce { msg } => {
+                self.broadcast(LobbyServerMessage::Announcement { msg });
+                AdminResponse::OK
+            }
+            AdminRequest::Shutdown { reason } => {
+                self.begin_shutdown(reason);
+                AdminResponse::OK
+            }
+        }
+    }
+
+    fn broadcast(&self, msg: LobbyServerMessage) {
+        for connection in self.connections.values() {
+            let _ = connection.sender.send(msg.clone());
+        }
+    }
 
-        for player in lobby.players.values().flatten() {
-            let client = self.players.get(player).unwrap();
+    fn send(&self, player: PlayerId, msg: LobbyServerMessage) {
+        if let Some(connection) = self.connections.get(&player) {
+            let _ = connection.sender.send(msg);
+        }
+    }
 
-            let _ = client.sender.send(LobbyServerMessage::PlayerLeftLobby {
-                player: left_player.clone(),
-            });
+    fn deliver(&self, outgoing: Vec<Outgoing>) {
+        for Outgoing { to, msg } in outgoing {
+            self.send(to, msg);
         }
     }
 }
 
-fn listen(sender: Sender<Command>) {
-    let listener = TcpListener::bind("[::]:65432").unwrap();
+fn listen(addr: SocketAddr, sender: CommandSender, metrics: Arc<Metrics>) {
+    let listener = TcpListener::bind(addr).unwrap();
 
     loop {
         let (mut stream, addr) = listener.accept().unwrap();
@@ -286,40 +580,59 @@ fn listen(sender: Sender<Command>) {
         let Ok(msg) =
             stream.read_message::<LobbyClientNewConnectionMessage>(Some(Duration::from_secs(3)))
         else {
+            metrics.handshake_failures.fetch_add(1, Ordering::Relaxed);
             continue;
         };
 
+        let Ok(writer_stream) = stream.try_clone() else {
+            continue;
+        };
         let (send1, recv1) = mpsc::channel();
+        let writer = std::thread::spawn(move || send_connection(writer_stream, recv1));
 
-        let client = Client {
-            player_id: id,
-            username: msg.username,
+        let connection = Connection {
+            addr,
             sender: send1,
-            in_lobby: None,
+            writer,
+            rate_limiter: RateLimiter::default(),
         };
 
-        sender.send(Command::NewClient(client)).unwrap();
+        sender
+            .send(Command::NewClient {
+                id,
+                username: msg.username,
+                connection: Box::new(connection),
+            })
+            .unwrap();
 
         let sender = sender.clone();
-        std::thread::spawn(move || listen_connection(id, stream, sender, recv1));
+        let metrics = metrics.clone();
+        std::thread::spawn(move || listen_connection(id, stream, sender, metrics));
     }
 }
 
 fn listen_connection(
     id: PlayerId,
     mut stream: TcpStream,
-    sender: Sender<Command>,
-    receiver: Receiver<LobbyServerMessage>,
+    sender: CommandSender,
+    metrics: Arc<Metrics>,
 ) {
-    let s = stream.try_clone().unwrap();
-    std::thread::spawn(move || send_connection(s, receiver));
-
     loop {
         let read_message = stream.read_message::<LobbyClientMessage>(None);
         println!("{:?}", read_message);
         match read_message {
-            Ok(msg) => sender.send(Command::MsgFromClient { id, msg }).unwrap(),
-            Err(_) => {
+            Ok(msg) => sender
+                .send(Command::MsgFromClient {
+                    id,
+                    msg: Box::new(msg),
+                })
+                .unwrap(),
+            Err(e) => {
+                // Anything other than an IO error means the bytes arrived but weren't a valid
+                // frame: too large, or not a message.
+                if e.downcast_ref::<std::io::Error>().is_none() {
+                    metrics.framing_errors.fetch_add(1, Ordering::Relaxed);
+                }
                 sender.send(Command::ClientDisconnected(id)).unwrap();
                 break;
             }
@@ -328,10 +641,18 @@ fn listen_connection(
 }
 
 fn send_connection(mut stream: TcpStream, receiver: Receiver<LobbyServerMessage>) {
-    let _: anyhow::Result<_> = try {
-        loop {
-            let msg = receiver.recv()?;
-            stream.write_message(&msg)?;
+    while let Ok(msg) = receiver.recv() {
+        let closes_connection = matches!(
+            msg,
+            LobbyServerMessage::Kicked { .. }
+                | LobbyServerMessage::ServerShuttingDown { .. }
+                | LobbyServerMessage::UsernameRejected { .. }
+        );
+
+        if stream.write_message(&msg).is_err() || closes_connection {
+            break;
         }
-    };
+    }
+
+    let _ = stream.shutdown(Shutdown::Both);
 }
@@ -0,0 +1,29 @@
+use std::path::Path;
+
+use anyhow::Context;
+use bevy::utils::HashMap;
+use common::{map::MapDefinition, network::lobby::list::MAX_TEAMS, Side};
+
+/// Loads every `*.json` file in `dir` as a [`MapDefinition`], and works out how many sides
+/// can play on each: game servers need a base for every side, counting from the first.
+/// Maps are named after their file, which is how game servers find them.
+pub fn load_sides(dir: &Path) -> anyhow::Result<HashMap<String, usize>> {
+    let mut paths = std::fs::read_dir(dir)
+        .with_context(|| format!("Could not read {}", dir.display()))?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "json"));
+
+    paths
+        .iter()
+        .filter_map(|path| Some((path.file_stem()?.to_str()?, path)))
+        .map(|(name, path)| {
+            let map = MapDefinition::load(path)
+                .with_context(|| format!("Invalid map in {}", path.display()))?;
+            let sides = Side::all(MAX_TEAMS)
+                .take_while(|&side| map.base(side).is_some())
+                .count();
+            Ok((name.to_string(), sides))
+        })
+        .collect()
+}
@@ -0,0 +1,224 @@
+use std::{
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use bevy::utils::HashMap;
+use common::network::lobby::LobbyClientMessage;
+
+/// Upper bounds, in seconds, of the message handling latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 10] = [
+    0.000_05, 0.000_1, 0.000_25, 0.000_5, 0.001, 0.002_5, 0.005, 0.01, 0.025, 0.1,
+];
+
+/// Counters and gauges shared between the state thread and the connection threads.
+#[derive(Default)]
+pub struct Metrics {
+    pub connected_players: AtomicU64,
+    pub lobbies: AtomicU64,
+    /// Commands sent to `State::run` that it hasn't picked up yet.
+    pub command_queue: AtomicU64,
+    pub handshake_failures: AtomicU64,
+    pub username_rejections: AtomicU64,
+    pub framing_errors: AtomicU64,
+    pub rate_limited: AtomicU64,
+    pub rate_limit_drops: AtomicU64,
+    messages: Mutex<HashMap<&'static str, MessageStats>>,
+}
+
+#[derive(Default)]
+struct MessageStats {
+    count: u64,
+    latency_buckets: [u64; LATENCY_BUCKETS.len()],
+    latency_sum: f64,
+}
+
+impl Metrics {
+    pub fn record_message(&self, kind: &'static str, latency: Duration) {
+        let latency = latency.as_secs_f64();
+        let mut messages = self.messages.lock().unwrap();
+        let stats = messages.entry(kind).or_default();
+
+        stats.count += 1;
+        stats.latency_sum += latency;
+        for (bucket, bound) in stats.latency_buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if latency <= bound {
+                *bucket += 1;
+            }
+        }
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        let mut gauge = |name: &str, help: &str, value: &AtomicU64| {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} gauge");
+            let _ = writeln!(out, "{name} {}", value.load(Ordering::Relaxed));
+        };
+        gauge(
+            "lobby_connected_players",
+            "Clients that completed the handshake and are still connected.",
+            &self.connected_players,
+        );
+        gauge("lobby_lobbies", "Open lobbies.", &self.lobbies);
+        gauge(
+            "lobby_command_queue",
+            "Commands waiting to be processed by the state thread.",
+            &self.command_queue,
+        );
+
+        let mut counter = |name: &str, help: &str, value: &AtomicU64| {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} counter");
+            let _ = writeln!(out, "{name} {}", value.load(Ordering::Relaxed));
+        };
+        counter(
+            "lobby_handshake_failures_total",
+            "Connections dropped before completing the handshake.",
+            &self.handshake_failures,
+        );
+        counter(
+            "lobby_username_rejections_total",
+            "Connections turned away because of their username.",
+            &self.username_rejections,
+        );
+        counter(
+            "lobby_framing_errors_total",
+            "Client messages that could not be decoded.",
+            &self.framing_errors,
+        );
+        counter(
+            "lobby_rate_limited_total",
+            "Client messages rejected for exceeding a rate limit.",
+            &self.rate_limited,
+        );
+        counter(
+            "lobby_rate_limit_drops_total",
+            "Connections dropped for repeatedly exceeding rate limits.",
+            &self.rate_limit_drops,
+        );
+
+        let messages = self.messages.lock().unwrap();
+        let mut messages = messages.iter().collect::<Vec<_>>();
+        messages.sort_by_key(|(kind, _)| **kind);
+
+        let _ = writeln!(
+            out,
+            "# HELP lobby_messages_received_total Client messages handled, by type."
+        );
+        let _ = writeln!(out, "# TYPE lobby_messages_received_total counter");
+        for (kind, stats) in &messages {
+            let _ = writeln!(
+                out,
+                "lobby_messages_received_total{{type=\"{kind}\"}} {}",
+                stats.count
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP lobby_message_handling_seconds Time spent handling a client message."
+        );
+        let _ = writeln!(out, "# TYPE lobby_message_handling_seconds histogram");
+        for (kind, stats) in &messages {
+            for (bucket, bound) in stats.latency_buckets.iter().zip(LATENCY_BUCKETS) {
+                let _ = writeln!(
+                    out,
+                    "lobby_message_handling_seconds_bucket{{type=\"{kind}\",le=\"{bound}\"}} {bucket}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "lobby_message_handling_seconds_bucket{{type=\"{kind}\",le=\"+Inf\"}} {}",
+                stats.count
+            );
+            let _ = writeln!(
+                out,
+                "lobby_message_handling_seconds_sum{{type=\"{kind}\"}} {}",
+                stats.latency_sum
+            );
+            let _ = writeln!(
+                out,
+                "lobby_message_handling_seconds_count{{type=\"{kind}\"}} {}",
+                stats.count
+            );
+        }
+
+        out
+    }
+}
+
+/// Label used for a client message in the per-type metrics.
+pub fn message_kind(msg: &LobbyClientMessage) -> &'static str {
+    match msg {
+        LobbyClientMessage::StartMatchmaking { .. } => "StartMatchmaking",
+        LobbyClientMessage::StopMatchmaking => "StopMatchmaking",
+        LobbyClientMessage::CreateLobby { .. } => "CreateLobby",
+        LobbyClientMessage::ListLobbies => "ListLobbies",
+        LobbyClientMessage::SubscribeLobbyList { .. } => "SubscribeLobbyList",
+        LobbyClientMessage::ViewLobbyListPage { .. } => "ViewLobbyListPage",
+        LobbyClientMessage::UnsubscribeLobbyList => "UnsubscribeLobbyList",
+        LobbyClientMessage::JoinLobby { .. } => "JoinLobby",
+        LobbyClientMessage::SpectateLobby { .. } => "SpectateLobby",
+        LobbyClientMessage::LeaveLobby => "LeaveLobby",
+        LobbyClientMessage::GetLobbyInfo { .. } => "GetLobbyInfo",
+        LobbyClientMessage::SwitchSide => "SwitchSide",
+        LobbyClientMessage::SetRolePreference { .. } => "SetRolePreference",
+        LobbyClientMessage::SetGameRules { .. } => "SetGameRules",
+        LobbyClientMessage::AddBot { .. } => "AddBot",
+        LobbyClientMessage::RemoveBot { .. } => "RemoveBot",
+        LobbyClientMessage::SelectChampion { .. } => "SelectChampion",
+        LobbyClientMessage::LockInChampion { .. } => "LockInChampion",
+        LobbyClientMessage::StartDraft => "StartDraft",
+        LobbyClientMessage::BanChampion { .. } => "BanChampion",
+        LobbyClientMessage::StartGame => "StartGame",
+        LobbyClientMessage::Chat { .. } => "Chat",
+    }
+}
+
+/// Serves `GET /metrics` over plain HTTP for Prometheus to scrape.
+pub fn serve_metrics(addr: SocketAddr, metrics: Arc<Metrics>) {
+    let listener = match TcpListener::bind(addr) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Could not bind metrics endpoint {addr}: {e}");
+            return;
+        }
+    };
+
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        // Scrapes are rare and cheap, so they are answered one at a time.
+        let _ = respond(stream, &metrics);
+    }
+}
+
+fn respond(mut stream: TcpStream, metrics: &Metrics) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(3)))?;
+
+    let mut request_line = String::new();
+    BufReader::new(&mut stream).read_line(&mut request_line)?;
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+
+    let (status, body) = if path == "/metrics" {
+        ("200 OK", metrics.render())
+    } else {
+        ("404 Not Found", String::new())
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
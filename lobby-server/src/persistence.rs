@@ -0,0 +1,31 @@
+use std::{net::IpAddr, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+/// Server state that survives restarts.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PersistentState {
+    pub banned: Vec<IpAddr>,
+}
+
+impl PersistentState {
+    /// Loads the state from `path`, or returns an empty state if the file does not exist yet.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    /// Writes the state to a temporary file first, so a crash mid-write can't leave
+    /// a truncated file behind.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let tmp = path.with_extension("tmp");
+        serde_json::to_writer_pretty(std::fs::File::create(&tmp)?, self)?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
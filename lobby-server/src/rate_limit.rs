@@ -0,0 +1,164 @@
+//! Limits on how often each connection can send messages.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use bevy::utils::HashMap;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct BucketConfig {
+    /// How many messages can be sent in a burst.
+    pub capacity: f64,
+    /// How many messages per second are allowed on average.
+    pub per_second: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// Limit over all messages from one connection.
+    pub connection: BucketConfig,
+    /// Additional limits for specific message types, keyed by the message's name.
+    pub per_message: HashMap<String, BucketConfig>,
+    /// A connection is dropped once it has gone over a limit this many times...
+    pub max_violations: usize,
+    /// ...within this many seconds.
+    pub violation_window_secs: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        let lobby_churn = BucketConfig {
+            capacity: 3.0,
+            per_second: 0.5,
+        };
+        let lobby_list = BucketConfig {
+            capacity: 5.0,
+            per_second: 1.0,
+        };
+
+        Self {
+            connection: BucketConfig {
+                capacity: 20.0,
+                per_second: 10.0,
+            },
+            per_message: [
+                ("ListLobbies", lobby_list),
+                ("SubscribeLobbyList", lobby_list),
+                ("ViewLobbyListPage", lobby_list),
+                ("CreateLobby", lobby_churn),
+                ("JoinLobby", lobby_churn),
+                ("LeaveLobby", lobby_churn),
+            ]
+            .into_iter()
+            .map(|(kind, bucket)| (kind.to_string(), bucket))
+            .collect(),
+            max_violations: 20,
+            violation_window_secs: 10,
+        }
+    }
+}
+
+struct TokenBucket {
+    config: BucketConfig,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(config: BucketConfig, now: Instant) -> Self {
+        Self {
+            config,
+            tokens: config.capacity,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.config.per_second).min(self.config.capacity);
+        self.last_refill = now;
+    }
+
+    /// How long until a token is available, or zero if one is available now.
+    fn wait_time(&self) -> Duration {
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else if self.config.per_second <= 0.0 {
+            Duration::MAX
+        } else {
+            // Slow enough buckets wait longer than a Duration can hold.
+            Duration::try_from_secs_f64((1.0 - self.tokens) / self.config.per_second)
+                .unwrap_or(Duration::MAX)
+        }
+    }
+}
+
+pub enum Verdict {
+    Allowed,
+    Limited {
+        retry_after: Duration,
+    },
+    /// The connection has gone over its limits too often and should be dropped.
+    Abusive,
+}
+
+/// Token buckets for a single connection. Buckets start out full when first used.
+#[derive(Default)]
+pub struct RateLimiter {
+    connection: Option<TokenBucket>,
+    per_message: HashMap<&'static str, TokenBucket>,
+    violations: VecDeque<Instant>,
+}
+
+impl RateLimiter {
+    /// Takes a token for a message of type `kind` if the connection's limits allow it.
+    pub fn check(&mut self, config: &RateLimitConfig, kind: &'static str, now: Instant) -> Verdict {
+        let connection = self
+            .connection
+            .get_or_insert_with(|| TokenBucket::new(config.connection, now));
+        connection.refill(now);
+        let mut wait = connection.wait_time();
+
+        let message_bucket = config.per_message.get(kind).map(|&bucket_config| {
+            let bucket = self
+                .per_message
+                .entry(kind)
+                .or_insert_with(|| TokenBucket::new(bucket_config, now));
+            bucket.refill(now);
+            bucket
+        });
+        if let Some(bucket) = &message_bucket {
+            wait = wait.max(bucket.wait_time());
+        }
+
+        if wait.is_zero() {
+            connection.tokens -= 1.0;
+            if let Some(bucket) = message_bucket {
+                bucket.tokens -= 1.0;
+            }
+            return Verdict::Allowed;
+        }
+
+        let window = Duration::from_secs(config.violation_window_secs);
+        while self
+            .violations
+            .front()
+            .is_some_and(|&at| now.saturating_duration_since(at) > window)
+        {
+            self.violations.pop_front();
+        }
+        self.violations.push_back(now);
+
+        if self.violations.len() > config.max_violations {
+            Verdict::Abusive
+        } else {
+            Verdict::Limited { retry_after: wait }
+        }
+    }
+}
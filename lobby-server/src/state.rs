@@ -0,0 +1,1216 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    time::Instant,
+};
+
+use anyhow::{bail, ensure, Context};
+use bevy::utils::{HashMap, HashSet};
+use common::{
+    champion::{ChampionCatalog, Role},
+    network::{
+        admin::AdminLobbyInfo,
+        game::{Controller, MatchParticipant, MatchSetup},
+        lobby::{
+            list::{MAX_LOBBY_NAME_LENGTH, MAX_LOBBY_PLAYERS, MAX_TEAMS},
+            BotDifficulty, LobbyClientMessage, LobbyFilter, LobbyId, LobbyInfo, LobbyServerMessage,
+            LobbySettings, LobbySort, PickMode, Player as NetworkPlayer, PlayerId, RolePreference,
+            ShortLobbyInfo, UsernameError,
+        },
+    },
+    rules::GameRules,
+    Side,
+};
+use uuid::Uuid;
+
+use crate::username::{self, UsernameRules};
+
+use self::{draft::Draft, game::Game, matchmaking::Queue};
+
+pub use self::game::MatchRequest;
+
+mod draft;
+mod game;
+mod matchmaking;
+
+/// Longest chat message accepted, in characters.
+const MAX_CHAT_LENGTH: usize = 500;
+
+/// Most lobbies sent per lobby list page.
+const MAX_PAGE_SIZE: usize = 50;
+
+/// A message that should be delivered to a client.
+#[derive(Debug)]
+pub struct Outgoing {
+    pub to: PlayerId,
+    pub msg: LobbyServerMessage,
+}
+
+struct Client {
+    username: String,
+    /// [`username::comparison_key`] of `username`, held in `State::usernames`.
+    username_key: String,
+    in_lobby: Option<LobbyId>,
+}
+
+struct Lobby {
+    id: LobbyId,
+    settings: LobbySettings,
+    players: HashMap<Side, Vec<PlayerId>>,
+    owner: PlayerId,
+    champions: HashMap<PlayerId, String>,
+    locked_in: HashSet<PlayerId>,
+    /// Players in `players` that the server controls.
+    bots: HashMap<PlayerId, Bot>,
+    /// Clients watching the lobby without a slot in `players`.
+    spectators: HashSet<PlayerId>,
+    draft: Option<Draft>,
+    role_preferences: HashMap<PlayerId, RolePreference>,
+    /// Kept up to date by [`State::assign_roles`].
+    roles: HashMap<PlayerId, Role>,
+    game: Option<Game>,
+}
+
+struct Bot {
+    name: String,
+    difficulty: BotDifficulty,
+}
+
+impl Lobby {
+    /// An empty lobby with `owner` on the first side.
+    fn new(settings: LobbySettings, owner: PlayerId) -> Self {
+        let mut players = Side::all(settings.teams)
+            .map(|side| (side, vec![]))
+            .collect::<HashMap<_, _>>();
+        players.entry(Side::RED).or_default().push(owner);
+
+        Self {
+            id: LobbyId(Uuid::new_v4()),
+            settings,
+            players,
+            owner,
+            champions: HashMap::new(),
+            locked_in: HashSet::new(),
+            bots: HashMap::new(),
+            spectators: HashSet::new(),
+            draft: None,
+            role_preferences: HashMap::new(),
+            roles: HashMap::new(),
+            game: None,
+        }
+    }
+
+    /// Every player that is a connected client rather than a bot.
+    fn humans(&self) -> impl Iterator<Item = PlayerId> + '_ {
+        self.players
+            .values()
+            .flatten()
+            .copied()
+            .filter(|id| !self.bots.contains_key(id))
+    }
+
+    /// The first champion in the catalog that is still available, for picking on a
+    /// player's behalf.
+    fn auto_pick(&self, champions: &ChampionCatalog) -> Option<String> {
+        champions
+            .enabled()
+            .find(|c| !self.is_taken(&c.id))
+            .map(|c| c.id.clone())
+    }
+
+    /// Whether `champion` is banned, or locked in by someone else in a lobby where
+    /// champions can't be picked twice.
+    fn is_taken(&self, champion: &str) -> bool {
+        let picked = self
+            .locked_in
+            .iter()
+            .any(|p| self.champions.get(p).is_some_and(|c| c == champion));
+        let unique = matches!(self.settings.pick_mode, PickMode::Draft { .. });
+
+        self.draft
+            .as_ref()
+            .is_some_and(|d| d.banned.contains(champion))
+            || (unique && picked)
+    }
+
+    fn bot_player(&self, id: PlayerId) -> Option<NetworkPlayer> {
+        let bot = self.bots.get(&id)?;
+        Some(NetworkPlayer {
+            id,
+            username: bot.name.clone(),
+            bot: Some(bot.difficulty),
+        })
+    }
+
+    fn side_of(&self, player: PlayerId) -> Option<Side> {
+        self.players
+            .iter()
+            .find_map(|(side, players)| players.contains(&player).then_some(*side))
+    }
+
+    /// The side with the fewest players, or the first of those if several tie.
+    fn emptiest_side(&self) -> Side {
+        Side::all(self.settings.teams)
+            .min_by_key(|side| self.players.get(side).map_or(0, Vec::len))
+            .unwrap_or(Side::RED)
+    }
+
+    fn player_count(&self) -> usize {
+        self.players.values().map(|v| v.len()).sum()
+    }
+
+    fn short_info(&self) -> ShortLobbyInfo {
+        ShortLobbyInfo {
+            id: self.id,
+            name: self.settings.name.clone(),
+            mode: self.settings.mode,
+            map: self.settings.map.clone(),
+            players: self.player_count(),
+            max_players: self.settings.max_players,
+            has_password: self.settings.password.is_some(),
+        }
+    }
+}
+
+/// A client watching a page of the lobby list.
+struct Subscription {
+    filter: LobbyFilter,
+    sort: LobbySort,
+    page_size: usize,
+    page: usize,
+    page_count: usize,
+    /// What the client was last told is on its page.
+    shown: Vec<ShortLobbyInfo>,
+}
+
+/// The time as of the last [`State::tick`].
+struct Clock(Instant);
+
+impl Default for Clock {
+    fn default() -> Self {
+        Clock(Instant::now())
+    }
+}
+
+/// A connected player, as reported to server operators.
+pub struct PlayerSummary<'a> {
+    pub id: PlayerId,
+    pub username: &'a str,
+    pub in_lobby: Option<LobbyId>,
+}
+
+#[derive(Default)]
+pub struct State {
+    username_rules: UsernameRules,
+    champions: ChampionCatalog,
+    /// How many sides can play on each map lobbies can be made for.
+    maps: HashMap<String, usize>,
+    players: HashMap<PlayerId, Client>,
+    usernames: HashSet<String>,
+    lobbies: HashMap<LobbyId, Lobby>,
+    subscriptions: HashMap<PlayerId, Subscription>,
+    queue: Queue,
+    /// Set when a lobby changes in a way the lobby list shows.
+    lobby_list_changed: bool,
+    match_requests: Vec<MatchRequest>,
+    /// Lobbies that closed while their game was starting or running.
+    cancelled_games: Vec<LobbyId>,
+    clock: Clock,
+    outbox: Vec<Outgoing>,
+}
+
+impl State {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_username_rules(username_rules: UsernameRules) -> Self {
+        Self {
+            username_rules,
+            ..Self::default()
+        }
+    }
+
+    /// Replaces the champions players can pick from, which are sent to clients when they
+    /// connect.
+    pub fn with_champions(self, champions: ChampionCatalog) -> Self {
+        Self { champions, ..self }
+    }
+
+    pub fn champions(&self) -> &ChampionCatalog {
+        &self.champions
+    }
+
+    /// Replaces the maps lobbies can be made for, with how many sides can play on each.
+    pub fn with_maps(self, maps: HashMap<String, usize>) -> Self {
+        Self { maps, ..self }
+    }
+
+    pub fn player_count(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn now(&self) -> Instant {
+        self.clock.0
+    }
+
+    /// When [`State::tick`] next has something to do, if ever.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.lobbies
+            .values()
+            .filter_map(|lobby| Some(lobby.draft.as_ref()?.deadline))
+            .min()
+    }
+
+    /// Moves time forward to `now`, acting on any timers that ran out. Call this before
+    /// handling each event, and again by [`State::next_deadline`].
+    pub fn tick(&mut self, now: Instant) -> Vec<Outgoing> {
+        self.clock.0 = now;
+        self.expire_draft_turns();
+        self.take_outbox()
+    }
+
+    pub fn lobby_count(&self) -> usize {
+        self.lobbies.len()
+    }
+
+    pub fn players(&self) -> impl Iterator<Item = PlayerSummary<'_>> {
+        self.players.iter().map(|(id, client)| PlayerSummary {
+            id: *id,
+            username: &client.username,
+            in_lobby: client.in_lobby,
+        })
+    }
+
+    pub fn lobbies(&self) -> impl Iterator<Item = AdminLobbyInfo> + '_ {
+        self.lobbies.values().map(|lobby| AdminLobbyInfo {
+            id: lobby.id,
+            owner: lobby.owner,
+            players: lobby.player_count(),
+        })
+    }
+
+    /// Everyone who would play if the lobby's match started now.
+    pub fn match_participants(&self, id: LobbyId) -> anyhow::Result<Vec<MatchParticipant>> {
+        let lobby = self
+            .lobbies
+            .get(&id)
+            .with_context(|| format!("No lobby with id {id}"))?;
+
+        lobby
+            .players
+            .iter()
+            .flat_map(|(side, players)| players.iter().map(move |p| (*side, *p)))
+            .map(|(side, id)| {
+                let (username, controller) = match lobby.bots.get(&id) {
+                    Some(bot) => (
+                        bot.name.clone(),
+                        Controller::Bot {
+                            difficulty: bot.difficulty,
+                        },
+                    ),
+                    None => (self.network_player(id)?.username, Controller::Human),
+                };
+                let champion = if lobby.settings.rules.all_random {
+                    self.random_champion()
+                } else {
+                    lobby.champions.get(&id).cloned()
+                };
+                Ok(MatchParticipant {
+                    id,
+                    username,
+                    side,
+                    champion,
+                    role: lobby.roles.get(&id).copied(),
+                    controller,
+                })
+            })
+            .collect()
+    }
+
+    /// Any enabled champion, for lobbies that hand them out at random.
+    fn random_champion(&self) -> Option<String> {
+        let enabled = self.champions.enabled().collect::<Vec<_>>();
+        let i = (Uuid::new_v4().as_u128() % enabled.len().max(1) as u128) as usize;
+        enabled.get(i).map(|champion| champion.id.clone())
+    }
+
+    /// What a game server is given to host the lobby's match, if it started now.
+    pub fn match_setup(&self, id: LobbyId) -> anyhow::Result<MatchSetup> {
+        let participants = self.match_participants(id)?;
+        let lobby = &self.lobbies[&id];
+        Ok(MatchSetup {
+            map: lobby.settings.map.clone(),
+            teams: lobby.settings.teams,
+            rules: lobby.settings.rules.clone(),
+            participants,
+        })
+    }
+
+    /// Adds a player if the username they asked for is acceptable, and welcomes them
+    /// with the name they ended up with.
+    pub fn client_connected(
+        &mut self,
+        id: PlayerId,
+        requested_username: &str,
+    ) -> Result<Vec<Outgoing>, UsernameError> {
+        let username = self.unique_username(self.username_rules.validate(requested_username)?)?;
+        let username_key = username::comparison_key(&username);
+
+        self.usernames.insert(username_key.clone());
+        self.players.insert(
+            id,
+            Client {
+                username: username.clone(),
+                username_key,
+                in_lobby: None,
+            },
+        );
+        self.send(
+            id,
+            LobbyServerMessage::Welcome {
+                id,
+                username,
+                champions: self.champions.clone(),
+            },
+        );
+        Ok(self.take_outbox())
+    }
+
+    pub fn client_disconnected(&mut self, id: PlayerId) -> Vec<Outgoing> {
+        self.queue.remove(id);
+        self.leave_lobby(id);
+        self.subscriptions.remove(&id);
+        if let Some(client) = self.players.remove(&id) {
+            self.usernames.remove(&client.username_key);
+        }
+        self.take_outbox()
+    }
+
+    /// Returns `name`, or a variant of it with a discriminator if it is in use.
+    fn unique_username(&self, name: String) -> Result<String, UsernameError> {
+        let is_free = |name: &str| !self.usernames.contains(&username::comparison_key(name));
+
+        if is_free(&name) {
+            return Ok(name);
+        }
+        if !self.username_rules.discriminators {
+            return Err(UsernameError::Taken);
+        }
+
+        // With 10000 discriminators per name a few random picks all but always find a
+        // free one.
+        (0..16)
+            .map(|_| format!("{name}#{:04}", Uuid::new_v4().as_u128() % 10000))
+            .find(|candidate| is_free(candidate))
+            .ok_or(UsernameError::Taken)
+    }
+
+    /// Removes every player from a lobby, which closes it.
+    pub fn close_lobby(&mut self, id: LobbyId) -> anyhow::Result<Vec<Outgoing>> {
+        let lobby = self
+            .lobbies
+            .get(&id)
+            .with_context(|| format!("No lobby with id {id}"))?;
+        let players = lobby.humans().collect::<Vec<_>>();
+        for player in players {
+            self.leave_lobby(player);
+        }
+        Ok(self.take_outbox())
+    }
+
+    /// Handles a message from a client. If handling fails the client gets a `Negative`
+    /// reply, and if the handler panics the client is kicked; either way nobody else
+    /// is affected.
+    pub fn handle_message(
+        &mut self,
+        player_id: PlayerId,
+        msg: LobbyClientMessage,
+    ) -> Vec<Outgoing> {
+        let result =
+            panic::catch_unwind(AssertUnwindSafe(|| self.try_handle_message(player_id, msg)));
+
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                println!("Request from {player_id} failed: {e:#}");
+                self.send(
+                    player_id,
+                    LobbyServerMessage::Negative { msg: e.to_string() },
+                );
+            }
+            Err(_) => {
+                eprintln!("Handler panicked on a message from {player_id}, dropping them");
+                // Whatever the handler queued before panicking may be inconsistent.
+                self.outbox.clear();
+                self.send(
+                    player_id,
+                    LobbyServerMessage::Kicked {
+                        reason: "Internal server error".into(),
+                    },
+                );
+            }
+        }
+
+        self.take_outbox()
+    }
+
+    fn take_outbox(&mut self) -> Vec<Outgoing> {
+        if std::mem::take(&mut self.lobby_list_changed) {
+            self.update_subscriptions();
+        }
+        std::mem::take(&mut self.outbox)
+    }
+
+    /// The lobbies on `page` of a subscription's view, and how many pages there are.
+    fn lobby_list_page(
+        &self,
+        filter: &LobbyFilter,
+        sort: LobbySort,
+        page_size: usize,
+        page: usize,
+    ) -> (Vec<ShortLobbyInfo>, usize) {
+        let mut lobbies = self
+            .lobbies
+            .values()
+            .map(Lobby::short_info)
+            .filter(|lobby| filter.matches(lobby))
+            .collect::<Vec<_>>();
+        lobbies.sort_by(|a, b| sort.compare(a, b));
+
+        let page_count = lobbies.len().div_ceil(page_size).max(1);
+        let page = lobbies
+            .into_iter()
+            .skip(page * page_size)
+            .take(page_size)
+            .collect();
+        (page, page_count)
+    }
+
+    /// Sends every subscriber the difference between what their page shows now and
+    /// what it showed when they were last told.
+    fn update_subscriptions(&mut self) {
+        let subscribers = self.subscriptions.keys().copied().collect::<Vec<_>>();
+        for player in subscribers {
+            let sub = &self.subscriptions[&player];
+            let (mut lobbies, page_count) =
+                self.lobby_list_page(&sub.filter, sub.sort, sub.page_size, sub.page);
+
+            // Lobbies closing can leave the client past the last page.
+            if sub.page >= page_count {
+                let page = page_count - 1;
+                (lobbies, _) = self.lobby_list_page(&sub.filter, sub.sort, sub.page_size, page);
+                self.show_page(player, page, page_count, lobbies);
+                continue;
+            }
+
+            let mut changes = Vec::new();
+            for old in &sub.shown {
+                if !lobbies.iter().any(|lobby| lobby.id == old.id) {
+                    changes.push(LobbyServerMessage::LobbyListRemoved { id: old.id });
+                }
+            }
+            for lobby in &lobbies {
+                match sub.shown.iter().find(|old| old.id == lobby.id) {
+                    None => changes.push(LobbyServerMessage::LobbyListAdded {
+                        lobby: lobby.clone(),
+                    }),
+                    Some(old) if old != lobby => {
+                        changes.push(LobbyServerMessage::LobbyListUpdated {
+                            lobby: lobby.clone(),
+                        })
+                    }
+                    Some(_) => {}
+                }
+            }
+            if sub.page_count != page_count {
+                changes.push(LobbyServerMessage::LobbyListPageCount { page_count });
+            }
+
+            let sub = self.subscriptions.get_mut(&player).unwrap();
+            sub.shown = lobbies;
+            sub.page_count = page_count;
+            for msg in changes {
+                self.send(player, msg);
+            }
+        }
+    }
+
+    fn show_page(
+        &mut self,
+        player: PlayerId,
+        page: usize,
+        page_count: usize,
+        lobbies: Vec<ShortLobbyInfo>,
+    ) {
+        let Some(sub) = self.subscriptions.get_mut(&player) else {
+            return;
+        };
+        sub.page = page;
+        sub.page_count = page_count;
+        sub.shown = lobbies.clone();
+        self.send(
+            player,
+            LobbyServerMessage::LobbyListPage {
+                page,
+                page_count,
+                lobbies,
+            },
+        );
+    }
+
+    fn send(&mut self, to: PlayerId, msg: LobbyServerMessage) {
+        self.outbox.push(Outgoing { to, msg });
+    }
+
+    fn send_to_lobby(&mut self, lobby_id: LobbyId, msg: LobbyServerMessage) {
+        let Some(lobby) = self.lobbies.get(&lobby_id) else {
+            return;
+        };
+        for player in lobby.humans().chain(lobby.spectators.iter().copied()) {
+            self.outbox.push(Outgoing {
+                to: player,
+                msg: msg.clone(),
+            });
+        }
+    }
+
+    fn network_player(&self, id: PlayerId) -> anyhow::Result<NetworkPlayer> {
+        let client = self.players.get(&id).context("Unknown player")?;
+        Ok(NetworkPlayer {
+            id,
+            username: client.username.clone(),
+            bot: None,
+        })
+    }
+
+    /// Records `player`'s final pick and tells the lobby.
+    fn lock_in(&mut self, lobby_id: LobbyId, player: PlayerId, champion: String) {
+        let Some(lobby) = self.lobbies.get_mut(&lobby_id) else {
+            return;
+        };
+        lobby.champions.insert(player, champion.clone());
+        lobby.locked_in.insert(player);
+
+        let Some(player) = lobby
+            .bot_player(player)
+            .or_else(|| self.network_player(player).ok())
+        else {
+            return;
+        };
+        self.send_to_lobby(
+            lobby_id,
+            LobbyServerMessage::PlayerLockedInChampion { player, champion },
+        );
+    }
+
+    fn try_handle_message(
+        &mut self,
+        player_id: PlayerId,
+        msg: LobbyClientMessage,
+    ) -> anyhow::Result<()> {
+        let client = self.players.get_mut(&player_id).context("Unknown player")?;
+        match msg {
+            LobbyClientMessage::StartMatchmaking { roles } => {
+                ensure!(client.in_lobby.is_none(), "Cannot queue while in a lobby");
+                self.start_matchmaking(player_id, roles)?;
+            }
+            LobbyClientMessage::StopMatchmaking => {
+                self.stop_matchmaking(player_id)?;
+            }
+            LobbyClientMessage::CreateLobby { settings } => {
+                ensure!(
+                    client.in_lobby.is_none(),
+                    "Cannot create lobby while in one"
+                );
+                ensure!(
+                    !self.queue.contains(player_id),
+                    "Cannot create lobby while queued"
+                );
+                ensure!(
+                    !settings.name.trim().is_empty()
+                        && settings.name.chars().count() <= MAX_LOBBY_NAME_LENGTH,
+                    "Lobby names must be 1 to {MAX_LOBBY_NAME_LENGTH} characters"
+                );
+                ensure!(
+                    (1..=MAX_LOBBY_PLAYERS).contains(&settings.max_players),
+                    "Lobbies can hold 1 to {MAX_LOBBY_PLAYERS} players"
+                );
+                ensure!(
+                    (2..=MAX_TEAMS).contains(&settings.teams),
+                    "Lobbies can have 2 to {MAX_TEAMS} sides"
+                );
+                let sides = *self
+                    .maps
+                    .get(&settings.map)
+                    .with_context(|| format!("Unknown map {}", settings.map))?;
+                ensure!(
+                    settings.teams <= sides,
+                    "{} only has bases for {sides} sides",
+                    settings.map
+                );
+                check_rules(&settings.rules, settings.pick_mode)?;
+
+                let lobby = Lobby::new(settings, player_id);
+                let lobby_id = lobby.id;
+                self.lobbies.insert(lobby_id, lobby);
+                client.in_lobby = Some(lobby_id);
+                self.lobby_list_changed = true;
+
+                self.send(player_id, LobbyServerMessage::YouJoinedLobby { lobby_id });
+            }
+            LobbyClientMessage::ListLobbies => {
+                let lobbies = self.lobbies.values().map(Lobby::short_info).collect();
+                self.send(player_id, LobbyServerMessage::LobbyList { lobbies });
+            }
+            LobbyClientMessage::SubscribeLobbyList {
+                filter,
+                sort,
+                page_size,
+            } => {
+                ensure!(
+                    (1..=MAX_PAGE_SIZE).contains(&page_size),
+                    "Page size must be 1 to {MAX_PAGE_SIZE}"
+                );
+
+                let (lobbies, page_count) = self.lobby_list_page(&filter, sort, page_size, 0);
+                self.subscriptions.insert(
+                    player_id,
+                    Subscription {
+                        filter,
+                        sort,
+                        page_size,
+                        page: 0,
+                        page_count,
+                        shown: Vec::new(),
+                    },
+                );
+                self.show_page(player_id, 0, page_count, lobbies);
+            }
+            LobbyClientMessage::ViewLobbyListPage { page } => {
+                let sub = self
+                    .subscriptions
+                    .get(&player_id)
+                    .context("Not subscribed to the lobby list")?;
+                let (lobbies, page_count) =
+                    self.lobby_list_page(&sub.filter, sub.sort, sub.page_size, page);
+                ensure!(page < page_count, "No such page");
+
+                self.show_page(player_id, page, page_count, lobbies);
+            }
+            LobbyClientMessage::UnsubscribeLobbyList => {
+                self.subscriptions.remove(&player_id);
+            }
+            LobbyClientMessage::JoinLobby { id, password } => {
+                ensure!(
+                    client.in_lobby.is_none(),
+                    "Cannot join lobby while in one already"
+                );
+                ensure!(
+                    !self.queue.contains(player_id),
+                    "Cannot join lobby while queued"
+                );
+
+                let lobby = self
+                    .lobbies
+                    .get_mut(&id)
+                    .context("Cannot join lobby; lobby does not exist")?;
+
+                if let Some(expected) = &lobby.settings.password {
+                    ensure!(
+                        password.as_ref() == Some(expected),
+                        "Cannot join lobby; wrong password"
+                    );
+                }
+                ensure!(
+                    lobby.player_count() < lobby.settings.max_players,
+                    "Cannot join lobby; lobby is full"
+                );
+                ensure!(lobby.draft.is_none(), "Cannot join lobby during the draft");
+                ensure!(
+                    lobby.game.is_none(),
+                    "Cannot join lobby; the game has started"
+                );
+
+                let side = lobby.emptiest_side();
+
+                lobby.players.entry(side).or_default().push(player_id);
+                client.in_lobby = Some(lobby.id);
+                self.lobby_list_changed = true;
+
+                let joined_player = NetworkPlayer {
+                    id: player_id,
+                    username: client.username.clone(),
+                    bot: None,
+                };
+
+                let others = lobby
+                    .humans()
+                    .filter(|p| *p != player_id)
+                    .collect::<Vec<_>>();
+
+                self.send(
+                    player_id,
+                    LobbyServerMessage::YouJoinedLobby { lobby_id: id },
+                );
+
+                for player in others {
+                    self.send(
+                        player,
+                        LobbyServerMessage::PlayerJoinedLobby {
+                            player: joined_player.clone(),
+                            side,
+                        },
+                    );
+                }
+            }
+            LobbyClientMessage::SpectateLobby { id, password } => {
+                ensure!(
+                    client.in_lobby.is_none(),
+                    "Cannot spectate a lobby while in one"
+                );
+                ensure!(
+                    !self.queue.contains(player_id),
+                    "Cannot spectate a lobby while queued"
+                );
+                let lobby = self
+                    .lobbies
+                    .get_mut(&id)
+                    .context("Cannot spectate lobby; lobby does not exist")?;
+                if let Some(expected) = &lobby.settings.password {
+                    ensure!(
+                        password.as_ref() == Some(expected),
+                        "Cannot spectate lobby; wrong password"
+                    );
+                }
+
+                lobby.spectators.insert(player_id);
+                client.in_lobby = Some(id);
+
+                self.send(
+                    player_id,
+                    LobbyServerMessage::YouAreSpectating { lobby_id: id },
+                );
+            }
+            LobbyClientMessage::LeaveLobby => {
+                self.leave_lobby(player_id);
+            }
+            LobbyClientMessage::GetLobbyInfo { id } => {
+                let lobby = self
+                    .lobbies
+                    .get(&id)
+                    .context("Cannot get lobby info of non-existant lobby")?;
+
+                let lobby_info = LobbyInfo {
+                    id,
+                    players: lobby
+                        .players
+                        .iter()
+                        .map(|(side, players)| {
+                            (
+                                *side,
+                                players
+                                    .iter()
+                                    .filter_map(|p| {
+                                        lobby
+                                            .bot_player(*p)
+                                            .or_else(|| self.network_player(*p).ok())
+                                    })
+                                    .collect(),
+                            )
+                        })
+                        .collect(),
+                    lobby_owner: lobby.owner,
+                    roles: lobby.roles.clone(),
+                    rules: lobby.settings.rules.clone(),
+                };
+
+                self.send(
+                    player_id,
+                    LobbyServerMessage::LobbyInfo { info: lobby_info },
+                );
+            }
+            LobbyClientMessage::SwitchSide => {
+                let lobby_id = client
+                    .in_lobby
+                    .context("Cannot switch side while not in a lobby")?;
+                let player = self.network_player(player_id)?;
+                let lobby = self
+                    .lobbies
+                    .get_mut(&lobby_id)
+                    .context("Lobby does not exist")?;
+
+                let from = lobby
+                    .side_of(player_id)
+                    .context("Spectators have no side to switch")?;
+                ensure!(lobby.draft.is_none(), "Cannot switch side during the draft");
+                ensure!(
+                    lobby.game.is_none(),
+                    "Cannot switch side once the game has started"
+                );
+                let to = from.next(lobby.settings.teams);
+
+                lobby
+                    .players
+                    .entry(from)
+                    .or_default()
+                    .retain(|p| *p != player_id);
+                lobby.players.entry(to).or_default().push(player_id);
+
+                self.send_to_lobby(
+                    lobby_id,
+                    LobbyServerMessage::PlayerSwitchedSide { player, side: to },
+                );
+                self.assign_roles(lobby_id);
+            }
+            LobbyClientMessage::SetRolePreference { roles } => {
+                let lobby_id = client
+                    .in_lobby
+                    .context("Cannot choose roles while not in a lobby")?;
+                check_role_preference(roles)?;
+                let lobby = self
+                    .lobbies
+                    .get_mut(&lobby_id)
+                    .context("Lobby does not exist")?;
+                ensure!(
+                    lobby.side_of(player_id).is_some(),
+                    "Spectators cannot choose roles"
+                );
+
+                lobby.role_preferences.insert(player_id, roles);
+                self.assign_roles(lobby_id);
+            }
+            LobbyClientMessage::SetGameRules { rules } => {
+                let lobby_id = client
+                    .in_lobby
+                    .context("Cannot change rules while not in a lobby")?;
+                let lobby = self
+                    .lobbies
+                    .get_mut(&lobby_id)
+                    .context("Lobby does not exist")?;
+                ensure!(
+                    lobby.owner == player_id,
+                    "Only the lobby owner can change the rules"
+                );
+                ensure!(
+                    lobby.draft.is_none(),
+                    "Cannot change the rules during the draft"
+                );
+                ensure!(
+                    lobby.game.is_none(),
+                    "Cannot change the rules once the game has started"
+                );
+                check_rules(&rules, lobby.settings.pick_mode)?;
+
+                // Picks made so far don't count once champions are handed out at random.
+                let clear_picks = rules.all_random && !lobby.champions.is_empty();
+                if clear_picks {
+                    lobby.champions.clear();
+                    lobby.locked_in.clear();
+                }
+                lobby.settings.rules = rules.clone();
+
+                self.send_to_lobby(lobby_id, LobbyServerMessage::GameRulesChanged { rules });
+                if clear_picks {
+                    self.send_to_lobby(lobby_id, LobbyServerMessage::ChampionPicksCleared);
+                }
+            }
+            LobbyClientMessage::AddBot { side, difficulty } => {
+                let lobby_id = client
+                    .in_lobby
+                    .context("Cannot add a bot while not in a lobby")?;
+                let lobby = self
+                    .lobbies
+                    .get_mut(&lobby_id)
+                    .context("Lobby does not exist")?;
+                ensure!(
+                    lobby.owner == player_id,
+                    "Only the lobby owner can add bots"
+                );
+                ensure!(
+                    lobby.players.contains_key(&side),
+                    "The lobby has no such side"
+                );
+                ensure!(
+                    lobby.player_count() < lobby.settings.max_players,
+                    "Cannot add a bot; lobby is full"
+                );
+                ensure!(lobby.draft.is_none(), "Cannot add bots during the draft");
+                ensure!(
+                    lobby.game.is_none(),
+                    "Cannot add bots once the game has started"
+                );
+
+                let number = (1..)
+                    .find(|n| {
+                        let name = format!("Bot {n}");
+                        !lobby.bots.values().any(|bot| bot.name == name)
+                    })
+                    .unwrap();
+                let id = PlayerId(Uuid::new_v4());
+                lobby.bots.insert(
+                    id,
+                    Bot {
+                        name: format!("Bot {number}"),
+                        difficulty,
+                    },
+                );
+                lobby.players.entry(side).or_default().push(id);
+                let player = lobby.bot_player(id).unwrap();
+                self.lobby_list_changed = true;
+
+                self.send_to_lobby(
+                    lobby_id,
+                    LobbyServerMessage::PlayerJoinedLobby {
+                        player: player.clone(),
+                        side,
+                    },
+                );
+
+                // Bots don't deliberate; in a draft they pick on their turn instead.
+                let lobby = &self.lobbies[&lobby_id];
+                if lobby.settings.pick_mode == PickMode::FreePick
+                    && !lobby.settings.rules.all_random
+                {
+                    if let Some(champion) = lobby.auto_pick(&self.champions) {
+                        self.lock_in(lobby_id, id, champion);
+                    }
+                }
+            }
+            LobbyClientMessage::RemoveBot { id } => {
+                let lobby_id = client
+                    .in_lobby
+                    .context("Cannot remove a bot while not in a lobby")?;
+                let lobby = self
+                    .lobbies
+                    .get_mut(&lobby_id)
+                    .context("Lobby does not exist")?;
+                ensure!(
+                    lobby.owner == player_id,
+                    "Only the lobby owner can remove bots"
+                );
+                ensure!(lobby.draft.is_none(), "Cannot remove bots during the draft");
+                ensure!(
+                    lobby.game.is_none(),
+                    "Cannot remove bots once the game has started"
+                );
+
+                let player = lobby.bot_player(id).context("No such bot in this lobby")?;
+                lobby.bots.remove(&id);
+                for players in lobby.players.values_mut() {
+                    players.retain(|p| *p != id);
+                }
+                lobby.champions.remove(&id);
+                lobby.locked_in.remove(&id);
+                self.lobby_list_changed = true;
+
+                self.send_to_lobby(lobby_id, LobbyServerMessage::PlayerLeftLobby { player });
+            }
+            LobbyClientMessage::SelectChampion { champion } => {
+                let lobby_id = client
+                    .in_lobby
+                    .context("Cannot select a champion while not in a lobby")?;
+                let player = self.network_player(player_id)?;
+                let lobby = self
+                    .lobbies
+                    .get_mut(&lobby_id)
+                    .context("Lobby does not exist")?;
+
+                ensure!(
+                    lobby.side_of(player_id).is_some(),
+                    "Spectators cannot pick champions"
+                );
+                ensure!(
+                    !lobby.settings.rules.all_random,
+                    "Champions are random in this lobby"
+                );
+                ensure!(
+                    !lobby.locked_in.contains(&player_id),
+                    "Cannot change champion after locking in"
+                );
+                check_champion(&self.champions, &champion)?;
+                ensure!(!lobby.is_taken(&champion), "{champion} is not available");
+                lobby.champions.insert(player_id, champion.clone());
+
+                self.send_to_lobby(
+                    lobby_id,
+                    LobbyServerMessage::PlayerSelectedChampion { player, champion },
+                );
+            }
+            LobbyClientMessage::LockInChampion { champion } => {
+                let lobby_id = client
+                    .in_lobby
+                    .context("Cannot lock in a champion while not in a lobby")?;
+                let lobby = self
+                    .lobbies
+                    .get(&lobby_id)
+                    .context("Lobby does not exist")?;
+
+                ensure!(
+                    lobby.side_of(player_id).is_some(),
+                    "Spectators cannot pick champions"
+                );
+                ensure!(
+                    !lobby.settings.rules.all_random,
+                    "Champions are random in this lobby"
+                );
+                ensure!(
+                    !lobby.locked_in.contains(&player_id),
+                    "Already locked in a champion"
+                );
+                check_champion(&self.champions, &champion)?;
+                ensure!(!lobby.is_taken(&champion), "{champion} is not available");
+
+                if let PickMode::Draft { .. } = lobby.settings.pick_mode {
+                    self.draft_pick(lobby_id, player_id, champion)?;
+                } else {
+                    self.lock_in(lobby_id, player_id, champion);
+                }
+            }
+            LobbyClientMessage::StartDraft => {
+                let lobby_id = client
+                    .in_lobby
+                    .context("Cannot start a draft while not in a lobby")?;
+                self.start_draft(lobby_id, player_id)?;
+            }
+            LobbyClientMessage::BanChampion { champion } => {
+                let lobby_id = client
+                    .in_lobby
+                    .context("Cannot ban a champion while not in a lobby")?;
+                self.draft_ban(lobby_id, player_id, champion)?;
+            }
+            LobbyClientMessage::StartGame => {
+                let lobby_id = client
+                    .in_lobby
+                    .context("Cannot start a game while not in a lobby")?;
+                self.start_game(lobby_id, player_id)?;
+            }
+            LobbyClientMessage::Chat { text } => {
+                let lobby_id = client
+                    .in_lobby
+                    .context("Cannot chat while not in a lobby")?;
+                ensure!(
+                    text.chars().count() <= MAX_CHAT_LENGTH,
+                    "Chat messages can be at most {MAX_CHAT_LENGTH} characters"
+                );
+                let from = self.network_player(player_id)?;
+
+                self.send_to_lobby(lobby_id, LobbyServerMessage::ChatMessage { from, text });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn leave_lobby(&mut self, player: PlayerId) {
+        let Some(client) = self.players.get_mut(&player) else {
+            return;
+        };
+        let Some(lobby_id) = client.in_lobby else {
+            return;
+        };
+        let Some(lobby) = self.lobbies.get_mut(&lobby_id) else {
+            return;
+        };
+
+        client.in_lobby = None;
+        self.outbox.push(Outgoing {
+            to: player,
+            msg: LobbyServerMessage::YouLeftLobby,
+        });
+
+        if lobby.spectators.remove(&player) {
+            return;
+        }
+
+        for players in lobby.players.values_mut() {
+            let Some(pos) = players.iter().position(|&id| id == player) else {
+                continue;
+            };
+
+            players.remove(pos);
+            break;
+        }
+        lobby.champions.remove(&player);
+        lobby.locked_in.remove(&player);
+        lobby.role_preferences.remove(&player);
+        self.lobby_list_changed = true;
+
+        let left_player = NetworkPlayer {
+            id: player,
+            username: client.username.clone(),
+            bot: None,
+        };
+
+        // The longest-standing player on the first side takes over if the owner leaves.
+        // Bots can't own a lobby, so it closes once only they are left.
+        let Some(new_owner) = Side::all(lobby.settings.teams).find_map(|side| {
+            lobby
+                .players
+                .get(&side)?
+                .iter()
+                .copied()
+                .find(|id| !lobby.bots.contains_key(id))
+        }) else {
+            let lobby = self.lobbies.remove(&lobby_id).unwrap();
+            if lobby.game.is_some() {
+                self.cancelled_games.push(lobby_id);
+            }
+            for spectator in lobby.spectators {
+                if let Some(client) = self.players.get_mut(&spectator) {
+                    client.in_lobby = None;
+                }
+                self.send(spectator, LobbyServerMessage::YouLeftLobby);
+            }
+            return;
+        };
+
+        let owner_changed = lobby.owner == player;
+        if owner_changed {
+            lobby.owner = new_owner;
+        }
+
+        self.send_to_lobby(
+            lobby_id,
+            LobbyServerMessage::PlayerLeftLobby {
+                player: left_player,
+            },
+        );
+        if owner_changed {
+            self.send_to_lobby(
+                lobby_id,
+                LobbyServerMessage::LobbyOwnerChanged { owner: new_owner },
+            );
+        }
+
+        self.assign_roles(lobby_id);
+        // Their turns can't be taken any more.
+        self.cancel_draft(lobby_id, "A player left");
+    }
+}
+
+fn check_role_preference(roles: RolePreference) -> anyhow::Result<()> {
+    ensure!(
+        roles.secondary != Some(roles.primary),
+        "The secondary role must differ from the primary one"
+    );
+    Ok(())
+}
+
+/// Fails if the game could not be played with `rules` in a lobby picking with `pick_mode`.
+fn check_rules(rules: &GameRules, pick_mode: PickMode) -> anyhow::Result<()> {
+    rules.check()?;
+    ensure!(
+        !(rules.all_random && matches!(pick_mode, PickMode::Draft { .. })),
+        "A draft lobby cannot give out random champions"
+    );
+    Ok(())
+}
+
+/// Fails unless `champion` is in the catalog and enabled.
+fn check_champion(champions: &ChampionCatalog, champion: &str) -> anyhow::Result<()> {
+    match champions.get(champion) {
+        None => bail!("There is no champion called {champion}"),
+        Some(c) if !c.enabled => bail!("{} is disabled", c.name),
+        Some(_) => Ok(()),
+    }
+}
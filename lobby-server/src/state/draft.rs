@@ -0,0 +1,294 @@
+//! Running [`PickMode::Draft`] champion select in a lobby.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, ensure, Context};
+use bevy::utils::{HashMap, HashSet};
+use common::{
+    network::lobby::{draft, DraftAction, LobbyId, LobbyServerMessage, PickMode, PlayerId},
+    Side,
+};
+
+use super::{check_champion, Lobby, State};
+
+pub(super) struct Draft {
+    turns: Vec<Turn>,
+    current: usize,
+    turn_time: Duration,
+    /// When the current turn runs out.
+    pub(super) deadline: Instant,
+    pub(super) banned: HashSet<String>,
+}
+
+impl Draft {
+    fn current_turn(&self) -> Option<Turn> {
+        self.turns.get(self.current).copied()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Turn {
+    /// Anyone on the side can ban.
+    Ban(Side),
+    Pick(Side, PlayerId),
+}
+
+impl Lobby {
+    /// Whether bots get to take `turn`, which they do without waiting.
+    fn is_bot_turn(&self, turn: Turn) -> bool {
+        match turn {
+            Turn::Ban(side) => self
+                .players
+                .get(&side)
+                .is_none_or(|players| players.iter().all(|p| self.bots.contains_key(p))),
+            Turn::Pick(_, player) => self.bots.contains_key(&player),
+        }
+    }
+}
+
+impl State {
+    pub(super) fn start_draft(
+        &mut self,
+        lobby_id: LobbyId,
+        player: PlayerId,
+    ) -> anyhow::Result<()> {
+        let lobby = self
+            .lobbies
+            .get_mut(&lobby_id)
+            .context("Lobby does not exist")?;
+        ensure!(
+            lobby.owner == player,
+            "Only the lobby owner can start the draft"
+        );
+        let PickMode::Draft {
+            bans_per_side,
+            turn_secs,
+        } = lobby.settings.pick_mode
+        else {
+            bail!("This lobby does not use draft pick");
+        };
+        ensure!(lobby.draft.is_none(), "The draft has already started");
+        ensure!(lobby.game.is_none(), "The game has already started");
+
+        let teams = lobby.settings.teams;
+        let team_size = |side| lobby.players.get(&side).map_or(0, Vec::len);
+        let size = team_size(Side::RED);
+        ensure!(
+            size > 0 && Side::all(teams).all(|side| team_size(side) == size),
+            "Every side needs the same number of players to draft"
+        );
+
+        // Within a side, players pick in the order they joined it.
+        let mut picked = HashMap::<Side, usize>::new();
+        let picks = draft::pick_order(teams, size).map(|side| {
+            let index = picked.entry(side).or_default();
+            let player = lobby.players[&side][*index];
+            *index += 1;
+            Turn::Pick(side, player)
+        });
+        let turns = draft::ban_order(teams, bans_per_side)
+            .map(Turn::Ban)
+            .chain(picks)
+            .collect();
+
+        lobby.champions.clear();
+        lobby.locked_in.clear();
+        lobby.draft = Some(Draft {
+            turns,
+            current: 0,
+            turn_time: Duration::from_secs(turn_secs),
+            deadline: self.clock.0,
+            banned: HashSet::new(),
+        });
+
+        self.run_draft(lobby_id);
+        Ok(())
+    }
+
+    pub(super) fn draft_ban(
+        &mut self,
+        lobby_id: LobbyId,
+        player: PlayerId,
+        champion: String,
+    ) -> anyhow::Result<()> {
+        let lobby = self
+            .lobbies
+            .get(&lobby_id)
+            .context("Lobby does not exist")?;
+        let draft = lobby.draft.as_ref().context("There is no draft going on")?;
+        let Some(Turn::Ban(side)) = draft.current_turn() else {
+            bail!("It is not time to ban");
+        };
+        ensure!(
+            lobby.side_of(player) == Some(side),
+            "It is not your side's turn to ban"
+        );
+        check_champion(&self.champions, &champion)?;
+        ensure!(!lobby.is_taken(&champion), "{champion} is not available");
+
+        self.ban(lobby_id, side, Some(champion));
+        self.end_turn(lobby_id);
+        Ok(())
+    }
+
+    pub(super) fn draft_pick(
+        &mut self,
+        lobby_id: LobbyId,
+        player: PlayerId,
+        champion: String,
+    ) -> anyhow::Result<()> {
+        let lobby = self
+            .lobbies
+            .get(&lobby_id)
+            .context("Lobby does not exist")?;
+        let draft = lobby
+            .draft
+            .as_ref()
+            .context("Champions are picked once the draft starts")?;
+        ensure!(
+            matches!(draft.current_turn(), Some(Turn::Pick(_, p)) if p == player),
+            "It is not your turn to pick"
+        );
+
+        self.lock_in(lobby_id, player, champion);
+        self.end_turn(lobby_id);
+        Ok(())
+    }
+
+    /// Stops the draft, if there is one, and throws away its bans and picks.
+    pub(super) fn cancel_draft(&mut self, lobby_id: LobbyId, reason: &str) {
+        let Some(lobby) = self.lobbies.get_mut(&lobby_id) else {
+            return;
+        };
+        if lobby.draft.take().is_none() {
+            return;
+        }
+        lobby.champions.clear();
+        lobby.locked_in.clear();
+
+        self.send_to_lobby(
+            lobby_id,
+            LobbyServerMessage::DraftCancelled {
+                reason: reason.into(),
+            },
+        );
+    }
+
+    pub(super) fn expire_draft_turns(&mut self) {
+        let now = self.clock.0;
+        let expired = self
+            .lobbies
+            .values()
+            .filter(|lobby| lobby.draft.as_ref().is_some_and(|d| d.deadline <= now))
+            .map(|lobby| lobby.id)
+            .collect::<Vec<_>>();
+
+        for lobby_id in expired {
+            self.auto_turn(lobby_id);
+            self.end_turn(lobby_id);
+        }
+    }
+
+    fn ban(&mut self, lobby_id: LobbyId, side: Side, champion: Option<String>) {
+        let Some(draft) = self
+            .lobbies
+            .get_mut(&lobby_id)
+            .and_then(|lobby| lobby.draft.as_mut())
+        else {
+            return;
+        };
+        if let Some(champion) = &champion {
+            draft.banned.insert(champion.clone());
+        }
+
+        self.send_to_lobby(
+            lobby_id,
+            LobbyServerMessage::ChampionBanned { side, champion },
+        );
+    }
+
+    /// Takes the current turn for whoever it belongs to. Bots ban and pick the first
+    /// available champion in the catalog. Players who ran out of time lose their ban, and lock in the
+    /// champion they had selected, or else the first available one.
+    fn auto_turn(&mut self, lobby_id: LobbyId) {
+        let Some(lobby) = self.lobbies.get(&lobby_id) else {
+            return;
+        };
+        let Some(turn) = lobby.draft.as_ref().and_then(Draft::current_turn) else {
+            return;
+        };
+
+        match turn {
+            Turn::Ban(side) => {
+                let champion = if lobby.is_bot_turn(turn) {
+                    lobby.auto_pick(&self.champions)
+                } else {
+                    None
+                };
+                self.ban(lobby_id, side, champion);
+            }
+            Turn::Pick(_, player) => {
+                let selected = lobby
+                    .champions
+                    .get(&player)
+                    .filter(|c| !lobby.is_taken(c))
+                    .cloned();
+                if let Some(champion) = selected.or_else(|| lobby.auto_pick(&self.champions)) {
+                    self.lock_in(lobby_id, player, champion);
+                }
+            }
+        }
+    }
+
+    fn end_turn(&mut self, lobby_id: LobbyId) {
+        if let Some(draft) = self
+            .lobbies
+            .get_mut(&lobby_id)
+            .and_then(|lobby| lobby.draft.as_mut())
+        {
+            draft.current += 1;
+        }
+        self.run_draft(lobby_id);
+    }
+
+    /// Announces the current turn and starts its timer, playing it straight away if it
+    /// is a bot's. Finishes the draft after the last turn.
+    fn run_draft(&mut self, lobby_id: LobbyId) {
+        let now = self.clock.0;
+        let Some(lobby) = self.lobbies.get_mut(&lobby_id) else {
+            return;
+        };
+        let Some(draft) = lobby.draft.as_mut() else {
+            return;
+        };
+
+        let Some(turn) = draft.current_turn() else {
+            lobby.draft = None;
+            self.send_to_lobby(lobby_id, LobbyServerMessage::DraftFinished);
+            return;
+        };
+
+        draft.deadline = now + draft.turn_time;
+        let time_left = draft.turn_time;
+        let (side, action, player) = match turn {
+            Turn::Ban(side) => (side, DraftAction::Ban, None),
+            Turn::Pick(side, player) => (side, DraftAction::Pick, Some(player)),
+        };
+        let bot_turn = lobby.is_bot_turn(turn);
+
+        self.send_to_lobby(
+            lobby_id,
+            LobbyServerMessage::DraftTurn {
+                side,
+                action,
+                player,
+                time_left,
+            },
+        );
+
+        if bot_turn {
+            self.auto_turn(lobby_id);
+            self.end_turn(lobby_id);
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Starting a lobby's game on a game server, and hearing back from it.
+
+use std::net::SocketAddr;
+
+use anyhow::{ensure, Context};
+use bevy::utils::HashMap;
+use common::{
+    network::{
+        game::{JoinToken, MatchSetup},
+        lobby::{LobbyId, LobbyServerMessage, PlayerId},
+    },
+    Side,
+};
+use uuid::Uuid;
+
+use super::{Outgoing, State};
+
+/// A lobby's game, from when the owner starts it until it ends.
+pub(super) struct Game {
+    tokens: HashMap<PlayerId, JoinToken>,
+}
+
+/// A game that needs a game server; see [`State::take_match_requests`].
+#[derive(Debug, Clone)]
+pub struct MatchRequest {
+    pub lobby: LobbyId,
+    pub setup: MatchSetup,
+    /// One for each human player.
+    pub tokens: HashMap<PlayerId, JoinToken>,
+}
+
+impl State {
+    pub(super) fn start_game(&mut self, lobby_id: LobbyId, player: PlayerId) -> anyhow::Result<()> {
+        let lobby = self
+            .lobbies
+            .get(&lobby_id)
+            .context("Lobby does not exist")?;
+        ensure!(
+            lobby.owner == player,
+            "Only the lobby owner can start the game"
+        );
+        ensure!(lobby.game.is_none(), "The game has already started");
+        ensure!(lobby.draft.is_none(), "The draft is not over yet");
+        ensure!(
+            lobby.settings.rules.all_random
+                || lobby
+                    .players
+                    .values()
+                    .flatten()
+                    .all(|p| lobby.locked_in.contains(p)),
+            "Everyone has to lock in a champion first"
+        );
+
+        let tokens = lobby
+            .humans()
+            .map(|p| (p, JoinToken(Uuid::new_v4())))
+            .collect::<HashMap<_, _>>();
+        let setup = self.match_setup(lobby_id)?;
+
+        self.lobbies.get_mut(&lobby_id).unwrap().game = Some(Game {
+            tokens: tokens.clone(),
+        });
+        self.match_requests.push(MatchRequest {
+            lobby: lobby_id,
+            setup,
+            tokens,
+        });
+        self.send_to_lobby(lobby_id, LobbyServerMessage::GameStarting);
+        Ok(())
+    }
+
+    /// Games started since the last call, which the caller should find game servers for.
+    /// Each one is answered with [`State::game_allocated`] or [`State::game_failed`].
+    pub fn take_match_requests(&mut self) -> Vec<MatchRequest> {
+        std::mem::take(&mut self.match_requests)
+    }
+
+    /// Lobbies closed since the last call whose game the caller should stop looking for a
+    /// game server for.
+    pub fn take_cancelled_games(&mut self) -> Vec<LobbyId> {
+        std::mem::take(&mut self.cancelled_games)
+    }
+
+    /// A game server at `addr` is hosting the lobby's game. Tells each player how to
+    /// join it.
+    pub fn game_allocated(&mut self, lobby_id: LobbyId, addr: SocketAddr) -> Vec<Outgoing> {
+        let Some(game) = self
+            .lobbies
+            .get(&lobby_id)
+            .and_then(|lobby| lobby.game.as_ref())
+        else {
+            return self.take_outbox();
+        };
+
+        let tokens = game.tokens.clone();
+        for (player, token) in tokens {
+            self.send(player, LobbyServerMessage::GameReady { addr, token });
+        }
+        self.take_outbox()
+    }
+
+    /// The lobby's game could not be started, or was lost. Opens the lobby again.
+    pub fn game_failed(&mut self, lobby_id: LobbyId, reason: String) -> Vec<Outgoing> {
+        let Some(lobby) = self.lobbies.get_mut(&lobby_id) else {
+            return self.take_outbox();
+        };
+        if lobby.game.take().is_some() {
+            self.send_to_lobby(lobby_id, LobbyServerMessage::GameFailed { reason });
+        }
+        self.take_outbox()
+    }
+
+    /// The lobby's game is over, so the lobby closes.
+    pub fn game_finished(&mut self, lobby_id: LobbyId, winner: Option<Side>) -> Vec<Outgoing> {
+        if self
+            .lobbies
+            .get(&lobby_id)
+            .is_none_or(|lobby| lobby.game.is_none())
+        {
+            return self.take_outbox();
+        }
+
+        self.lobbies.get_mut(&lobby_id).unwrap().game = None;
+        self.send_to_lobby(lobby_id, LobbyServerMessage::GameEnded { winner });
+        let mut outgoing = self.take_outbox();
+        outgoing.extend(self.close_lobby(lobby_id).unwrap_or_default());
+        outgoing
+    }
+}
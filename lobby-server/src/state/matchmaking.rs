@@ -0,0 +1,191 @@
+//! The matchmaking queue, and giving players the roles they asked for.
+
+use anyhow::ensure;
+use bevy::utils::HashMap;
+use common::{
+    champion::Role,
+    network::lobby::{LobbyId, LobbyServerMessage, LobbySettings, PlayerId, RolePreference},
+    Side,
+};
+
+use super::{check_role_preference, Lobby, State};
+
+/// Players per team in a matchmade game: one for each role.
+const TEAM_SIZE: usize = Role::ALL.len();
+
+/// Matchmade games are always one team against another.
+const TEAMS: usize = 2;
+
+/// Players waiting for a match, longest waiting first.
+#[derive(Default)]
+pub(super) struct Queue {
+    entries: Vec<(PlayerId, RolePreference)>,
+}
+
+impl Queue {
+    pub(super) fn contains(&self, player: PlayerId) -> bool {
+        self.entries.iter().any(|(p, _)| *p == player)
+    }
+
+    pub(super) fn remove(&mut self, player: PlayerId) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|(p, _)| *p != player);
+        self.entries.len() != len
+    }
+}
+
+impl State {
+    pub(super) fn start_matchmaking(
+        &mut self,
+        player: PlayerId,
+        roles: RolePreference,
+    ) -> anyhow::Result<()> {
+        check_role_preference(roles)?;
+        ensure!(!self.queue.contains(player), "Already queued");
+
+        self.queue.entries.push((player, roles));
+        self.send(player, LobbyServerMessage::OK);
+        self.make_matches();
+        Ok(())
+    }
+
+    pub(super) fn stop_matchmaking(&mut self, player: PlayerId) -> anyhow::Result<()> {
+        ensure!(self.queue.remove(player), "Not queued");
+        self.send(player, LobbyServerMessage::OK);
+        Ok(())
+    }
+
+    /// Puts the longest waiting players into lobbies, as long as there are enough of
+    /// them for two full teams.
+    fn make_matches(&mut self) {
+        while self.queue.entries.len() >= TEAM_SIZE * TEAMS {
+            let entries = self
+                .queue
+                .entries
+                .drain(..TEAM_SIZE * TEAMS)
+                .collect::<Vec<_>>();
+            self.create_match(entries);
+        }
+    }
+
+    fn create_match(&mut self, entries: Vec<(PlayerId, RolePreference)>) {
+        let teams = split_teams(&entries);
+        let owner = entries[0].0;
+
+        let mut lobby = Lobby::new(
+            LobbySettings {
+                name: "Matchmade".into(),
+                max_players: entries.len(),
+                teams: TEAMS,
+                ..Default::default()
+            },
+            owner,
+        );
+        lobby.players = teams;
+        lobby.role_preferences = entries.iter().copied().collect();
+        let lobby_id = lobby.id;
+        self.lobbies.insert(lobby_id, lobby);
+        self.lobby_list_changed = true;
+
+        for (player, _) in entries {
+            if let Some(client) = self.players.get_mut(&player) {
+                client.in_lobby = Some(lobby_id);
+            }
+            self.send(player, LobbyServerMessage::MatchmakingDone { lobby_id });
+            self.send(player, LobbyServerMessage::YouJoinedLobby { lobby_id });
+        }
+        self.assign_roles(lobby_id);
+    }
+
+    /// Works out everyone's role from their preferences, and tells the lobby if anything
+    /// changed.
+    pub(super) fn assign_roles(&mut self, lobby_id: LobbyId) {
+        let Some(lobby) = self.lobbies.get_mut(&lobby_id) else {
+            return;
+        };
+
+        let roles = lobby
+            .players
+            .values()
+            .flat_map(|players| {
+                let wanting = players
+                    .iter()
+                    .filter_map(|p| Some((*p, *lobby.role_preferences.get(p)?)))
+                    .collect::<Vec<_>>();
+                fill_roles(&wanting)
+            })
+            .collect::<HashMap<_, _>>();
+        if roles == lobby.roles {
+            return;
+        }
+        lobby.roles = roles.clone();
+
+        self.send_to_lobby(lobby_id, LobbyServerMessage::RolesAssigned { roles });
+    }
+}
+
+/// Gives each player in a team a different role: their primary one if nobody before them
+/// took it, then their secondary one, then whatever is left. Players beyond the number
+/// of roles get none.
+fn fill_roles(players: &[(PlayerId, RolePreference)]) -> Vec<(PlayerId, Role)> {
+    fill_slots(players, Role::ALL.to_vec(), |role| *role)
+}
+
+/// Splits players into two teams that each have every role covered, giving as many
+/// players as possible a role they asked for.
+fn split_teams(entries: &[(PlayerId, RolePreference)]) -> HashMap<Side, Vec<PlayerId>> {
+    let slots = Side::all(TEAMS)
+        .flat_map(|side| Role::ALL.map(|role| (side, role)))
+        .collect();
+    let sides = fill_slots(entries, slots, |(_, role)| *role);
+
+    let mut teams = HashMap::new();
+    for side in Side::all(TEAMS) {
+        teams.insert(side, Vec::new());
+    }
+    // Keep queue order within each team, so earlier players still come first when
+    // `fill_roles` runs on it.
+    for (player, _) in entries {
+        if let Some((_, (side, _))) = sides.iter().find(|(p, _)| p == player) {
+            teams.entry(*side).or_default().push(*player);
+        }
+    }
+    teams
+}
+
+/// Hands out `open` slots, each for one role, to players in order: first by primary role,
+/// then by secondary role, then whichever slot is left.
+fn fill_slots<S>(
+    players: &[(PlayerId, RolePreference)],
+    mut open: Vec<S>,
+    role_of: impl Fn(&S) -> Role,
+) -> Vec<(PlayerId, S)> {
+    let mut filled = Vec::new();
+    let is_filled = |filled: &[(PlayerId, S)], player| filled.iter().any(|(p, _)| *p == player);
+
+    let choices: [fn(&RolePreference) -> Option<Role>; 2] = [|p| Some(p.primary), |p| p.secondary];
+    for choice in choices {
+        for (player, preference) in players {
+            if is_filled(&filled, *player) {
+                continue;
+            }
+            let Some(role) = choice(preference) else {
+                continue;
+            };
+            if let Some(i) = open.iter().position(|slot| role_of(slot) == role) {
+                filled.push((*player, open.remove(i)));
+            }
+        }
+    }
+
+    for (player, _) in players {
+        if open.is_empty() {
+            break;
+        }
+        if !is_filled(&filled, *player) {
+            filled.push((*player, open.remove(0)));
+        }
+    }
+
+    filled
+}
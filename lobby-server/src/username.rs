@@ -0,0 +1,84 @@
+//! Rules for the usernames clients connect with.
+
+use common::network::lobby::UsernameError;
+use serde::Deserialize;
+use unicode_normalization::UnicodeNormalization;
+use unicode_security::{confusable_detection::skeleton, MixedScript};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct UsernameRules {
+    /// Length limits in characters, not counting any discriminator.
+    pub min_length: usize,
+    pub max_length: usize,
+    /// Names that can't be used as a whole, like "admin".
+    pub reserved: Vec<String>,
+    /// Words that can't appear anywhere in a name.
+    pub blocklist: Vec<String>,
+    /// Add a `#1234` suffix to names already in use instead of rejecting them.
+    pub discriminators: bool,
+}
+
+impl Default for UsernameRules {
+    fn default() -> Self {
+        Self {
+            min_length: 3,
+            max_length: 16,
+            reserved: ["admin", "administrator", "moderator", "server", "system"]
+                .map(String::from)
+                .to_vec(),
+            blocklist: Vec::new(),
+            discriminators: true,
+        }
+    }
+}
+
+impl UsernameRules {
+    /// Checks a requested name against everything but uniqueness, and returns it cleaned
+    /// up for display.
+    pub fn validate(&self, requested: &str) -> Result<String, UsernameError> {
+        let name = requested.trim().nfc().collect::<String>();
+
+        let length = name.chars().count();
+        if length < self.min_length {
+            return Err(UsernameError::TooShort {
+                min: self.min_length,
+            });
+        }
+        if length > self.max_length {
+            return Err(UsernameError::TooLong {
+                max: self.max_length,
+            });
+        }
+
+        if let Some(character) = name
+            .chars()
+            .find(|c| !(c.is_alphanumeric() || matches!(c, '_' | '-' | ' ')))
+        {
+            return Err(UsernameError::InvalidCharacter { character });
+        }
+        if !name.is_single_script() {
+            return Err(UsernameError::MixedScripts);
+        }
+
+        let key = comparison_key(&name);
+        if self.reserved.iter().any(|r| comparison_key(r) == key) {
+            return Err(UsernameError::Reserved);
+        }
+        if self
+            .blocklist
+            .iter()
+            .any(|b| key.contains(&comparison_key(b)))
+        {
+            return Err(UsernameError::Blocked);
+        }
+
+        Ok(name)
+    }
+}
+
+/// Two names with the same key look alike: it ignores case and maps confusable
+/// characters, like Cyrillic `а` and Latin `a`, or `0` and `O`, to the same thing.
+pub fn comparison_key(name: &str) -> String {
+    skeleton(&name.to_lowercase()).collect()
+}
@@ -0,0 +1,323 @@
+use std::time::Duration;
+
+use common::{
+    network::lobby::{
+        BotDifficulty, DraftAction, LobbyClientMessage, LobbyId, LobbyServerMessage, LobbySettings,
+        PickMode, PlayerId,
+    },
+    Side,
+};
+use lobby_server::{expect_msg, harness::Harness};
+
+const TURN: Duration = Duration::from_secs(30);
+
+fn draft_settings(bans_per_side: usize) -> LobbySettings {
+    LobbySettings {
+        pick_mode: PickMode::Draft {
+            bans_per_side,
+            turn_secs: TURN.as_secs(),
+        },
+        ..Default::default()
+    }
+}
+
+/// Sets up a draft lobby with `alice` on Red and `bob` on Blue, with empty inboxes.
+fn one_on_one(harness: &mut Harness, bans_per_side: usize) -> (LobbyId, PlayerId, PlayerId) {
+    let alice = harness.connect("alice");
+    let bob = harness.connect("bob");
+    harness.send(
+        alice,
+        LobbyClientMessage::CreateLobby {
+            settings: draft_settings(bans_per_side),
+        },
+    );
+    let lobby =
+        expect_msg!(harness, alice, LobbyServerMessage::YouJoinedLobby { lobby_id } => lobby_id);
+    harness.send(
+        bob,
+        LobbyClientMessage::JoinLobby {
+            id: lobby,
+            password: None,
+        },
+    );
+    harness.drain(alice);
+    harness.drain(bob);
+    (lobby, alice, bob)
+}
+
+fn ban(champion: &str) -> LobbyClientMessage {
+    LobbyClientMessage::BanChampion {
+        champion: champion.into(),
+    }
+}
+
+fn lock_in(champion: &str) -> LobbyClientMessage {
+    LobbyClientMessage::LockInChampion {
+        champion: champion.into(),
+    }
+}
+
+#[test]
+fn bans_then_picks_alternate_between_sides() {
+    let mut harness = Harness::new();
+    let (_, alice, bob) = one_on_one(&mut harness, 1);
+
+    harness.send(bob, LobbyClientMessage::StartDraft);
+    expect_msg!(harness, bob, LobbyServerMessage::Negative { .. });
+
+    harness.send(alice, LobbyClientMessage::StartDraft);
+    for player in [alice, bob] {
+        expect_msg!(
+            harness,
+            player,
+            LobbyServerMessage::DraftTurn { side: Side::RED, action: DraftAction::Ban, player: None, time_left }
+                if time_left == TURN
+        );
+    }
+
+    harness.send(bob, ban("mage"));
+    expect_msg!(harness, bob, LobbyServerMessage::Negative { .. });
+
+    harness.send(alice, ban("mage"));
+    for player in [alice, bob] {
+        expect_msg!(
+            harness,
+            player,
+            LobbyServerMessage::ChampionBanned { side: Side::RED, champion: Some(c) } if c == "mage"
+        );
+        expect_msg!(
+            harness,
+            player,
+            LobbyServerMessage::DraftTurn {
+                side: Side::BLUE,
+                action: DraftAction::Ban,
+                ..
+            }
+        );
+    }
+
+    harness.send(bob, ban("mage"));
+    expect_msg!(harness, bob, LobbyServerMessage::Negative { .. });
+    harness.send(bob, ban("ranger"));
+    harness.drain(alice);
+    harness.drain(bob);
+
+    // Banned champions can't be picked, and only the player whose turn it is can pick.
+    harness.send(bob, lock_in("warrior"));
+    expect_msg!(harness, bob, LobbyServerMessage::Negative { .. });
+    harness.send(alice, lock_in("ranger"));
+    expect_msg!(harness, alice, LobbyServerMessage::Negative { .. });
+
+    harness.send(alice, lock_in("warrior"));
+    expect_msg!(
+        harness,
+        bob,
+        LobbyServerMessage::PlayerLockedInChampion { player, champion } if player.id == alice && champion == "warrior"
+    );
+    expect_msg!(
+        harness,
+        bob,
+        LobbyServerMessage::DraftTurn { side: Side::BLUE, action: DraftAction::Pick, player: Some(p), .. } if p == bob
+    );
+
+    harness.send(bob, lock_in("warrior"));
+    expect_msg!(harness, bob, LobbyServerMessage::Negative { .. });
+    harness.send(bob, lock_in("knight"));
+    expect_msg!(
+        harness,
+        bob,
+        LobbyServerMessage::PlayerLockedInChampion { .. }
+    );
+    expect_msg!(harness, bob, LobbyServerMessage::DraftFinished);
+}
+
+#[test]
+fn pick_order_goes_one_two_two_one() {
+    let mut harness = Harness::new();
+    let (lobby, alice, _) = one_on_one(&mut harness, 0);
+    let carol = harness.connect("carol");
+    let dave = harness.connect("dave");
+    for player in [carol, dave] {
+        harness.send(
+            player,
+            LobbyClientMessage::JoinLobby {
+                id: lobby,
+                password: None,
+            },
+        );
+    }
+    harness.drain(alice);
+
+    harness.send(alice, LobbyClientMessage::StartDraft);
+    let mut sides = Vec::new();
+    for champion in ["warrior", "ranger", "mage", "guardian"] {
+        let (side, player) = expect_msg!(
+            harness,
+            alice,
+            LobbyServerMessage::DraftTurn { side, action: DraftAction::Pick, player: Some(player), .. }
+                => (side, player)
+        );
+        sides.push(side);
+        harness.send(player, lock_in(champion));
+        expect_msg!(
+            harness,
+            alice,
+            LobbyServerMessage::PlayerLockedInChampion { .. }
+        );
+    }
+    expect_msg!(harness, alice, LobbyServerMessage::DraftFinished);
+
+    assert_eq!(sides, [Side::RED, Side::BLUE, Side::BLUE, Side::RED]);
+}
+
+#[test]
+fn timed_out_turns_are_played_automatically() {
+    let mut harness = Harness::new();
+    let (_, alice, bob) = one_on_one(&mut harness, 1);
+
+    harness.send(alice, LobbyClientMessage::StartDraft);
+    harness.send(alice, ban("warrior"));
+    harness.drain(bob);
+
+    harness.advance(TURN / 2);
+    harness.assert_no_messages(bob);
+
+    // Blue loses its ban.
+    harness.advance(TURN);
+    expect_msg!(
+        harness,
+        bob,
+        LobbyServerMessage::ChampionBanned {
+            side: Side::BLUE,
+            champion: None
+        }
+    );
+
+    // Alice gets the champion she was hovering.
+    harness.send(
+        alice,
+        LobbyClientMessage::SelectChampion {
+            champion: "cleric".into(),
+        },
+    );
+    harness.advance(TURN);
+    harness.drain(alice);
+    let msgs = harness.drain(bob);
+    assert!(msgs.iter().any(|msg| matches!(
+        msg,
+        LobbyServerMessage::PlayerLockedInChampion { player, champion } if player.id == alice && champion == "cleric"
+    )), "{msgs:?}");
+
+    // Bob selected nothing, so gets the first champion that is still available.
+    harness.advance(TURN);
+    expect_msg!(
+        harness,
+        bob,
+        LobbyServerMessage::PlayerLockedInChampion { player, champion } if player.id == bob && champion == "ranger"
+    );
+    expect_msg!(harness, bob, LobbyServerMessage::DraftFinished);
+}
+
+#[test]
+fn bots_take_their_turns_immediately() {
+    let mut harness = Harness::new();
+    let alice = harness.connect("alice");
+    harness.send(
+        alice,
+        LobbyClientMessage::CreateLobby {
+            settings: draft_settings(1),
+        },
+    );
+    harness.send(
+        alice,
+        LobbyClientMessage::AddBot {
+            side: Side::BLUE,
+            difficulty: BotDifficulty::Easy,
+        },
+    );
+    harness.drain(alice);
+
+    harness.send(alice, LobbyClientMessage::StartDraft);
+    harness.send(alice, ban("warrior"));
+    harness.send(alice, lock_in("mage"));
+
+    let msgs = harness.drain(alice);
+    assert!(msgs.iter().any(|msg| matches!(
+        msg,
+        LobbyServerMessage::ChampionBanned { side: Side::BLUE, champion: Some(c) } if c == "ranger"
+    )), "{msgs:?}");
+    assert!(msgs.iter().any(|msg| matches!(
+        msg,
+        LobbyServerMessage::PlayerLockedInChampion { player, champion } if player.bot.is_some() && champion == "guardian"
+    )), "{msgs:?}");
+    assert!(
+        matches!(msgs.last(), Some(LobbyServerMessage::DraftFinished)),
+        "{msgs:?}"
+    );
+}
+
+#[test]
+fn spectators_see_the_draft() {
+    let mut harness = Harness::new();
+    let (lobby, alice, bob) = one_on_one(&mut harness, 1);
+    let eve = harness.connect("eve");
+
+    harness.send(
+        eve,
+        LobbyClientMessage::SpectateLobby {
+            id: lobby,
+            password: None,
+        },
+    );
+    expect_msg!(harness, eve, LobbyServerMessage::YouAreSpectating { lobby_id } if lobby_id == lobby);
+
+    harness.send(alice, LobbyClientMessage::StartDraft);
+    harness.send(alice, ban("mage"));
+    expect_msg!(harness, eve, LobbyServerMessage::DraftTurn { .. });
+    expect_msg!(harness, eve, LobbyServerMessage::ChampionBanned { champion: Some(c), .. } if c == "mage");
+    expect_msg!(harness, eve, LobbyServerMessage::DraftTurn { .. });
+
+    harness.send(eve, ban("ranger"));
+    expect_msg!(harness, eve, LobbyServerMessage::Negative { .. });
+    harness.send(eve, lock_in("ranger"));
+    expect_msg!(harness, eve, LobbyServerMessage::Negative { .. });
+
+    harness.send(eve, LobbyClientMessage::LeaveLobby);
+    expect_msg!(harness, eve, LobbyServerMessage::YouLeftLobby);
+    harness.drain(bob);
+    harness.send(bob, ban("ranger"));
+    expect_msg!(harness, bob, LobbyServerMessage::ChampionBanned { .. });
+    harness.assert_no_messages(eve);
+}
+
+#[test]
+fn leaving_cancels_the_draft() {
+    let mut harness = Harness::new();
+    let (lobby, alice, bob) = one_on_one(&mut harness, 1);
+    let carol = harness.connect("carol");
+
+    harness.send(alice, LobbyClientMessage::StartDraft);
+    harness.drain(alice);
+    harness.send(
+        carol,
+        LobbyClientMessage::JoinLobby {
+            id: lobby,
+            password: None,
+        },
+    );
+    expect_msg!(harness, carol, LobbyServerMessage::Negative { .. });
+    harness.send(alice, LobbyClientMessage::SwitchSide);
+    expect_msg!(harness, alice, LobbyServerMessage::Negative { .. });
+
+    harness.send(bob, LobbyClientMessage::LeaveLobby);
+    let msgs = harness.drain(alice);
+    assert!(
+        matches!(msgs.last(), Some(LobbyServerMessage::DraftCancelled { .. })),
+        "{msgs:?}"
+    );
+
+    // Nothing is left to time out.
+    harness.advance(TURN * 2);
+    harness.assert_no_messages(alice);
+    assert_eq!(harness.state.next_deadline(), None);
+}
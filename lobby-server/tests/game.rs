@@ -0,0 +1,189 @@
+use std::net::SocketAddr;
+
+use common::{
+    network::lobby::{BotDifficulty, LobbyClientMessage, LobbyId, LobbyServerMessage, PlayerId},
+    Side,
+};
+use lobby_server::{expect_msg, harness::Harness};
+
+const GAME_ADDR: &str = "127.0.0.1:7000";
+
+fn lock_in(champion: &str) -> LobbyClientMessage {
+    LobbyClientMessage::LockInChampion {
+        champion: champion.into(),
+    }
+}
+
+/// Sets up a lobby with `alice` and `bob` on opposite sides and a bot on Blue, with
+/// empty inboxes. Nobody but the bot has locked in.
+fn lobby_with_bot(harness: &mut Harness) -> (LobbyId, PlayerId, PlayerId) {
+    let alice = harness.connect("alice");
+    let bob = harness.connect("bob");
+    harness.send(
+        alice,
+        LobbyClientMessage::CreateLobby {
+            settings: Default::default(),
+        },
+    );
+    let lobby =
+        expect_msg!(harness, alice, LobbyServerMessage::YouJoinedLobby { lobby_id } => lobby_id);
+    harness.send(
+        bob,
+        LobbyClientMessage::JoinLobby {
+            id: lobby,
+            password: None,
+        },
+    );
+    harness.send(
+        alice,
+        LobbyClientMessage::AddBot {
+            side: Side::BLUE,
+            difficulty: BotDifficulty::Easy,
+        },
+    );
+    harness.drain(alice);
+    harness.drain(bob);
+    (lobby, alice, bob)
+}
+
+/// Locks everyone in and starts the game, leaving inboxes empty.
+fn start_game(harness: &mut Harness, alice: PlayerId, bob: PlayerId) {
+    harness.send(alice, lock_in("warrior"));
+    harness.send(bob, lock_in("mage"));
+    harness.drain(bob);
+    harness.send(alice, LobbyClientMessage::StartGame);
+    expect_msg!(harness, bob, LobbyServerMessage::GameStarting);
+    harness.drain(alice);
+}
+
+#[test]
+fn starting_needs_the_owner_and_everyone_locked_in() {
+    let mut harness = Harness::new();
+    let (_, alice, bob) = lobby_with_bot(&mut harness);
+
+    harness.send(alice, lock_in("warrior"));
+    harness.send(alice, LobbyClientMessage::StartGame);
+    harness.drain(bob);
+    assert!(matches!(
+        harness.drain(alice).last(),
+        Some(LobbyServerMessage::Negative { .. })
+    ));
+
+    harness.send(bob, lock_in("mage"));
+    harness.send(bob, LobbyClientMessage::StartGame);
+    assert!(matches!(
+        harness.drain(bob).last(),
+        Some(LobbyServerMessage::Negative { .. })
+    ));
+    assert!(harness.state.take_match_requests().is_empty());
+
+    harness.drain(alice);
+    harness.send(alice, LobbyClientMessage::StartGame);
+    expect_msg!(harness, alice, LobbyServerMessage::GameStarting);
+    harness.send(alice, LobbyClientMessage::StartGame);
+    expect_msg!(harness, alice, LobbyServerMessage::Negative { .. });
+}
+
+#[test]
+fn match_requests_carry_the_roster_and_a_token_per_human() {
+    let mut harness = Harness::new();
+    let (lobby, alice, bob) = lobby_with_bot(&mut harness);
+    start_game(&mut harness, alice, bob);
+
+    let requests = harness.state.take_match_requests();
+    assert_eq!(requests.len(), 1);
+    let request = &requests[0];
+    assert_eq!(request.lobby, lobby);
+    assert_eq!(request.setup.participants.len(), 3);
+    assert_eq!(request.tokens.len(), 2);
+    assert!(request.tokens.contains_key(&alice) && request.tokens.contains_key(&bob));
+    assert!(harness.state.take_match_requests().is_empty());
+
+    let addr = GAME_ADDR.parse::<SocketAddr>().unwrap();
+    let outgoing = harness.state.game_allocated(lobby, addr);
+    harness.deliver(outgoing);
+    for player in [alice, bob] {
+        expect_msg!(
+            harness,
+            player,
+            LobbyServerMessage::GameReady { addr: a, token } if a == addr && token == request.tokens[&player]
+        );
+    }
+}
+
+#[test]
+fn lobbies_are_locked_while_the_game_runs() {
+    let mut harness = Harness::new();
+    let (lobby, alice, bob) = lobby_with_bot(&mut harness);
+    start_game(&mut harness, alice, bob);
+    let carol = harness.connect("carol");
+
+    harness.send(
+        carol,
+        LobbyClientMessage::JoinLobby {
+            id: lobby,
+            password: None,
+        },
+    );
+    expect_msg!(harness, carol, LobbyServerMessage::Negative { .. });
+    harness.send(bob, LobbyClientMessage::SwitchSide);
+    expect_msg!(harness, bob, LobbyServerMessage::Negative { .. });
+}
+
+#[test]
+fn closing_a_lobby_cancels_its_game() {
+    let mut harness = Harness::new();
+    let (lobby, alice, bob) = lobby_with_bot(&mut harness);
+    start_game(&mut harness, alice, bob);
+    assert!(harness.state.take_cancelled_games().is_empty());
+
+    harness.send(alice, LobbyClientMessage::LeaveLobby);
+    assert!(harness.state.take_cancelled_games().is_empty());
+    harness.send(bob, LobbyClientMessage::LeaveLobby);
+    assert_eq!(harness.state.take_cancelled_games(), [lobby]);
+}
+
+#[test]
+fn failed_games_reopen_the_lobby() {
+    let mut harness = Harness::new();
+    let (lobby, alice, bob) = lobby_with_bot(&mut harness);
+    start_game(&mut harness, alice, bob);
+    harness.state.take_match_requests();
+
+    let outgoing = harness.state.game_failed(lobby, "No game servers".into());
+    harness.deliver(outgoing);
+    for player in [alice, bob] {
+        expect_msg!(harness, player, LobbyServerMessage::GameFailed { .. });
+    }
+
+    harness.send(bob, LobbyClientMessage::SwitchSide);
+    expect_msg!(harness, bob, LobbyServerMessage::PlayerSwitchedSide { .. });
+}
+
+#[test]
+fn finished_games_close_the_lobby() {
+    let mut harness = Harness::new();
+    let (lobby, alice, bob) = lobby_with_bot(&mut harness);
+    start_game(&mut harness, alice, bob);
+
+    let outgoing = harness.state.game_finished(lobby, Some(Side::BLUE));
+    harness.deliver(outgoing);
+    for player in [alice, bob] {
+        expect_msg!(
+            harness,
+            player,
+            LobbyServerMessage::GameEnded {
+                winner: Some(Side::BLUE)
+            }
+        );
+    }
+    let msgs = harness.drain(alice);
+    assert!(msgs
+        .iter()
+        .any(|msg| matches!(msg, LobbyServerMessage::YouLeftLobby)));
+    assert_eq!(harness.state.lobby_count(), 0);
+    assert!(harness.state.take_cancelled_games().is_empty());
+
+    // Reports about a lobby that is gone are ignored.
+    assert!(harness.state.game_finished(lobby, None).is_empty());
+}
@@ -0,0 +1,786 @@
+use common::{
+    champion::ChampionCatalog,
+    network::{
+        game::Controller,
+        lobby::{
+            BotDifficulty, GameMode, LobbyClientMessage, LobbyFilter, LobbyId, LobbyServerMessage,
+            LobbySettings, LobbySort, PickMode, PlayerId,
+        },
+    },
+    rules::GameRules,
+    Side,
+};
+use lobby_server::{
+    expect_msg,
+    harness::{self, Harness},
+    Outgoing, State,
+};
+use uuid::Uuid;
+
+fn create_lobby(harness: &mut Harness, owner: PlayerId) -> LobbyId {
+    create_lobby_with(harness, owner, LobbySettings::default())
+}
+
+fn create_lobby_with(harness: &mut Harness, owner: PlayerId, settings: LobbySettings) -> LobbyId {
+    harness.send(owner, LobbyClientMessage::CreateLobby { settings });
+    expect_msg!(harness, owner, LobbyServerMessage::YouJoinedLobby { lobby_id } => lobby_id)
+}
+
+fn join(id: LobbyId) -> LobbyClientMessage {
+    LobbyClientMessage::JoinLobby { id, password: None }
+}
+
+#[test]
+fn join_balances_sides_and_notifies_members() {
+    let mut harness = Harness::new();
+    let alice = harness.connect("alice");
+    let bob = harness.connect("bob");
+    let carol = harness.connect("carol");
+
+    let lobby = create_lobby(&mut harness, alice);
+
+    harness.send(bob, join(lobby));
+    expect_msg!(harness, bob, LobbyServerMessage::YouJoinedLobby { lobby_id } if lobby_id == lobby);
+    expect_msg!(
+        harness,
+        alice,
+        LobbyServerMessage::PlayerJoinedLobby { player, side: Side::BLUE } if player.id == bob
+    );
+
+    harness.send(carol, join(lobby));
+    expect_msg!(harness, carol, LobbyServerMessage::YouJoinedLobby { .. });
+    expect_msg!(
+        harness,
+        alice,
+        LobbyServerMessage::PlayerJoinedLobby {
+            side: Side::RED,
+            ..
+        }
+    );
+    expect_msg!(
+        harness,
+        bob,
+        LobbyServerMessage::PlayerJoinedLobby {
+            side: Side::RED,
+            ..
+        }
+    );
+    harness.assert_no_messages(carol);
+}
+
+#[test]
+fn cannot_join_twice_or_join_missing_lobby() {
+    let mut harness = Harness::new();
+    let alice = harness.connect("alice");
+    let lobby = create_lobby(&mut harness, alice);
+
+    harness.send(alice, join(lobby));
+    expect_msg!(harness, alice, LobbyServerMessage::Negative { .. });
+
+    let bob = harness.connect("bob");
+    harness.send(alice, LobbyClientMessage::LeaveLobby);
+    harness.drain(alice);
+    harness.send(bob, join(lobby));
+    expect_msg!(harness, bob, LobbyServerMessage::Negative { .. });
+}
+
+#[test]
+fn leave_notifies_remaining_members() {
+    let mut harness = Harness::new();
+    let alice = harness.connect("alice");
+    let bob = harness.connect("bob");
+    let lobby = create_lobby(&mut harness, alice);
+    harness.send(bob, join(lobby));
+    harness.drain(alice);
+    harness.drain(bob);
+
+    harness.send(bob, LobbyClientMessage::LeaveLobby);
+    expect_msg!(harness, bob, LobbyServerMessage::YouLeftLobby);
+    expect_msg!(
+        harness,
+        alice,
+        LobbyServerMessage::PlayerLeftLobby { player } if player.id == bob
+    );
+    harness.assert_no_messages(alice);
+}
+
+#[test]
+fn last_player_leaving_closes_lobby() {
+    let mut harness = Harness::new();
+    let alice = harness.connect("alice");
+    create_lobby(&mut harness, alice);
+    assert_eq!(harness.state.lobby_count(), 1);
+
+    harness.disconnect(alice);
+    assert_eq!(harness.state.lobby_count(), 0);
+    assert_eq!(harness.state.player_count(), 0);
+}
+
+#[test]
+fn owner_leaving_hands_lobby_over() {
+    let mut harness = Harness::new();
+    let alice = harness.connect("alice");
+    let bob = harness.connect("bob");
+    let lobby = create_lobby(&mut harness, alice);
+    harness.send(bob, join(lobby));
+    harness.drain(bob);
+
+    harness.disconnect(alice);
+    expect_msg!(harness, bob, LobbyServerMessage::PlayerLeftLobby { player } if player.id == alice);
+    expect_msg!(harness, bob, LobbyServerMessage::LobbyOwnerChanged { owner } if owner == bob);
+
+    harness.send(bob, LobbyClientMessage::GetLobbyInfo { id: lobby });
+    expect_msg!(harness, bob, LobbyServerMessage::LobbyInfo { info } if info.lobby_owner == bob);
+}
+
+#[test]
+fn champion_select_is_broadcast_and_locks() {
+    let mut harness = Harness::new();
+    let alice = harness.connect("alice");
+    let bob = harness.connect("bob");
+    let lobby = create_lobby(&mut harness, alice);
+    harness.send(bob, join(lobby));
+    harness.drain(alice);
+    harness.drain(bob);
+
+    harness.send(
+        alice,
+        LobbyClientMessage::SelectChampion {
+            champion: "warrior".into(),
+        },
+    );
+    for player in [alice, bob] {
+        expect_msg!(
+            harness,
+            player,
+            LobbyServerMessage::PlayerSelectedChampion { player, champion }
+                if player.id == alice && champion == "warrior"
+        );
+    }
+
+    harness.send(
+        alice,
+        LobbyClientMessage::LockInChampion {
+            champion: "ranger".into(),
+        },
+    );
+    for player in [alice, bob] {
+        expect_msg!(
+            harness,
+            player,
+            LobbyServerMessage::PlayerLockedInChampion { champion, .. } if champion == "ranger"
+        );
+    }
+
+    harness.send(
+        alice,
+        LobbyClientMessage::SelectChampion {
+            champion: "mage".into(),
+        },
+    );
+    expect_msg!(harness, alice, LobbyServerMessage::Negative { .. });
+    harness.assert_no_messages(bob);
+}
+
+#[test]
+fn chat_reaches_whole_lobby() {
+    let mut harness = Harness::new();
+    let alice = harness.connect("alice");
+    let bob = harness.connect("bob");
+    let lobby = create_lobby(&mut harness, alice);
+    harness.send(bob, join(lobby));
+    harness.drain(alice);
+    harness.drain(bob);
+
+    harness.send(
+        bob,
+        LobbyClientMessage::Chat {
+            text: "hello".into(),
+        },
+    );
+    for player in [alice, bob] {
+        expect_msg!(
+            harness,
+            player,
+            LobbyServerMessage::ChatMessage { from, text } if from.id == bob && text == "hello"
+        );
+    }
+}
+
+#[test]
+fn switch_side_moves_player() {
+    let mut harness = Harness::new();
+    let alice = harness.connect("alice");
+    create_lobby(&mut harness, alice);
+
+    harness.send(alice, LobbyClientMessage::SwitchSide);
+    expect_msg!(
+        harness,
+        alice,
+        LobbyServerMessage::PlayerSwitchedSide {
+            side: Side::BLUE,
+            ..
+        }
+    );
+}
+
+#[test]
+fn lobbies_cannot_have_more_sides_than_their_map() {
+    let mut harness = Harness::new();
+    let alice = harness.connect("alice");
+
+    for settings in [
+        LobbySettings {
+            teams: 3,
+            ..Default::default()
+        },
+        LobbySettings {
+            map: "nowhere".into(),
+            ..Default::default()
+        },
+    ] {
+        harness.send(alice, LobbyClientMessage::CreateLobby { settings });
+        expect_msg!(harness, alice, LobbyServerMessage::Negative { .. });
+    }
+    assert_eq!(harness.state.lobby_count(), 0);
+}
+
+#[test]
+fn lobbies_can_have_more_than_two_sides() {
+    let mut harness = Harness::new();
+    let alice = harness.connect("alice");
+    let bob = harness.connect("bob");
+    let carol = harness.connect("carol");
+
+    harness.send(
+        alice,
+        LobbyClientMessage::CreateLobby {
+            settings: LobbySettings {
+                teams: 1,
+                ..Default::default()
+            },
+        },
+    );
+    expect_msg!(harness, alice, LobbyServerMessage::Negative { .. });
+
+    let lobby = create_lobby_with(
+        &mut harness,
+        alice,
+        LobbySettings {
+            map: "triangle".into(),
+            teams: 3,
+            ..Default::default()
+        },
+    );
+
+    harness.send(bob, join(lobby));
+    expect_msg!(harness, bob, LobbyServerMessage::YouJoinedLobby { .. });
+    expect_msg!(
+        harness,
+        alice,
+        LobbyServerMessage::PlayerJoinedLobby { side: Side(1), .. }
+    );
+    harness.send(carol, join(lobby));
+    expect_msg!(harness, carol, LobbyServerMessage::YouJoinedLobby { .. });
+    expect_msg!(
+        harness,
+        alice,
+        LobbyServerMessage::PlayerJoinedLobby { side: Side(2), .. }
+    );
+    harness.drain(bob);
+
+    // Switching goes round every side in turn.
+    harness.send(carol, LobbyClientMessage::SwitchSide);
+    expect_msg!(
+        harness,
+        carol,
+        LobbyServerMessage::PlayerSwitchedSide { side: Side(0), .. }
+    );
+
+    harness.drain(alice);
+    harness.send(
+        alice,
+        LobbyClientMessage::AddBot {
+            side: Side(3),
+            difficulty: BotDifficulty::Easy,
+        },
+    );
+    expect_msg!(harness, alice, LobbyServerMessage::Negative { .. });
+}
+
+#[test]
+fn lobby_messages_outside_lobby_are_rejected() {
+    let mut harness = Harness::new();
+    let alice = harness.connect("alice");
+
+    for msg in [
+        LobbyClientMessage::SwitchSide,
+        LobbyClientMessage::SelectChampion {
+            champion: "champ".into(),
+        },
+        LobbyClientMessage::LockInChampion {
+            champion: "champ".into(),
+        },
+        LobbyClientMessage::LeaveLobby,
+    ] {
+        harness.send(alice, msg);
+    }
+
+    for _ in 0..3 {
+        expect_msg!(harness, alice, LobbyServerMessage::Negative { .. });
+    }
+    harness.assert_no_messages(alice);
+}
+
+#[test]
+fn message_from_unknown_player_is_rejected() {
+    let mut harness = Harness::new();
+    let outgoing = harness.state.handle_message(
+        PlayerId(uuid::Uuid::new_v4()),
+        LobbyClientMessage::SwitchSide,
+    );
+    assert!(matches!(
+        &outgoing[..],
+        [lobby_server::Outgoing {
+            msg: LobbyServerMessage::Negative { .. },
+            ..
+        }]
+    ));
+}
+
+#[test]
+fn join_checks_password_and_capacity() {
+    let mut harness = Harness::new();
+    let alice = harness.connect("alice");
+    let bob = harness.connect("bob");
+    let carol = harness.connect("carol");
+    let lobby = create_lobby_with(
+        &mut harness,
+        alice,
+        LobbySettings {
+            max_players: 2,
+            password: Some("hunter2".into()),
+            ..Default::default()
+        },
+    );
+
+    harness.send(bob, join(lobby));
+    expect_msg!(harness, bob, LobbyServerMessage::Negative { .. });
+
+    harness.send(
+        bob,
+        LobbyClientMessage::JoinLobby {
+            id: lobby,
+            password: Some("hunter2".into()),
+        },
+    );
+    expect_msg!(harness, bob, LobbyServerMessage::YouJoinedLobby { .. });
+
+    harness.send(
+        carol,
+        LobbyClientMessage::JoinLobby {
+            id: lobby,
+            password: Some("hunter2".into()),
+        },
+    );
+    expect_msg!(harness, carol, LobbyServerMessage::Negative { msg } if msg.contains("full"));
+}
+
+#[test]
+fn lobby_list_subscription_pushes_changes() {
+    let mut harness = Harness::new();
+    let watcher = harness.connect("watcher");
+    let alice = harness.connect("alice");
+    let bob = harness.connect("bob");
+
+    harness.send(
+        watcher,
+        LobbyClientMessage::SubscribeLobbyList {
+            filter: LobbyFilter {
+                mode: Some(GameMode::Classic),
+                not_full: true,
+                ..Default::default()
+            },
+            sort: LobbySort::Name,
+            page_size: 10,
+        },
+    );
+    expect_msg!(
+        harness,
+        watcher,
+        LobbyServerMessage::LobbyListPage { page: 0, page_count: 1, lobbies } if lobbies.is_empty()
+    );
+
+    let lobby = create_lobby_with(
+        &mut harness,
+        alice,
+        LobbySettings {
+            max_players: 2,
+            ..Default::default()
+        },
+    );
+    expect_msg!(
+        harness,
+        watcher,
+        LobbyServerMessage::LobbyListAdded { lobby: info } if info.id == lobby && info.players == 1
+    );
+
+    // Filtered out by mode, so the watcher is not told about it.
+    let carol = harness.connect("carol");
+    create_lobby_with(
+        &mut harness,
+        carol,
+        LobbySettings {
+            mode: GameMode::Practice,
+            ..Default::default()
+        },
+    );
+    harness.assert_no_messages(watcher);
+
+    // Full lobbies are filtered out too.
+    harness.send(bob, join(lobby));
+    expect_msg!(harness, watcher, LobbyServerMessage::LobbyListRemoved { id } if id == lobby);
+
+    harness.send(bob, LobbyClientMessage::LeaveLobby);
+    expect_msg!(
+        harness,
+        watcher,
+        LobbyServerMessage::LobbyListAdded { lobby: info } if info.id == lobby
+    );
+
+    harness.send(alice, LobbyClientMessage::SwitchSide);
+    harness.assert_no_messages(watcher);
+
+    harness.send(watcher, LobbyClientMessage::UnsubscribeLobbyList);
+    harness.disconnect(alice);
+    harness.assert_no_messages(watcher);
+}
+
+#[test]
+fn lobby_list_pages_and_sorts() {
+    let mut harness = Harness::new();
+    let watcher = harness.connect("watcher");
+    for name in ["c", "a", "d", "b", "e"] {
+        let owner = harness.connect(&format!("owner-{name}"));
+        create_lobby_with(
+            &mut harness,
+            owner,
+            LobbySettings {
+                name: name.into(),
+                ..Default::default()
+            },
+        );
+    }
+
+    harness.send(
+        watcher,
+        LobbyClientMessage::SubscribeLobbyList {
+            filter: LobbyFilter::default(),
+            sort: LobbySort::Name,
+            page_size: 2,
+        },
+    );
+    expect_msg!(
+        harness,
+        watcher,
+        LobbyServerMessage::LobbyListPage { page: 0, page_count: 3, lobbies }
+            if lobbies.iter().map(|l| l.name.as_str()).eq(["a", "b"])
+    );
+
+    harness.send(watcher, LobbyClientMessage::ViewLobbyListPage { page: 2 });
+    expect_msg!(
+        harness,
+        watcher,
+        LobbyServerMessage::LobbyListPage { page: 2, page_count: 3, lobbies }
+            if lobbies.iter().map(|l| l.name.as_str()).eq(["e"])
+    );
+
+    harness.send(watcher, LobbyClientMessage::ViewLobbyListPage { page: 3 });
+    expect_msg!(harness, watcher, LobbyServerMessage::Negative { .. });
+}
+
+#[test]
+fn owner_can_add_and_remove_bots() {
+    let mut harness = Harness::new();
+    let alice = harness.connect("alice");
+    let bob = harness.connect("bob");
+    let lobby = create_lobby(&mut harness, alice);
+    harness.send(bob, join(lobby));
+    harness.drain(alice);
+    harness.drain(bob);
+
+    harness.send(
+        bob,
+        LobbyClientMessage::AddBot {
+            side: Side::RED,
+            difficulty: BotDifficulty::Easy,
+        },
+    );
+    expect_msg!(harness, bob, LobbyServerMessage::Negative { .. });
+
+    harness.send(
+        alice,
+        LobbyClientMessage::AddBot {
+            side: Side::BLUE,
+            difficulty: BotDifficulty::Hard,
+        },
+    );
+    let bot = expect_msg!(
+        harness,
+        bob,
+        LobbyServerMessage::PlayerJoinedLobby { player, side: Side::BLUE }
+            if player.bot == Some(BotDifficulty::Hard) => player.id
+    );
+    expect_msg!(
+        harness,
+        bob,
+        LobbyServerMessage::PlayerLockedInChampion { player, .. } if player.id == bot
+    );
+    harness.drain(alice);
+
+    harness.send(alice, LobbyClientMessage::GetLobbyInfo { id: lobby });
+    expect_msg!(
+        harness,
+        alice,
+        LobbyServerMessage::LobbyInfo { info }
+            if info.players[&Side::BLUE].iter().any(|p| p.id == bot && p.bot.is_some())
+    );
+
+    let participants = harness.state.match_participants(lobby).unwrap();
+    assert_eq!(participants.len(), 3);
+    assert!(participants.iter().any(|p| p.id == bot
+        && p.champion.is_some()
+        && p.controller
+            == Controller::Bot {
+                difficulty: BotDifficulty::Hard
+            }));
+
+    harness.send(alice, LobbyClientMessage::RemoveBot { id: bot });
+    for player in [alice, bob] {
+        expect_msg!(harness, player, LobbyServerMessage::PlayerLeftLobby { player } if player.id == bot);
+    }
+}
+
+#[test]
+fn lobby_with_only_bots_left_closes() {
+    let mut harness = Harness::new();
+    let alice = harness.connect("alice");
+    let bob = harness.connect("bob");
+    let lobby = create_lobby(&mut harness, alice);
+    harness.send(
+        alice,
+        LobbyClientMessage::AddBot {
+            side: Side::RED,
+            difficulty: BotDifficulty::Medium,
+        },
+    );
+    harness.send(bob, join(lobby));
+    harness.drain(alice);
+    harness.drain(bob);
+
+    // The bot comes first in side order, but bots can't own a lobby.
+    harness.send(alice, LobbyClientMessage::LeaveLobby);
+    expect_msg!(harness, bob, LobbyServerMessage::PlayerLeftLobby { .. });
+    expect_msg!(harness, bob, LobbyServerMessage::LobbyOwnerChanged { owner } if owner == bob);
+
+    harness.send(bob, LobbyClientMessage::LeaveLobby);
+    assert_eq!(harness.state.lobby_count(), 0);
+}
+
+#[test]
+fn champions_are_checked_against_the_catalog() {
+    let mut catalog = harness::champions().iter().cloned().collect::<Vec<_>>();
+    catalog[0].enabled = false;
+    let catalog = ChampionCatalog::new(catalog).unwrap();
+
+    let mut state = State::new().with_champions(catalog.clone());
+    let outgoing = state
+        .client_connected(PlayerId(Uuid::new_v4()), "alice")
+        .unwrap();
+    assert!(matches!(
+        &outgoing[..],
+        [Outgoing { msg: LobbyServerMessage::Welcome { champions, .. }, .. }] if *champions == catalog
+    ));
+
+    let mut harness = Harness::with_state(
+        State::new()
+            .with_champions(catalog)
+            .with_maps(harness::maps()),
+    );
+    let alice = harness.connect("alice");
+    create_lobby(&mut harness, alice);
+
+    for champion in ["nobody", "warrior"] {
+        harness.send(
+            alice,
+            LobbyClientMessage::SelectChampion {
+                champion: champion.into(),
+            },
+        );
+        expect_msg!(harness, alice, LobbyServerMessage::Negative { .. });
+    }
+
+    // Bots skip disabled champions too.
+    harness.send(
+        alice,
+        LobbyClientMessage::AddBot {
+            side: Side::BLUE,
+            difficulty: BotDifficulty::Easy,
+        },
+    );
+    expect_msg!(harness, alice, LobbyServerMessage::PlayerJoinedLobby { .. });
+    expect_msg!(
+        harness,
+        alice,
+        LobbyServerMessage::PlayerLockedInChampion { champion, .. } if champion == "ranger"
+    );
+}
+
+#[test]
+fn owner_sets_game_rules() {
+    let mut harness = Harness::new();
+    let alice = harness.connect("alice");
+    let bob = harness.connect("bob");
+    let lobby = create_lobby(&mut harness, alice);
+    harness.send(bob, join(lobby));
+    harness.drain(alice);
+    harness.drain(bob);
+
+    let quick = GameRules::presets()
+        .into_iter()
+        .find_map(|(name, rules)| (name == "Quick").then_some(rules))
+        .unwrap();
+
+    harness.send(
+        bob,
+        LobbyClientMessage::SetGameRules {
+            rules: quick.clone(),
+        },
+    );
+    expect_msg!(harness, bob, LobbyServerMessage::Negative { .. });
+    harness.send(
+        alice,
+        LobbyClientMessage::SetGameRules {
+            rules: GameRules {
+                starting_level: 0,
+                ..Default::default()
+            },
+        },
+    );
+    expect_msg!(harness, alice, LobbyServerMessage::Negative { .. });
+
+    harness.send(
+        alice,
+        LobbyClientMessage::SetGameRules {
+            rules: quick.clone(),
+        },
+    );
+    for player in [alice, bob] {
+        expect_msg!(
+            harness,
+            player,
+            LobbyServerMessage::GameRulesChanged { rules } if rules == quick
+        );
+    }
+
+    harness.send(bob, LobbyClientMessage::GetLobbyInfo { id: lobby });
+    expect_msg!(harness, bob, LobbyServerMessage::LobbyInfo { info } if info.rules == quick);
+    assert_eq!(harness.state.match_setup(lobby).unwrap().rules, quick);
+}
+
+#[test]
+fn turning_on_all_random_clears_picks() {
+    let mut harness = Harness::new();
+    let alice = harness.connect("alice");
+    let bob = harness.connect("bob");
+    let lobby = create_lobby(&mut harness, alice);
+    harness.send(bob, join(lobby));
+    harness.send(
+        bob,
+        LobbyClientMessage::SelectChampion {
+            champion: "warrior".into(),
+        },
+    );
+    harness.drain(alice);
+    harness.drain(bob);
+
+    let all_random = GameRules {
+        all_random: true,
+        ..Default::default()
+    };
+    harness.send(
+        alice,
+        LobbyClientMessage::SetGameRules {
+            rules: all_random.clone(),
+        },
+    );
+    for player in [alice, bob] {
+        expect_msg!(harness, player, LobbyServerMessage::GameRulesChanged { .. });
+        expect_msg!(harness, player, LobbyServerMessage::ChampionPicksCleared);
+    }
+    // Everyone is handed a champion when the game starts instead.
+    let champions = harness::champions();
+    for participant in harness.state.match_setup(lobby).unwrap().participants {
+        let champion = participant.champion.unwrap();
+        assert!(champions.get(&champion).is_some());
+    }
+
+    // With nothing picked, there is nothing to clear.
+    harness.send(
+        alice,
+        LobbyClientMessage::SetGameRules { rules: all_random },
+    );
+    expect_msg!(harness, alice, LobbyServerMessage::GameRulesChanged { .. });
+    harness.assert_no_messages(alice);
+}
+
+#[test]
+fn all_random_lobbies_skip_champion_select() {
+    let mut harness = Harness::new();
+    let alice = harness.connect("alice");
+    let rules = GameRules {
+        all_random: true,
+        ..Default::default()
+    };
+
+    harness.send(
+        alice,
+        LobbyClientMessage::CreateLobby {
+            settings: LobbySettings {
+                pick_mode: PickMode::Draft {
+                    bans_per_side: 1,
+                    turn_secs: 30,
+                },
+                rules: rules.clone(),
+                ..Default::default()
+            },
+        },
+    );
+    expect_msg!(harness, alice, LobbyServerMessage::Negative { .. });
+
+    create_lobby_with(
+        &mut harness,
+        alice,
+        LobbySettings {
+            rules,
+            ..Default::default()
+        },
+    );
+    harness.send(
+        alice,
+        LobbyClientMessage::SelectChampion {
+            champion: "warrior".into(),
+        },
+    );
+    expect_msg!(harness, alice, LobbyServerMessage::Negative { .. });
+
+    harness.send(
+        alice,
+        LobbyClientMessage::AddBot {
+            side: Side::BLUE,
+            difficulty: BotDifficulty::Easy,
+        },
+    );
+    expect_msg!(harness, alice, LobbyServerMessage::PlayerJoinedLobby { .. });
+    harness.assert_no_messages(alice);
+}
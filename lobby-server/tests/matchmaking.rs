@@ -0,0 +1,232 @@
+use bevy::utils::HashMap;
+use common::{
+    champion::Role,
+    network::lobby::{
+        LobbyClientMessage, LobbyId, LobbyServerMessage, LobbySettings, PlayerId, RolePreference,
+    },
+    Side,
+};
+use lobby_server::{expect_msg, harness::Harness};
+
+fn queue(primary: Role, secondary: Option<Role>) -> LobbyClientMessage {
+    LobbyClientMessage::StartMatchmaking {
+        roles: RolePreference { primary, secondary },
+    }
+}
+
+fn lobby_info(
+    harness: &mut Harness,
+    player: PlayerId,
+    id: LobbyId,
+) -> common::network::lobby::LobbyInfo {
+    harness.send(player, LobbyClientMessage::GetLobbyInfo { id });
+    expect_msg!(harness, player, LobbyServerMessage::LobbyInfo { info } => info)
+}
+
+/// Queues ten players with the given preferences and returns them, along with the lobby
+/// they were matched into.
+fn match_ten(
+    harness: &mut Harness,
+    preferences: [(Role, Option<Role>); 10],
+) -> (Vec<PlayerId>, LobbyId) {
+    let players = (0..10)
+        .map(|i| harness.connect(&format!("player{i}")))
+        .collect::<Vec<_>>();
+
+    for (player, (primary, secondary)) in players.iter().zip(preferences) {
+        harness.send(*player, queue(primary, secondary));
+        expect_msg!(harness, *player, LobbyServerMessage::OK);
+    }
+
+    let mut lobby = None;
+    for player in &players {
+        let id = expect_msg!(harness, *player, LobbyServerMessage::MatchmakingDone { lobby_id } => lobby_id);
+        expect_msg!(harness, *player, LobbyServerMessage::YouJoinedLobby { lobby_id } if lobby_id == id);
+        expect_msg!(harness, *player, LobbyServerMessage::RolesAssigned { .. });
+        lobby = Some(id);
+    }
+    (players, lobby.unwrap())
+}
+
+/// Asserts that both teams have one player in every role, and returns everyone's role.
+#[track_caller]
+fn assert_full_coverage(
+    harness: &mut Harness,
+    player: PlayerId,
+    lobby: LobbyId,
+) -> HashMap<PlayerId, Role> {
+    let info = lobby_info(harness, player, lobby);
+    for side in Side::all(2) {
+        let mut roles = info.players[&side]
+            .iter()
+            .map(|p| info.roles[&p.id])
+            .collect::<Vec<_>>();
+        roles.sort();
+        assert_eq!(roles, Role::ALL, "{side:?}");
+    }
+    info.roles
+}
+
+#[test]
+fn queueing_and_leaving_the_queue() {
+    let mut harness = Harness::new();
+    let alice = harness.connect("alice");
+
+    harness.send(alice, queue(Role::Mid, Some(Role::Mid)));
+    expect_msg!(harness, alice, LobbyServerMessage::Negative { .. });
+
+    harness.send(alice, queue(Role::Mid, None));
+    expect_msg!(harness, alice, LobbyServerMessage::OK);
+    harness.send(alice, queue(Role::Mid, None));
+    expect_msg!(harness, alice, LobbyServerMessage::Negative { .. });
+    harness.send(
+        alice,
+        LobbyClientMessage::CreateLobby {
+            settings: LobbySettings::default(),
+        },
+    );
+    expect_msg!(harness, alice, LobbyServerMessage::Negative { .. });
+
+    harness.send(alice, LobbyClientMessage::StopMatchmaking);
+    expect_msg!(harness, alice, LobbyServerMessage::OK);
+    harness.send(alice, LobbyClientMessage::StopMatchmaking);
+    expect_msg!(harness, alice, LobbyServerMessage::Negative { .. });
+}
+
+#[test]
+fn matched_teams_cover_every_role() {
+    let mut harness = Harness::new();
+    // Everybody wants to play mid.
+    let (players, lobby) = match_ten(&mut harness, [(Role::Mid, None); 10]);
+
+    let roles = assert_full_coverage(&mut harness, players[0], lobby);
+    // The two who queued first got their way.
+    assert_eq!(roles[&players[0]], Role::Mid);
+    assert_eq!(roles[&players[1]], Role::Mid);
+}
+
+#[test]
+fn matchmaking_honours_preferences_where_it_can() {
+    let mut harness = Harness::new();
+    let (players, lobby) = match_ten(
+        &mut harness,
+        [
+            (Role::Top, Some(Role::Mid)),
+            (Role::Top, Some(Role::Jungle)),
+            (Role::Top, Some(Role::Support)),
+            (Role::Jungle, None),
+            (Role::Mid, None),
+            (Role::Bot, Some(Role::Top)),
+            (Role::Bot, None),
+            (Role::Support, None),
+            (Role::Mid, Some(Role::Jungle)),
+            (Role::Support, None),
+        ],
+    );
+
+    let roles = assert_full_coverage(&mut harness, players[0], lobby);
+    // Player 2 finds both their roles taken by players who queued earlier.
+    let expected = [
+        Role::Top,
+        Role::Top,
+        Role::Jungle,
+        Role::Jungle,
+        Role::Mid,
+        Role::Bot,
+        Role::Bot,
+        Role::Support,
+        Role::Mid,
+        Role::Support,
+    ];
+    for (player, role) in players.iter().zip(expected) {
+        assert_eq!(roles[player], role, "{}", harness.username(*player));
+    }
+}
+
+#[test]
+fn disconnecting_leaves_the_queue() {
+    let mut harness = Harness::new();
+    let quitter = harness.connect("quitter");
+    harness.send(quitter, queue(Role::Top, None));
+    harness.disconnect(quitter);
+
+    let players = (0..9)
+        .map(|i| harness.connect(&format!("player{i}")))
+        .collect::<Vec<_>>();
+    for player in &players {
+        harness.send(*player, queue(Role::Top, None));
+        expect_msg!(harness, *player, LobbyServerMessage::OK);
+    }
+    harness.assert_no_messages(players[0]);
+}
+
+#[test]
+fn lobby_roles_follow_preferences() {
+    let mut harness = Harness::new();
+    let alice = harness.connect("alice");
+    let bob = harness.connect("bob");
+    let carol = harness.connect("carol");
+    harness.send(
+        alice,
+        LobbyClientMessage::CreateLobby {
+            settings: LobbySettings::default(),
+        },
+    );
+    let lobby =
+        expect_msg!(harness, alice, LobbyServerMessage::YouJoinedLobby { lobby_id } => lobby_id);
+    for player in [bob, carol] {
+        harness.send(
+            player,
+            LobbyClientMessage::JoinLobby {
+                id: lobby,
+                password: None,
+            },
+        );
+    }
+    harness.drain(alice);
+    harness.drain(bob);
+
+    // Alice and Carol are both on red.
+    let set_roles = |primary, secondary| LobbyClientMessage::SetRolePreference {
+        roles: RolePreference { primary, secondary },
+    };
+    harness.send(alice, set_roles(Role::Mid, None));
+    expect_msg!(
+        harness,
+        bob,
+        LobbyServerMessage::RolesAssigned { roles } if roles.len() == 1 && roles[&alice] == Role::Mid
+    );
+    harness.send(carol, set_roles(Role::Mid, Some(Role::Support)));
+    expect_msg!(
+        harness,
+        bob,
+        LobbyServerMessage::RolesAssigned { roles } if roles[&carol] == Role::Support
+    );
+
+    // Nothing changes, so nothing is sent.
+    harness.send(bob, set_roles(Role::Bot, None));
+    harness.drain(bob);
+    harness.send(bob, set_roles(Role::Bot, Some(Role::Top)));
+    harness.assert_no_messages(bob);
+
+    // Same role on different sides is fine.
+    harness.send(bob, set_roles(Role::Mid, None));
+    expect_msg!(
+        harness,
+        bob,
+        LobbyServerMessage::RolesAssigned { roles } if roles[&bob] == Role::Mid
+    );
+
+    harness.send(alice, LobbyClientMessage::LeaveLobby);
+    expect_msg!(harness, bob, LobbyServerMessage::PlayerLeftLobby { .. });
+    expect_msg!(harness, bob, LobbyServerMessage::LobbyOwnerChanged { .. });
+    expect_msg!(
+        harness,
+        bob,
+        LobbyServerMessage::RolesAssigned { roles } if roles.len() == 2 && roles[&carol] == Role::Mid
+    );
+    assert_eq!(
+        lobby_info(&mut harness, bob, lobby).roles[&carol],
+        Role::Mid
+    );
+}
@@ -0,0 +1,145 @@
+use std::time::{Duration, Instant};
+
+use lobby_server::rate_limit::{BucketConfig, RateLimitConfig, RateLimiter, Verdict};
+
+/// Bursts of 3, refilling at 2 a second, with no per-message limits.
+fn config() -> RateLimitConfig {
+    RateLimitConfig {
+        connection: BucketConfig {
+            capacity: 3.0,
+            per_second: 2.0,
+        },
+        per_message: Default::default(),
+        ..Default::default()
+    }
+}
+
+/// How many messages are allowed in a row at `now`.
+fn burst(limiter: &mut RateLimiter, config: &RateLimitConfig, now: Instant) -> usize {
+    let mut allowed = 0;
+    while let Verdict::Allowed = limiter.check(config, "Ping", now) {
+        allowed += 1;
+    }
+    allowed
+}
+
+#[test]
+fn buckets_start_full() {
+    let config = config();
+    let mut limiter = RateLimiter::default();
+
+    assert_eq!(burst(&mut limiter, &config, Instant::now()), 3);
+}
+
+#[test]
+fn tokens_refill_over_time() {
+    let config = config();
+    let mut limiter = RateLimiter::default();
+    let start = Instant::now();
+    burst(&mut limiter, &config, start);
+
+    assert_eq!(
+        burst(&mut limiter, &config, start + Duration::from_millis(400)),
+        0
+    );
+    assert_eq!(
+        burst(&mut limiter, &config, start + Duration::from_millis(500)),
+        1
+    );
+    assert_eq!(
+        burst(&mut limiter, &config, start + Duration::from_millis(1500)),
+        2
+    );
+}
+
+#[test]
+fn tokens_stop_at_the_capacity() {
+    let config = config();
+    let mut limiter = RateLimiter::default();
+    let start = Instant::now();
+    burst(&mut limiter, &config, start);
+
+    let later = start + Duration::from_secs(60);
+    assert_eq!(burst(&mut limiter, &config, later), 3);
+}
+
+#[test]
+fn limited_messages_say_when_to_retry() {
+    let config = config();
+    let mut limiter = RateLimiter::default();
+    let start = Instant::now();
+    burst(&mut limiter, &config, start);
+
+    let Verdict::Limited { retry_after } = limiter.check(&config, "Ping", start) else {
+        panic!("The bucket should be empty");
+    };
+    assert_eq!(retry_after, Duration::from_millis(500));
+
+    let now = start + Duration::from_millis(200);
+    let Verdict::Limited { retry_after } = limiter.check(&config, "Ping", now) else {
+        panic!("The bucket should still be empty");
+    };
+    assert_eq!(retry_after, Duration::from_millis(300));
+}
+
+#[test]
+fn message_limits_apply_on_top_of_the_connection_limit() {
+    let mut config = config();
+    config.per_message.insert(
+        "CreateLobby".into(),
+        BucketConfig {
+            capacity: 1.0,
+            per_second: 0.5,
+        },
+    );
+    let mut limiter = RateLimiter::default();
+    let now = Instant::now();
+
+    assert!(matches!(
+        limiter.check(&config, "CreateLobby", now),
+        Verdict::Allowed
+    ));
+    let Verdict::Limited { retry_after } = limiter.check(&config, "CreateLobby", now) else {
+        panic!("CreateLobby should be limited");
+    };
+    assert_eq!(retry_after, Duration::from_secs(2));
+    assert_eq!(burst(&mut limiter, &config, now), 2);
+}
+
+#[test]
+fn very_slow_buckets_do_not_overflow_the_retry_time() {
+    let config = RateLimitConfig {
+        connection: BucketConfig {
+            capacity: 0.0,
+            per_second: 1e-300,
+        },
+        ..config()
+    };
+    let mut limiter = RateLimiter::default();
+
+    let Verdict::Limited { retry_after } = limiter.check(&config, "Ping", Instant::now()) else {
+        panic!("An empty bucket should limit");
+    };
+    assert_eq!(retry_after, Duration::MAX);
+}
+
+#[test]
+fn repeat_offenders_are_dropped() {
+    let config = RateLimitConfig {
+        max_violations: 2,
+        ..config()
+    };
+    let mut limiter = RateLimiter::default();
+    let now = Instant::now();
+    burst(&mut limiter, &config, now);
+
+    // The burst already ended with one violation.
+    assert!(matches!(
+        limiter.check(&config, "Ping", now),
+        Verdict::Limited { .. }
+    ));
+    assert!(matches!(
+        limiter.check(&config, "Ping", now),
+        Verdict::Abusive
+    ));
+}
@@ -0,0 +1,90 @@
+use common::network::lobby::UsernameError;
+use lobby_server::{harness::Harness, username::UsernameRules, State};
+
+fn strict_harness() -> Harness {
+    Harness::with_state(State::with_username_rules(UsernameRules {
+        blocklist: vec!["darn".into()],
+        discriminators: false,
+        ..Default::default()
+    }))
+}
+
+#[test]
+fn length_and_characters_are_checked() {
+    let mut harness = strict_harness();
+
+    assert_eq!(
+        harness.try_connect("  ab  "),
+        Err(UsernameError::TooShort { min: 3 })
+    );
+    assert_eq!(
+        harness.try_connect(&"a".repeat(10_000)),
+        Err(UsernameError::TooLong { max: 16 })
+    );
+    assert_eq!(
+        harness.try_connect("bad\u{7}name"),
+        Err(UsernameError::InvalidCharacter { character: '\u{7}' })
+    );
+    assert!(harness.try_connect("Some_Player-1").is_ok());
+}
+
+#[test]
+fn names_are_trimmed() {
+    let mut harness = strict_harness();
+    let alice = harness.connect("  alice ");
+    assert_eq!(harness.username(alice), "alice");
+}
+
+#[test]
+fn mixed_scripts_are_rejected() {
+    let mut harness = strict_harness();
+    // Latin "p" and "l" around Cyrillic "а".
+    assert_eq!(
+        harness.try_connect("pаypal"),
+        Err(UsernameError::MixedScripts)
+    );
+    assert!(harness.try_connect("пользователь").is_ok());
+}
+
+#[test]
+fn reserved_and_blocked_names_are_rejected() {
+    let mut harness = strict_harness();
+
+    assert_eq!(harness.try_connect("Admin"), Err(UsernameError::Reserved));
+    assert_eq!(
+        harness.try_connect("rnoderator"),
+        Err(UsernameError::Reserved)
+    );
+    assert_eq!(harness.try_connect("xXdarnXx"), Err(UsernameError::Blocked));
+    assert!(harness.try_connect("administrators").is_ok());
+}
+
+#[test]
+fn names_must_be_unique_ignoring_case_and_lookalikes() {
+    let mut harness = strict_harness();
+    let alice = harness.connect("alice");
+
+    assert_eq!(harness.try_connect("ALICE"), Err(UsernameError::Taken));
+    // Cyrillic "а" in place of the Latin one.
+    assert_eq!(
+        harness.try_connect("аlice"),
+        Err(UsernameError::MixedScripts)
+    );
+    harness.connect("modern");
+    assert_eq!(harness.try_connect("rnodern"), Err(UsernameError::Taken));
+
+    harness.disconnect(alice);
+    assert!(harness.try_connect("alice").is_ok());
+}
+
+#[test]
+fn discriminators_tell_duplicates_apart() {
+    let mut harness = Harness::new();
+    let first = harness.connect("Guest");
+    let second = harness.connect("Guest");
+
+    assert_eq!(harness.username(first), "Guest");
+    let second_name = harness.username(second);
+    assert!(second_name.starts_with("Guest#"), "{second_name}");
+    assert_eq!(second_name.len(), "Guest#0000".len());
+}